@@ -23,6 +23,7 @@ use std::fs::File;
 use std::path::Path;
 
 use jpeg_decoder::{Decoder, PixelFormat};
+use texpresso::PixelLayout;
 
 use super::RawImage;
 
@@ -36,22 +37,17 @@ pub fn read(path: &Path) -> RawImage {
     // Decode the image
     let info = decoder.info().unwrap();
 
-    let mut buf = decoder.decode().unwrap();
-    buf = match info.pixel_format {
-        PixelFormat::L8 => buf[..]
-            .iter()
-            .flat_map(|&l| vec![l, l, l, 255u8])
-            .collect::<Vec<u8>>(),
-        PixelFormat::RGB24 => buf[..]
-            .chunks(3)
-            .flat_map(|rgb| vec![rgb[0], rgb[1], rgb[2], 255u8])
-            .collect::<Vec<u8>>(),
-        x @ _ => panic!("JPEG files with format {:?} are not supported", x),
+    let buf = decoder.decode().unwrap();
+    let layout = match info.pixel_format {
+        PixelFormat::L8 => PixelLayout::L8,
+        PixelFormat::RGB24 => PixelLayout::Rgb8,
+        x => panic!("JPEG files with format {:?} are not supported", x),
     };
 
     RawImage {
         width: info.width as usize,
         height: info.height as usize,
+        layout,
         data: buf,
     }
 }
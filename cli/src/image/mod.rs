@@ -19,11 +19,14 @@
 // TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION WITH THE
 // SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
 
+use texpresso::PixelLayout;
+
 pub mod jpeg;
 pub mod png;
 
 pub struct RawImage {
     pub width: usize,
     pub height: usize,
+    pub layout: PixelLayout,
     pub data: Vec<u8>,
 }
@@ -24,6 +24,7 @@ use std::io::BufWriter;
 use std::path::Path;
 
 use png::{BitDepth, ColorType, Transformations};
+use texpresso::PixelLayout;
 
 use super::RawImage;
 
@@ -44,27 +45,18 @@ pub fn read(path: &Path) -> RawImage {
     // Read the next frame. Currently this function should only called once.
     let info = reader.next_frame(&mut buf).unwrap();
 
-    // expand to rgba
-    buf = match info.color_type {
-        ColorType::Grayscale => buf[..]
-            .iter()
-            .flat_map(|&r| vec![r, r, r, 255])
-            .collect::<Vec<u8>>(),
-        ColorType::GrayscaleAlpha => buf[..]
-            .chunks(2)
-            .flat_map(|rg| vec![rg[0], rg[0], rg[0], rg[1]])
-            .collect::<Vec<u8>>(),
-        ColorType::Rgb => buf[..]
-            .chunks(3)
-            .flat_map(|rgb| vec![rgb[0], rgb[1], rgb[2], 255])
-            .collect::<Vec<u8>>(),
-        ColorType::Rgba => buf,
-        ColorType::Indexed => buf,
+    // the compressor reads gray and RGB pixels directly
+    let layout = match info.color_type {
+        ColorType::Grayscale => PixelLayout::L8,
+        ColorType::GrayscaleAlpha => PixelLayout::La8,
+        ColorType::Rgb => PixelLayout::Rgb8,
+        ColorType::Rgba | ColorType::Indexed => PixelLayout::Rgba8,
     };
 
     RawImage {
         width: info.width as usize,
         height: info.height as usize,
+        layout,
         data: buf,
     }
 }
@@ -25,7 +25,11 @@ use std::path::{Path, PathBuf};
 
 use clap::{Parser, ValueEnum};
 use ddsfile::{AlphaMode, D3D10ResourceDimension, D3DFormat, Dds, DxgiFormat};
-use texpresso::{Algorithm, Format, Params, COLOUR_WEIGHTS_PERCEPTUAL};
+use texpresso::{
+    decode_dxt5nm, decode_ycocg, mip_chain_len, mip_levels, reconstruct_normal_z, Algorithm,
+    ColourSpace, EdgeMode, ErrorMetric, Format, MipFilter, MipmapParams, Params,
+    COLOUR_WEIGHTS_PERCEPTUAL,
+};
 
 mod image;
 
@@ -36,13 +40,38 @@ enum Profile {
     Quality,
 }
 
+#[derive(Clone, ValueEnum)]
+enum CliErrorMetric {
+    Rgb,
+    Ycocg,
+    Oklab,
+    Cielab,
+}
+
+#[derive(Clone, ValueEnum)]
+enum CliMipFilter {
+    Box,
+    Triangle,
+    Kaiser,
+    Lanczos,
+}
+
 #[derive(Clone, ValueEnum)]
 enum CliFormat {
     Bc1,
     Bc2,
     Bc3,
+    /// BC3 with a normal map in the DXT5nm layout
+    Bc3n,
+    /// BC3 with colours stored as scaled YCoCg
+    Bc3Ycocg,
     Bc4,
     Bc5,
+    Bc4Snorm,
+    Bc5Snorm,
+    Bc6hUf16,
+    Bc6hSf16,
+    Bc7,
 }
 
 #[derive(Parser)]
@@ -63,7 +92,7 @@ enum Opt {
         #[arg(short = 'f', long = "format")]
         format: CliFormat,
 
-        /// Compressor profile (speed, balanced, quality).
+        /// Compressor profile (speed, balanced, quality). The quality profile is much slower, most of all for BC3 alpha, BC4 and BC5.
         #[arg(short = 'p', long = "profile", default_value = "balanced")]
         profile: Profile,
 
@@ -71,10 +100,42 @@ enum Opt {
         #[arg(long = "weigh-colour-by-alpha")]
         weigh_colour_by_alpha: bool,
 
+        /// Alpha below which BC1 pixels are encoded as transparent.
+        #[arg(long = "alpha-threshold", default_value_t = 128)]
+        alpha_threshold: u8,
+
         // TODO: replace with something nicer
         /// Colour weights to be used for matching colours during fitting.
         #[arg(short = 'w', long = "weights")]
         weights: Vec<f32>,
+
+        /// Colour space to measure colour errors in while fitting (rgb, ycocg, oklab, cielab). Defaults to oklab.
+        #[arg(short = 'm', long = "error-metric")]
+        error_metric: Option<CliErrorMetric>,
+
+        /// Rate-distortion trade-off for BC1-BC5. Higher values make the output smaller after LZ compression at some loss of quality.
+        #[arg(long = "rdo-lambda", default_value_t = 0.0)]
+        rdo_lambda: f32,
+
+        /// Generate a full mip chain and store every level in the output file.
+        #[arg(long = "mipmaps")]
+        mipmaps: bool,
+
+        /// Filter for downsampling mip levels (box, triangle, kaiser, lanczos).
+        #[arg(long = "mip-filter", default_value = "box")]
+        mip_filter: CliMipFilter,
+
+        /// Wrap around the edges of the image when downsampling mip levels, for tiling textures.
+        #[arg(long = "mip-wrap")]
+        mip_wrap: bool,
+
+        /// Keep the fraction of pixels with an alpha at or above the alpha threshold the same in every mip level, for alpha-tested textures.
+        #[arg(long = "mip-alpha-coverage")]
+        mip_alpha_coverage: bool,
+
+        /// Treat the input as a tangent space normal map. BC5 is fitted by the angles between normals and mip levels are renormalised.
+        #[arg(long = "normal-map")]
+        normal_map: bool,
     },
 
     /// Deompress a DDS file to PNG
@@ -87,6 +148,14 @@ enum Opt {
         /// Input file (DDS)
         #[clap(name = "INFILE")]
         infile: PathBuf,
+
+        /// Rebuild a normal map stored in two channels: reconstruct Z into blue, and move X from alpha to red for BC3 (DXT5nm).
+        #[clap(long = "normal-map")]
+        normal_map: bool,
+
+        /// Convert BC3 colours stored as scaled YCoCg back to RGB.
+        #[clap(long = "ycocg")]
+        ycocg: bool,
     },
 }
 
@@ -98,7 +167,15 @@ fn main() {
             format,
             profile,
             weigh_colour_by_alpha,
+            alpha_threshold,
             weights,
+            error_metric,
+            rdo_lambda,
+            mipmaps,
+            mip_filter,
+            mip_wrap,
+            mip_alpha_coverage,
+            normal_map,
         } => {
             let w;
             if weights.is_empty() {
@@ -108,18 +185,54 @@ fn main() {
             } else {
                 panic!("Weights must have 3 values");
             }
-            let params = Params {
-                algorithm: profile.into(),
-                weights: w,
-                weigh_colour_by_alpha,
+            let normal_map = normal_map || matches!(format, CliFormat::Bc3n);
+            let mut params = Params::default();
+            params.algorithm = profile.into();
+            params.weights = w;
+            params.weigh_colour_by_alpha = weigh_colour_by_alpha;
+            params.alpha_threshold = alpha_threshold;
+            params.normal_map = normal_map;
+            params.ycocg = matches!(format, CliFormat::Bc3Ycocg);
+            params.error_metric = error_metric.map(ErrorMetric::from);
+            params.rdo_lambda = rdo_lambda;
+            let format = format.into();
+
+            // fit and filter in the colour space the output file is tagged with
+            let colour_space = if is_srgb(output_dxgiformat(format, &params)) {
+                ColourSpace::Srgb
+            } else {
+                ColourSpace::Linear
             };
-            compress_file(outfile, &infile, format.into(), params)
+            params.colour_space = colour_space;
+            let mipmaps = mipmaps.then(|| MipmapParams {
+                filter: mip_filter.into(),
+                edge_mode: if mip_wrap {
+                    EdgeMode::Wrap
+                } else {
+                    EdgeMode::Clamp
+                },
+                colour_space,
+                alpha_coverage: mip_alpha_coverage.then_some(alpha_threshold),
+                normal_map,
+            });
+            compress_file(outfile, &infile, format, params, mipmaps)
         }
-        Opt::Decompress { outfile, infile } => decompress_file(outfile, &infile),
+        Opt::Decompress {
+            outfile,
+            infile,
+            normal_map,
+            ycocg,
+        } => decompress_file(outfile, &infile, normal_map, ycocg),
     };
 }
 
-fn compress_file(outfile: Option<PathBuf>, infile: &Path, format: Format, params: Params) {
+fn compress_file(
+    outfile: Option<PathBuf>,
+    infile: &Path,
+    format: Format,
+    params: Params,
+    mipmaps: Option<MipmapParams>,
+) {
     let outfile = outfile.unwrap_or_else(|| {
         PathBuf::new()
             .with_file_name(infile.file_name().unwrap_or_else(|| OsStr::new("output")))
@@ -129,7 +242,6 @@ fn compress_file(outfile: Option<PathBuf>, infile: &Path, format: Format, params
         .extension()
         .expect("Input filename has no extension, can't guess type")
         .to_string_lossy()
-        .to_owned()
         .to_lowercase();
     let image = match in_ext.as_str() {
         "jpg" | "jpeg" => image::jpeg::read(infile),
@@ -137,8 +249,42 @@ fn compress_file(outfile: Option<PathBuf>, infile: &Path, format: Format, params
         _ => panic!("Unrecognized image format. Supported formats are PNG and JPEG"),
     };
 
-    let mut buf = vec![0u8; format.compressed_size(image.width, image.height)];
-    format.compress(&image.data, image.width, image.height, params, &mut buf);
+    let mut mipmap_levels = None;
+    let buf = if let Some(mipmaps) = mipmaps {
+        let mut chain = vec![0u8; mip_chain_len(image.width, image.height)];
+        if let Err(error) = mipmaps.generate_with_layout(
+            &image.data,
+            image.layout,
+            image.width,
+            image.height,
+            &mut chain,
+        ) {
+            panic!("Failed to generate mipmaps: {}", error);
+        }
+
+        let mut buf = vec![0u8; format.compressed_mip_chain_size(image.width, image.height)];
+        if let Err(error) =
+            format.compress_mipmaps(&chain, image.width, image.height, params, &mut buf)
+        {
+            panic!("Failed to compress mipmaps: {}", error);
+        }
+        mipmap_levels = Some(mip_levels(image.width, image.height) as u32);
+        buf
+    } else {
+        let mut buf = vec![0u8; format.compressed_size(image.width, image.height)];
+        if let Err(error) = format.try_compress_with_layout(
+            &image.data,
+            image.layout,
+            image.width,
+            image.height,
+            image.width * image.layout.channels(),
+            params,
+            &mut buf,
+        ) {
+            panic!("Failed to compress: {}", error);
+        }
+        buf
+    };
 
     let alphamode = if format == Format::Bc1 {
         AlphaMode::PreMultiplied
@@ -149,8 +295,8 @@ fn compress_file(outfile: Option<PathBuf>, infile: &Path, format: Format, params
         height: image.height as u32,
         width: image.width as u32,
         depth: None,
-        format: format_to_dxgiformat(format),
-        mipmap_levels: None,
+        format: output_dxgiformat(format, &params),
+        mipmap_levels,
         array_layers: None,
         caps2: None,
         is_cubemap: false,
@@ -164,14 +310,14 @@ fn compress_file(outfile: Option<PathBuf>, infile: &Path, format: Format, params
     dds.write(&mut outfile).unwrap();
 }
 
-fn decompress_file(outfile: Option<PathBuf>, infile: &Path) {
+fn decompress_file(outfile: Option<PathBuf>, infile: &Path, normal_map: bool, ycocg: bool) {
     let outfile = outfile.unwrap_or_else(|| {
         PathBuf::new()
             .with_file_name(infile.file_name().unwrap_or_else(|| OsStr::new("output")))
             .with_extension("png")
     });
 
-    let mut infile = File::open(&infile).expect("Failed to open file");
+    let mut infile = File::open(infile).expect("Failed to open file");
     let dds = Dds::read(&mut infile).unwrap();
 
     let d3dformat = D3DFormat::try_from_pixel_format(&dds.header.spf);
@@ -190,14 +336,23 @@ fn decompress_file(outfile: Option<PathBuf>, infile: &Path) {
     let height = dds.header.height as usize;
     let mut decompressed = vec![0u8; 4 * width * height];
 
-    format.decompress(&dds.data, width, height, &mut decompressed);
+    if let Err(error) = format.try_decompress(&dds.data, width, height, &mut decompressed) {
+        panic!("Failed to decompress image: {}", error);
+    }
+    if normal_map && format == Format::Bc3 {
+        decode_dxt5nm(&mut decompressed);
+    } else if normal_map {
+        reconstruct_normal_z(&mut decompressed);
+    } else if ycocg && format == Format::Bc3 {
+        decode_ycocg(&mut decompressed);
+    }
 
     image::png::write(&outfile, width as u32, height as u32, &decompressed);
 }
 
-impl Into<Algorithm> for Profile {
-    fn into(self) -> Algorithm {
-        match self {
+impl From<Profile> for Algorithm {
+    fn from(val: Profile) -> Self {
+        match val {
             Profile::Speed => Algorithm::RangeFit,
             Profile::Balanced => Algorithm::ClusterFit,
             Profile::Quality => Algorithm::IterativeClusterFit,
@@ -205,14 +360,41 @@ impl Into<Algorithm> for Profile {
     }
 }
 
-impl Into<Format> for CliFormat {
-    fn into(self) -> Format {
-        match self {
+impl From<CliErrorMetric> for ErrorMetric {
+    fn from(val: CliErrorMetric) -> Self {
+        match val {
+            CliErrorMetric::Rgb => ErrorMetric::Rgb,
+            CliErrorMetric::Ycocg => ErrorMetric::YCoCg,
+            CliErrorMetric::Oklab => ErrorMetric::OkLab,
+            CliErrorMetric::Cielab => ErrorMetric::CieLab,
+        }
+    }
+}
+
+impl From<CliMipFilter> for MipFilter {
+    fn from(val: CliMipFilter) -> Self {
+        match val {
+            CliMipFilter::Box => MipFilter::Box,
+            CliMipFilter::Triangle => MipFilter::Triangle,
+            CliMipFilter::Kaiser => MipFilter::Kaiser,
+            CliMipFilter::Lanczos => MipFilter::Lanczos,
+        }
+    }
+}
+
+impl From<CliFormat> for Format {
+    fn from(val: CliFormat) -> Self {
+        match val {
             CliFormat::Bc1 => Format::Bc1,
             CliFormat::Bc2 => Format::Bc2,
-            CliFormat::Bc3 => Format::Bc3,
+            CliFormat::Bc3 | CliFormat::Bc3n | CliFormat::Bc3Ycocg => Format::Bc3,
             CliFormat::Bc4 => Format::Bc4,
             CliFormat::Bc5 => Format::Bc5,
+            CliFormat::Bc4Snorm => Format::Bc4Snorm,
+            CliFormat::Bc5Snorm => Format::Bc5Snorm,
+            CliFormat::Bc6hUf16 => Format::Bc6hUf16,
+            CliFormat::Bc6hSf16 => Format::Bc6hSf16,
+            CliFormat::Bc7 => Format::Bc7,
         }
     }
 }
@@ -224,16 +406,76 @@ fn format_to_dxgiformat(f: Format) -> DxgiFormat {
         Format::Bc3 => DxgiFormat::BC3_UNorm_sRGB,
         Format::Bc4 => DxgiFormat::BC4_UNorm,
         Format::Bc5 => DxgiFormat::BC5_UNorm,
+        Format::Bc4Snorm => DxgiFormat::BC4_SNorm,
+        Format::Bc5Snorm => DxgiFormat::BC5_SNorm,
+        Format::Bc6hUf16 => DxgiFormat::BC6H_UF16,
+        Format::Bc6hSf16 => DxgiFormat::BC6H_SF16,
+        Format::Bc7 => DxgiFormat::BC7_UNorm_sRGB,
+        Format::Etc2Rgb | Format::Etc2RgbA1 | Format::Etc2Rgba => {
+            panic!("ETC2 formats can't be stored in DDS files")
+        }
+        Format::EacR11 | Format::EacRg11 | Format::EacR11Snorm | Format::EacRg11Snorm => {
+            panic!("EAC formats can't be stored in DDS files")
+        }
+        Format::Astc4x4
+        | Format::Astc5x4
+        | Format::Astc5x5
+        | Format::Astc6x5
+        | Format::Astc6x6
+        | Format::Astc8x5
+        | Format::Astc8x6
+        | Format::Astc8x8
+        | Format::Astc10x5
+        | Format::Astc10x6
+        | Format::Astc10x8
+        | Format::Astc10x10
+        | Format::Astc12x10
+        | Format::Astc12x12 => panic!("ASTC formats can't be stored in DDS files"),
+    }
+}
+
+fn linear_dxgiformat(d: DxgiFormat) -> DxgiFormat {
+    match d {
+        DxgiFormat::BC1_UNorm_sRGB => DxgiFormat::BC1_UNorm,
+        DxgiFormat::BC2_UNorm_sRGB => DxgiFormat::BC2_UNorm,
+        DxgiFormat::BC3_UNorm_sRGB => DxgiFormat::BC3_UNorm,
+        DxgiFormat::BC7_UNorm_sRGB => DxgiFormat::BC7_UNorm,
+        _ => d,
     }
 }
 
+/// Returns the DXGI format written to DDS files, which is linear for normal maps and YCoCg as
+/// they aren't colours
+fn output_dxgiformat(f: Format, params: &Params) -> DxgiFormat {
+    if params.normal_map || params.ycocg {
+        linear_dxgiformat(format_to_dxgiformat(f))
+    } else {
+        format_to_dxgiformat(f)
+    }
+}
+
+fn is_srgb(d: DxgiFormat) -> bool {
+    matches!(
+        d,
+        DxgiFormat::BC1_UNorm_sRGB
+            | DxgiFormat::BC2_UNorm_sRGB
+            | DxgiFormat::BC3_UNorm_sRGB
+            | DxgiFormat::BC7_UNorm_sRGB
+    )
+}
+
 fn dxgiformat_to_format(d: DxgiFormat) -> Format {
     match d {
-        DxgiFormat::BC1_UNorm_sRGB => Format::Bc1,
-        DxgiFormat::BC2_UNorm_sRGB => Format::Bc2,
-        DxgiFormat::BC3_UNorm_sRGB => Format::Bc3,
+        DxgiFormat::BC1_UNorm | DxgiFormat::BC1_UNorm_sRGB => Format::Bc1,
+        DxgiFormat::BC2_UNorm | DxgiFormat::BC2_UNorm_sRGB => Format::Bc2,
+        DxgiFormat::BC3_UNorm | DxgiFormat::BC3_UNorm_sRGB => Format::Bc3,
         DxgiFormat::BC4_UNorm => Format::Bc4,
         DxgiFormat::BC5_UNorm => Format::Bc5,
+        DxgiFormat::BC4_SNorm => Format::Bc4Snorm,
+        DxgiFormat::BC5_SNorm => Format::Bc5Snorm,
+        DxgiFormat::BC6H_UF16 => Format::Bc6hUf16,
+        DxgiFormat::BC6H_SF16 => Format::Bc6hSf16,
+        DxgiFormat::BC7_UNorm | DxgiFormat::BC7_UNorm_sRGB => Format::Bc7,
         _ => panic!("Unsupported DXGI format!"),
     }
 }
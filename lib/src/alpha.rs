@@ -20,9 +20,8 @@
 // TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION WITH THE
 // SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
 
-use core::{f32, u32, u8};
-
 use crate::math::f32_to_i32_clamped;
+use crate::{Algorithm, Params};
 
 pub fn compress_bc2(rgba: &[[u8; 4]; 16], mask: u32, block: &mut [u8]) {
     let mut tmp = [0u8; 8];
@@ -67,131 +66,344 @@ pub fn decompress_bc2(rgba: &mut [[u8; 4]; 16], bytes: &[u8]) {
     }
 }
 
-fn fix_range(min: &mut u8, max: &mut u8, steps: u8) {
-    if (*max - *min) < steps {
-        *max = (i32::from(*min) + i32::from(steps)).min(i32::from(u8::MAX)) as u8;
+/// The fields of a BC4 block, also a channel of BC3 and BC5 blocks
+///
+/// Blocks whose first endpoint is greater than the second interpolate seven values between
+/// them, the others five plus the extremes of the range. SNORM blocks store the endpoints as
+/// signed bytes, which compare and decode as `i8`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Bc4Block {
+    pub endpoint0: u8,
+    pub endpoint1: u8,
+    /// 3-bit codebook indices in row-major pixel order
+    pub indices: [u8; 16],
+}
+
+impl Bc4Block {
+    /// Unpacks the fields of a block
+    ///
+    /// Panics if `bytes` holds fewer than 8 bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        let mut word = [0u8; 8];
+        word.copy_from_slice(&bytes[..8]);
+        let bits = u64::from_le_bytes(word);
+
+        let mut indices = [0u8; 16];
+        for (i, index) in indices.iter_mut().enumerate() {
+            *index = ((bits >> (16 + 3 * i)) & 0x07) as u8;
+        }
+
+        Bc4Block {
+            endpoint0: bytes[0],
+            endpoint1: bytes[1],
+            indices,
+        }
     }
-    if (*max - *min) < steps {
-        *min = (i32::from(*max) - i32::from(steps)).max(0) as u8;
+
+    /// Packs the fields of a block
+    pub fn to_bytes(self) -> [u8; 8] {
+        let mut bits = u64::from(self.endpoint0) | u64::from(self.endpoint1) << 8;
+        for (i, &index) in self.indices.iter().enumerate() {
+            bits |= u64::from(index & 0x07) << (16 + 3 * i);
+        }
+        bits.to_le_bytes()
+    }
+
+    /// Returns the number of interpolation steps, 5 or 7, that the order of the endpoints
+    /// selects
+    pub(crate) fn steps(&self, signed: bool) -> u8 {
+        let seven = if signed {
+            self.endpoint0 as i8 > self.endpoint1 as i8
+        } else {
+            self.endpoint0 > self.endpoint1
+        };
+        if seven {
+            7
+        } else {
+            5
+        }
     }
 }
 
-fn fit_codes(
-    rgba: &[[u8; 4]; 16],
-    channel: usize,
-    mask: u32,
-    codes: [u8; 8],
-    indices: &mut [u8; 16],
-) -> u32 {
-    let mut err = 0;
-
-    // fit each alpha value to the codebook
-    for i in 0..16 {
-        // check if pixel is valid
-        let bit = 1 << i;
-        if (mask & bit) == 0 {
-            // use the first code
-            indices[i] = 0;
-            continue;
+/// Value range and codebook rules of an interpolated alpha block
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Range {
+    /// BC3 alpha and BC4/BC5 UNORM, endpoints in 0..255
+    Unsigned,
+    /// BC4/BC5 SNORM, endpoints in -127..127 with -128 decoding like -127
+    Signed,
+}
+
+impl Range {
+    /// Lowest and highest endpoint values
+    fn limits(self) -> (i32, i32) {
+        match self {
+            Range::Unsigned => (0, 255),
+            Range::Signed => (-127, 127),
         }
+    }
 
-        let value = rgba[i][channel];
-        let mut least = u32::MAX;
-        let mut index = 0;
-        for (j, &code) in codes.iter().enumerate().take(8) {
-            // get squared error from this code
-            let dist = i32::from(value) - i32::from(code);
-            let dist = (dist * dist) as u32;
-
-            // compare with best so far
-            if dist < least {
-                least = dist;
-                index = j as u8;
-            }
+    /// Reads an endpoint as stored in a block
+    fn endpoint(self, byte: u8) -> i32 {
+        match self {
+            Range::Unsigned => i32::from(byte),
+            Range::Signed => i32::from(byte as i8),
         }
+    }
 
-        // save this index and accumulate the error
-        indices[i] = index;
-        err += least;
+    /// Builds the codebook of a block from its endpoints as stored
+    ///
+    /// The endpoints select the codebook before the reserved signed value -128 is clamped.
+    fn codes(self, alpha0: i32, alpha1: i32) -> [f32; 8] {
+        let (min, max) = self.limits();
+        let seven = alpha0 > alpha1;
+        let (alpha0, alpha1) = (alpha0.max(min), alpha1.max(min));
+        let steps = if seven { 7 } else { 5 };
+        let interpolate = |i: i32| match self {
+            // the unsigned codebook is interpolated with integer division
+            Range::Unsigned => (((steps - i) * alpha0 + i * alpha1) / steps) as f32,
+            Range::Signed => ((steps - i) * alpha0 + i * alpha1) as f32 / steps as f32,
+        };
+
+        let mut codes = [0.0; 8];
+        codes[0] = alpha0 as f32;
+        codes[1] = alpha1 as f32;
+        for i in 1..steps {
+            codes[1 + i as usize] = interpolate(i);
+        }
+        if !seven {
+            // the 5-value codebook adds the extremes
+            codes[6] = min as f32;
+            codes[7] = max as f32;
+        }
+        codes
     }
+}
 
-    err
+/// Search effort for the different compression algorithms
+///
+/// `RangeFit` and the default `ClusterFit` keep the endpoints of the value range, so only
+/// `IterativeClusterFit` changes the output.
+struct Effort {
+    /// Maximum number of least squares refinements of the endpoints
+    iterations: usize,
+    /// Whether to try every endpoint pair around the refined endpoints
+    exhaustive: bool,
 }
 
-fn write_alpha_block(alpha0: u8, alpha1: u8, indices: &[u8; 16], block: &mut [u8]) {
-    let mut buf = [0u8; 8];
+impl Effort {
+    fn new(algorithm: Algorithm) -> Self {
+        match algorithm {
+            Algorithm::RangeFit => Effort {
+                iterations: 0,
+                exhaustive: false,
+            },
+            Algorithm::ClusterFit => Effort {
+                iterations: 0,
+                exhaustive: false,
+            },
+            Algorithm::IterativeClusterFit => Effort {
+                iterations: 8,
+                exhaustive: true,
+            },
+        }
+    }
+}
 
-    // write endpoints
-    buf[0] = alpha0;
-    buf[1] = alpha1;
+/// Largest distance from the refined endpoints covered by the exhaustive search
+const MAX_SEARCH_RADIUS: i32 = 16;
 
-    // pack the indices with 3 bits each
-    for i in 0..2 {
-        // pack 8 3-bit values
-        let mut value = 0u32;
-        for j in 0..8 {
-            let index = u32::from(indices[8 * i + j]);
-            value |= index << (3 * j);
+/// Endpoints of a block in their stored order, with the indices and error of the values
+#[derive(Clone, Copy)]
+struct Fit {
+    alpha0: i32,
+    alpha1: i32,
+    indices: [u8; 16],
+    error: f32,
+}
+
+impl Fit {
+    /// Fits each valid value to the codebook of the endpoints
+    fn new(values: &[f32; 16], mask: u32, range: Range, alpha0: i32, alpha1: i32) -> Self {
+        let codes = range.codes(alpha0, alpha1);
+
+        // ties go to the first code tried. Unsigned blocks try the codes from the lowest value
+        // up, as they were fitted before being stored with the endpoints swapped.
+        let order = if range == Range::Unsigned && alpha0 > alpha1 {
+            [1, 0, 7, 6, 5, 4, 3, 2]
+        } else {
+            [0, 1, 2, 3, 4, 5, 6, 7]
+        };
+        let mut indices = [order[0]; 16];
+        let mut error = 0.0;
+
+        for (i, &value) in values.iter().enumerate() {
+            // check if pixel is valid
+            let bit = 1 << i;
+            if (mask & bit) == 0 {
+                // use the first code
+                continue;
+            }
+
+            let mut least = f32::MAX;
+            let mut index = 0;
+            for j in order {
+                // get squared error from this code
+                let code = codes[usize::from(j)];
+                let dist = (value - code) * (value - code);
+
+                // compare with best so far
+                if dist < least {
+                    least = dist;
+                    index = j;
+                }
+            }
+
+            // save this index and accumulate the error
+            indices[i] = index;
+            error += least;
+        }
+
+        Fit {
+            alpha0,
+            alpha1,
+            indices,
+            error,
         }
+    }
+
+    /// Whether the fit uses the 7-value codebook
+    fn seven(&self) -> bool {
+        self.alpha0 > self.alpha1
+    }
 
-        // store in 3 bytes
-        let tmp = &mut buf[2 + i * 3..5 + i * 3];
-        for (j, t) in tmp.iter_mut().enumerate() {
-            *t = ((value >> (8 * j)) & 0xFF) as u8;
+    /// Returns the number of interpolation steps of the codebook
+    fn steps(&self) -> u8 {
+        if self.seven() {
+            7
+        } else {
+            5
         }
     }
-    block.copy_from_slice(&buf);
+
+    /// Fits the values to a pair of endpoints in either order, using the codebook of this fit
+    fn with_endpoints(&self, values: &[f32; 16], mask: u32, range: Range, a: i32, b: i32) -> Self {
+        let (low, high) = (a.min(b), a.max(b));
+        if self.seven() {
+            Fit::new(values, mask, range, high, low)
+        } else {
+            Fit::new(values, mask, range, low, high)
+        }
+    }
+
+    /// Writes the endpoints and the 3-bit indices to a block
+    fn write(&self, block: &mut [u8]) {
+        let fields = Bc4Block {
+            endpoint0: self.alpha0 as u8,
+            endpoint1: self.alpha1 as u8,
+            indices: self.indices,
+        };
+        block.copy_from_slice(&fields.to_bytes());
+    }
 }
 
-fn write_alpha_block5(alpha0: u8, alpha1: u8, indices: &[u8; 16], block: &mut [u8]) {
-    if alpha0 > alpha1 {
-        // invert indices
-        let mut swapped = *indices;
-        for index in &mut swapped[..] {
-            *index = match *index {
-                0 => 1,
-                1 => 0,
-                x @ 2..=5 => 7 - x,
-                x => x,
+fn fix_range(min: &mut i32, max: &mut i32, steps: i32, range: Range) {
+    let (lowest, highest) = range.limits();
+    if (*max - *min) < steps {
+        *max = (*min + steps).min(highest);
+    }
+    if (*max - *min) < steps {
+        *min = (*max - steps).max(lowest);
+    }
+}
+
+/// Moves the endpoints to the least squares solution for the current indices, for as long as
+/// that reduces the error
+fn refine(values: &[f32; 16], mask: u32, range: Range, fit: Fit, iterations: usize) -> Fit {
+    let (lowest, highest) = range.limits();
+    let steps = if fit.seven() { 7.0 } else { 5.0 };
+
+    let mut best = fit;
+    for _ in 0..iterations {
+        // accumulate the normal equations of the interpolation weights
+        let (mut aa, mut ab, mut bb, mut av, mut bv) = (0.0, 0.0, 0.0, 0.0, 0.0);
+        for (i, (&value, &index)) in values.iter().zip(&best.indices).enumerate() {
+            if (mask & (1 << i)) == 0 {
+                continue;
             }
+            let weight = match index {
+                0 => 0.0,
+                1 => 1.0,
+                // the extremes of the 5-value codebook don't depend on the endpoints
+                6 | 7 if !best.seven() => continue,
+                index => f32::from(index - 1) / steps,
+            };
+            let (a, b) = (1.0 - weight, weight);
+            aa += a * a;
+            ab += a * b;
+            bb += b * b;
+            av += a * value;
+            bv += b * value;
         }
 
-        // write with endpoints swapped
-        write_alpha_block(alpha1, alpha0, &swapped, block);
-    } else {
-        // write as-is
-        write_alpha_block(alpha0, alpha1, indices, block);
+        // stop if the values don't pin down both endpoints
+        let det = aa * bb - ab * ab;
+        if det < f32::EPSILON {
+            break;
+        }
+        let alpha0 = libm::roundf((av * bb - bv * ab) / det) as i32;
+        let alpha1 = libm::roundf((bv * aa - av * ab) / det) as i32;
+        let alpha0 = alpha0.clamp(lowest, highest);
+        let alpha1 = alpha1.clamp(lowest, highest);
+        if best.seven() && alpha0 == alpha1 {
+            break;
+        }
+
+        let candidate = best.with_endpoints(values, mask, range, alpha0, alpha1);
+        if candidate.error < best.error {
+            best = candidate;
+        } else {
+            break;
+        }
     }
+    best
 }
 
-fn write_alpha_block7(alpha0: u8, alpha1: u8, indices: &[u8; 16], block: &mut [u8]) {
-    if alpha0 < alpha1 {
-        // invert indices
-        let mut swapped = *indices;
-        for index in &mut swapped[..] {
-            *index = match *index {
-                0 => 1,
-                1 => 0,
-                x => 9 - x,
+/// Tries every pair of endpoints within a codebook step of the current ones
+fn search(values: &[f32; 16], mask: u32, range: Range, fit: Fit) -> Fit {
+    let (lowest, highest) = range.limits();
+    let (low, high) = (fit.alpha0.min(fit.alpha1), fit.alpha0.max(fit.alpha1));
+    let steps = if fit.seven() { 7 } else { 5 };
+    let radius = ((high - low) / steps + 1).min(MAX_SEARCH_RADIUS);
+
+    let mut best = fit;
+    for a in (low - radius).max(lowest)..=(low + radius).min(highest) {
+        for b in (high - radius).max(a)..=(high + radius).min(highest) {
+            // the 7-value codebook needs distinct endpoints
+            if fit.seven() && a == b {
+                continue;
+            }
+            let candidate = fit.with_endpoints(values, mask, range, a, b);
+            if candidate.error < best.error {
+                best = candidate;
             }
         }
-
-        // write with endpoints swapped
-        write_alpha_block(alpha1, alpha0, &swapped, block);
-    } else {
-        // write as-is
-        write_alpha_block(alpha0, alpha1, indices, block);
     }
+    best
 }
 
-pub fn compress_bc3(rgba: &[[u8; 4]; 16], channel: usize, mask: u32, block: &mut [u8]) {
+/// Compresses 16 values within the limits of a range to an interpolated alpha block,
+/// returning the number of interpolation steps of the chosen codebook
+fn compress(values: &[f32; 16], mask: u32, range: Range, params: &Params, block: &mut [u8]) -> u8 {
+    let (lowest, highest) = range.limits();
+
     // get range for 5-alpha and 7-alpha interpolation
-    let mut min5 = u8::MAX;
-    let mut max5 = 0u8;
-    let mut min7 = u8::MAX;
-    let mut max7 = 0u8;
+    let mut min5 = highest;
+    let mut max5 = lowest;
+    let mut min7 = highest;
+    let mut max7 = lowest;
 
-    for (i, pixel) in rgba.iter().enumerate() {
+    for (i, &value) in values.iter().enumerate() {
         // skip masked-out bits
         let bit = 1 << i;
         if (mask & bit) == 0 {
@@ -199,14 +411,14 @@ pub fn compress_bc3(rgba: &[[u8; 4]; 16], channel: usize, mask: u32, block: &mut
         }
 
         // incorporate into the min/max
-        let value = pixel[channel];
+        let value = libm::roundf(value) as i32;
         min7 = min7.min(value);
         max7 = max7.max(value);
 
-        if value != 0 {
+        if value != lowest {
             min5 = min5.min(value);
         }
-        if value != u8::MAX {
+        if value != highest {
             max5 = max5.max(value);
         }
     }
@@ -220,85 +432,106 @@ pub fn compress_bc3(rgba: &[[u8; 4]; 16], channel: usize, mask: u32, block: &mut
     }
 
     // fix range to be the minimum in both cases
-    fix_range(&mut min5, &mut max5, 5);
-    fix_range(&mut min7, &mut max7, 7);
-
-    // set up the 5-alpha codebook
-    let mut codes5 = [0u8; 8];
-    codes5[0] = min5;
-    codes5[1] = max5;
-    for i in 1..5i32 {
-        codes5[1 + i as usize] = (((5 - i) * i32::from(min5) + i * i32::from(max5)) / 5) as u8;
-    }
-    codes5[6] = 0;
-    codes5[7] = u8::MAX;
-
-    // set up the 7-alpha codebook
-    let mut codes7 = [0u8; 8];
-    codes7[0] = min5;
-    codes7[1] = max5;
-    for i in 1..7i32 {
-        codes7[1 + i as usize] = (((7 - i) * i32::from(min7) + i * i32::from(max7)) / 7) as u8;
-    }
-
-    // fit the data to both codebooks
-    let mut indices5 = [0u8; 16];
-    let mut indices7 = [0u8; 16];
-    let err5 = fit_codes(rgba, channel, mask, codes5, &mut indices5);
-    let err7 = fit_codes(rgba, channel, mask, codes7, &mut indices7);
+    fix_range(&mut min5, &mut max5, 5, range);
+    fix_range(&mut min7, &mut max7, 7, range);
+
+    // fit the data to both codebooks, with endpoints in the order that selects them
+    let effort = Effort::new(params.algorithm);
+    let fits = [(min5, max5), (max7, min7)].map(|(alpha0, alpha1)| {
+        let mut fit = Fit::new(values, mask, range, alpha0, alpha1);
+        fit = refine(values, mask, range, fit, effort.iterations);
+        if effort.exhaustive {
+            fit = search(values, mask, range, fit);
+        }
+        fit
+    });
 
     // save the block with the least error
-    if err5 <= err7 {
-        write_alpha_block5(min5, max5, &indices5, block);
+    let best = if fits[0].error <= fits[1].error {
+        &fits[0]
     } else {
-        write_alpha_block7(min7, max7, &indices7, block);
-    }
+        &fits[1]
+    };
+    best.write(block);
+    best.steps()
 }
 
-pub fn decompress_bc3(rgba: &mut [[u8; 4]; 16], channel: usize, bytes: &[u8]) {
+/// Decompresses an interpolated alpha block to 16 values within the limits of a range
+fn decompress(bytes: &[u8], range: Range) -> [f32; 16] {
     assert!(bytes.len() == 8);
 
-    // get endpoint values
-    let alpha0 = i32::from(bytes[0]);
-    let alpha1 = i32::from(bytes[1]);
-
     // build the codebook
-    let mut codes = [0u8; 8];
-    codes[0] = bytes[0];
-    codes[1] = bytes[1];
-    if alpha0 <= alpha1 {
-        // use 5-alpha codebook
-        for i in 1..5i32 {
-            codes[1 + i as usize] = (((5 - i) * alpha0 + i * alpha1) / 5) as u8
-        }
-        codes[6] = 0;
-        codes[7] = u8::MAX;
+    let fields = Bc4Block::from_bytes(bytes);
+    let codes = range.codes(
+        range.endpoint(fields.endpoint0),
+        range.endpoint(fields.endpoint1),
+    );
+
+    // look up the indexed codebook values
+    fields.indices.map(|index| codes[index as usize])
+}
+
+/// Returns the codebook of an interpolated alpha block as normal components in the range -1..1
+///
+/// Unsigned blocks map 0..255 to -1..1, as the 8-bit normal map encoding does.
+pub fn normal_codes(bytes: &[u8], signed: bool) -> [f32; 8] {
+    let range = if signed {
+        Range::Signed
     } else {
-        // use 7-alpha codebook
-        for i in 1..7i32 {
-            codes[1 + i as usize] = (((7 - i) * alpha0 + i * alpha1) / 7) as u8;
-        }
+        Range::Unsigned
+    };
+    let fields = Bc4Block::from_bytes(bytes);
+    let codes = range.codes(
+        range.endpoint(fields.endpoint0),
+        range.endpoint(fields.endpoint1),
+    );
+    match range {
+        Range::Unsigned => codes.map(|code| code / 255.0 * 2.0 - 1.0),
+        Range::Signed => codes.map(|code| code / 127.0),
     }
+}
 
-    // decode the indices
-    let mut indices = [0u8; 16];
-    for i in 0..2 {
-        // grab 3 bytes
-        let mut value = 0i32;
-        for j in 0..3 {
-            let byte = i32::from(bytes[2 + 3 * i + j]);
-            value |= byte << (8 * j);
-        }
+/// Compresses a channel of 8-bit pixels to an interpolated alpha block, returning the number
+/// of interpolation steps it uses
+pub fn compress_bc3(
+    rgba: &[[u8; 4]; 16],
+    channel: usize,
+    mask: u32,
+    params: &Params,
+    block: &mut [u8],
+) -> u8 {
+    let values = rgba.map(|pixel| f32::from(pixel[channel]));
+    compress(&values, mask, Range::Unsigned, params, block)
+}
 
-        // unpack 8 3-bit values from it
-        for j in 0..8 {
-            let index = (value >> (3 * j)) & 0x07;
-            indices[8 * i + j] = index as u8;
-        }
+pub fn decompress_bc3(rgba: &mut [[u8; 4]; 16], channel: usize, bytes: &[u8]) {
+    let values = decompress(bytes, Range::Unsigned);
+    for (pixel, value) in rgba.iter_mut().zip(values) {
+        pixel[channel] = value as u8;
     }
+}
+
+/// Compresses a channel of values in the range -1..1 to a signed BC4 block, returning the
+/// number of interpolation steps it uses
+///
+/// The endpoints are stored as signed bytes. -128 is never written since decoders clamp it to
+/// -127.
+pub fn compress_bc4_snorm(
+    rgba: &[[f32; 4]; 16],
+    channel: usize,
+    mask: u32,
+    params: &Params,
+    block: &mut [u8],
+) -> u8 {
+    // scale to the range of the endpoints
+    let values = rgba.map(|pixel| pixel[channel].clamp(-1.0, 1.0) * 127.0);
+    compress(&values, mask, Range::Signed, params, block)
+}
 
-    // write out the indexed codebook values
-    for (pixel, &index) in rgba.iter_mut().zip(indices.iter()) {
-        pixel[channel] = codes[index as usize];
+/// Decompresses a signed BC4 block to values in the range -1..1
+pub fn decompress_bc4_snorm(rgba: &mut [[f32; 4]; 16], channel: usize, bytes: &[u8]) {
+    let values = decompress(bytes, Range::Signed);
+    for (pixel, value) in rgba.iter_mut().zip(values) {
+        pixel[channel] = value / 127.0;
     }
 }
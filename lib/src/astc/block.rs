@@ -0,0 +1,578 @@
+// Copyright (c) 2018-2021 Jan Solanti <jhs@psonet.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to	deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be included
+// in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.
+// IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT,
+// TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION WITH THE
+// SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Physical block layout
+//!
+//! A 128-bit block starts with an 11-bit block mode giving the size of the weight grid, the
+//! weight quantisation level and whether a second weight plane is present. The partition
+//! count, partition seed and colour endpoint modes follow, then the integer sequence encoded
+//! colour values. The weights are stored bit-reversed from the top of the block downwards.
+//!
+//! Blocks whose mode field is `0x1FC` are void extent blocks, which hold a single colour.
+//! `AstcBlock` unpacks those and keeps other blocks as they are.
+
+use super::endpoints::{self, Endpoints};
+use super::ise::{self, MIN_COLOUR_LEVEL, RANGES};
+use super::partition::Pattern;
+use crate::math::f16_to_f32;
+
+/// Maximum number of texels in a block footprint
+pub const MAX_TEXELS: usize = 144;
+
+/// Maximum number of weights in a block, across both planes
+pub const MAX_WEIGHTS: usize = 64;
+
+/// Maximum number of colour values in a block
+pub const MAX_COLOUR_VALUES: usize = 18;
+
+/// Range of weight bits in a valid block
+const MIN_WEIGHT_BITS: usize = 24;
+const MAX_WEIGHT_BITS: usize = 96;
+
+/// Colour decoded from blocks that violate the specification
+pub const ERROR_COLOUR: [f32; 4] = [1.0, 0.0, 1.0, 1.0];
+
+/// Layout of the weights of a block
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlockMode {
+    /// Width of the weight grid
+    pub grid_width: usize,
+    /// Height of the weight grid
+    pub grid_height: usize,
+    /// Quantisation level of the weights
+    pub weight_level: usize,
+    /// Whether a second plane of weights is present
+    pub dual_plane: bool,
+}
+
+impl BlockMode {
+    /// Decodes the 11-bit block mode field, returning `None` for reserved encodings
+    pub fn decode(bits: u32) -> Option<Self> {
+        let a = ((bits >> 5) & 3) as usize;
+        let mut high_precision = (bits >> 9) & 1 == 1;
+        let mut dual_plane = (bits >> 10) & 1 == 1;
+
+        let (grid_width, grid_height, quant);
+        if bits & 3 != 0 {
+            quant = (bits & 3) << 1 | (bits >> 4) & 1;
+            let b = ((bits >> 7) & 3) as usize;
+            (grid_width, grid_height) = match (bits >> 2) & 3 {
+                0 => (b + 4, a + 2),
+                1 => (b + 8, a + 2),
+                2 => (a + 2, b + 8),
+                _ if bits & 0x100 != 0 => ((b & 1) + 2, a + 2),
+                _ => (a + 2, (b & 1) + 6),
+            };
+        } else {
+            quant = ((bits >> 2) & 3) << 1 | (bits >> 4) & 1;
+            if (bits >> 2) & 3 == 0 {
+                return None;
+            }
+            let b = ((bits >> 9) & 3) as usize;
+            (grid_width, grid_height) = match (bits >> 7) & 3 {
+                0 => (12, a + 2),
+                1 => (a + 2, 12),
+                2 => {
+                    high_precision = false;
+                    dual_plane = false;
+                    (a + 6, b + 6)
+                }
+                _ => match a {
+                    0 => (6, 10),
+                    1 => (10, 6),
+                    _ => return None,
+                },
+            };
+        }
+
+        let mode = BlockMode {
+            grid_width,
+            grid_height,
+            weight_level: quant as usize - 2 + if high_precision { 6 } else { 0 },
+            dual_plane,
+        };
+
+        let bits = mode.weight_bits();
+        let valid = mode.weight_count() <= MAX_WEIGHTS
+            && (MIN_WEIGHT_BITS..=MAX_WEIGHT_BITS).contains(&bits);
+        valid.then_some(mode)
+    }
+
+    /// Number of weights across both planes
+    pub fn weight_count(&self) -> usize {
+        let planes = if self.dual_plane { 2 } else { 1 };
+        self.grid_width * self.grid_height * planes
+    }
+
+    /// Number of bits taken up by the weights
+    pub fn weight_bits(&self) -> usize {
+        ise::sequence_bits(self.weight_count(), self.weight_level)
+    }
+}
+
+/// Returns the highest colour quantisation level that fits into a block, if any
+///
+/// `extra_bits` counts the bits of the colour endpoint mode stored below the weights.
+pub fn colour_level(
+    mode: &BlockMode,
+    partitions: usize,
+    extra_bits: usize,
+    values: usize,
+) -> Option<usize> {
+    let header = if partitions == 1 { 17 } else { 29 };
+    let planes = if mode.dual_plane { 2 } else { 0 };
+    let available = 128usize.checked_sub(mode.weight_bits() + header + extra_bits + planes)?;
+    if values > MAX_COLOUR_VALUES {
+        return None;
+    }
+    (MIN_COLOUR_LEVEL..RANGES.len())
+        .rev()
+        .find(|&level| ise::sequence_bits(values, level) <= available)
+}
+
+/// A block that isn't a void extent block, with its fields in unpacked form
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Block {
+    /// Weight layout and the 11-bit field it was decoded from
+    pub mode: BlockMode,
+    pub mode_bits: u32,
+    /// Number of partitions, 1 to 4
+    pub partitions: usize,
+    /// Seed of the partition pattern
+    pub seed: u32,
+    /// Colour endpoint mode of each partition
+    pub endpoint_modes: [u8; 4],
+    /// Quantisation level and quantised values of the colour endpoints
+    pub colour_level: usize,
+    pub colours: [u8; MAX_COLOUR_VALUES],
+    /// Channel that uses the second plane of weights
+    pub plane2_channel: usize,
+    /// Quantised weights, interleaved with the second plane if present
+    pub weights: [u8; MAX_WEIGHTS],
+}
+
+/// Contents of a physical block
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Contents {
+    /// Void extent block holding a single colour
+    Constant { colour: [u16; 4], hdr: bool },
+    /// Block with weights and endpoints
+    Encoded(Block),
+}
+
+/// A 128-bit ASTC block, with the fields of void extent blocks unpacked
+///
+/// The fields of other blocks depend on the footprint and are integer sequence encoded, so
+/// they are kept as raw bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AstcBlock {
+    /// A block of a single colour
+    VoidExtent {
+        /// Whether the colour is given as half floats instead of UNORM16 values
+        hdr: bool,
+        /// The 13-bit texel coordinates of the area around the block that has the same
+        /// colour, as the low and high S then the low and high T coordinate, or
+        /// `AstcBlock::NO_EXTENT`
+        extent: [u16; 4],
+        /// The colour as UNORM16 values or as the bit patterns of half floats
+        colour: [u16; 4],
+    },
+    /// Any other block, including malformed void extent blocks, kept as is
+    Raw([u8; 16]),
+}
+
+impl AstcBlock {
+    /// The extent of void extent blocks that don't give one
+    pub const NO_EXTENT: [u16; 4] = [0x1FFF; 4];
+
+    /// Unpacks the fields of a block
+    ///
+    /// Panics if `bytes` holds fewer than 16 bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        let raw: [u8; 16] = bytes[..16].try_into().unwrap();
+        let block = u128::from_le_bytes(raw);
+        if bits(block, 0, 9) == 0x1FC {
+            if let Some(Contents::Constant { colour, hdr }) = parse_void_extent(block) {
+                let extent = [0, 1, 2, 3].map(|i| bits(block, 12 + 13 * i, 13) as u16);
+                return AstcBlock::VoidExtent {
+                    hdr,
+                    extent,
+                    colour,
+                };
+            }
+        }
+        AstcBlock::Raw(raw)
+    }
+
+    /// Packs the fields of a block
+    pub fn to_bytes(self) -> [u8; 16] {
+        match self {
+            AstcBlock::VoidExtent {
+                hdr,
+                extent,
+                colour,
+            } => {
+                let mut block = 0xDFC | u128::from(hdr) << 9;
+                for (i, &coordinate) in extent.iter().enumerate() {
+                    block |= u128::from(coordinate & 0x1FFF) << (12 + 13 * i);
+                }
+                for (i, &value) in colour.iter().enumerate() {
+                    block |= u128::from(value) << (64 + 16 * i);
+                }
+                block.to_le_bytes()
+            }
+            AstcBlock::Raw(bytes) => bytes,
+        }
+    }
+}
+
+/// Extracts `count` bits at `position`
+fn bits(block: u128, position: usize, count: usize) -> u32 {
+    ((block >> position) as u32) & ((1 << count) - 1)
+}
+
+/// Extended colour endpoint mode bits are only used by blocks with several partitions
+fn extra_endpoint_bits(partitions: usize) -> usize {
+    3 * partitions - 4
+}
+
+impl Block {
+    /// Number of colour values used by the endpoint modes of all partitions
+    pub fn colour_count(&self) -> usize {
+        self.endpoint_modes[..self.partitions]
+            .iter()
+            .map(|&mode| endpoints::value_count(mode))
+            .sum()
+    }
+
+    /// Packs the block into its 128-bit representation
+    pub fn encode(&self) -> u128 {
+        let mut block = u128::from(self.mode_bits);
+        block |= ((self.partitions - 1) as u128) << 11;
+
+        let mut below_weights = 128 - self.mode.weight_bits();
+        let colour_start;
+        if self.partitions == 1 {
+            block |= u128::from(self.endpoint_modes[0]) << 13;
+            colour_start = 17;
+        } else {
+            block |= u128::from(self.seed) << 13;
+            colour_start = 29;
+
+            let modes = &self.endpoint_modes[..self.partitions];
+            if modes.iter().all(|&mode| mode == modes[0]) {
+                block |= u128::from(modes[0]) << 25;
+            } else {
+                // a shared class plus one bit per partition selecting the next class up
+                let class = modes.iter().map(|&mode| mode >> 2).min().unwrap_or(0);
+                let mut encoded = u32::from(class) + 1;
+                for (i, &mode) in modes.iter().enumerate() {
+                    encoded |= u32::from((mode >> 2) - class) << (2 + i);
+                    encoded |= u32::from(mode & 3) << (2 + self.partitions + 2 * i);
+                }
+                block |= u128::from(encoded & 0x3F) << 23;
+
+                let extra = extra_endpoint_bits(self.partitions);
+                below_weights -= extra;
+                block |= u128::from(encoded >> 6) << below_weights;
+            }
+        }
+
+        if self.mode.dual_plane {
+            below_weights -= 2;
+            block |= (self.plane2_channel as u128) << below_weights;
+        }
+
+        let count = self.colour_count();
+        ise::encode(
+            &self.colours[..count],
+            self.colour_level,
+            &mut block,
+            colour_start,
+        );
+
+        let mut weights = 0;
+        ise::encode(
+            &self.weights[..self.mode.weight_count()],
+            self.mode.weight_level,
+            &mut weights,
+            0,
+        );
+        block | weights.reverse_bits()
+    }
+}
+
+/// Unpacks a 128-bit block of the given footprint, returning `None` for invalid blocks
+pub fn parse(block: u128, width: usize, height: usize) -> Option<Contents> {
+    let mode_bits = bits(block, 0, 11);
+    if mode_bits & 0x1FF == 0x1FC {
+        return parse_void_extent(block);
+    }
+
+    let mode = BlockMode::decode(mode_bits)?;
+    if mode.grid_width > width || mode.grid_height > height {
+        return None;
+    }
+
+    let partitions = bits(block, 11, 2) as usize + 1;
+    if mode.dual_plane && partitions == 4 {
+        return None;
+    }
+
+    let mut below_weights = 128 - mode.weight_bits();
+    let mut extra_bits = 0;
+    let mut endpoint_modes = [0; 4];
+    let (seed, colour_start);
+    if partitions == 1 {
+        seed = 0;
+        endpoint_modes[0] = bits(block, 13, 4) as u8;
+        colour_start = 17;
+    } else {
+        seed = bits(block, 13, 10);
+        colour_start = 29;
+
+        let field = bits(block, 23, 6);
+        if field & 3 == 0 {
+            endpoint_modes[..partitions].fill((field >> 2) as u8);
+        } else {
+            extra_bits = extra_endpoint_bits(partitions);
+            below_weights = below_weights.checked_sub(extra_bits)?;
+            let encoded = field | bits(block, below_weights, extra_bits) << 6;
+            let class = (encoded & 3) - 1;
+            for (i, mode) in endpoint_modes[..partitions].iter_mut().enumerate() {
+                let next = (encoded >> (2 + i)) & 1;
+                let low = (encoded >> (2 + partitions + 2 * i)) & 3;
+                *mode = ((class + next) << 2 | low) as u8;
+            }
+        }
+    }
+
+    let plane2_channel = if mode.dual_plane {
+        below_weights = below_weights.checked_sub(2)?;
+        bits(block, below_weights, 2) as usize
+    } else {
+        0
+    };
+
+    let mut parsed = Block {
+        mode,
+        mode_bits,
+        partitions,
+        seed,
+        endpoint_modes,
+        colour_level: 0,
+        colours: [0; MAX_COLOUR_VALUES],
+        plane2_channel,
+        weights: [0; MAX_WEIGHTS],
+    };
+
+    let count = parsed.colour_count();
+    parsed.colour_level = colour_level(&mode, partitions, extra_bits, count)?;
+    ise::decode(
+        block,
+        colour_start,
+        parsed.colour_level,
+        &mut parsed.colours[..count],
+    );
+    ise::decode(
+        block.reverse_bits(),
+        0,
+        mode.weight_level,
+        &mut parsed.weights[..mode.weight_count()],
+    );
+
+    Some(Contents::Encoded(parsed))
+}
+
+/// Unpacks a void extent block
+fn parse_void_extent(block: u128) -> Option<Contents> {
+    let hdr = bits(block, 9, 1) == 1;
+    if bits(block, 10, 2) != 3 {
+        return None;
+    }
+
+    // the extent is only a hint, but must be well-formed unless it's all ones
+    let extent = (block >> 12) as u64 & ((1 << 52) - 1);
+    if extent != (1 << 52) - 1 {
+        let coordinate = |i: usize| (extent >> (13 * i)) & 0x1FFF;
+        if coordinate(0) >= coordinate(1) || coordinate(2) >= coordinate(3) {
+            return None;
+        }
+    }
+
+    let colour = [0, 1, 2, 3].map(|i| (block >> (64 + 16 * i)) as u16);
+    Some(Contents::Constant { colour, hdr })
+}
+
+/// The texels of a block sample the weight grid bilinearly
+#[derive(Clone, Copy, Debug)]
+pub struct Infill {
+    /// Indices of the four grid points around each texel
+    pub indices: [[u8; 4]; MAX_TEXELS],
+    /// Bilinear factors of the grid points, summing to 16
+    pub factors: [[u8; 4]; MAX_TEXELS],
+}
+
+impl Infill {
+    /// Computes the sampling positions of a `width` by `height` block on a grid
+    pub fn new(width: usize, height: usize, grid_width: usize, grid_height: usize) -> Self {
+        let mut infill = Infill {
+            indices: [[0; 4]; MAX_TEXELS],
+            factors: [[0; 4]; MAX_TEXELS],
+        };
+
+        let scale = |size: usize| (1024 + size / 2) / (size - 1).max(1);
+        let (ds, dt) = (scale(width), scale(height));
+
+        for t in 0..height {
+            for s in 0..width {
+                let cs = ds * s;
+                let ct = dt * t;
+                let gs = (cs * (grid_width - 1) + 32) >> 6;
+                let gt = (ct * (grid_height - 1) + 32) >> 6;
+                let (js, fs) = (gs >> 4, gs & 0xF);
+                let (jt, ft) = (gt >> 4, gt & 0xF);
+
+                let w11 = (fs * ft + 8) >> 4;
+                let w10 = ft - w11;
+                let w01 = fs - w11;
+                let w00 = 16 + w11 - fs - ft;
+
+                // clamp the neighbours to the grid; their factors are zero there anyway
+                let v0 = js + jt * grid_width;
+                let right = if js + 1 < grid_width { 1 } else { 0 };
+                let down = if jt + 1 < grid_height { grid_width } else { 0 };
+
+                let texel = s + t * width;
+                infill.indices[texel] =
+                    [v0, v0 + right, v0 + down, v0 + right + down].map(|i| i as u8);
+                infill.factors[texel] = [w00, w01, w10, w11].map(|f| f as u8);
+            }
+        }
+
+        infill
+    }
+
+    /// Interpolates the unquantised weights of a plane for one texel
+    pub fn weight(&self, texel: usize, weights: &[u8]) -> u32 {
+        let mut sum = 8;
+        for (&index, &factor) in self.indices[texel].iter().zip(&self.factors[texel]) {
+            sum += u32::from(weights[usize::from(index)]) * u32::from(factor);
+        }
+        sum >> 4
+    }
+}
+
+/// Decompresses a block of `width` by `height` texels
+///
+/// LDR colours decode to the range 0..1, HDR colours to the values of their half floats.
+pub fn decompress_astc(bytes: &[u8], width: usize, height: usize, rgba: &mut [[f32; 4]]) {
+    let mut word = [0u8; 16];
+    word.copy_from_slice(bytes);
+    let block = u128::from_le_bytes(word);
+
+    let texels = width * height;
+    match parse(block, width, height) {
+        Some(Contents::Constant { colour, hdr: false }) => {
+            rgba[..texels].fill(colour.map(|c| f32::from(c) / 65535.0));
+        }
+        Some(Contents::Constant { colour, hdr: true }) => {
+            rgba[..texels].fill(colour.map(f16_to_f32));
+        }
+        Some(Contents::Encoded(block)) => decode_texels(&block, width, height, rgba),
+        None => rgba[..texels].fill(ERROR_COLOUR),
+    }
+}
+
+/// Unquantises and interpolates the endpoints and weights of a block
+pub fn decode_texels(block: &Block, width: usize, height: usize, rgba: &mut [[f32; 4]]) {
+    // unquantise the endpoints of every partition
+    let mut endpoints = [Endpoints::default(); 4];
+    let mut offset = 0;
+    for (endpoints, &mode) in endpoints
+        .iter_mut()
+        .zip(&block.endpoint_modes)
+        .take(block.partitions)
+    {
+        let count = endpoints::value_count(mode);
+        let mut values = [0; 8];
+        for (value, &colour) in values
+            .iter_mut()
+            .zip(&block.colours[offset..offset + count])
+        {
+            *value = ise::unquantise_colour(block.colour_level, colour);
+        }
+        *endpoints = endpoints::decode(mode, &values[..count]);
+        offset += count;
+    }
+
+    // split the interleaved weight planes
+    let mode = &block.mode;
+    let planes = if mode.dual_plane { 2 } else { 1 };
+    let mut weights = [[0u8; MAX_WEIGHTS]; 2];
+    for (i, &weight) in block.weights[..mode.weight_count()].iter().enumerate() {
+        weights[i % planes][i / planes] = ise::unquantise_weight(mode.weight_level, weight);
+    }
+
+    let infill = Infill::new(width, height, mode.grid_width, mode.grid_height);
+    let pattern = Pattern::new(block.seed, block.partitions, width * height);
+    for y in 0..height {
+        for x in 0..width {
+            let texel = x + y * width;
+            let partition = if block.partitions > 1 {
+                pattern.partition(x, y)
+            } else {
+                0
+            };
+            let Endpoints {
+                colours: [e0, e1],
+                hdr,
+            } = endpoints[partition];
+
+            let w0 = infill.weight(texel, &weights[0]);
+            let w1 = if mode.dual_plane {
+                infill.weight(texel, &weights[1])
+            } else {
+                w0
+            };
+
+            for (channel, value) in rgba[texel].iter_mut().enumerate() {
+                let weight = if mode.dual_plane && channel == block.plane2_channel {
+                    w1
+                } else {
+                    w0
+                };
+                let c = interpolate(e0[channel], e1[channel], weight);
+                *value = if hdr[channel] {
+                    f16_to_f32(endpoints::lns_to_f16(c))
+                } else {
+                    f32::from(c) / 65535.0
+                };
+            }
+        }
+    }
+}
+
+/// Interpolates between two 16-bit endpoints
+fn interpolate(e0: u16, e1: u16, weight: u32) -> u16 {
+    let (c0, c1) = (u32::from(e0), u32::from(e1));
+    ((c0 * (64 - weight) + c1 * weight + 32) >> 6) as u16
+}
@@ -0,0 +1,623 @@
+// Copyright (c) 2018-2021 Jan Solanti <jhs@psonet.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to	deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be included
+// in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.
+// IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT,
+// TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION WITH THE
+// SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Colour endpoint modes
+//!
+//! Each partition stores its two endpoint colours in one of 16 formats. They differ in the
+//! channels they cover, whether the second endpoint is stored directly, as an offset or as a
+//! scale factor, and whether the colours are LDR or HDR.
+
+use super::ise;
+
+/// Number of colour values used by an endpoint mode
+pub fn value_count(mode: u8) -> usize {
+    2 * (usize::from(mode >> 2) + 1)
+}
+
+/// Moves the top bit of `b` into `a`, turning `a` into a signed 6-bit offset
+fn bit_transfer_signed(a: &mut i32, b: &mut i32) {
+    *b >>= 1;
+    *b |= *a & 0x80;
+    *a >>= 1;
+    *a &= 0x3F;
+    if *a & 0x20 != 0 {
+        *a -= 0x40;
+    }
+}
+
+/// Pulls red and green towards blue, expanding the precision of colours near grey
+fn blue_contract([r, g, b, a]: [i32; 4]) -> [i32; 4] {
+    [(r + b) >> 1, (g + b) >> 1, b, a]
+}
+
+/// Endpoints of a partition expanded to 16 bits
+///
+/// LDR channels hold UNORM16 values, HDR channels hold the logarithmic encoding converted to
+/// half floats by `lns_to_f16`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Endpoints {
+    pub colours: [[u16; 4]; 2],
+    pub hdr: [bool; 4],
+}
+
+/// Alpha of one in the 12-bit logarithmic encoding, used by the HDR modes without alpha
+const HDR_ONE: i32 = 0x780;
+
+/// Positions of the six variable bits of HDR RGB direct endpoints as value and bit
+const RGB_DIRECT_VARIABLE_BITS: [(usize, u32); 6] =
+    [(2, 6), (3, 6), (4, 6), (5, 6), (4, 5), (5, 5)];
+
+/// Field and bit carried by each variable bit of HDR RGB direct endpoints in every submode,
+/// with the fields a, b0, b1, c, d0 and d1
+const RGB_DIRECT_FIELDS: [[(usize, u32); 6]; 8] = [
+    [(1, 6), (2, 6), (4, 6), (5, 6), (4, 5), (5, 5)],
+    [(1, 6), (2, 6), (1, 7), (2, 7), (4, 5), (5, 5)],
+    [(0, 9), (3, 6), (4, 6), (5, 6), (4, 5), (5, 5)],
+    [(1, 6), (2, 6), (0, 9), (3, 6), (4, 5), (5, 5)],
+    [(1, 6), (2, 6), (1, 7), (2, 7), (0, 9), (0, 10)],
+    [(0, 9), (0, 10), (3, 7), (3, 6), (4, 5), (5, 5)],
+    [(1, 6), (2, 6), (0, 11), (3, 6), (0, 9), (0, 10)],
+    [(0, 9), (0, 10), (0, 11), (3, 6), (4, 5), (5, 5)],
+];
+
+/// Bits of the fields a, b, c and d of HDR RGB direct endpoints before the variable bits
+const RGB_DIRECT_BASE_WIDTHS: [u32; 6] = [9, 6, 6, 6, 5, 5];
+
+/// Positions of the seven variable bits of HDR RGB base and scale endpoints as value and bit
+const RGB_SCALE_VARIABLE_BITS: [(usize, u32); 7] =
+    [(1, 6), (1, 5), (2, 6), (2, 5), (3, 7), (3, 6), (3, 5)];
+
+/// Field and bit carried by each variable bit of HDR RGB base and scale endpoints in every
+/// submode, with the fields red, green, blue and scale
+const RGB_SCALE_FIELDS: [[(usize, u32); 7]; 6] = [
+    [(0, 9), (0, 8), (0, 7), (0, 10), (0, 6), (3, 6), (3, 5)],
+    [(0, 8), (1, 5), (0, 7), (2, 5), (0, 6), (0, 10), (0, 9)],
+    [(0, 9), (0, 8), (0, 7), (0, 6), (3, 7), (3, 6), (3, 5)],
+    [(0, 8), (1, 5), (0, 7), (2, 5), (0, 6), (3, 6), (3, 5)],
+    [(1, 6), (1, 5), (2, 6), (2, 5), (0, 6), (0, 7), (3, 5)],
+    [(1, 6), (1, 5), (2, 6), (2, 5), (0, 6), (3, 6), (3, 5)],
+];
+
+/// Whether an endpoint mode stores HDR colours
+pub fn is_hdr(mode: u8) -> bool {
+    matches!(mode, 2 | 3 | 7 | 11 | 14 | 15)
+}
+
+/// Decodes the endpoints of a mode from unquantised colour values
+pub fn decode(mode: u8, values: &[u8]) -> Endpoints {
+    let mut v = [0i32; 8];
+    for (v, &value) in v.iter_mut().zip(values) {
+        *v = i32::from(value);
+    }
+
+    if !is_hdr(mode) {
+        let colours = decode_ldr(mode, &mut v).map(|e| e.map(|c| u16::from(c) * 257));
+        return Endpoints {
+            colours,
+            hdr: [false; 4],
+        };
+    }
+
+    // the alpha of mode 14 is LDR, everything else is 12 bits of the logarithmic encoding
+    let hdr = [true, true, true, mode != 14];
+    let [e0, e1] = decode_hdr(mode, &v);
+    let expand = |e: [i32; 4]| -> [u16; 4] {
+        core::array::from_fn(|c| {
+            if hdr[c] {
+                (e[c] << 4) as u16
+            } else {
+                (e[c] * 257) as u16
+            }
+        })
+    };
+    Endpoints {
+        colours: [expand(e0), expand(e1)],
+        hdr,
+    }
+}
+
+/// Decodes the endpoints of an LDR mode
+fn decode_ldr(mode: u8, v: &mut [i32; 8]) -> [[u8; 4]; 2] {
+    let (e0, e1) = match mode {
+        // luminance, direct
+        0 => ([v[0], v[0], v[0], 0xFF], [v[1], v[1], v[1], 0xFF]),
+        // luminance, base and offset
+        1 => {
+            let l0 = (v[0] >> 2) | (v[1] & 0xC0);
+            let l1 = (l0 + (v[1] & 0x3F)).min(0xFF);
+            ([l0, l0, l0, 0xFF], [l1, l1, l1, 0xFF])
+        }
+        // luminance and alpha, direct
+        4 => ([v[0], v[0], v[0], v[2]], [v[1], v[1], v[1], v[3]]),
+        // luminance and alpha, base and offset
+        5 => {
+            let [v0, v1, v2, v3, ..] = v;
+            bit_transfer_signed(v1, v0);
+            bit_transfer_signed(v3, v2);
+            let l1 = *v0 + *v1;
+            let a1 = *v2 + *v3;
+            ([*v0, *v0, *v0, *v2], [l1, l1, l1, a1])
+        }
+        // RGB, base and scale, optionally with two alpha values
+        6 | 10 => {
+            let (a0, a1) = if mode == 10 {
+                (v[4], v[5])
+            } else {
+                (0xFF, 0xFF)
+            };
+            let scale = |c: i32| (c * v[3]) >> 8;
+            (
+                [scale(v[0]), scale(v[1]), scale(v[2]), a0],
+                [v[0], v[1], v[2], a1],
+            )
+        }
+        // RGB(A), direct
+        8 | 12 => {
+            let (a0, a1) = if mode == 12 {
+                (v[6], v[7])
+            } else {
+                (0xFF, 0xFF)
+            };
+            let e0 = [v[0], v[2], v[4], a0];
+            let e1 = [v[1], v[3], v[5], a1];
+            if v[1] + v[3] + v[5] >= v[0] + v[2] + v[4] {
+                (e0, e1)
+            } else {
+                (blue_contract(e1), blue_contract(e0))
+            }
+        }
+        // RGB(A), base and offset
+        9 | 13 => {
+            let pairs = if mode == 13 { 4 } else { 3 };
+            for pair in v.chunks_exact_mut(2).take(pairs) {
+                let [base, offset] = pair else { unreachable!() };
+                bit_transfer_signed(offset, base);
+            }
+            let (a0, a1) = if mode == 13 {
+                (v[6], v[6] + v[7])
+            } else {
+                (0xFF, 0xFF)
+            };
+            let e0 = [v[0], v[2], v[4], a0];
+            let e1 = [v[0] + v[1], v[2] + v[3], v[4] + v[5], a1];
+            if v[1] + v[3] + v[5] >= 0 {
+                (e0, e1)
+            } else {
+                (blue_contract(e1), blue_contract(e0))
+            }
+        }
+        _ => unreachable!("HDR endpoint mode"),
+    };
+
+    [e0, e1].map(|e| e.map(|c| c.clamp(0, 0xFF) as u8))
+}
+
+/// Decodes the 12-bit endpoints of an HDR mode, the LDR alpha of mode 14 is left at 8 bits
+fn decode_hdr(mode: u8, v: &[i32; 8]) -> [[i32; 4]; 2] {
+    match mode {
+        // luminance, large range
+        2 => {
+            let (y0, y1) = if v[1] >= v[0] {
+                (v[0] << 4, v[1] << 4)
+            } else {
+                ((v[1] << 4) + 8, (v[0] << 4) - 8)
+            };
+            [[y0, y0, y0, HDR_ONE], [y1, y1, y1, HDR_ONE]]
+        }
+        // luminance, small range
+        3 => {
+            let (y0, offset) = if v[0] & 0x80 != 0 {
+                ((v[1] & 0xE0) << 4 | (v[0] & 0x7F) << 2, (v[1] & 0x1F) << 2)
+            } else {
+                ((v[1] & 0xF0) << 4 | (v[0] & 0x7F) << 1, (v[1] & 0x0F) << 1)
+            };
+            let y1 = (y0 + offset).min(0xFFF);
+            [[y0, y0, y0, HDR_ONE], [y1, y1, y1, HDR_ONE]]
+        }
+        // RGB, base and scale
+        7 => {
+            let [c0, c1] = decode_rgb_scale(v);
+            [
+                [c0[0], c0[1], c0[2], HDR_ONE],
+                [c1[0], c1[1], c1[2], HDR_ONE],
+            ]
+        }
+        // RGB, direct, with no alpha, LDR alpha or HDR alpha
+        _ => {
+            let [c0, c1] = decode_rgb_direct(v);
+            let (a0, a1) = match mode {
+                11 => (HDR_ONE, HDR_ONE),
+                14 => (v[6], v[7]),
+                _ => decode_alpha(v[6], v[7]),
+            };
+            [[c0[0], c0[1], c0[2], a0], [c1[0], c1[1], c1[2], a1]]
+        }
+    }
+}
+
+/// Collects the fields of HDR endpoints from their base bits and the variable bits placed
+/// according to the submode
+fn gather_fields<const N: usize>(
+    v: &[i32; 8],
+    mut fields: [i32; N],
+    positions: &[(usize, u32)],
+    placement: &[(usize, u32)],
+) -> [i32; N] {
+    for (&(value, bit), &(field, shift)) in positions.iter().zip(placement) {
+        fields[field] |= ((v[value] >> bit) & 1) << shift;
+    }
+    fields
+}
+
+/// Decodes HDR RGB endpoints stored as a base colour and a scale, mode 7
+fn decode_rgb_scale(v: &[i32; 8]) -> [[i32; 3]; 2] {
+    let mode_bits = (v[0] >> 6) | (v[1] >> 7) << 2 | (v[2] >> 7) << 3;
+    let (major, submode) = match mode_bits {
+        0xF => (0, 5),
+        _ if mode_bits & 0xC == 0xC => (mode_bits & 3, 4),
+        _ => (mode_bits >> 2, mode_bits & 3),
+    };
+
+    let fields = [v[0] & 0x3F, v[1] & 0x1F, v[2] & 0x1F, v[3] & 0x1F];
+    let fields = gather_fields(
+        v,
+        fields,
+        &RGB_SCALE_VARIABLE_BITS,
+        &RGB_SCALE_FIELDS[submode as usize],
+    );
+    let shift = [1, 1, 2, 3, 4, 5][submode as usize];
+    let [mut red, mut green, mut blue, scale] = fields.map(|field| field << shift);
+
+    // the minor components are differences from the major one, except in submode 5
+    if submode != 5 {
+        green = red - green;
+        blue = red - blue;
+    }
+    match major {
+        1 => core::mem::swap(&mut red, &mut green),
+        2 => core::mem::swap(&mut red, &mut blue),
+        _ => {}
+    }
+
+    let e1 = [red, green, blue];
+    [e1.map(|c| (c - scale).max(0)), e1.map(|c| c.max(0))]
+}
+
+/// Decodes HDR RGB endpoints stored directly, modes 11, 14 and 15
+fn decode_rgb_direct(v: &[i32; 8]) -> [[i32; 3]; 2] {
+    let submode = (v[1] >> 7) | (v[2] >> 7) << 1 | (v[3] >> 7) << 2;
+    let major = (v[4] >> 7) | (v[5] >> 7) << 1;
+    if major == 3 {
+        return [
+            [v[0] << 4, v[2] << 4, (v[4] & 0x7F) << 5],
+            [v[1] << 4, v[3] << 4, (v[5] & 0x7F) << 5],
+        ];
+    }
+
+    let fields = [
+        v[0] | (v[1] & 0x40) << 2,
+        v[2] & 0x3F,
+        v[3] & 0x3F,
+        v[1] & 0x3F,
+        v[4] & 0x1F,
+        v[5] & 0x1F,
+    ];
+    let placement = &RGB_DIRECT_FIELDS[submode as usize];
+    let [a, b0, b1, c, d0, d1] = gather_fields(v, fields, &RGB_DIRECT_VARIABLE_BITS, placement);
+
+    // d0 and d1 are signed
+    let d_bits = field_width(placement, 4);
+    let extend = |d: i32| (d << (32 - d_bits)) >> (32 - d_bits);
+    let (d0, d1) = (extend(d0), extend(d1));
+
+    let shift = (submode >> 1) ^ 3;
+    let [a, b0, b1, c, d0, d1] = [a, b0, b1, c, d0, d1].map(|field| field << shift);
+    let mut e0 = [a - c, a - b0 - c - d0, a - b1 - c - d1].map(|c| c.clamp(0, 0xFFF));
+    let mut e1 = [a, a - b0, a - b1].map(|c| c.clamp(0, 0xFFF));
+    if major > 0 {
+        let major = major as usize;
+        e0.swap(0, major);
+        e1.swap(0, major);
+    }
+    [e0, e1]
+}
+
+/// Number of bits of a field of HDR RGB direct endpoints in a submode
+fn field_width(placement: &[(usize, u32); 6], field: usize) -> u32 {
+    let extra = placement.iter().filter(|&&(f, _)| f == field).count() as u32;
+    RGB_DIRECT_BASE_WIDTHS[field] + extra
+}
+
+/// Decodes the 12-bit HDR alpha endpoints of mode 15
+fn decode_alpha(v6: i32, v7: i32) -> (i32, i32) {
+    let selector = (v6 >> 7) & 1 | (v7 >> 6) & 2;
+    let (v6, v7) = (v6 & 0x7F, v7 & 0x7F);
+    if selector == 3 {
+        return (v6 << 5, v7 << 5);
+    }
+
+    // the top bits of the offset extend the base, the rest is a signed offset
+    let base = (v6 | (v7 << (selector + 1)) & 0x780) << (4 - selector);
+    let offset_bits = 6 - selector;
+    let offset = v7 & (0x3F >> selector);
+    let offset = (offset << (32 - offset_bits)) >> (32 - offset_bits);
+    let offset = offset << (4 - selector);
+    (base, (base + offset).clamp(0, 0xFFF))
+}
+
+/// Converts a value in the 16-bit logarithmic encoding of HDR endpoints to a half float
+///
+/// The top five bits are the exponent of the half float, the mantissa bits are mapped
+/// piecewise linearly so that interpolating the encoding approximates a logarithmic curve.
+pub fn lns_to_f16(value: u16) -> u16 {
+    let exponent = value >> 11;
+    let mantissa = value & 0x7FF;
+    let mantissa = if mantissa < 512 {
+        3 * mantissa
+    } else if mantissa < 1536 {
+        4 * mantissa - 512
+    } else {
+        5 * mantissa - 2048
+    };
+    ((exponent << 10) | (mantissa >> 3)).min(0x7BFF)
+}
+
+/// Converts a value to the logarithmic encoding of HDR endpoints, without rounding
+///
+/// The result lies in the middle of the range of encodings that convert to the nearest half
+/// float, negative values map to zero.
+pub fn to_lns(value: f32) -> f32 {
+    if value.is_nan() || value <= 0.0 {
+        return 0.0;
+    }
+
+    // the exponent field of subnormal half floats is zero
+    let (exponent, mantissa) = if value < libm::exp2f(-14.0) {
+        (0.0, value * libm::exp2f(27.0))
+    } else {
+        let (fraction, exponent) = libm::frexpf(value.min(65504.0));
+        ((exponent + 14) as f32, (2.0 * fraction - 1.0) * 8192.0)
+    };
+
+    let mantissa = mantissa + 4.0;
+    let mantissa = if mantissa < 1536.0 {
+        mantissa / 3.0
+    } else if mantissa < 5632.0 {
+        (mantissa + 512.0) / 4.0
+    } else {
+        (mantissa + 2048.0) / 5.0
+    };
+    exponent * 2048.0 + mantissa
+}
+
+/// Returns the quantised value at the given level that unquantises closest to `value`
+pub fn quantise(level: usize, value: f32) -> u8 {
+    let mut best = 0;
+    let mut best_err = f32::MAX;
+    for quantised in 0..ise::RANGES[level] {
+        let err = libm::fabsf(f32::from(ise::unquantise_colour(level, quantised as u8)) - value);
+        if err < best_err {
+            best = quantised as u8;
+            best_err = err;
+        }
+    }
+    best
+}
+
+/// Quantises a pair of endpoints for one of the modes used by the encoder
+///
+/// These are the direct LDR modes 0, 4, 8 and 12 and the HDR modes 2, 11 and 14. Endpoints
+/// are given in the range 0..255, with HDR channels holding the logarithmic encoding scaled
+/// down by 257.
+pub fn encode(mode: u8, endpoints: &[[f32; 4]; 2], level: usize, values: &mut [u8]) {
+    match mode {
+        2 => encode_hdr_luminance(endpoints, level, values),
+        11 | 14 => {
+            encode_hdr_rgb(endpoints, level, values);
+            if mode == 14 {
+                values[6] = quantise(level, endpoints[0][3]);
+                values[7] = quantise(level, endpoints[1][3]);
+            }
+        }
+        _ => encode_direct(mode, endpoints, level, values),
+    }
+}
+
+/// Quantises a pair of endpoints for one of the direct modes 0, 4, 8 and 12
+///
+/// The endpoints are swapped if needed so the decoder doesn't apply blue contraction.
+fn encode_direct(mode: u8, endpoints: &[[f32; 4]; 2], level: usize, values: &mut [u8]) {
+    let channels: &[usize] = match mode {
+        0 => &[0],
+        4 => &[0, 3],
+        8 => &[0, 1, 2],
+        _ => &[0, 1, 2, 3],
+    };
+
+    for (pair, &channel) in values.chunks_exact_mut(2).zip(channels) {
+        // luminance is the average of the colour channels
+        let value = |e: &[f32; 4]| {
+            if channels.len() <= 2 && channel == 0 {
+                (e[0] + e[1] + e[2]) / 3.0
+            } else {
+                e[channel]
+            }
+        };
+        pair[0] = quantise(level, value(&endpoints[0]));
+        pair[1] = quantise(level, value(&endpoints[1]));
+    }
+
+    if mode >= 8 {
+        let sum = |offset: usize| -> u32 {
+            (0..3)
+                .map(|c| u32::from(ise::unquantise_colour(level, values[2 * c + offset])))
+                .sum()
+        };
+        if sum(1) < sum(0) {
+            for pair in values.chunks_exact_mut(2) {
+                pair.swap(0, 1);
+            }
+        }
+    }
+}
+
+/// Quantises a pair of HDR luminance endpoints for mode 2
+fn encode_hdr_luminance(endpoints: &[[f32; 4]; 2], level: usize, values: &mut [u8]) {
+    // the values are the top eight bits of the 12-bit endpoints
+    let luminance = |e: &[f32; 4]| (e[0] + e[1] + e[2]) / 3.0 * (257.0 / 256.0);
+    let (l0, l1) = (luminance(&endpoints[0]), luminance(&endpoints[1]));
+    values[0] = quantise(level, l0.min(l1));
+    values[1] = quantise(level, l0.max(l1));
+}
+
+/// Returns the quantised value that unquantises closest to `value` through `table` while
+/// keeping the bits set in `retained`, or the closest one if no value keeps them
+fn quantise_retaining(table: &[u8], value: i32, retained: u8) -> u8 {
+    let mut best = (i32::MAX, 0);
+    let mut closest = (i32::MAX, 0);
+    for (quantised, &unquantised) in table.iter().enumerate() {
+        let error = (i32::from(unquantised) - value).abs();
+        if error < closest.0 {
+            closest = (error, quantised);
+        }
+        if (unquantised ^ value as u8) & retained == 0 && error < best.0 {
+            best = (error, quantised);
+        }
+    }
+    if best.0 == i32::MAX {
+        closest.1 as u8
+    } else {
+        best.1 as u8
+    }
+}
+
+/// Quantises a pair of HDR RGB endpoints to the first six values of modes 11, 14 and 15
+///
+/// Every submode is tried along with storing the endpoints directly at lower precision,
+/// keeping whichever decodes closest after quantisation. The values carrying submode bits
+/// and the high bits of fields only move to values that keep those bits.
+fn encode_hdr_rgb(endpoints: &[[f32; 4]; 2], level: usize, values: &mut [u8]) {
+    let range = usize::from(ise::RANGES[level]);
+    let mut table = [0u8; 256];
+    for (quantised, unquantised) in table[..range].iter_mut().enumerate() {
+        *unquantised = ise::unquantise_colour(level, quantised as u8);
+    }
+    let table = &table[..range];
+
+    let targets =
+        endpoints.map(|e| [e[0], e[1], e[2]].map(|c| (c * (257.0 / 16.0)).clamp(0.0, 4095.0)));
+
+    let mut best_error = f32::MAX;
+    let mut consider = |packed: [i32; 6], retained: [u8; 6]| {
+        let mut quantised = [0u8; 6];
+        let mut v = [0i32; 8];
+        for ((quantised, v), (&packed, &retained)) in quantised
+            .iter_mut()
+            .zip(v.iter_mut())
+            .zip(packed.iter().zip(&retained))
+        {
+            *quantised = quantise_retaining(table, packed, retained);
+            *v = i32::from(table[usize::from(*quantised)]);
+        }
+
+        let mut error = 0.0;
+        for (decoded, target) in decode_rgb_direct(&v).iter().zip(&targets) {
+            for (&d, &t) in decoded.iter().zip(target) {
+                let diff = d as f32 - t;
+                error += diff * diff;
+            }
+        }
+        if error < best_error {
+            best_error = error;
+            values[..6].copy_from_slice(&quantised);
+        }
+    };
+
+    // the major component is the largest, so the differences to the others stay positive
+    let [mut low, mut high] = targets;
+    let mut major = 0;
+    for c in 1..3 {
+        if low[c].max(high[c]) > low[major].max(high[major]) {
+            major = c;
+        }
+    }
+    if low[major] > high[major] {
+        core::mem::swap(&mut low, &mut high);
+    }
+    low.swap(0, major);
+    high.swap(0, major);
+    let major = major as i32;
+
+    for (submode, placement) in RGB_DIRECT_FIELDS.iter().enumerate() {
+        let shift = (submode as i32 >> 1) ^ 3;
+        let step = (1 << shift) as f32;
+        let fit =
+            |value: f32, min: i32, max: i32| (libm::roundf(value / step) as i32).clamp(min, max);
+        let limit = |field: usize| (1 << field_width(placement, field)) - 1;
+        let d_limit = 1 << (field_width(placement, 4) - 1);
+
+        // fit each field to what the decoder reconstructs from the ones before it
+        let a = fit(high[0], 0, limit(0));
+        let b0 = fit((a << shift) as f32 - high[1], 0, limit(1));
+        let b1 = fit((a << shift) as f32 - high[2], 0, limit(2));
+        let c = fit((a << shift) as f32 - low[0], 0, limit(3));
+        let d0 = fit(
+            ((a - b0 - c) << shift) as f32 - low[1],
+            -d_limit,
+            d_limit - 1,
+        );
+        let d1 = fit(
+            ((a - b1 - c) << shift) as f32 - low[2],
+            -d_limit,
+            d_limit - 1,
+        );
+
+        let submode = submode as i32;
+        let fields = [a, b0, b1, c, d0, d1];
+        let mut packed = [
+            a & 0xFF,
+            (submode & 1) << 7 | (a >> 8 & 1) << 6 | c & 0x3F,
+            (submode >> 1 & 1) << 7 | b0 & 0x3F,
+            (submode >> 2) << 7 | b1 & 0x3F,
+            (major & 1) << 7 | d0 & 0x1F,
+            (major >> 1) << 7 | d1 & 0x1F,
+        ];
+        for (&(value, bit), &(field, shift)) in RGB_DIRECT_VARIABLE_BITS.iter().zip(placement) {
+            packed[value] |= ((fields[field] >> shift) & 1) << bit;
+        }
+        consider(packed, [0, 0xC0, 0xC0, 0xC0, 0xE0, 0xE0]);
+    }
+
+    // red and green at eight bits, blue at seven
+    let [t0, t1] = targets;
+    let byte = |value: f32, step: f32, max: f32| libm::roundf(value / step).clamp(0.0, max) as i32;
+    consider(
+        [
+            byte(t0[0], 16.0, 255.0),
+            byte(t1[0], 16.0, 255.0),
+            byte(t0[1], 16.0, 255.0),
+            byte(t1[1], 16.0, 255.0),
+            0x80 | byte(t0[2], 32.0, 127.0),
+            0x80 | byte(t1[2], 32.0, 127.0),
+        ],
+        [0, 0, 0, 0, 0x80, 0x80],
+    );
+}
@@ -0,0 +1,1112 @@
+// Copyright (c) 2018-2021 Jan Solanti <jhs@psonet.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to	deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be included
+// in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.
+// IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT,
+// TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION WITH THE
+// SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! ASTC block encoder
+//!
+//! The encoder picks an endpoint mode from the channels in use, then tries a handful of
+//! partition and weight plane layouts. For each layout it fits ideal endpoints and weights,
+//! ranks every block mode the footprint allows by an estimate of its error and fully encodes
+//! only the most promising ones.
+
+use super::block::{
+    self, AstcBlock, Block, BlockMode, Infill, MAX_COLOUR_VALUES, MAX_TEXELS, MAX_WEIGHTS,
+};
+use super::endpoints;
+use super::ise::{self, RANGES};
+use super::partition::Pattern;
+use crate::math::f32_to_f16;
+use crate::{Algorithm, Params};
+
+/// Texel values in the range 0..255 and per-channel error weights of the block being
+/// compressed
+///
+/// Blocks with colours above one are HDR blocks, whose colour channels hold the logarithmic
+/// encoding of HDR endpoints scaled down by 257 so that they interpolate like the decoder.
+struct Texels {
+    width: usize,
+    height: usize,
+    hdr: bool,
+    values: [[f32; 4]; MAX_TEXELS],
+    weights: [[f32; 4]; MAX_TEXELS],
+    valid: [bool; MAX_TEXELS],
+}
+
+impl Texels {
+    fn new(rgba: &[[f32; 4]], mask: &[bool], width: usize, height: usize, params: &Params) -> Self {
+        let hdr = rgba
+            .iter()
+            .zip(mask)
+            .take(width * height)
+            .any(|(pixel, &valid)| valid && pixel[..3].iter().any(|&c| c > 1.0));
+        let mut texels = Texels {
+            width,
+            height,
+            hdr,
+            values: [[0.0; 4]; MAX_TEXELS],
+            weights: [[0.0; 4]; MAX_TEXELS],
+            valid: [false; MAX_TEXELS],
+        };
+
+        let sum: f32 = params.weights.iter().sum();
+        let scale = if sum > 0.0 { 3.0 / sum } else { 1.0 };
+
+        for (i, (pixel, &valid)) in rgba.iter().zip(mask).enumerate().take(width * height) {
+            if !valid {
+                continue;
+            }
+
+            let mut values = pixel.map(|c| (c * 255.0).clamp(0.0, 255.0));
+            if hdr {
+                for (value, &c) in values.iter_mut().zip(pixel).take(3) {
+                    *value = endpoints::to_lns(c) / 257.0;
+                }
+            }
+            let alpha_weight = if params.weigh_colour_by_alpha {
+                (values[3] + 1.0) / 256.0
+            } else {
+                1.0
+            };
+
+            texels.values[i] = values;
+            for (weight, colour_weight) in texels.weights[i].iter_mut().zip(params.weights) {
+                *weight = colour_weight * scale * alpha_weight;
+            }
+            texels.weights[i][3] = 1.0;
+            texels.valid[i] = true;
+        }
+
+        texels
+    }
+
+    fn count(&self) -> usize {
+        self.width * self.height
+    }
+
+    /// Weighted squared error of decoded texels
+    fn error(&self, decoded: &[[f32; 4]]) -> f32 {
+        let mut error = 0.0;
+        for ((values, weights), decoded) in self.values.iter().zip(&self.weights).zip(decoded) {
+            for channel in 0..4 {
+                let decoded = if self.hdr && channel < 3 {
+                    endpoints::to_lns(decoded[channel]) / 257.0
+                } else {
+                    decoded[channel] * 255.0
+                };
+                let d = values[channel] - decoded;
+                error += weights[channel] * d * d;
+            }
+        }
+        error
+    }
+
+    /// Returns the colour shared by all valid texels, if there is one
+    fn constant(&self) -> Option<[f32; 4]> {
+        let mut colour = None;
+        for (values, &valid) in self.values.iter().zip(&self.valid) {
+            if !valid {
+                continue;
+            }
+            match colour {
+                None => colour = Some(*values),
+                Some(colour) if colour != *values => return None,
+                _ => {}
+            }
+        }
+        Some(colour.unwrap_or([0.0; 4]))
+    }
+}
+
+/// Channels covered by the endpoint modes used by the encoder
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Channels {
+    Luminance,
+    LuminanceAlpha,
+    Rgb,
+    Rgba,
+}
+
+impl Channels {
+    /// Picks the cheapest mode that can represent the valid texels
+    fn select(texels: &Texels) -> Self {
+        let mut grey = true;
+        let mut opaque = true;
+        for (values, &valid) in texels.values.iter().zip(&texels.valid) {
+            if valid {
+                grey &= values[0] == values[1] && values[1] == values[2];
+                opaque &= values[3] == 255.0;
+            }
+        }
+
+        match (grey, opaque) {
+            (true, true) => Channels::Luminance,
+            (true, false) => Channels::LuminanceAlpha,
+            (false, true) => Channels::Rgb,
+            (false, false) => Channels::Rgba,
+        }
+    }
+
+    /// HDR blocks keep their alpha LDR, which only the RGB modes can pair with HDR colours
+    fn endpoint_mode(self, hdr: bool) -> u8 {
+        match (self, hdr) {
+            (Channels::Luminance, false) => 0,
+            (Channels::LuminanceAlpha, false) => 4,
+            (Channels::Rgb, false) => 8,
+            (Channels::Rgba, false) => 12,
+            (Channels::Luminance, true) => 2,
+            (Channels::Rgb, true) => 11,
+            (Channels::LuminanceAlpha | Channels::Rgba, true) => 14,
+        }
+    }
+}
+
+/// Partitioning and weight planes of a candidate encoding
+#[derive(Clone, Copy, Debug)]
+struct Layout {
+    partitions: usize,
+    seed: u32,
+    dual_plane: bool,
+    plane2_channel: usize,
+}
+
+impl Layout {
+    /// Channels interpolated by each weight plane
+    fn planes(&self) -> [&'static [usize]; 2] {
+        if !self.dual_plane {
+            return [&[0, 1, 2, 3], &[]];
+        }
+        match self.plane2_channel {
+            0 => [&[1, 2, 3], &[0]],
+            1 => [&[0, 2, 3], &[1]],
+            2 => [&[0, 1, 3], &[2]],
+            _ => [&[0, 1, 2], &[3]],
+        }
+    }
+
+    fn plane_count(&self) -> usize {
+        if self.dual_plane {
+            2
+        } else {
+            1
+        }
+    }
+
+    /// Partition of every texel of the block
+    fn labels(&self, width: usize, height: usize) -> [u8; MAX_TEXELS] {
+        let mut labels = [0; MAX_TEXELS];
+        if self.partitions > 1 {
+            let pattern = Pattern::new(self.seed, self.partitions, width * height);
+            for y in 0..height {
+                for x in 0..width {
+                    labels[x + y * width] = pattern.partition(x, y) as u8;
+                }
+            }
+        }
+        labels
+    }
+}
+
+/// Weighted mean and covariance of the texels of a partition
+fn covariance(
+    texels: &Texels,
+    labels: &[u8],
+    partition: u8,
+    channels: &[usize],
+) -> ([f32; 4], [[f32; 4]; 4]) {
+    let mut total = 0.0;
+    let mut mean = [0f32; 4];
+    let mut weights = [0f32; MAX_TEXELS];
+    for (i, weight) in weights.iter_mut().enumerate().take(texels.count()) {
+        if !texels.valid[i] || labels[i] != partition {
+            continue;
+        }
+        let w: f32 = channels.iter().map(|&c| texels.weights[i][c]).sum();
+        for &c in channels {
+            mean[c] += w * texels.values[i][c];
+        }
+        *weight = w;
+        total += w;
+    }
+
+    let mut covariance = [[0f32; 4]; 4];
+    if total <= 0.0 {
+        return (mean, covariance);
+    }
+    for m in mean.iter_mut() {
+        *m /= total;
+    }
+
+    for (values, &weight) in texels.values.iter().zip(&weights) {
+        if weight == 0.0 {
+            continue;
+        }
+        for &a in channels {
+            let da = values[a] - mean[a];
+            for &b in channels {
+                let db = values[b] - mean[b];
+                covariance[a][b] += weight * da * db;
+            }
+        }
+    }
+
+    (mean, covariance)
+}
+
+/// Finds the principal axis of a covariance matrix using power iteration
+fn principal_axis(covariance: &[[f32; 4]; 4], channels: &[usize]) -> [f32; 4] {
+    // start from the row with the most energy to avoid starting orthogonal to the solution
+    let mut axis = [0f32; 4];
+    let mut best = 0.0;
+    for &c in channels {
+        if covariance[c][c] > best {
+            best = covariance[c][c];
+            axis = covariance[c];
+        }
+    }
+    if best <= 0.0 {
+        return [0.0; 4];
+    }
+
+    for _ in 0..8 {
+        let mut next = [0f32; 4];
+        for &a in channels {
+            for &b in channels {
+                next[a] += covariance[a][b] * axis[b];
+            }
+        }
+
+        let norm = next.iter().fold(0f32, |acc, v| acc.max(v.abs()));
+        if norm <= f32::EPSILON {
+            return [0.0; 4];
+        }
+        for (a, n) in axis.iter_mut().zip(next.iter()) {
+            *a = n / norm;
+        }
+    }
+
+    axis
+}
+
+/// Squared error of the texels of a partition that is not explained by their principal axis
+fn line_residual(texels: &Texels, labels: &[u8], partition: u8, channels: &[usize]) -> f32 {
+    let (_, covariance) = covariance(texels, labels, partition, channels);
+    let axis = principal_axis(&covariance, channels);
+
+    let trace: f32 = channels.iter().map(|&c| covariance[c][c]).sum();
+    let length2: f32 = axis.iter().map(|a| a * a).sum();
+    if length2 <= f32::EPSILON {
+        return trace;
+    }
+
+    // Rayleigh quotient gives the variance along the axis
+    let mut variance = 0.0;
+    for &a in channels {
+        for &b in channels {
+            variance += axis[a] * covariance[a][b] * axis[b];
+        }
+    }
+
+    (trace - variance / length2).max(0.0)
+}
+
+/// Endpoints spanning the texels of a partition along their principal axis, for the
+/// channels of one weight plane
+fn principal_endpoints(
+    texels: &Texels,
+    labels: &[u8],
+    partition: u8,
+    channels: &[usize],
+    endpoints: &mut [[f32; 4]; 2],
+) {
+    let (mean, covariance) = covariance(texels, labels, partition, channels);
+    let axis = principal_axis(&covariance, channels);
+
+    let length2: f32 = axis.iter().map(|a| a * a).sum();
+    let (mut min, mut max) = (0.0, 0.0);
+    if length2 > f32::EPSILON {
+        (min, max) = (f32::MAX, f32::MIN);
+        let members = texels.values.iter().zip(&texels.valid).zip(labels);
+        for ((values, &valid), &label) in members.take(texels.count()) {
+            if !valid || label != partition {
+                continue;
+            }
+            let t: f32 = channels
+                .iter()
+                .map(|&c| (values[c] - mean[c]) * axis[c])
+                .sum::<f32>()
+                / length2;
+            min = min.min(t);
+            max = max.max(t);
+        }
+    }
+
+    for &c in channels {
+        endpoints[0][c] = (mean[c] + axis[c] * min).clamp(0.0, 255.0);
+        endpoints[1][c] = (mean[c] + axis[c] * max).clamp(0.0, 255.0);
+    }
+}
+
+/// Solves for the least squares optimal endpoints of a partition given the weight of every
+/// texel in the range 0..1
+///
+/// Channels for which the system is singular keep their current endpoints.
+fn refine_endpoints(
+    texels: &Texels,
+    labels: &[u8],
+    partition: u8,
+    channels: &[usize],
+    weights: &[f32],
+    endpoints: &mut [[f32; 4]; 2],
+) {
+    for &c in channels {
+        let (mut aa, mut ab, mut bb, mut ax, mut bx) = (0f32, 0f32, 0f32, 0f32, 0f32);
+        for (i, &t) in weights.iter().enumerate().take(texels.count()) {
+            if !texels.valid[i] || labels[i] != partition {
+                continue;
+            }
+            let s = 1.0 - t;
+            let w = texels.weights[i][c];
+            let x = texels.values[i][c];
+            aa += w * s * s;
+            ab += w * s * t;
+            bb += w * t * t;
+            ax += w * s * x;
+            bx += w * t * x;
+        }
+
+        let det = aa * bb - ab * ab;
+        if det.abs() <= f32::EPSILON * (aa * bb).max(1.0) {
+            continue;
+        }
+
+        endpoints[0][c] = ((ax * bb - bx * ab) / det).clamp(0.0, 255.0);
+        endpoints[1][c] = ((bx * aa - ax * ab) / det).clamp(0.0, 255.0);
+    }
+}
+
+/// Projects every texel onto the endpoints of its partition, giving the ideal weight of each
+/// texel in the range 0..1 and the importance of matching it
+fn ideal_weights(
+    texels: &Texels,
+    labels: &[u8],
+    endpoints: &[[[f32; 4]; 2]; 4],
+    channels: &[usize],
+) -> ([f32; MAX_TEXELS], [f32; MAX_TEXELS]) {
+    let mut weights = [0f32; MAX_TEXELS];
+    let mut importance = [0f32; MAX_TEXELS];
+    for i in 0..texels.count() {
+        if !texels.valid[i] {
+            continue;
+        }
+
+        let [e0, e1] = &endpoints[usize::from(labels[i])];
+        let (mut dot, mut length2) = (0.0, 0.0);
+        for &c in channels {
+            let d = e1[c] - e0[c];
+            dot += texels.weights[i][c] * d * (texels.values[i][c] - e0[c]);
+            length2 += texels.weights[i][c] * d * d;
+        }
+
+        if length2 > f32::EPSILON {
+            weights[i] = (dot / length2).clamp(0.0, 1.0);
+            importance[i] = length2;
+        }
+    }
+    (weights, importance)
+}
+
+/// Averages the ideal texel weights onto a weight grid, inverting the bilinear infill
+fn downsample(
+    infill: &Infill,
+    texels: usize,
+    weights: &[f32],
+    importance: &[f32],
+    grid: &mut [f32],
+) {
+    let mut totals = [0f32; MAX_WEIGHTS];
+    grid.fill(0.0);
+    for i in 0..texels {
+        for (&index, &factor) in infill.indices[i].iter().zip(&infill.factors[i]) {
+            let w = f32::from(factor) * (importance[i] + 1e-3);
+            grid[usize::from(index)] += w * weights[i];
+            totals[usize::from(index)] += w;
+        }
+    }
+    for (value, &total) in grid.iter_mut().zip(&totals) {
+        if total > 0.0 {
+            *value /= total;
+        }
+    }
+}
+
+/// Interpolates grid weights back onto a texel
+fn upsample(infill: &Infill, texel: usize, grid: &[f32]) -> f32 {
+    let mut sum = 0.0;
+    for (&index, &factor) in infill.indices[texel].iter().zip(&infill.factors[texel]) {
+        sum += f32::from(factor) * grid[usize::from(index)];
+    }
+    sum / 16.0
+}
+
+/// Returns the quantised weight that unquantises closest to a weight in the range 0..1
+fn quantise_weight(level: usize, weight: f32) -> u8 {
+    let target = weight * 64.0;
+    let mut best = 0;
+    let mut best_err = f32::MAX;
+    for quantised in 0..RANGES[level] as u8 {
+        let err = libm::fabsf(f32::from(ise::unquantise_weight(level, quantised)) - target);
+        if err < best_err {
+            best = quantised;
+            best_err = err;
+        }
+    }
+    best
+}
+
+/// Ideal endpoints and weights of a layout, ahead of quantisation
+#[derive(Clone, Copy)]
+struct Fit {
+    layout: Layout,
+    labels: [u8; MAX_TEXELS],
+    endpoints: [[[f32; 4]; 2]; 4],
+    weights: [[f32; MAX_TEXELS]; 2],
+    importance: [[f32; MAX_TEXELS]; 2],
+}
+
+impl Fit {
+    fn new(texels: &Texels, layout: Layout) -> Self {
+        let labels = layout.labels(texels.width, texels.height);
+
+        // unused channels stay opaque
+        let mut endpoints = [[[0.0, 0.0, 0.0, 255.0]; 2]; 4];
+        for (partition, endpoints) in endpoints.iter_mut().enumerate().take(layout.partitions) {
+            for channels in layout.planes() {
+                principal_endpoints(texels, &labels, partition as u8, channels, endpoints);
+            }
+        }
+
+        let mut fit = Fit {
+            layout,
+            labels,
+            endpoints,
+            weights: [[0.0; MAX_TEXELS]; 2],
+            importance: [[0.0; MAX_TEXELS]; 2],
+        };
+        fit.project(texels);
+        fit
+    }
+
+    /// Updates the ideal weights for the current endpoints
+    fn project(&mut self, texels: &Texels) {
+        let planes = self.layout.planes();
+        for (plane, channels) in planes
+            .into_iter()
+            .enumerate()
+            .take(self.layout.plane_count())
+        {
+            (self.weights[plane], self.importance[plane]) =
+                ideal_weights(texels, &self.labels, &self.endpoints, channels);
+        }
+    }
+
+    /// Estimates the error of a block mode from the loss of resolution of the weight grid
+    /// and the quantisation of weights and endpoints
+    fn estimate(
+        &self,
+        texels: &Texels,
+        mode: &BlockMode,
+        colour_level: usize,
+        grid_errors: &mut [[f32; 13]; 13],
+    ) -> f32 {
+        let grid_error = &mut grid_errors[mode.grid_width][mode.grid_height];
+        if grid_error.is_nan() {
+            let infill = Infill::new(
+                texels.width,
+                texels.height,
+                mode.grid_width,
+                mode.grid_height,
+            );
+            let mut error = 0.0;
+            let mut grid = [0f32; MAX_WEIGHTS];
+            for plane in 0..self.layout.plane_count() {
+                let (weights, importance) = (&self.weights[plane], &self.importance[plane]);
+                downsample(&infill, texels.count(), weights, importance, &mut grid);
+                for (i, (&weight, &importance)) in weights.iter().zip(importance).enumerate() {
+                    let d = weight - upsample(&infill, i, &grid);
+                    error += importance * d * d;
+                }
+            }
+            *grid_error = error;
+        }
+
+        // uniformly distributed rounding errors have a variance of a twelfth of the step
+        let weight_step = 1.0 / f32::from(RANGES[mode.weight_level] - 1);
+        let colour_step = 255.0 / f32::from(RANGES[colour_level] - 1);
+        let mut importance = 0.0;
+        let mut channel_weights = 0.0;
+        for i in 0..texels.count() {
+            importance += self.importance[0][i] + self.importance[1][i];
+            channel_weights += texels.weights[i].iter().sum::<f32>();
+        }
+
+        *grid_error
+            + importance * weight_step * weight_step / 12.0
+            + channel_weights * colour_step * colour_step / 12.0
+    }
+}
+
+/// A fully encoded candidate block and its error
+#[derive(Clone, Copy)]
+struct Candidate {
+    block: Block,
+    error: f32,
+}
+
+/// Quantises the endpoints and weights of a fit with the given block mode
+fn encode(
+    texels: &Texels,
+    fit: &mut Fit,
+    channels: Channels,
+    mode: &BlockMode,
+    mode_bits: u32,
+    colour_level: usize,
+) -> Candidate {
+    let layout = fit.layout;
+    let endpoint_mode = channels.endpoint_mode(texels.hdr);
+    let count = endpoints::value_count(endpoint_mode);
+
+    let mut block = Block {
+        mode: *mode,
+        mode_bits,
+        partitions: layout.partitions,
+        seed: layout.seed,
+        endpoint_modes: [endpoint_mode; 4],
+        colour_level,
+        colours: [0; MAX_COLOUR_VALUES],
+        plane2_channel: layout.plane2_channel,
+        weights: [0; MAX_WEIGHTS],
+    };
+
+    // quantise the endpoints, then fit the weights to what the decoder will see
+    for (partition, values) in block
+        .colours
+        .chunks_exact_mut(count)
+        .enumerate()
+        .take(layout.partitions)
+    {
+        endpoints::encode(
+            endpoint_mode,
+            &fit.endpoints[partition],
+            colour_level,
+            values,
+        );
+        let mut unquantised = [0; 8];
+        for (u, &value) in unquantised.iter_mut().zip(values.iter()) {
+            *u = ise::unquantise_colour(colour_level, value);
+        }
+        let decoded = endpoints::decode(endpoint_mode, &unquantised[..count]);
+        fit.endpoints[partition] = decoded.colours.map(|e| e.map(|c| f32::from(c) / 257.0));
+    }
+    fit.project(texels);
+
+    let infill = Infill::new(
+        texels.width,
+        texels.height,
+        mode.grid_width,
+        mode.grid_height,
+    );
+    let planes = layout.plane_count();
+    let mut grid = [0f32; MAX_WEIGHTS];
+    for plane in 0..planes {
+        downsample(
+            &infill,
+            texels.count(),
+            &fit.weights[plane],
+            &fit.importance[plane],
+            &mut grid,
+        );
+        let grid_count = mode.grid_width * mode.grid_height;
+        for (i, &weight) in grid[..grid_count].iter().enumerate() {
+            block.weights[i * planes + plane] = quantise_weight(mode.weight_level, weight);
+        }
+    }
+
+    let mut decoded = [[0f32; 4]; MAX_TEXELS];
+    block::decode_texels(&block, texels.width, texels.height, &mut decoded);
+    let error = texels.error(&decoded[..texels.count()]);
+    Candidate { block, error }
+}
+
+/// Encodes a fit with a block mode, then refits the endpoints to the quantised weights
+fn encode_refined(
+    texels: &Texels,
+    fit: &Fit,
+    channels: Channels,
+    mode: &BlockMode,
+    mode_bits: u32,
+    colour_level: usize,
+    iterations: usize,
+) -> Candidate {
+    let mut current = *fit;
+    let mut best = encode(
+        texels,
+        &mut current,
+        channels,
+        mode,
+        mode_bits,
+        colour_level,
+    );
+
+    for _ in 0..iterations {
+        // recover the weight of every texel from the quantised grid
+        let planes = mode.dual_plane as usize + 1;
+        let mut grids = [[0u8; MAX_WEIGHTS]; 2];
+        for (i, &weight) in best.block.weights[..mode.weight_count()].iter().enumerate() {
+            grids[i % planes][i / planes] = ise::unquantise_weight(mode.weight_level, weight);
+        }
+        let infill = Infill::new(
+            texels.width,
+            texels.height,
+            mode.grid_width,
+            mode.grid_height,
+        );
+
+        let mut refined = *fit;
+        for (plane, range) in fit.layout.planes().into_iter().enumerate().take(planes) {
+            let mut weights = [0f32; MAX_TEXELS];
+            for (i, weight) in weights.iter_mut().enumerate().take(texels.count()) {
+                *weight = infill.weight(i, &grids[plane]) as f32 / 64.0;
+            }
+            for partition in 0..fit.layout.partitions {
+                refine_endpoints(
+                    texels,
+                    &fit.labels,
+                    partition as u8,
+                    range,
+                    &weights,
+                    &mut refined.endpoints[partition],
+                );
+            }
+        }
+
+        let candidate = encode(
+            texels,
+            &mut refined,
+            channels,
+            mode,
+            mode_bits,
+            colour_level,
+        );
+        if candidate.error >= best.error {
+            break;
+        }
+        best = candidate;
+    }
+
+    best
+}
+
+/// Groups the valid texels into clusters with k-means, returning the cluster of each texel
+fn cluster(texels: &Texels, count: usize) -> [u8; MAX_TEXELS] {
+    let distance = |i: usize, centre: &[f32; 4]| -> f32 {
+        (0..4)
+            .map(|c| {
+                let d = texels.values[i][c] - centre[c];
+                texels.weights[i][c] * d * d
+            })
+            .sum()
+    };
+    let valid = || (0..texels.count()).filter(|&i| texels.valid[i]);
+
+    // seed the clusters with texels far away from each other
+    let mut centres = [[0f32; 4]; 4];
+    let mut mean = [0f32; 4];
+    let mut total = 0.0;
+    for i in valid() {
+        for (m, v) in mean.iter_mut().zip(&texels.values[i]) {
+            *m += v;
+        }
+        total += 1.0;
+    }
+    let mean = mean.map(|m| m / f32::max(total, 1.0));
+    for k in 0..count {
+        let mut farthest = 0;
+        let mut farthest_distance = -1.0;
+        for i in valid() {
+            let d = if k == 0 {
+                distance(i, &mean)
+            } else {
+                centres[..k]
+                    .iter()
+                    .map(|centre| distance(i, centre))
+                    .fold(f32::MAX, f32::min)
+            };
+            if d > farthest_distance {
+                farthest = i;
+                farthest_distance = d;
+            }
+        }
+        centres[k] = texels.values[farthest];
+    }
+
+    let mut labels = [0u8; MAX_TEXELS];
+    for _ in 0..4 {
+        for i in valid() {
+            let mut nearest = f32::MAX;
+            for (k, centre) in centres.iter().enumerate().take(count) {
+                let d = distance(i, centre);
+                if d < nearest {
+                    nearest = d;
+                    labels[i] = k as u8;
+                }
+            }
+        }
+
+        let mut sums = [[0f32; 4]; 4];
+        let mut totals = [0f32; 4];
+        for i in valid() {
+            let k = usize::from(labels[i]);
+            for (s, v) in sums[k].iter_mut().zip(&texels.values[i]) {
+                *s += v;
+            }
+            totals[k] += 1.0;
+        }
+        for ((centre, sum), &total) in centres.iter_mut().zip(&sums).zip(&totals) {
+            if total > 0.0 {
+                *centre = sum.map(|s| s / total);
+            }
+        }
+    }
+
+    labels
+}
+
+/// Finds the partition seeds whose patterns best match a clustering of the texels
+///
+/// Only every `step`th seed is considered. Returns the number of seeds written to `seeds`.
+fn match_seeds(
+    texels: &Texels,
+    count: usize,
+    clusters: &[u8],
+    step: usize,
+    seeds: &mut [u32],
+) -> usize {
+    let mut best = [(usize::MAX, 0u32); 8];
+    let wanted = seeds.len().min(best.len());
+
+    for seed in (0..1024).step_by(step) {
+        let pattern = Pattern::new(seed, count, texels.count());
+
+        // count how often each cluster lands in each partition
+        let mut matches = [[0usize; 4]; 4];
+        let mut sizes = [0usize; 4];
+        for y in 0..texels.height {
+            for x in 0..texels.width {
+                let i = x + y * texels.width;
+                if texels.valid[i] {
+                    let partition = pattern.partition(x, y);
+                    matches[usize::from(clusters[i])][partition] += 1;
+                    sizes[partition] += 1;
+                }
+            }
+        }
+        if sizes[..count].contains(&0) {
+            continue;
+        }
+
+        // the labels of the clusters are arbitrary, so try every assignment
+        let total: usize = sizes.iter().sum();
+        let mut agreement = 0;
+        let permutations: &[[usize; 3]] = if count == 2 {
+            &[[0, 1, 2], [1, 0, 2]]
+        } else {
+            &[
+                [0, 1, 2],
+                [0, 2, 1],
+                [1, 0, 2],
+                [1, 2, 0],
+                [2, 0, 1],
+                [2, 1, 0],
+            ]
+        };
+        for permutation in permutations {
+            let sum = (0..count).map(|k| matches[k][permutation[k]]).sum();
+            agreement = agreement.max(sum);
+        }
+        let mismatches = total - agreement;
+
+        // keep the list of best seeds sorted
+        if mismatches < best[wanted - 1].0 {
+            let mut position = wanted - 1;
+            while position > 0 && best[position - 1].0 > mismatches {
+                best[position] = best[position - 1];
+                position -= 1;
+            }
+            best[position] = (mismatches, seed);
+        }
+    }
+
+    let mut found = 0;
+    for (seed, &(mismatches, candidate)) in seeds.iter_mut().zip(&best[..wanted]) {
+        if mismatches == usize::MAX {
+            break;
+        }
+        *seed = candidate;
+        found += 1;
+    }
+    found
+}
+
+/// Search effort of an algorithm
+struct Effort {
+    /// Number of block modes fully encoded per layout
+    candidates: usize,
+    /// Number of least squares endpoint refits per candidate
+    iterations: usize,
+    /// Stride through the partition seeds and number of seeds tried for two and three
+    /// partitions
+    seed_step: usize,
+    two_partitions: usize,
+    three_partitions: usize,
+    /// Number of channels tried on a second weight plane
+    plane2_channels: usize,
+}
+
+impl Effort {
+    fn new(algorithm: Algorithm) -> Self {
+        match algorithm {
+            Algorithm::RangeFit => Effort {
+                candidates: 1,
+                iterations: 0,
+                seed_step: 1,
+                two_partitions: 0,
+                three_partitions: 0,
+                plane2_channels: 1,
+            },
+            Algorithm::ClusterFit => Effort {
+                candidates: 3,
+                iterations: 1,
+                seed_step: 4,
+                two_partitions: 1,
+                three_partitions: 0,
+                plane2_channels: 1,
+            },
+            Algorithm::IterativeClusterFit => Effort {
+                candidates: 8,
+                iterations: 2,
+                seed_step: 1,
+                two_partitions: 3,
+                three_partitions: 2,
+                plane2_channels: 4,
+            },
+        }
+    }
+}
+
+/// Encodes a block holding a single colour given like the texel values
+fn void_extent(colour: [f32; 4], hdr: bool) -> u128 {
+    // HDR void extents store half floats
+    let mut values = [0u16; 4];
+    for (i, (value, c)) in values.iter_mut().zip(colour).enumerate() {
+        *value = match (hdr, i) {
+            (false, _) => libm::roundf(c * 257.0) as u16,
+            (true, 3) => f32_to_f16(c / 255.0),
+            (true, _) => endpoints::lns_to_f16(libm::roundf(c * 257.0) as u16),
+        };
+    }
+    let block = AstcBlock::VoidExtent {
+        hdr,
+        extent: AstcBlock::NO_EXTENT,
+        colour: values,
+    };
+    u128::from_le_bytes(block.to_bytes())
+}
+
+/// Compresses a block of `width` by `height` texels
+///
+/// Blocks with colours above one use the HDR endpoint modes, otherwise texels are clamped to
+/// the range 0..1. Texels whose `mask` entry is false don't contribute to the error.
+pub fn compress_astc(
+    rgba: &[[f32; 4]],
+    mask: &[bool],
+    width: usize,
+    height: usize,
+    params: &Params,
+    block: &mut [u8],
+) {
+    let texels = Texels::new(rgba, mask, width, height, params);
+    if let Some(colour) = texels.constant() {
+        block.copy_from_slice(&void_extent(colour, texels.hdr).to_le_bytes());
+        return;
+    }
+
+    let channels = Channels::select(&texels);
+    let effort = Effort::new(params.algorithm);
+    let values = endpoints::value_count(channels.endpoint_mode(texels.hdr));
+
+    // channels that can get a weight plane of their own, ordered by how well the others
+    // fit a line without them
+    let candidates: &[usize] = match channels {
+        Channels::Luminance => &[],
+        Channels::LuminanceAlpha => &[3],
+        Channels::Rgb => &[0, 1, 2],
+        Channels::Rgba => &[3, 0, 1, 2],
+    };
+    let single = [0; MAX_TEXELS];
+    let mut plane2_ranking = [(0f32, 0usize); 4];
+    for (rank, &channel) in plane2_ranking.iter_mut().zip(candidates) {
+        let others = Layout {
+            partitions: 1,
+            seed: 0,
+            dual_plane: true,
+            plane2_channel: channel,
+        }
+        .planes()[0];
+        *rank = (line_residual(&texels, &single, 0, others), channel);
+    }
+    plane2_ranking[..candidates.len()].sort_unstable_by(|a, b| a.0.total_cmp(&b.0));
+    let plane2_channels = candidates.len().min(effort.plane2_channels);
+
+    // gather the layouts worth trying
+    let single_plane = Layout {
+        partitions: 1,
+        seed: 0,
+        dual_plane: false,
+        plane2_channel: 0,
+    };
+    let mut layouts = [single_plane; 16];
+    let mut layout_count = 1;
+    for &(_, channel) in &plane2_ranking[..plane2_channels] {
+        layouts[layout_count] = Layout {
+            dual_plane: true,
+            plane2_channel: channel,
+            ..single_plane
+        };
+        layout_count += 1;
+    }
+    for (partitions, tries) in [(2, effort.two_partitions), (3, effort.three_partitions)] {
+        if tries == 0 || partitions * values > MAX_COLOUR_VALUES {
+            continue;
+        }
+        let clusters = cluster(&texels, partitions);
+        let mut seeds = [0u32; 8];
+        let found = match_seeds(&texels, partitions, &clusters, effort.seed_step, &mut seeds);
+
+        // the clusters only approximate the partitions, so prefer the patterns whose
+        // partitions fit lines best
+        let mut ranking = [(0f32, 0u32); 8];
+        for (rank, &seed) in ranking.iter_mut().zip(&seeds[..found]) {
+            let labels = Layout {
+                partitions,
+                seed,
+                ..single_plane
+            }
+            .labels(width, height);
+            let residual = (0..partitions as u8)
+                .map(|partition| line_residual(&texels, &labels, partition, &[0, 1, 2, 3]))
+                .sum();
+            *rank = (residual, seed);
+        }
+        ranking[..found].sort_unstable_by(|a, b| a.0.total_cmp(&b.0));
+
+        for &(_, seed) in ranking[..found].iter().take(tries) {
+            let layout = Layout {
+                partitions,
+                seed,
+                ..single_plane
+            };
+            layouts[layout_count] = layout;
+            layout_count += 1;
+
+            // two partitions leave room for a second plane for the most independent channel
+            if plane2_channels > 0 && partitions == 2 {
+                layouts[layout_count] = Layout {
+                    dual_plane: true,
+                    plane2_channel: plane2_ranking[0].1,
+                    ..layout
+                };
+                layout_count += 1;
+            }
+        }
+    }
+
+    let mut best: Option<Candidate> = None;
+    for layout in &layouts[..layout_count] {
+        let fit = Fit::new(&texels, *layout);
+
+        // rank the block modes by their estimated error
+        let mut ranking = [(f32::MAX, 0u32, 0usize); 16];
+        let candidates = effort.candidates.min(ranking.len());
+        let mut grid_errors = [[f32::NAN; 13]; 13];
+        for mode_bits in 0..2048 {
+            if mode_bits & 0x1FF == 0x1FC {
+                continue;
+            }
+            let Some(mode) = BlockMode::decode(mode_bits) else {
+                continue;
+            };
+            if mode.dual_plane != layout.dual_plane
+                || mode.grid_width > width
+                || mode.grid_height > height
+            {
+                continue;
+            }
+            let Some(colour_level) =
+                block::colour_level(&mode, layout.partitions, 0, layout.partitions * values)
+            else {
+                continue;
+            };
+
+            let estimate = fit.estimate(&texels, &mode, colour_level, &mut grid_errors);
+            if estimate < ranking[candidates - 1].0 {
+                let mut position = candidates - 1;
+                while position > 0 && ranking[position - 1].0 > estimate {
+                    ranking[position] = ranking[position - 1];
+                    position -= 1;
+                }
+                ranking[position] = (estimate, mode_bits, colour_level);
+            }
+        }
+
+        for &(estimate, mode_bits, colour_level) in &ranking[..candidates] {
+            if estimate == f32::MAX {
+                break;
+            }
+            let Some(mode) = BlockMode::decode(mode_bits) else {
+                continue;
+            };
+            let candidate = encode_refined(
+                &texels,
+                &fit,
+                channels,
+                &mode,
+                mode_bits,
+                colour_level,
+                effort.iterations,
+            );
+            if best.map_or(true, |best| candidate.error < best.error) {
+                best = Some(candidate);
+            }
+        }
+    }
+
+    let encoded = best.map_or(void_extent([0.0; 4], false), |best| best.block.encode());
+    block.copy_from_slice(&encoded.to_le_bytes());
+}
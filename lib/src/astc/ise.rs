@@ -0,0 +1,323 @@
+// Copyright (c) 2018-2021 Jan Solanti <jhs@psonet.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to	deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be included
+// in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.
+// IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT,
+// TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION WITH THE
+// SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Integer sequence encoding of colour endpoints and weights
+//!
+//! Values of a quantisation level are stored as a number of low bits each, plus a trit or a
+//! quint for levels whose range is a multiple of three or five. Trits are packed in groups of
+//! five into eight bits and quints in groups of three into seven bits, interleaved with the
+//! low bits of the values.
+
+/// Number of values representable at each quantisation level
+pub const RANGES: [u16; 21] = [
+    2, 3, 4, 5, 6, 8, 10, 12, 16, 20, 24, 32, 40, 48, 64, 80, 96, 128, 160, 192, 256,
+];
+
+/// Lowest quantisation level allowed for colour endpoints
+pub const MIN_COLOUR_LEVEL: usize = 4;
+
+/// How the values of a quantisation level are stored
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Packing {
+    Bits,
+    Trits,
+    Quints,
+}
+
+/// Returns the packing and the number of low bits per value of a quantisation level
+fn encoding(level: usize) -> (Packing, usize) {
+    let range = RANGES[level] as usize;
+    if range % 3 == 0 {
+        (Packing::Trits, (range / 3).trailing_zeros() as usize)
+    } else if range % 5 == 0 {
+        (Packing::Quints, (range / 5).trailing_zeros() as usize)
+    } else {
+        (Packing::Bits, range.trailing_zeros() as usize)
+    }
+}
+
+/// Number of bits needed to store `count` values at a quantisation level
+pub fn sequence_bits(count: usize, level: usize) -> usize {
+    let (packing, bits) = encoding(level);
+    match packing {
+        Packing::Bits => bits * count,
+        Packing::Trits => bits * count + (8 * count).div_ceil(5),
+        Packing::Quints => bits * count + (7 * count).div_ceil(3),
+    }
+}
+
+/// Extracts `count` bits at `position`, treating bits at or beyond `end` as zero
+fn read_bits(block: u128, position: usize, count: usize, end: usize) -> u32 {
+    if position >= end || count == 0 {
+        return 0;
+    }
+    let count = count.min(end - position);
+    ((block >> position) as u32) & (u32::MAX >> (32 - count))
+}
+
+/// Inserts `count` bits at `position`, dropping bits at or beyond `end`
+fn write_bits(block: &mut u128, value: u32, position: usize, count: usize, end: usize) {
+    if position >= end || count == 0 {
+        return;
+    }
+    let count = count.min(end - position);
+    let value = value & (u32::MAX >> (32 - count));
+    *block |= u128::from(value) << position;
+}
+
+/// Unpacks five trits from their 8-bit encoding
+fn decode_trits(t: u32) -> [u32; 5] {
+    let bit = |value: u32, n: u32| (value >> n) & 1;
+
+    let (c, t4, t3);
+    if (t >> 2) & 7 == 7 {
+        c = ((t >> 5) & 7) << 2 | (t & 3);
+        t4 = 2;
+        t3 = 2;
+    } else {
+        c = t & 0x1F;
+        if (t >> 5) & 3 == 3 {
+            t4 = 2;
+            t3 = bit(t, 7);
+        } else {
+            t4 = bit(t, 7);
+            t3 = (t >> 5) & 3;
+        }
+    }
+
+    let (t2, t1, t0);
+    if c & 3 == 3 {
+        t2 = 2;
+        t1 = bit(c, 4);
+        t0 = bit(c, 3) << 1 | (bit(c, 2) & !bit(c, 3) & 1);
+    } else if (c >> 2) & 3 == 3 {
+        t2 = 2;
+        t1 = 2;
+        t0 = c & 3;
+    } else {
+        t2 = bit(c, 4);
+        t1 = (c >> 2) & 3;
+        t0 = bit(c, 1) << 1 | (bit(c, 0) & !bit(c, 1) & 1);
+    }
+
+    [t0, t1, t2, t3, t4]
+}
+
+/// Unpacks three quints from their 7-bit encoding
+fn decode_quints(q: u32) -> [u32; 3] {
+    let bit = |value: u32, n: u32| (value >> n) & 1;
+
+    if (q >> 1) & 3 == 3 && (q >> 5) & 3 == 0 {
+        let not0 = !bit(q, 0) & 1;
+        let q2 = bit(q, 0) << 2 | (bit(q, 4) & not0) << 1 | (bit(q, 3) & not0);
+        return [4, 4, q2];
+    }
+
+    let (q2, c);
+    if (q >> 1) & 3 == 3 {
+        q2 = 4;
+        c = ((q >> 3) & 3) << 3 | (!(q >> 5) & 3) << 1 | bit(q, 0);
+    } else {
+        q2 = (q >> 5) & 3;
+        c = q & 0x1F;
+    }
+
+    if c & 7 == 5 {
+        [(c >> 3) & 3, 4, q2]
+    } else {
+        [c & 7, (c >> 3) & 3, q2]
+    }
+}
+
+/// Finds the smallest 8-bit encoding of five trits
+fn encode_trits(trits: [u32; 5]) -> u32 {
+    (0..256).find(|&t| decode_trits(t) == trits).unwrap_or(0)
+}
+
+/// Finds the smallest 7-bit encoding of three quints
+fn encode_quints(quints: [u32; 3]) -> u32 {
+    (0..128).find(|&q| decode_quints(q) == quints).unwrap_or(0)
+}
+
+/// Positions of the packed trit and quint bits between the low bits of the values in a group
+const TRIT_BITS: [usize; 5] = [2, 2, 1, 2, 1];
+const QUINT_BITS: [usize; 3] = [3, 2, 2];
+
+/// Decodes a sequence of values at a quantisation level, starting at bit `position`
+pub fn decode(block: u128, position: usize, level: usize, values: &mut [u8]) {
+    let (packing, bits) = encoding(level);
+    let end = position + sequence_bits(values.len(), level);
+    let mask = (1 << bits) - 1;
+
+    let (group, packed_bits): (usize, &[usize]) = match packing {
+        Packing::Bits => (1, &[0]),
+        Packing::Trits => (5, &TRIT_BITS),
+        Packing::Quints => (3, &QUINT_BITS),
+    };
+
+    let mut position = position;
+    for chunk in values.chunks_mut(group) {
+        // gather the low bits of each value and the packed trits or quints between them
+        let mut low = [0u32; 5];
+        let mut packed = 0;
+        let mut shift = 0;
+        for (i, &count) in packed_bits.iter().enumerate() {
+            low[i] = read_bits(block, position, bits, end) & mask;
+            position += bits;
+            packed |= read_bits(block, position, count, end) << shift;
+            position += count;
+            shift += count;
+        }
+
+        let high: [u32; 5] = match packing {
+            Packing::Bits => [0; 5],
+            Packing::Trits => decode_trits(packed),
+            Packing::Quints => {
+                let [q0, q1, q2] = decode_quints(packed);
+                [q0, q1, q2, 0, 0]
+            }
+        };
+
+        for (i, value) in chunk.iter_mut().enumerate() {
+            *value = (high[i] << bits | low[i]) as u8;
+        }
+
+        // a partial group only occupies the bits that belong to its values
+        if chunk.len() < group {
+            break;
+        }
+    }
+}
+
+/// Encodes a sequence of values at a quantisation level, starting at bit `position`
+pub fn encode(values: &[u8], level: usize, block: &mut u128, position: usize) {
+    let (packing, bits) = encoding(level);
+    let end = position + sequence_bits(values.len(), level);
+    let mask = (1 << bits) - 1;
+
+    let (group, packed_bits): (usize, &[usize]) = match packing {
+        Packing::Bits => (1, &[0]),
+        Packing::Trits => (5, &TRIT_BITS),
+        Packing::Quints => (3, &QUINT_BITS),
+    };
+
+    let mut position = position;
+    for chunk in values.chunks(group) {
+        // missing values of a partial group count as zero
+        let mut high = [0u32; 5];
+        let mut low = [0u32; 5];
+        for (i, &value) in chunk.iter().enumerate() {
+            high[i] = u32::from(value) >> bits;
+            low[i] = u32::from(value) & mask;
+        }
+
+        let packed = match packing {
+            Packing::Bits => 0,
+            Packing::Trits => encode_trits(high),
+            Packing::Quints => encode_quints([high[0], high[1], high[2]]),
+        };
+
+        let mut shift = 0;
+        for (i, &count) in packed_bits.iter().enumerate() {
+            write_bits(block, low[i], position, bits, end);
+            position += bits;
+            write_bits(block, packed >> shift, position, count, end);
+            position += count;
+            shift += count;
+        }
+    }
+}
+
+/// Repeats the lowest `bits` bits of a value until they fill `width` bits
+fn replicate(value: u32, bits: usize, width: usize) -> u32 {
+    let mut result = 0;
+    let mut filled = 0;
+    while filled < width {
+        result = (result << bits) | value;
+        filled += bits;
+    }
+    result >> (filled - width)
+}
+
+/// Unquantises a colour endpoint value to the range 0..255
+pub fn unquantise_colour(level: usize, value: u8) -> u8 {
+    let (packing, bits) = encoding(level);
+    let value = u32::from(value);
+    if packing == Packing::Bits {
+        return replicate(value, bits, 8) as u8;
+    }
+
+    // the trit or quint is scaled by C and the remaining low bits are scattered over B
+    let d = value >> bits;
+    let x = (value & ((1 << bits) - 1)) >> 1;
+    let a = if value & 1 == 1 { 0x1FF } else { 0 };
+    let (b, c) = match (packing, bits) {
+        (Packing::Trits, 1) => (0, 204),
+        (Packing::Trits, 2) => (x * 0x116, 93),
+        (Packing::Trits, 3) => (x << 7 | x << 2 | x, 44),
+        (Packing::Trits, 4) => (x << 6 | x, 22),
+        (Packing::Trits, 5) => (x << 5 | x >> 2, 11),
+        (Packing::Trits, 6) => (x << 4 | x >> 4, 5),
+        (Packing::Quints, 1) => (0, 113),
+        (Packing::Quints, 2) => (x * 0x10C, 54),
+        (Packing::Quints, 3) => (x << 7 | x << 1 | x >> 1, 26),
+        (Packing::Quints, 4) => (x << 6 | x >> 1, 13),
+        (Packing::Quints, 5) => (x << 5 | x >> 3, 6),
+        _ => unreachable!(),
+    };
+
+    let t = (d * c + b) ^ a;
+    ((a & 0x80) | (t >> 2)) as u8
+}
+
+/// Unquantises a weight to the range 0..64
+pub fn unquantise_weight(level: usize, value: u8) -> u8 {
+    let (packing, bits) = encoding(level);
+    let value = u32::from(value);
+
+    let result = match (packing, bits) {
+        (Packing::Bits, _) => replicate(value, bits, 6),
+        (Packing::Trits, 0) => [0, 32, 63][value as usize],
+        (Packing::Quints, 0) => [0, 16, 32, 47, 63][value as usize],
+        _ => {
+            let d = value >> bits;
+            let x = (value & ((1 << bits) - 1)) >> 1;
+            let a = if value & 1 == 1 { 0x7F } else { 0 };
+            let (b, c) = match (packing, bits) {
+                (Packing::Trits, 1) => (0, 50),
+                (Packing::Trits, 2) => (x * 0x45, 23),
+                (Packing::Trits, 3) => (x << 5 | x, 11),
+                (Packing::Quints, 1) => (0, 28),
+                (Packing::Quints, 2) => (x * 0x42, 13),
+                _ => unreachable!(),
+            };
+            let t = (d * c + b) ^ a;
+            (a & 0x20) | (t >> 2)
+        }
+    };
+
+    // stretch 0..63 to 0..64
+    if result > 32 {
+        result as u8 + 1
+    } else {
+        result as u8
+    }
+}
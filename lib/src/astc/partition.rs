@@ -0,0 +1,117 @@
+// Copyright (c) 2018-2021 Jan Solanti <jhs@psonet.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to	deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be included
+// in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.
+// IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT,
+// TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION WITH THE
+// SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Procedural partition patterns
+//!
+//! ASTC doesn't store partition tables. Instead the partition of a texel is computed from a
+//! 10-bit seed, the partition count and the texel coordinates with a hash function.
+
+/// Hash function used to expand the partition seed
+fn hash52(mut value: u32) -> u32 {
+    value ^= value >> 15;
+    value = value.wrapping_mul(0xEEDE_0891);
+    value ^= value >> 5;
+    value = value.wrapping_add(value << 16);
+    value ^= value >> 7;
+    value ^= value >> 3;
+    value ^= value << 6;
+    value ^= value >> 17;
+    value
+}
+
+/// Per-seed coefficients of the partition function, ready to be evaluated for any texel
+#[derive(Clone, Copy, Debug)]
+pub struct Pattern {
+    slopes: [[u32; 2]; 4],
+    offsets: [u32; 4],
+    count: usize,
+    small: bool,
+}
+
+impl Pattern {
+    /// Sets up the pattern of a seed for blocks with `texels` texels
+    pub fn new(seed: u32, count: usize, texels: usize) -> Self {
+        let seed = seed + (count as u32 - 1) * 1024;
+        let random = hash52(seed);
+
+        // eight 4-bit values, squared; the remaining ones only affect 3D blocks
+        let mut seeds = [0u32; 8];
+        for (i, value) in seeds.iter_mut().enumerate() {
+            let bits = (random >> (4 * i)) & 0xF;
+            *value = bits * bits;
+        }
+
+        let (sh1, sh2) = if seed & 1 == 1 {
+            (
+                if seed & 2 != 0 { 4 } else { 5 },
+                if count == 3 { 6 } else { 5 },
+            )
+        } else {
+            (
+                if count == 3 { 6 } else { 5 },
+                if seed & 2 != 0 { 4 } else { 5 },
+            )
+        };
+
+        // only the x and y terms matter for 2D blocks
+        let slopes = [
+            [seeds[0] >> sh1, seeds[1] >> sh2],
+            [seeds[2] >> sh1, seeds[3] >> sh2],
+            [seeds[4] >> sh1, seeds[5] >> sh2],
+            [seeds[6] >> sh1, seeds[7] >> sh2],
+        ];
+        let offsets = [random >> 14, random >> 10, random >> 6, random >> 2];
+
+        Pattern {
+            slopes,
+            offsets,
+            count,
+            small: texels < 31,
+        }
+    }
+
+    /// Returns the partition of the texel at the given coordinates
+    pub fn partition(&self, x: usize, y: usize) -> usize {
+        let (x, y) = if self.small {
+            (2 * x as u32, 2 * y as u32)
+        } else {
+            (x as u32, y as u32)
+        };
+
+        let mut values = [0u32; 4];
+        for (i, value) in values.iter_mut().enumerate().take(self.count) {
+            let [sx, sy] = self.slopes[i];
+            *value = (sx * x + sy * y + self.offsets[i]) & 0x3F;
+        }
+
+        // pick the partition with the largest value, preferring the lower index on ties
+        let [a, b, c, d] = values;
+        if a >= b && a >= c && a >= d {
+            0
+        } else if b >= c && b >= d {
+            1
+        } else if c >= d {
+            2
+        } else {
+            3
+        }
+    }
+}
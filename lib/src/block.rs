@@ -0,0 +1,173 @@
+// Copyright (c) 2018-2021 Jan Solanti <jhs@psonet.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to	deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be included
+// in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.
+// IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT,
+// TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION WITH THE
+// SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Block types of the formats made up of several blocks of other formats
+//!
+//! BC2, BC3 and ETC2 RGBA blocks store their alpha half first, BC5 and RG11 blocks their red
+//! half.
+
+use crate::alpha::Bc4Block;
+use crate::colourblock::Bc1Block;
+use crate::eac::EacBlock;
+use crate::etc::Etc2Block;
+
+/// The fields of a BC2 block
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Bc2Block {
+    /// 4-bit alpha values in row-major pixel order
+    pub alpha: [u8; 16],
+    pub colour: Bc1Block,
+}
+
+impl Bc2Block {
+    /// Unpacks the fields of a block
+    ///
+    /// Panics if `bytes` holds fewer than 16 bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        let mut alpha = [0u8; 16];
+        for (i, value) in alpha.iter_mut().enumerate() {
+            *value = (bytes[i / 2] >> (4 * (i % 2))) & 0x0F;
+        }
+
+        Bc2Block {
+            alpha,
+            colour: Bc1Block::from_bytes(&bytes[8..16]),
+        }
+    }
+
+    /// Packs the fields of a block
+    pub fn to_bytes(self) -> [u8; 16] {
+        let mut block = [0u8; 16];
+        for (i, &value) in self.alpha.iter().enumerate() {
+            block[i / 2] |= (value & 0x0F) << (4 * (i % 2));
+        }
+        block[8..].copy_from_slice(&self.colour.to_bytes());
+        block
+    }
+}
+
+/// The fields of a BC3 block
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Bc3Block {
+    pub alpha: Bc4Block,
+    pub colour: Bc1Block,
+}
+
+impl Bc3Block {
+    /// Unpacks the fields of a block
+    ///
+    /// Panics if `bytes` holds fewer than 16 bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        Bc3Block {
+            alpha: Bc4Block::from_bytes(&bytes[..8]),
+            colour: Bc1Block::from_bytes(&bytes[8..16]),
+        }
+    }
+
+    /// Packs the fields of a block
+    pub fn to_bytes(self) -> [u8; 16] {
+        let mut block = [0u8; 16];
+        block[..8].copy_from_slice(&self.alpha.to_bytes());
+        block[8..].copy_from_slice(&self.colour.to_bytes());
+        block
+    }
+}
+
+/// The fields of a BC5 block, unsigned or signed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Bc5Block {
+    pub red: Bc4Block,
+    pub green: Bc4Block,
+}
+
+impl Bc5Block {
+    /// Unpacks the fields of a block
+    ///
+    /// Panics if `bytes` holds fewer than 16 bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        Bc5Block {
+            red: Bc4Block::from_bytes(&bytes[..8]),
+            green: Bc4Block::from_bytes(&bytes[8..16]),
+        }
+    }
+
+    /// Packs the fields of a block
+    pub fn to_bytes(self) -> [u8; 16] {
+        let mut block = [0u8; 16];
+        block[..8].copy_from_slice(&self.red.to_bytes());
+        block[8..].copy_from_slice(&self.green.to_bytes());
+        block
+    }
+}
+
+/// The fields of an ETC2 RGBA block
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Etc2RgbaBlock {
+    pub alpha: EacBlock,
+    pub colour: Etc2Block,
+}
+
+impl Etc2RgbaBlock {
+    /// Unpacks the fields of a block
+    ///
+    /// Panics if `bytes` holds fewer than 16 bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        Etc2RgbaBlock {
+            alpha: EacBlock::from_bytes(&bytes[..8]),
+            colour: Etc2Block::from_bytes(&bytes[8..16], false),
+        }
+    }
+
+    /// Packs the fields of a block
+    pub fn to_bytes(self) -> [u8; 16] {
+        let mut block = [0u8; 16];
+        block[..8].copy_from_slice(&self.alpha.to_bytes());
+        block[8..].copy_from_slice(&self.colour.to_bytes());
+        block
+    }
+}
+
+/// The fields of an EAC RG11 block, unsigned or signed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct EacRg11Block {
+    pub red: EacBlock,
+    pub green: EacBlock,
+}
+
+impl EacRg11Block {
+    /// Unpacks the fields of a block
+    ///
+    /// Panics if `bytes` holds fewer than 16 bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        EacRg11Block {
+            red: EacBlock::from_bytes(&bytes[..8]),
+            green: EacBlock::from_bytes(&bytes[8..16]),
+        }
+    }
+
+    /// Packs the fields of a block
+    pub fn to_bytes(self) -> [u8; 16] {
+        let mut block = [0u8; 16];
+        block[..8].copy_from_slice(&self.red.to_bytes());
+        block[8..].copy_from_slice(&self.green.to_bytes());
+        block
+    }
+}
@@ -0,0 +1,87 @@
+// Copyright (c) 2018-2021 Jan Solanti <jhs@psonet.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to	deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be included
+// in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.
+// IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT,
+// TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION WITH THE
+// SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Block Partition Texture Compression (BC6H and BC7)
+
+mod bc6h;
+mod bc7;
+mod fit;
+mod tables;
+
+pub use self::bc6h::{compress_bc6h, decompress_bc6h, Bc6hBlock, Bc6hFields};
+pub use self::bc7::{compress_bc7, decompress_bc7, Bc7Block, Bc7Fields};
+
+/// Reads little endian bit fields from a 128-bit block
+pub struct BitReader {
+    bits: u128,
+    position: usize,
+}
+
+impl BitReader {
+    pub fn new(bytes: &[u8]) -> Self {
+        let mut tmp = [0u8; 16];
+        tmp.copy_from_slice(&bytes[..16]);
+        BitReader {
+            bits: u128::from_le_bytes(tmp),
+            position: 0,
+        }
+    }
+
+    pub fn read(&mut self, count: usize) -> u32 {
+        if count == 0 {
+            return 0;
+        }
+
+        let value = (self.bits >> self.position) as u32 & (u32::MAX >> (32 - count));
+        self.position += count;
+        value
+    }
+}
+
+/// Writes little endian bit fields into a 128-bit block
+pub struct BitWriter {
+    bits: u128,
+    position: usize,
+}
+
+impl BitWriter {
+    pub fn new() -> Self {
+        BitWriter {
+            bits: 0,
+            position: 0,
+        }
+    }
+
+    pub fn write(&mut self, value: u32, count: usize) {
+        if count == 0 {
+            return;
+        }
+
+        let value = value & (u32::MAX >> (32 - count));
+        self.bits |= u128::from(value) << self.position;
+        self.position += count;
+    }
+
+    pub fn into_bytes(self) -> [u8; 16] {
+        debug_assert!(self.position == 128);
+        self.bits.to_le_bytes()
+    }
+}
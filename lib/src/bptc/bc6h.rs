@@ -0,0 +1,694 @@
+// Copyright (c) 2018-2021 Jan Solanti <jhs@psonet.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to	deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be included
+// in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.
+// IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT,
+// TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION WITH THE
+// SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+use super::fit::{self, Pixels};
+use super::tables;
+use super::{BitReader, BitWriter};
+use crate::math::f32_to_f16;
+use crate::{Algorithm, Params};
+
+// endpoint fields as named in the format specification: endpoints 0 and 1 belong to the
+// first region and endpoints 2 and 3 to the second, d is the partition
+const R0: u8 = 0;
+const G0: u8 = 1;
+const B0: u8 = 2;
+const R1: u8 = 3;
+const G1: u8 = 4;
+const B1: u8 = 5;
+const R2: u8 = 6;
+const G2: u8 = 7;
+const B2: u8 = 8;
+const R3: u8 = 9;
+const G3: u8 = 10;
+const B3: u8 = 11;
+const D: u8 = 12;
+
+/// Bit allocation of a BC6H mode
+struct ModeInfo {
+    code: u8,
+    code_bits: usize,
+    regions: usize,
+    transformed: bool,
+    endpoint_bits: usize,
+    delta_bits: [usize; 3],
+    /// Fields following the mode bits, where `(field, a, b)` stands for `field[a:b]` in the
+    /// specification. Bits are stored in order from `b` to `a`.
+    layout: &'static [(u8, u8, u8)],
+}
+
+#[rustfmt::skip]
+const MODES: [ModeInfo; 14] = [
+    // mode 1
+    ModeInfo { code: 0b00, code_bits: 2, regions: 2, transformed: true, endpoint_bits: 10, delta_bits: [5, 5, 5], layout: &[
+        (G2, 4, 4), (B2, 4, 4), (B3, 4, 4), (R0, 9, 0), (G0, 9, 0), (B0, 9, 0), (R1, 4, 0), (G3, 4, 4),
+        (G2, 3, 0), (G1, 4, 0), (B3, 0, 0), (G3, 3, 0), (B1, 4, 0), (B3, 1, 1), (B2, 3, 0), (R2, 4, 0),
+        (B3, 2, 2), (R3, 4, 0), (B3, 3, 3), (D, 4, 0),
+    ] },
+    // mode 2
+    ModeInfo { code: 0b01, code_bits: 2, regions: 2, transformed: true, endpoint_bits: 7, delta_bits: [6, 6, 6], layout: &[
+        (G2, 5, 5), (G3, 4, 4), (G3, 5, 5), (R0, 6, 0), (B3, 0, 0), (B3, 1, 1), (B2, 4, 4), (G0, 6, 0),
+        (B2, 5, 5), (B3, 2, 2), (G2, 4, 4), (B0, 6, 0), (B3, 3, 3), (B3, 5, 5), (B3, 4, 4), (R1, 5, 0),
+        (G2, 3, 0), (G1, 5, 0), (G3, 3, 0), (B1, 5, 0), (B2, 3, 0), (R2, 5, 0), (R3, 5, 0), (D, 4, 0),
+    ] },
+    // mode 3
+    ModeInfo { code: 0b00010, code_bits: 5, regions: 2, transformed: true, endpoint_bits: 11, delta_bits: [5, 4, 4], layout: &[
+        (R0, 9, 0), (G0, 9, 0), (B0, 9, 0), (R1, 4, 0), (R0, 10, 10), (G2, 3, 0), (G1, 3, 0), (G0, 10, 10),
+        (B3, 0, 0), (G3, 3, 0), (B1, 3, 0), (B0, 10, 10), (B3, 1, 1), (B2, 3, 0), (R2, 4, 0), (B3, 2, 2),
+        (R3, 4, 0), (B3, 3, 3), (D, 4, 0),
+    ] },
+    // mode 4
+    ModeInfo { code: 0b00110, code_bits: 5, regions: 2, transformed: true, endpoint_bits: 11, delta_bits: [4, 5, 4], layout: &[
+        (R0, 9, 0), (G0, 9, 0), (B0, 9, 0), (R1, 3, 0), (R0, 10, 10), (G3, 4, 4), (G2, 3, 0), (G1, 4, 0),
+        (G0, 10, 10), (G3, 3, 0), (B1, 3, 0), (B0, 10, 10), (B3, 1, 1), (B2, 3, 0), (R2, 3, 0), (B3, 0, 0),
+        (B3, 2, 2), (R3, 3, 0), (G2, 4, 4), (B3, 3, 3), (D, 4, 0),
+    ] },
+    // mode 5
+    ModeInfo { code: 0b01010, code_bits: 5, regions: 2, transformed: true, endpoint_bits: 11, delta_bits: [4, 4, 5], layout: &[
+        (R0, 9, 0), (G0, 9, 0), (B0, 9, 0), (R1, 3, 0), (R0, 10, 10), (B2, 4, 4), (G2, 3, 0), (G1, 3, 0),
+        (G0, 10, 10), (B3, 0, 0), (G3, 3, 0), (B1, 4, 0), (B0, 10, 10), (B2, 3, 0), (R2, 3, 0), (B3, 1, 1),
+        (B3, 2, 2), (R3, 3, 0), (B3, 4, 4), (B3, 3, 3), (D, 4, 0),
+    ] },
+    // mode 6
+    ModeInfo { code: 0b01110, code_bits: 5, regions: 2, transformed: true, endpoint_bits: 9, delta_bits: [5, 5, 5], layout: &[
+        (R0, 8, 0), (B2, 4, 4), (G0, 8, 0), (G2, 4, 4), (B0, 8, 0), (B3, 4, 4), (R1, 4, 0), (G3, 4, 4),
+        (G2, 3, 0), (G1, 4, 0), (B3, 0, 0), (G3, 3, 0), (B1, 4, 0), (B3, 1, 1), (B2, 3, 0), (R2, 4, 0),
+        (B3, 2, 2), (R3, 4, 0), (B3, 3, 3), (D, 4, 0),
+    ] },
+    // mode 7
+    ModeInfo { code: 0b10010, code_bits: 5, regions: 2, transformed: true, endpoint_bits: 8, delta_bits: [6, 5, 5], layout: &[
+        (R0, 7, 0), (G3, 4, 4), (B2, 4, 4), (G0, 7, 0), (B3, 2, 2), (G2, 4, 4), (B0, 7, 0), (B3, 3, 3),
+        (B3, 4, 4), (R1, 5, 0), (G2, 3, 0), (G1, 4, 0), (B3, 0, 0), (G3, 3, 0), (B1, 4, 0), (B3, 1, 1),
+        (B2, 3, 0), (R2, 5, 0), (R3, 5, 0), (D, 4, 0),
+    ] },
+    // mode 8
+    ModeInfo { code: 0b10110, code_bits: 5, regions: 2, transformed: true, endpoint_bits: 8, delta_bits: [5, 6, 5], layout: &[
+        (R0, 7, 0), (B3, 0, 0), (B2, 4, 4), (G0, 7, 0), (G2, 5, 5), (G2, 4, 4), (B0, 7, 0), (G3, 5, 5),
+        (B3, 4, 4), (R1, 4, 0), (G3, 4, 4), (G2, 3, 0), (G1, 5, 0), (G3, 3, 0), (B1, 4, 0), (B3, 1, 1),
+        (B2, 3, 0), (R2, 4, 0), (B3, 2, 2), (R3, 4, 0), (B3, 3, 3), (D, 4, 0),
+    ] },
+    // mode 9
+    ModeInfo { code: 0b11010, code_bits: 5, regions: 2, transformed: true, endpoint_bits: 8, delta_bits: [5, 5, 6], layout: &[
+        (R0, 7, 0), (B3, 1, 1), (B2, 4, 4), (G0, 7, 0), (B2, 5, 5), (G2, 4, 4), (B0, 7, 0), (B3, 5, 5),
+        (B3, 4, 4), (R1, 4, 0), (G3, 4, 4), (G2, 3, 0), (G1, 4, 0), (B3, 0, 0), (G3, 3, 0), (B1, 5, 0),
+        (B2, 3, 0), (R2, 4, 0), (B3, 2, 2), (R3, 4, 0), (B3, 3, 3), (D, 4, 0),
+    ] },
+    // mode 10
+    ModeInfo { code: 0b11110, code_bits: 5, regions: 2, transformed: false, endpoint_bits: 6, delta_bits: [6, 6, 6], layout: &[
+        (R0, 5, 0), (G3, 4, 4), (B3, 0, 0), (B3, 1, 1), (B2, 4, 4), (G0, 5, 0), (G2, 5, 5), (B2, 5, 5),
+        (B3, 2, 2), (G2, 4, 4), (B0, 5, 0), (G3, 5, 5), (B3, 3, 3), (B3, 5, 5), (B3, 4, 4), (R1, 5, 0),
+        (G2, 3, 0), (G1, 5, 0), (G3, 3, 0), (B1, 5, 0), (B2, 3, 0), (R2, 5, 0), (R3, 5, 0), (D, 4, 0),
+    ] },
+    // mode 11
+    ModeInfo { code: 0b00011, code_bits: 5, regions: 1, transformed: false, endpoint_bits: 10, delta_bits: [10, 10, 10], layout: &[
+        (R0, 9, 0), (G0, 9, 0), (B0, 9, 0), (R1, 9, 0), (G1, 9, 0), (B1, 9, 0),
+    ] },
+    // mode 12
+    ModeInfo { code: 0b00111, code_bits: 5, regions: 1, transformed: true, endpoint_bits: 11, delta_bits: [9, 9, 9], layout: &[
+        (R0, 9, 0), (G0, 9, 0), (B0, 9, 0), (R1, 8, 0), (R0, 10, 10), (G1, 8, 0), (G0, 10, 10), (B1, 8, 0),
+        (B0, 10, 10),
+    ] },
+    // mode 13
+    ModeInfo { code: 0b01011, code_bits: 5, regions: 1, transformed: true, endpoint_bits: 12, delta_bits: [8, 8, 8], layout: &[
+        (R0, 9, 0), (G0, 9, 0), (B0, 9, 0), (R1, 7, 0), (R0, 10, 11), (G1, 7, 0), (G0, 10, 11), (B1, 7, 0),
+        (B0, 10, 11),
+    ] },
+    // mode 14
+    ModeInfo { code: 0b01111, code_bits: 5, regions: 1, transformed: true, endpoint_bits: 16, delta_bits: [4, 4, 4], layout: &[
+        (R0, 9, 0), (G0, 9, 0), (B0, 9, 0), (R1, 3, 0), (R0, 10, 15), (G1, 3, 0), (G0, 10, 15), (B1, 3, 0),
+        (B0, 10, 15),
+    ] },
+];
+
+/// The fields of a BC6H block in one of its modes
+///
+/// Endpoints are stored as they appear in the block, i.e. the second to fourth endpoints
+/// of transformed modes are deltas from the first one. Modes are numbered from zero, one less
+/// than in the format specification.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Bc6hFields {
+    pub mode: u8,
+    pub partition: u8,
+    pub endpoints: [[u16; 3]; 4],
+    pub indices: [u8; 16],
+}
+
+/// A BC6H block, in one of the fourteen modes or in one of the reserved modes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bc6hBlock {
+    Mode(Bc6hFields),
+    /// A block of the reserved 5-bit mode codes, which decodes to black. It is kept as is,
+    /// since its other bits have no meaning.
+    Reserved([u8; 16]),
+}
+
+impl Bc6hBlock {
+    /// Unpacks the fields of a block
+    ///
+    /// Panics if `bytes` holds fewer than 16 bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        match Bc6hFields::from_bytes(bytes) {
+            Some(fields) => Bc6hBlock::Mode(fields),
+            None => Bc6hBlock::Reserved(bytes[..16].try_into().unwrap()),
+        }
+    }
+
+    /// Packs the fields of a block
+    pub fn to_bytes(self) -> [u8; 16] {
+        match self {
+            Bc6hBlock::Mode(fields) => fields.to_bytes(),
+            Bc6hBlock::Reserved(bytes) => bytes,
+        }
+    }
+}
+
+/// Sign extends the lowest `bits` bits of a value
+fn sign_extend(value: i32, bits: usize) -> i32 {
+    let shift = 32 - bits;
+    (value << shift) >> shift
+}
+
+/// Expands an endpoint channel to the 16-bit range used for interpolation
+fn unquantise(value: i32, bits: usize, signed: bool) -> i32 {
+    if !signed {
+        if bits >= 15 || value == 0 {
+            value
+        } else if value == (1 << bits) - 1 {
+            0xFFFF
+        } else {
+            ((value << 16) + 0x8000) >> bits
+        }
+    } else {
+        if bits >= 16 || value == 0 {
+            return value;
+        }
+
+        let magnitude = value.abs();
+        let unquantised = if magnitude >= (1 << (bits - 1)) - 1 {
+            0x7FFF
+        } else {
+            ((magnitude << 15) + 0x4000) >> (bits - 1)
+        };
+        if value < 0 {
+            -unquantised
+        } else {
+            unquantised
+        }
+    }
+}
+
+/// Finds the endpoint channel value that expands closest to `value`
+fn quantise(value: f32, bits: usize, signed: bool) -> i32 {
+    let (min, max, scale) = if signed {
+        let max = (1 << (bits - 1)) - 1;
+        (-max, max, (1 << (bits - 1)) as f32 / 32768.0)
+    } else {
+        (0, (1 << bits) - 1, (1 << bits) as f32 / 65536.0)
+    };
+    let estimate = libm::roundf(value * scale) as i32;
+
+    let mut best = 0;
+    let mut least = f32::MAX;
+    for candidate in (estimate - 1).clamp(min, max)..=(estimate + 1).clamp(min, max) {
+        let error = (unquantise(candidate, bits, signed) as f32 - value).abs();
+        if error < least {
+            least = error;
+            best = candidate;
+        }
+    }
+
+    best
+}
+
+/// Interpolates between two unquantised endpoint values
+fn interpolate(e0: i32, e1: i32, weight: u8) -> i32 {
+    let weight = i32::from(weight);
+    ((64 - weight) * e0 + weight * e1 + 32) >> 6
+}
+
+/// Scales an interpolated value to a half float
+fn finish_unquantise(value: i32, signed: bool) -> u16 {
+    if !signed {
+        ((value * 31) >> 6) as u16
+    } else if value < 0 {
+        0x8000 | (((-value * 31) >> 5) as u16)
+    } else {
+        ((value * 31) >> 5) as u16
+    }
+}
+
+/// Yields the bit positions of a layout field in storage order
+fn field_bits(a: u8, b: u8) -> impl Iterator<Item = u8> {
+    let (low, high) = if a >= b { (b, a) } else { (a, b) };
+    let reversed = a < b;
+    (low..=high).map(move |bit| if reversed { low + high - bit } else { bit })
+}
+
+impl Bc6hFields {
+    fn info(&self) -> &'static ModeInfo {
+        &MODES[usize::from(self.mode)]
+    }
+
+    fn index_bits(&self) -> usize {
+        if self.info().regions == 2 {
+            3
+        } else {
+            4
+        }
+    }
+
+    fn region(&self, pixel: usize) -> usize {
+        tables::subset(self.info().regions, usize::from(self.partition), pixel)
+    }
+
+    fn is_anchor(&self, pixel: usize) -> bool {
+        let regions = self.info().regions;
+        tables::anchor(regions, usize::from(self.partition), self.region(pixel)) == pixel
+    }
+
+    /// Unpacks the fields of a block, returns `None` for the reserved modes
+    ///
+    /// Panics if `bytes` holds fewer than 16 bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let mut reader = BitReader::new(bytes);
+
+        // modes 1 and 2 have two mode bits, the others five
+        let mut code = reader.read(2);
+        if code > 1 {
+            code |= reader.read(3) << 2;
+        }
+        let mode = MODES.iter().position(|info| u32::from(info.code) == code)?;
+
+        let mut block = Bc6hFields {
+            mode: mode as u8,
+            ..Default::default()
+        };
+        for &(field, a, b) in MODES[mode].layout {
+            for bit in field_bits(a, b) {
+                let value = reader.read(1) as u16;
+                if field == D {
+                    block.partition |= (value as u8) << bit;
+                } else {
+                    block.endpoints[usize::from(field / 3)][usize::from(field % 3)] |= value << bit;
+                }
+            }
+        }
+
+        let index_bits = block.index_bits();
+        for i in 0..16 {
+            // anchor indices have an implicit zero high bit
+            let bits = if block.is_anchor(i) {
+                index_bits - 1
+            } else {
+                index_bits
+            };
+            block.indices[i] = reader.read(bits) as u8;
+        }
+
+        Some(block)
+    }
+
+    /// Packs the fields of a block
+    ///
+    /// Panics if the mode is above 13.
+    pub fn to_bytes(self) -> [u8; 16] {
+        let info = self.info();
+        let mut writer = BitWriter::new();
+
+        writer.write(u32::from(info.code), info.code_bits);
+        for &(field, a, b) in info.layout {
+            let value = if field == D {
+                u16::from(self.partition)
+            } else {
+                self.endpoints[usize::from(field / 3)][usize::from(field % 3)]
+            };
+            for bit in field_bits(a, b) {
+                writer.write(u32::from(value >> bit), 1);
+            }
+        }
+
+        let index_bits = self.index_bits();
+        for i in 0..16 {
+            let bits = if self.is_anchor(i) {
+                index_bits - 1
+            } else {
+                index_bits
+            };
+            writer.write(u32::from(self.indices[i]), bits);
+        }
+
+        writer.into_bytes()
+    }
+
+    /// Returns the endpoints expanded to the 16-bit interpolation range
+    fn unquantised_endpoints(&self, signed: bool) -> [[i32; 3]; 4] {
+        let info = self.info();
+        let bits = info.endpoint_bits;
+        let mask = (1 << bits) - 1;
+
+        let extend = |value: i32| {
+            if signed {
+                sign_extend(value, bits)
+            } else {
+                value
+            }
+        };
+
+        // undo the delta transform
+        let mut quantised = [[0i32; 3]; 4];
+        quantised[0] = self.endpoints[0].map(|value| extend(i32::from(value)));
+        let base = quantised[0];
+        let count = 2 * info.regions;
+        for (quantised, raw) in quantised.iter_mut().zip(self.endpoints).take(count).skip(1) {
+            for (c, value) in quantised.iter_mut().enumerate() {
+                *value = if info.transformed {
+                    let delta = sign_extend(i32::from(raw[c]), info.delta_bits[c]);
+                    extend((base[c] + delta) & mask)
+                } else {
+                    extend(i32::from(raw[c]))
+                };
+            }
+        }
+
+        quantised.map(|endpoint| endpoint.map(|value| unquantise(value, bits, signed)))
+    }
+
+    /// Decodes the block to half float RGB pixels
+    pub fn decode(&self, signed: bool) -> [[u16; 3]; 16] {
+        let endpoints = self.unquantised_endpoints(signed);
+        let weights = tables::weights(self.index_bits());
+
+        let mut rgb = [[0u16; 3]; 16];
+        for (i, pixel) in rgb.iter_mut().enumerate() {
+            let region = self.region(i);
+            let weight = weights[usize::from(self.indices[i])];
+            for (c, value) in pixel.iter_mut().enumerate() {
+                let e0 = endpoints[2 * region][c];
+                let e1 = endpoints[2 * region + 1][c];
+                *value = finish_unquantise(interpolate(e0, e1, weight), signed);
+            }
+        }
+
+        rgb
+    }
+}
+
+//--------------------------------------------------------------------------------
+// Compression
+//--------------------------------------------------------------------------------
+
+/// Search effort for the different compression algorithms
+struct Effort {
+    partitions: usize,
+    refinements: usize,
+}
+
+impl Effort {
+    fn new(algorithm: Algorithm) -> Self {
+        match algorithm {
+            Algorithm::RangeFit => Effort {
+                partitions: 1,
+                refinements: 0,
+            },
+            Algorithm::ClusterFit => Effort {
+                partitions: 4,
+                refinements: 1,
+            },
+            Algorithm::IterativeClusterFit => Effort {
+                partitions: 32,
+                refinements: 3,
+            },
+        }
+    }
+}
+
+/// Maps a half float to the 16-bit range in which endpoints are interpolated
+fn expand_half(half: u16, signed: bool) -> f32 {
+    let magnitude = half & 0x7FFF;
+    if magnitude > 0x7C00 {
+        // NaN
+        return 0.0;
+    }
+
+    // clamp infinity to the largest finite value
+    let magnitude = f32::from(magnitude.min(0x7BFF));
+    let negative = (half & 0x8000) != 0;
+    match (signed, negative) {
+        (false, true) => 0.0,
+        (false, false) => (magnitude * 64.0 / 31.0).min(65535.0),
+        (true, true) => -(magnitude * 32.0 / 31.0).min(32767.0),
+        (true, false) => (magnitude * 32.0 / 31.0).min(32767.0),
+    }
+}
+
+/// Loads the pixels of a block in the interpolation range
+fn load_pixels(rgb: &[[f32; 4]; 16], mask: u32, signed: bool, params: &Params) -> Pixels {
+    let mut pixels = Pixels {
+        values: [[0.0; 4]; 16],
+        weights: [[0.0; 4]; 16],
+        range: if signed {
+            (-32767.0, 32767.0)
+        } else {
+            (0.0, 65535.0)
+        },
+    };
+
+    let sum: f32 = params.weights.iter().sum();
+    let scale = if sum > 0.0 { 3.0 / sum } else { 1.0 };
+
+    for (i, pixel) in rgb.iter().enumerate() {
+        if (mask & (1 << i)) == 0 {
+            continue;
+        }
+
+        for (c, &value) in pixel.iter().take(3).enumerate() {
+            pixels.values[i][c] = expand_half(f32_to_f16(value), signed);
+            pixels.weights[i][c] = params.weights[c] * scale;
+        }
+    }
+
+    pixels
+}
+
+/// Packs quantised endpoints into the fields of a mode, clamping deltas that don't fit
+fn pack_endpoints(info: &ModeInfo, quantised: &[[[i32; 3]; 2]; 2]) -> [[u16; 3]; 4] {
+    let bits = info.endpoint_bits;
+    let mut packed = [[0u16; 3]; 4];
+
+    for endpoint in 0..2 * info.regions {
+        for c in 0..3 {
+            let value = quantised[endpoint / 2][endpoint % 2][c];
+            packed[endpoint][c] = if endpoint == 0 || !info.transformed {
+                (value & ((1 << bits) - 1)) as u16
+            } else {
+                let delta_bits = info.delta_bits[c];
+                let limit = 1 << (delta_bits - 1);
+                let delta = (value - quantised[0][0][c]).clamp(-limit, limit - 1);
+                (delta & ((1 << delta_bits) - 1)) as u16
+            };
+        }
+    }
+
+    packed
+}
+
+/// Assigns indices to the pixels of a block and returns the total error
+///
+/// With `restrict_anchors` set the anchor pixels only use indices with a zero high bit,
+/// otherwise the regions whose anchor needs a swap of endpoints are returned as a bit mask.
+fn assign_indices(
+    pixels: &Pixels,
+    block: &mut Bc6hFields,
+    signed: bool,
+    restrict_anchors: bool,
+) -> (f32, u32) {
+    let endpoints = block.unquantised_endpoints(signed);
+    let weights = tables::weights(block.index_bits());
+
+    let mut palettes = [[[0f32; 3]; 16]; 2];
+    for (region, palette) in palettes.iter_mut().enumerate() {
+        for (entry, &weight) in palette.iter_mut().zip(weights) {
+            for (c, value) in entry.iter_mut().enumerate() {
+                let e0 = endpoints[2 * region][c];
+                let e1 = endpoints[2 * region + 1][c];
+                *value = interpolate(e0, e1, weight) as f32;
+            }
+        }
+    }
+
+    let mut total = 0.0;
+    let mut swaps = 0;
+    for i in 0..16 {
+        let region = block.region(i);
+        let anchor = block.is_anchor(i);
+        let count = if restrict_anchors && anchor {
+            weights.len() / 2
+        } else {
+            weights.len()
+        };
+
+        let mut least = f32::MAX;
+        let mut index = 0;
+        for (j, entry) in palettes[region].iter().enumerate().take(count) {
+            let mut error = 0.0;
+            for (c, value) in entry.iter().enumerate() {
+                let d = pixels.values[i][c] - value;
+                error += pixels.weights[i][c] * d * d;
+            }
+            if error < least {
+                least = error;
+                index = j;
+            }
+        }
+
+        if anchor && index >= weights.len() / 2 {
+            swaps |= 1 << region;
+        }
+        block.indices[i] = index as u8;
+        total += least;
+    }
+
+    (total, swaps)
+}
+
+/// Compresses a block with the given mode and partition
+fn compress_mode(
+    pixels: &Pixels,
+    mode: usize,
+    partition: usize,
+    signed: bool,
+    effort: &Effort,
+) -> (Bc6hFields, f32) {
+    let info = &MODES[mode];
+    let mut block = Bc6hFields {
+        mode: mode as u8,
+        partition: partition as u8,
+        ..Default::default()
+    };
+    let index_bits = block.index_bits();
+
+    let mut members = [0u16; 2];
+    let mut endpoints = [[[0f32; 4]; 2]; 2];
+    for region in 0..info.regions {
+        members[region] = fit::subset_members(info.regions, partition, region);
+        endpoints[region] = fit::principal_endpoints(pixels, members[region], 0..3);
+    }
+
+    let mut best = block;
+    let mut best_error = f32::MAX;
+    for iteration in 0..=effort.refinements {
+        let mut quantised = [[[0i32; 3]; 2]; 2];
+        for region in 0..info.regions {
+            for e in 0..2 {
+                for c in 0..3 {
+                    let value = endpoints[region][e][c];
+                    quantised[region][e][c] = quantise(value, info.endpoint_bits, signed);
+                }
+            }
+        }
+
+        // swap endpoints where the anchor index would need its high bit, and if the
+        // clamped deltas still disagree restrict the anchors to the lower half
+        block.endpoints = pack_endpoints(info, &quantised);
+        let (mut error, swaps) = assign_indices(pixels, &mut block, signed, false);
+        if swaps != 0 {
+            for (region, endpoints) in quantised.iter_mut().enumerate() {
+                if (swaps & (1 << region)) != 0 {
+                    endpoints.swap(0, 1);
+                }
+            }
+            block.endpoints = pack_endpoints(info, &quantised);
+            error = assign_indices(pixels, &mut block, signed, true).0;
+        }
+
+        if error < best_error {
+            best = block;
+            best_error = error;
+        } else {
+            break;
+        }
+
+        if iteration == effort.refinements || best_error == 0.0 {
+            break;
+        }
+        let mut changed = false;
+        for region in 0..info.regions {
+            changed |= fit::refine_endpoints(
+                pixels,
+                members[region],
+                0..3,
+                &block.indices,
+                index_bits,
+                &mut endpoints[region],
+            );
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    (best, best_error)
+}
+
+/// Compresses a 4x4 block of pixels to BC6H, ignoring alpha
+pub fn compress_bc6h(
+    rgb: &[[f32; 4]; 16],
+    mask: u32,
+    signed: bool,
+    params: &Params,
+    block: &mut [u8],
+) {
+    let pixels = load_pixels(rgb, mask, signed, params);
+    let effort = Effort::new(params.algorithm);
+
+    let (order, count) = fit::rank_partitions(&pixels, 2, 32, 0..3);
+
+    let mut best = Bc6hFields::default();
+    let mut best_error = f32::MAX;
+    for (mode, info) in MODES.iter().enumerate() {
+        let partitions = if info.regions == 2 {
+            &order[..count.min(effort.partitions)]
+        } else {
+            &[0][..]
+        };
+
+        for &partition in partitions {
+            let (candidate, error) =
+                compress_mode(&pixels, mode, usize::from(partition), signed, &effort);
+            if error < best_error {
+                best = candidate;
+                best_error = error;
+            }
+        }
+    }
+
+    block.copy_from_slice(&best.to_bytes());
+}
+
+/// Decompresses a BC6H block to 4x4 half float RGBA pixels with an alpha of one
+pub fn decompress_bc6h(bytes: &[u8], signed: bool) -> [[u16; 4]; 16] {
+    assert!(bytes.len() == 16);
+
+    match Bc6hFields::from_bytes(bytes) {
+        Some(block) => block.decode(signed).map(|[r, g, b]| [r, g, b, 0x3C00]),
+        // reserved modes decode to black
+        None => [[0, 0, 0, 0x3C00]; 16],
+    }
+}
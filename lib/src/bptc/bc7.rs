@@ -0,0 +1,787 @@
+// Copyright (c) 2018-2021 Jan Solanti <jhs@psonet.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to	deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be included
+// in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.
+// IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT,
+// TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION WITH THE
+// SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+use core::ops::Range;
+
+use super::fit::{self, Pixels};
+use super::tables;
+use super::{BitReader, BitWriter};
+use crate::{Algorithm, Params};
+
+/// How p-bits are shared between the endpoints of a mode
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum PBits {
+    None,
+    Endpoint,
+    Shared,
+}
+
+/// Bit allocation of a BC7 mode
+struct ModeInfo {
+    subsets: usize,
+    partition_bits: usize,
+    rotation_bits: usize,
+    index_selection_bits: usize,
+    colour_bits: usize,
+    alpha_bits: usize,
+    pbits: PBits,
+    index_bits: usize,
+    index_bits2: usize,
+}
+
+#[rustfmt::skip]
+const MODES: [ModeInfo; 8] = [
+    ModeInfo { subsets: 3, partition_bits: 4, rotation_bits: 0, index_selection_bits: 0, colour_bits: 4, alpha_bits: 0, pbits: PBits::Endpoint, index_bits: 3, index_bits2: 0 },
+    ModeInfo { subsets: 2, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, colour_bits: 6, alpha_bits: 0, pbits: PBits::Shared, index_bits: 3, index_bits2: 0 },
+    ModeInfo { subsets: 3, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, colour_bits: 5, alpha_bits: 0, pbits: PBits::None, index_bits: 2, index_bits2: 0 },
+    ModeInfo { subsets: 2, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, colour_bits: 7, alpha_bits: 0, pbits: PBits::Endpoint, index_bits: 2, index_bits2: 0 },
+    ModeInfo { subsets: 1, partition_bits: 0, rotation_bits: 2, index_selection_bits: 1, colour_bits: 5, alpha_bits: 6, pbits: PBits::None, index_bits: 2, index_bits2: 3 },
+    ModeInfo { subsets: 1, partition_bits: 0, rotation_bits: 2, index_selection_bits: 0, colour_bits: 7, alpha_bits: 8, pbits: PBits::None, index_bits: 2, index_bits2: 2 },
+    ModeInfo { subsets: 1, partition_bits: 0, rotation_bits: 0, index_selection_bits: 0, colour_bits: 7, alpha_bits: 7, pbits: PBits::Endpoint, index_bits: 4, index_bits2: 0 },
+    ModeInfo { subsets: 2, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, colour_bits: 5, alpha_bits: 5, pbits: PBits::Endpoint, index_bits: 2, index_bits2: 0 },
+];
+
+/// The fields of a BC7 block in one of its modes
+///
+/// Endpoints are stored at the precision of the mode, without p-bits. Modes 4 and 5
+/// store their second set of indices in `indices2`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Bc7Fields {
+    pub mode: u8,
+    pub partition: u8,
+    pub rotation: u8,
+    pub index_selection: u8,
+    pub endpoints: [[[u8; 4]; 2]; 3],
+    pub pbits: [[u8; 2]; 3],
+    pub indices: [u8; 16],
+    pub indices2: [u8; 16],
+}
+
+/// A BC7 block, in one of the eight modes or in the reserved mode
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bc7Block {
+    Mode(Bc7Fields),
+    /// A block whose first byte is zero, which decodes to transparent black. It is kept as
+    /// is, since its other bits have no meaning.
+    Reserved([u8; 16]),
+}
+
+impl Bc7Block {
+    /// Unpacks the fields of a block
+    ///
+    /// Panics if `bytes` holds fewer than 16 bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        match Bc7Fields::from_bytes(bytes) {
+            Some(fields) => Bc7Block::Mode(fields),
+            None => Bc7Block::Reserved(bytes[..16].try_into().unwrap()),
+        }
+    }
+
+    /// Packs the fields of a block
+    pub fn to_bytes(self) -> [u8; 16] {
+        match self {
+            Bc7Block::Mode(fields) => fields.to_bytes(),
+            Bc7Block::Reserved(bytes) => bytes,
+        }
+    }
+}
+
+/// Expands an endpoint channel with an optional p-bit to 8 bits
+fn unquantise(value: u8, bits: usize, pbit: Option<u8>) -> u8 {
+    let (value, bits) = match pbit {
+        Some(pbit) => ((u32::from(value) << 1) | u32::from(pbit), bits + 1),
+        None => (u32::from(value), bits),
+    };
+
+    ((value << (8 - bits)) | (value >> (2 * bits - 8))) as u8
+}
+
+/// Finds the endpoint channel value that expands closest to `value`
+fn quantise(value: f32, bits: usize, pbit: Option<u8>) -> u8 {
+    let max = (1i32 << bits) - 1;
+    let estimate = libm::roundf(value * max as f32 / 255.0) as i32;
+
+    let mut best = 0;
+    let mut least = f32::MAX;
+    for candidate in (estimate - 1).max(0)..=(estimate + 1).min(max) {
+        let error = (f32::from(unquantise(candidate as u8, bits, pbit)) - value).abs();
+        if error < least {
+            least = error;
+            best = candidate as u8;
+        }
+    }
+
+    best
+}
+
+/// Interpolates between two 8-bit endpoint values
+fn interpolate(e0: u8, e1: u8, weight: u8) -> u8 {
+    let (e0, e1, weight) = (u32::from(e0), u32::from(e1), u32::from(weight));
+    (((64 - weight) * e0 + weight * e1 + 32) >> 6) as u8
+}
+
+impl Bc7Fields {
+    fn info(&self) -> &'static ModeInfo {
+        &MODES[usize::from(self.mode)]
+    }
+
+    fn is_anchor(&self, pixel: usize) -> bool {
+        let info = self.info();
+        let partition = usize::from(self.partition);
+        let subset = tables::subset(info.subsets, partition, pixel);
+        tables::anchor(info.subsets, partition, subset) == pixel
+    }
+
+    /// Unpacks the fields of a block, returns `None` for the reserved mode
+    ///
+    /// Panics if `bytes` holds fewer than 16 bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let mut reader = BitReader::new(bytes);
+
+        // the mode is given by the position of the lowest set bit
+        let mut mode = 0;
+        while mode < 8 && reader.read(1) == 0 {
+            mode += 1;
+        }
+        if mode == 8 {
+            return None;
+        }
+
+        let info = &MODES[mode];
+        let mut block = Bc7Fields {
+            mode: mode as u8,
+            ..Default::default()
+        };
+        block.partition = reader.read(info.partition_bits) as u8;
+        block.rotation = reader.read(info.rotation_bits) as u8;
+        block.index_selection = reader.read(info.index_selection_bits) as u8;
+
+        // endpoints are stored one channel at a time
+        for channel in 0..4 {
+            let bits = if channel < 3 {
+                info.colour_bits
+            } else {
+                info.alpha_bits
+            };
+            for subset in 0..info.subsets {
+                for endpoint in 0..2 {
+                    block.endpoints[subset][endpoint][channel] = reader.read(bits) as u8;
+                }
+            }
+        }
+
+        match info.pbits {
+            PBits::None => {}
+            PBits::Endpoint => {
+                for subset in 0..info.subsets {
+                    for endpoint in 0..2 {
+                        block.pbits[subset][endpoint] = reader.read(1) as u8;
+                    }
+                }
+            }
+            PBits::Shared => {
+                for subset in 0..info.subsets {
+                    let pbit = reader.read(1) as u8;
+                    block.pbits[subset] = [pbit, pbit];
+                }
+            }
+        }
+
+        // anchor indices have an implicit zero as their most significant bit
+        for i in 0..16 {
+            let bits = info.index_bits - usize::from(block.is_anchor(i));
+            block.indices[i] = reader.read(bits) as u8;
+        }
+        if info.index_bits2 > 0 {
+            for i in 0..16 {
+                let bits = info.index_bits2 - usize::from(i == 0);
+                block.indices2[i] = reader.read(bits) as u8;
+            }
+        }
+
+        Some(block)
+    }
+
+    /// Packs the fields of a block
+    ///
+    /// Panics if the mode is above 7.
+    pub fn to_bytes(self) -> [u8; 16] {
+        let info = self.info();
+        let mut writer = BitWriter::new();
+
+        writer.write(1 << self.mode, usize::from(self.mode) + 1);
+        writer.write(u32::from(self.partition), info.partition_bits);
+        writer.write(u32::from(self.rotation), info.rotation_bits);
+        writer.write(u32::from(self.index_selection), info.index_selection_bits);
+
+        for channel in 0..4 {
+            let bits = if channel < 3 {
+                info.colour_bits
+            } else {
+                info.alpha_bits
+            };
+            for subset in 0..info.subsets {
+                for endpoint in 0..2 {
+                    writer.write(u32::from(self.endpoints[subset][endpoint][channel]), bits);
+                }
+            }
+        }
+
+        match info.pbits {
+            PBits::None => {}
+            PBits::Endpoint => {
+                for subset in 0..info.subsets {
+                    for endpoint in 0..2 {
+                        writer.write(u32::from(self.pbits[subset][endpoint]), 1);
+                    }
+                }
+            }
+            PBits::Shared => {
+                for subset in 0..info.subsets {
+                    writer.write(u32::from(self.pbits[subset][0]), 1);
+                }
+            }
+        }
+
+        for i in 0..16 {
+            let bits = info.index_bits - usize::from(self.is_anchor(i));
+            writer.write(u32::from(self.indices[i]), bits);
+        }
+        if info.index_bits2 > 0 {
+            for i in 0..16 {
+                let bits = info.index_bits2 - usize::from(i == 0);
+                writer.write(u32::from(self.indices2[i]), bits);
+            }
+        }
+
+        writer.into_bytes()
+    }
+
+    /// Returns an endpoint expanded to 8 bits per channel
+    fn endpoint(&self, subset: usize, endpoint: usize) -> [u8; 4] {
+        let info = self.info();
+        let pbit = match info.pbits {
+            PBits::None => None,
+            _ => Some(self.pbits[subset][endpoint]),
+        };
+
+        let raw = self.endpoints[subset][endpoint];
+        let mut colour = [u8::MAX; 4];
+        for channel in 0..3 {
+            colour[channel] = unquantise(raw[channel], info.colour_bits, pbit);
+        }
+        if info.alpha_bits > 0 {
+            colour[3] = unquantise(raw[3], info.alpha_bits, pbit);
+        }
+
+        colour
+    }
+
+    /// Decodes the block to 4x4 RGBA pixels
+    pub fn decode(&self) -> [[u8; 4]; 16] {
+        let info = self.info();
+        let partition = usize::from(self.partition);
+
+        let mut endpoints = [[[0u8; 4]; 2]; 3];
+        for (subset, endpoints) in endpoints.iter_mut().enumerate().take(info.subsets) {
+            endpoints[0] = self.endpoint(subset, 0);
+            endpoints[1] = self.endpoint(subset, 1);
+        }
+
+        // select which index set drives colour and alpha
+        let (ib, ib2) = (info.index_bits, info.index_bits2);
+        let (colour_indices, colour_bits, alpha_indices, alpha_bits) = if ib2 == 0 {
+            (&self.indices, ib, &self.indices, ib)
+        } else if self.index_selection == 0 {
+            (&self.indices, ib, &self.indices2, ib2)
+        } else {
+            (&self.indices2, ib2, &self.indices, ib)
+        };
+        let colour_weights = tables::weights(colour_bits);
+        let alpha_weights = tables::weights(alpha_bits);
+
+        let mut rgba = [[0u8; 4]; 16];
+        for (i, pixel) in rgba.iter_mut().enumerate() {
+            let [e0, e1] = endpoints[tables::subset(info.subsets, partition, i)];
+            let colour_weight = colour_weights[usize::from(colour_indices[i])];
+            let alpha_weight = alpha_weights[usize::from(alpha_indices[i])];
+
+            for channel in 0..3 {
+                pixel[channel] = interpolate(e0[channel], e1[channel], colour_weight);
+            }
+            pixel[3] = interpolate(e0[3], e1[3], alpha_weight);
+
+            match self.rotation {
+                1 => pixel.swap(0, 3),
+                2 => pixel.swap(1, 3),
+                3 => pixel.swap(2, 3),
+                _ => {}
+            }
+        }
+
+        rgba
+    }
+
+    /// Swaps endpoints where needed so that every anchor index has a zero high bit
+    fn fix_anchors(&mut self) {
+        let info = self.info();
+        let partition = usize::from(self.partition);
+
+        if info.index_bits2 == 0 {
+            let max = (1u8 << info.index_bits) - 1;
+            for subset in 0..info.subsets {
+                let anchor = tables::anchor(info.subsets, partition, subset);
+                if self.indices[anchor] <= max >> 1 {
+                    continue;
+                }
+
+                self.endpoints[subset].swap(0, 1);
+                self.pbits[subset].swap(0, 1);
+                for i in 0..16 {
+                    if tables::subset(info.subsets, partition, i) == subset {
+                        self.indices[i] = max - self.indices[i];
+                    }
+                }
+            }
+        } else {
+            // colour and alpha have separate index sets, both anchored at the first pixel
+            let (primary, secondary) = if self.index_selection == 0 {
+                (0..3, 3..4)
+            } else {
+                (3..4, 0..3)
+            };
+            let endpoints = &mut self.endpoints[0];
+            flip_single(endpoints, &mut self.indices, info.index_bits, primary);
+            flip_single(endpoints, &mut self.indices2, info.index_bits2, secondary);
+        }
+    }
+}
+
+/// Swaps the given channels of a single subset's endpoints if the anchor index requires it
+fn flip_single(
+    endpoints: &mut [[u8; 4]; 2],
+    indices: &mut [u8; 16],
+    index_bits: usize,
+    channels: Range<usize>,
+) {
+    let max = (1u8 << index_bits) - 1;
+    if indices[0] <= max >> 1 {
+        return;
+    }
+
+    for channel in channels {
+        let tmp = endpoints[0][channel];
+        endpoints[0][channel] = endpoints[1][channel];
+        endpoints[1][channel] = tmp;
+    }
+    for index in indices.iter_mut() {
+        *index = max - *index;
+    }
+}
+
+//--------------------------------------------------------------------------------
+// Compression
+//--------------------------------------------------------------------------------
+
+/// Search effort for the different compression algorithms
+struct Effort {
+    opaque_modes: &'static [usize],
+    alpha_modes: &'static [usize],
+    partitions: usize,
+    refinements: usize,
+    rotations: usize,
+}
+
+impl Effort {
+    fn new(algorithm: Algorithm) -> Self {
+        match algorithm {
+            Algorithm::RangeFit => Effort {
+                opaque_modes: &[1, 6],
+                alpha_modes: &[5, 6],
+                partitions: 1,
+                refinements: 1,
+                rotations: 4,
+            },
+            Algorithm::ClusterFit => Effort {
+                opaque_modes: &[0, 1, 2, 3, 4, 5, 6],
+                alpha_modes: &[4, 5, 6, 7],
+                partitions: 4,
+                refinements: 2,
+                rotations: 4,
+            },
+            Algorithm::IterativeClusterFit => Effort {
+                opaque_modes: &[0, 1, 2, 3, 4, 5, 6, 7],
+                alpha_modes: &[4, 5, 6, 7],
+                partitions: 16,
+                refinements: 4,
+                rotations: 4,
+            },
+        }
+    }
+}
+
+/// Loads the pixels of a block, scaling the colour weights so that uniform weights have the
+/// same importance as alpha
+fn load_pixels(rgba: &[[u8; 4]; 16], mask: u32, params: &Params) -> Pixels {
+    let mut pixels = Pixels {
+        values: [[0.0; 4]; 16],
+        weights: [[0.0; 4]; 16],
+        range: (0.0, 255.0),
+    };
+
+    let sum: f32 = params.weights.iter().sum();
+    let scale = if sum > 0.0 { 3.0 / sum } else { 1.0 };
+
+    for (i, pixel) in rgba.iter().enumerate() {
+        if (mask & (1 << i)) == 0 {
+            continue;
+        }
+
+        let alpha_weight = if params.weigh_colour_by_alpha {
+            (f32::from(pixel[3]) + 1.0) / 256.0
+        } else {
+            1.0
+        };
+
+        pixels.values[i] = pixel.map(f32::from);
+        for (weight, colour_weight) in pixels.weights[i].iter_mut().zip(params.weights) {
+            *weight = colour_weight * scale * alpha_weight;
+        }
+        pixels.weights[i][3] = 1.0;
+    }
+
+    pixels
+}
+
+/// Weighted squared error of a BC7 block
+fn block_error(pixels: &Pixels, block: &Bc7Fields) -> f32 {
+    pixels.error(&block.decode().map(|pixel| pixel.map(f32::from)))
+}
+
+/// Endpoint precision and index width used when fitting a subset
+struct Precision {
+    bits: [usize; 4],
+    pbits: PBits,
+    index_bits: usize,
+}
+
+/// Quantised endpoints of a fitted subset
+struct SubsetFit {
+    endpoints: [[u8; 4]; 2],
+    pbits: [u8; 2],
+}
+
+/// Assigns each pixel of a subset to the closest palette entry and returns the total error
+fn assign_indices(
+    pixels: &Pixels,
+    members: u16,
+    channels: Range<usize>,
+    palette: &[[u8; 4]],
+    indices: &mut [u8; 16],
+) -> f32 {
+    let mut total = 0.0;
+    for (i, target) in indices.iter_mut().enumerate() {
+        if (members & (1 << i)) == 0 {
+            continue;
+        }
+
+        let mut least = f32::MAX;
+        let mut index = 0;
+        for (j, entry) in palette.iter().enumerate() {
+            let mut error = 0.0;
+            for c in channels.clone() {
+                let d = pixels.values[i][c] - f32::from(entry[c]);
+                error += pixels.weights[i][c] * d * d;
+            }
+            if error < least {
+                least = error;
+                index = j;
+            }
+        }
+
+        *target = index as u8;
+        total += least;
+    }
+
+    total
+}
+
+/// Fits quantised endpoints and indices to the pixels of a single subset
+fn fit_subset(
+    pixels: &Pixels,
+    members: u16,
+    channels: Range<usize>,
+    precision: &Precision,
+    refinements: usize,
+    indices: &mut [u8; 16],
+) -> SubsetFit {
+    let pbit_candidates: &[[u8; 2]] = match precision.pbits {
+        PBits::None => &[[0, 0]],
+        PBits::Endpoint => &[[0, 0], [0, 1], [1, 0], [1, 1]],
+        PBits::Shared => &[[0, 0], [1, 1]],
+    };
+    let weights = tables::weights(precision.index_bits);
+
+    let mut endpoints = fit::principal_endpoints(pixels, members, channels.clone());
+    let mut best = SubsetFit {
+        endpoints: [[0; 4]; 2],
+        pbits: [0; 2],
+    };
+    let mut best_error = f32::MAX;
+
+    for iteration in 0..=refinements {
+        let mut improved = false;
+
+        for pbits in pbit_candidates {
+            // quantise the endpoints and expand them back to 8 bits
+            let mut quantised = [[0u8; 4]; 2];
+            let mut expanded = [[0u8; 4]; 2];
+            for e in 0..2 {
+                let pbit = match precision.pbits {
+                    PBits::None => None,
+                    _ => Some(pbits[e]),
+                };
+                for c in channels.clone() {
+                    let bits = precision.bits[c];
+                    quantised[e][c] = quantise(endpoints[e][c], bits, pbit);
+                    expanded[e][c] = unquantise(quantised[e][c], bits, pbit);
+                }
+            }
+
+            // build the palette
+            let mut palette = [[0u8; 4]; 16];
+            for (entry, &weight) in palette.iter_mut().zip(weights) {
+                for c in channels.clone() {
+                    entry[c] = interpolate(expanded[0][c], expanded[1][c], weight);
+                }
+            }
+
+            let mut trial = *indices;
+            let error = assign_indices(
+                pixels,
+                members,
+                channels.clone(),
+                &palette[..weights.len()],
+                &mut trial,
+            );
+            if error < best_error {
+                best_error = error;
+                best = SubsetFit {
+                    endpoints: quantised,
+                    pbits: *pbits,
+                };
+                *indices = trial;
+                improved = true;
+            }
+        }
+
+        if iteration == refinements || !improved || best_error == 0.0 {
+            break;
+        }
+        if !fit::refine_endpoints(
+            pixels,
+            members,
+            channels.clone(),
+            indices,
+            precision.index_bits,
+            &mut endpoints,
+        ) {
+            break;
+        }
+    }
+
+    best
+}
+
+/// Compresses a block with one of the partitioned modes 0-3, 6 or 7
+fn compress_partitioned(pixels: &Pixels, mode: usize, effort: &Effort) -> (Bc7Fields, f32) {
+    let info = &MODES[mode];
+    let channels = if info.alpha_bits > 0 { 0..4 } else { 0..3 };
+    let precision = Precision {
+        bits: [
+            info.colour_bits,
+            info.colour_bits,
+            info.colour_bits,
+            info.alpha_bits,
+        ],
+        pbits: info.pbits,
+        index_bits: info.index_bits,
+    };
+
+    let (order, count) = fit::rank_partitions(
+        pixels,
+        info.subsets,
+        1 << info.partition_bits,
+        channels.clone(),
+    );
+
+    let mut best = Bc7Fields::default();
+    let mut best_error = f32::MAX;
+    for &partition in order.iter().take(count.min(effort.partitions)) {
+        let mut block = Bc7Fields {
+            mode: mode as u8,
+            partition,
+            ..Default::default()
+        };
+
+        for subset in 0..info.subsets {
+            let members = fit::subset_members(info.subsets, usize::from(partition), subset);
+            let fit = fit_subset(
+                pixels,
+                members,
+                channels.clone(),
+                &precision,
+                effort.refinements,
+                &mut block.indices,
+            );
+            block.endpoints[subset] = fit.endpoints;
+            block.pbits[subset] = fit.pbits;
+        }
+        block.fix_anchors();
+
+        let error = block_error(pixels, &block);
+        if error < best_error {
+            best = block;
+            best_error = error;
+        }
+    }
+
+    (best, best_error)
+}
+
+/// Compresses a block with one of the modes 4 or 5, which have separate colour and alpha indices
+fn compress_rotated(pixels: &Pixels, mode: usize, effort: &Effort) -> (Bc7Fields, f32) {
+    let info = &MODES[mode];
+
+    let mut best = Bc7Fields::default();
+    let mut best_error = f32::MAX;
+    for rotation in 0..effort.rotations.min(1 << info.rotation_bits) {
+        let rotated = if rotation > 0 {
+            pixels.swapped(rotation - 1, 3)
+        } else {
+            *pixels
+        };
+
+        for selection in 0..(1 << info.index_selection_bits) {
+            let (colour_index_bits, alpha_index_bits) = if selection == 0 {
+                (info.index_bits, info.index_bits2)
+            } else {
+                (info.index_bits2, info.index_bits)
+            };
+
+            let mut colour_indices = [0u8; 16];
+            let colour = fit_subset(
+                &rotated,
+                0xFFFF,
+                0..3,
+                &Precision {
+                    bits: [info.colour_bits, info.colour_bits, info.colour_bits, 0],
+                    pbits: PBits::None,
+                    index_bits: colour_index_bits,
+                },
+                effort.refinements,
+                &mut colour_indices,
+            );
+
+            let mut alpha_indices = [0u8; 16];
+            let alpha = fit_subset(
+                &rotated,
+                0xFFFF,
+                3..4,
+                &Precision {
+                    bits: [0, 0, 0, info.alpha_bits],
+                    pbits: PBits::None,
+                    index_bits: alpha_index_bits,
+                },
+                effort.refinements,
+                &mut alpha_indices,
+            );
+
+            let mut block = Bc7Fields {
+                mode: mode as u8,
+                rotation: rotation as u8,
+                index_selection: selection as u8,
+                ..Default::default()
+            };
+            for e in 0..2 {
+                block.endpoints[0][e] = colour.endpoints[e];
+                block.endpoints[0][e][3] = alpha.endpoints[e][3];
+            }
+            if selection == 0 {
+                block.indices = colour_indices;
+                block.indices2 = alpha_indices;
+            } else {
+                block.indices = alpha_indices;
+                block.indices2 = colour_indices;
+            }
+            block.fix_anchors();
+
+            let error = block_error(pixels, &block);
+            if error < best_error {
+                best = block;
+                best_error = error;
+            }
+        }
+    }
+
+    (best, best_error)
+}
+
+/// Compresses a 4x4 block of pixels to BC7
+pub fn compress_bc7(rgba: &[[u8; 4]; 16], mask: u32, params: &Params, block: &mut [u8]) {
+    let pixels = load_pixels(rgba, mask, params);
+    let effort = Effort::new(params.algorithm);
+
+    let opaque = (0..16).all(|i| (mask & (1 << i)) == 0 || rgba[i][3] == u8::MAX);
+    let modes = if opaque {
+        effort.opaque_modes
+    } else {
+        effort.alpha_modes
+    };
+
+    let mut best = Bc7Fields::default();
+    let mut best_error = f32::MAX;
+    for &mode in modes {
+        let (candidate, error) = if MODES[mode].rotation_bits > 0 {
+            compress_rotated(&pixels, mode, &effort)
+        } else {
+            compress_partitioned(&pixels, mode, &effort)
+        };
+
+        if error < best_error {
+            best = candidate;
+            best_error = error;
+        }
+    }
+
+    block.copy_from_slice(&best.to_bytes());
+}
+
+/// Decompresses a BC7 block to 4x4 RGBA pixels
+pub fn decompress_bc7(bytes: &[u8]) -> [[u8; 4]; 16] {
+    assert!(bytes.len() == 16);
+
+    match Bc7Fields::from_bytes(bytes) {
+        Some(block) => block.decode(),
+        // reserved mode decodes to transparent black
+        None => [[0u8; 4]; 16],
+    }
+}
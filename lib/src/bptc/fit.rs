@@ -0,0 +1,272 @@
+// Copyright (c) 2018-2021 Jan Solanti <jhs@psonet.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to	deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be included
+// in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.
+// IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT,
+// TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION WITH THE
+// SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Endpoint fitting shared by the BPTC formats
+
+use core::ops::Range;
+
+use super::tables;
+
+/// Pixel values and per-channel error weights of the block being compressed
+#[derive(Clone, Copy)]
+pub struct Pixels {
+    pub values: [[f32; 4]; 16],
+    pub weights: [[f32; 4]; 16],
+    /// The range of values that endpoints can represent
+    pub range: (f32, f32),
+}
+
+impl Pixels {
+    /// Returns a copy with two channels swapped
+    pub fn swapped(&self, a: usize, b: usize) -> Self {
+        let mut swapped = *self;
+        for (values, weights) in swapped.values.iter_mut().zip(swapped.weights.iter_mut()) {
+            values.swap(a, b);
+            weights.swap(a, b);
+        }
+        swapped
+    }
+
+    /// Weighted squared error of a decoded block
+    pub fn error(&self, decoded: &[[f32; 4]; 16]) -> f32 {
+        let mut error = 0.0;
+        for ((values, weights), decoded) in self.values.iter().zip(&self.weights).zip(decoded) {
+            for channel in 0..4 {
+                let d = values[channel] - decoded[channel];
+                error += weights[channel] * d * d;
+            }
+        }
+        error
+    }
+}
+
+/// Weighted mean and covariance of the pixels of a subset
+fn covariance(pixels: &Pixels, members: u16, channels: Range<usize>) -> ([f32; 4], [[f32; 4]; 4]) {
+    let mut total = 0.0;
+    let mut mean = [0f32; 4];
+    let mut weights = [0f32; 16];
+    for (i, weight) in weights.iter_mut().enumerate() {
+        if (members & (1 << i)) == 0 {
+            continue;
+        }
+        let w: f32 = channels.clone().map(|c| pixels.weights[i][c]).sum();
+        for c in channels.clone() {
+            mean[c] += w * pixels.values[i][c];
+        }
+        *weight = w;
+        total += w;
+    }
+
+    let mut covariance = [[0f32; 4]; 4];
+    if total <= 0.0 {
+        return (mean, covariance);
+    }
+    for m in mean.iter_mut() {
+        *m /= total;
+    }
+
+    for (values, &weight) in pixels.values.iter().zip(&weights) {
+        if weight == 0.0 {
+            continue;
+        }
+        for a in channels.clone() {
+            let da = values[a] - mean[a];
+            for b in channels.clone() {
+                let db = values[b] - mean[b];
+                covariance[a][b] += weight * da * db;
+            }
+        }
+    }
+
+    (mean, covariance)
+}
+
+/// Finds the principal axis of a covariance matrix using power iteration
+fn principal_axis(covariance: &[[f32; 4]; 4], channels: Range<usize>) -> [f32; 4] {
+    // start from the row with the most energy to avoid starting orthogonal to the solution
+    let mut axis = [0f32; 4];
+    let mut best = 0.0;
+    for c in channels.clone() {
+        if covariance[c][c] > best {
+            best = covariance[c][c];
+            axis = covariance[c];
+        }
+    }
+    if best <= 0.0 {
+        return [0.0; 4];
+    }
+
+    for _ in 0..8 {
+        let mut next = [0f32; 4];
+        for a in channels.clone() {
+            for b in channels.clone() {
+                next[a] += covariance[a][b] * axis[b];
+            }
+        }
+
+        let norm = next.iter().fold(0f32, |acc, v| acc.max(v.abs()));
+        if norm <= f32::EPSILON {
+            return [0.0; 4];
+        }
+        for (a, n) in axis.iter_mut().zip(next.iter()) {
+            *a = n / norm;
+        }
+    }
+
+    axis
+}
+
+/// Squared error of the pixels in a subset that is not explained by their principal axis
+pub fn line_residual(pixels: &Pixels, members: u16, channels: Range<usize>) -> f32 {
+    let (_, covariance) = covariance(pixels, members, channels.clone());
+    let axis = principal_axis(&covariance, channels.clone());
+
+    let trace: f32 = channels.clone().map(|c| covariance[c][c]).sum();
+    let length2: f32 = axis.iter().map(|a| a * a).sum();
+    if length2 <= f32::EPSILON {
+        return trace;
+    }
+
+    // Rayleigh quotient gives the variance along the axis
+    let mut variance = 0.0;
+    for a in channels.clone() {
+        for b in channels.clone() {
+            variance += axis[a] * covariance[a][b] * axis[b];
+        }
+    }
+
+    (trace - variance / length2).max(0.0)
+}
+
+/// Initial endpoints spanning the pixels of a subset along their principal axis
+pub fn principal_endpoints(pixels: &Pixels, members: u16, channels: Range<usize>) -> [[f32; 4]; 2] {
+    let (mean, covariance) = covariance(pixels, members, channels.clone());
+    let axis = principal_axis(&covariance, channels.clone());
+
+    let length2: f32 = axis.iter().map(|a| a * a).sum();
+    if length2 <= f32::EPSILON {
+        return [mean, mean];
+    }
+
+    let mut min = f32::MAX;
+    let mut max = f32::MIN;
+    for i in 0..16 {
+        if (members & (1 << i)) == 0 {
+            continue;
+        }
+        let t: f32 = channels
+            .clone()
+            .map(|c| (pixels.values[i][c] - mean[c]) * axis[c])
+            .sum::<f32>()
+            / length2;
+        min = min.min(t);
+        max = max.max(t);
+    }
+
+    let mut endpoints = [mean, mean];
+    for c in channels {
+        endpoints[0][c] = (mean[c] + axis[c] * min).clamp(pixels.range.0, pixels.range.1);
+        endpoints[1][c] = (mean[c] + axis[c] * max).clamp(pixels.range.0, pixels.range.1);
+    }
+    endpoints
+}
+
+/// Solves for the least squares optimal endpoints given a set of indices
+///
+/// Channels for which the system is singular keep their current endpoints.
+pub fn refine_endpoints(
+    pixels: &Pixels,
+    members: u16,
+    channels: Range<usize>,
+    indices: &[u8; 16],
+    index_bits: usize,
+    endpoints: &mut [[f32; 4]; 2],
+) -> bool {
+    let weights = tables::weights(index_bits);
+    let mut changed = false;
+
+    for c in channels {
+        let (mut aa, mut ab, mut bb, mut ax, mut bx) = (0f32, 0f32, 0f32, 0f32, 0f32);
+        for i in 0..16 {
+            if (members & (1 << i)) == 0 {
+                continue;
+            }
+            let t = f32::from(weights[usize::from(indices[i])]) / 64.0;
+            let s = 1.0 - t;
+            let w = pixels.weights[i][c];
+            let x = pixels.values[i][c];
+            aa += w * s * s;
+            ab += w * s * t;
+            bb += w * t * t;
+            ax += w * s * x;
+            bx += w * t * x;
+        }
+
+        let det = aa * bb - ab * ab;
+        if det.abs() <= f32::EPSILON * (aa * bb).max(1.0) {
+            continue;
+        }
+
+        endpoints[0][c] = ((ax * bb - bx * ab) / det).clamp(pixels.range.0, pixels.range.1);
+        endpoints[1][c] = ((bx * aa - ax * ab) / det).clamp(pixels.range.0, pixels.range.1);
+        changed = true;
+    }
+
+    changed
+}
+
+/// Returns the pixel mask of a subset within a partition
+pub fn subset_members(subsets: usize, partition: usize, subset: usize) -> u16 {
+    let mut members = 0;
+    for i in 0..16 {
+        if tables::subset(subsets, partition, i) == subset {
+            members |= 1 << i;
+        }
+    }
+    members
+}
+
+/// Orders the partitions of a mode by how well each subset fits a line
+pub fn rank_partitions(
+    pixels: &Pixels,
+    subsets: usize,
+    count: usize,
+    channels: Range<usize>,
+) -> ([u8; 64], usize) {
+    let mut ranking = [(0f32, 0u8); 64];
+
+    for (partition, rank) in ranking.iter_mut().enumerate().take(count) {
+        let mut error = 0.0;
+        for subset in 0..subsets {
+            let members = subset_members(subsets, partition, subset);
+            error += line_residual(pixels, members, channels.clone());
+        }
+        *rank = (error, partition as u8);
+    }
+
+    ranking[..count].sort_unstable_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut order = [0u8; 64];
+    for (o, rank) in order.iter_mut().zip(ranking.iter()) {
+        *o = rank.1;
+    }
+    (order, count)
+}
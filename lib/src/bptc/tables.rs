@@ -0,0 +1,114 @@
+// Copyright (c) 2018-2021 Jan Solanti <jhs@psonet.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to	deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be included
+// in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.
+// IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT,
+// TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION WITH THE
+// SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+/// Subset assignment of the two-subset partitions, one bit per pixel
+const PARTITIONS_2: [u16; 64] = [
+    0xCCCC, 0x8888, 0xEEEE, 0xECC8, 0xC880, 0xFEEC, 0xFEC8, 0xEC80, // 0-7
+    0xC800, 0xFFEC, 0xFE80, 0xE800, 0xFFE8, 0xFF00, 0xFFF0, 0xF000, // 8-15
+    0xF710, 0x008E, 0x7100, 0x08CE, 0x008C, 0x7310, 0x3100, 0x8CCE, // 16-23
+    0x088C, 0x3110, 0x6666, 0x366C, 0x17E8, 0x0FF0, 0x718E, 0x399C, // 24-31
+    0xAAAA, 0xF0F0, 0x5A5A, 0x33CC, 0x3C3C, 0x55AA, 0x9696, 0xA55A, // 32-39
+    0x73CE, 0x13C8, 0x324C, 0x3BDC, 0x6996, 0xC33C, 0x9966, 0x0660, // 40-47
+    0x0272, 0x04E4, 0x4E40, 0x2720, 0xC936, 0x936C, 0x39C6, 0x639C, // 48-55
+    0x9336, 0x9CC6, 0x817E, 0xE718, 0xCCF0, 0x0FCC, 0x7744, 0xEE22, // 56-63
+];
+
+/// Subset assignment of the three-subset partitions, two bits per pixel
+const PARTITIONS_3: [u32; 64] = [
+    0xAA685050, 0x6A5A5040, 0x5A5A4200, 0x5450A0A8, // 0-3
+    0xA5A50000, 0xA0A05050, 0x5555A0A0, 0x5A5A5050, // 4-7
+    0xAA550000, 0xAA555500, 0xAAAA5500, 0x90909090, // 8-11
+    0x94949494, 0xA4A4A4A4, 0xA9A59450, 0x2A0A4250, // 12-15
+    0xA5945040, 0x0A425054, 0xA5A5A500, 0x55A0A0A0, // 16-19
+    0xA8A85454, 0x6A6A4040, 0xA4A45000, 0x1A1A0500, // 20-23
+    0x0050A4A4, 0xAAA59090, 0x14696914, 0x69691400, // 24-27
+    0xA08585A0, 0xAA821414, 0x50A4A450, 0x6A5A0200, // 28-31
+    0xA9A58000, 0x5090A0A8, 0xA8A09050, 0x24242424, // 32-35
+    0x00AA5500, 0x24924924, 0x24499224, 0x50A50A50, // 36-39
+    0x500AA550, 0xAAAA4444, 0x66660000, 0xA5A0A5A0, // 40-43
+    0x50A050A0, 0x69286928, 0x44AAAA44, 0x66666600, // 44-47
+    0xAA444444, 0x54A854A8, 0x95809580, 0x96969600, // 48-51
+    0xA85454A8, 0x80959580, 0xAA141414, 0x96960000, // 52-55
+    0xAAAA1414, 0xA05050A0, 0xA0A5A5A0, 0x96000000, // 56-59
+    0x40804080, 0xA9A8A9A8, 0xAAAAAA44, 0x2A4A5254, // 60-63
+];
+
+/// Anchor pixel of the second subset in the two-subset partitions
+const ANCHORS_2: [u8; 64] = [
+    15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, // 0-15
+    15, 2, 8, 2, 2, 8, 8, 15, 2, 8, 2, 2, 8, 8, 2, 2, // 16-31
+    15, 15, 6, 8, 2, 8, 15, 15, 2, 8, 2, 2, 2, 15, 15, 6, // 32-47
+    6, 2, 6, 8, 15, 15, 2, 2, 15, 15, 15, 15, 15, 2, 2, 15, // 48-63
+];
+
+/// Anchor pixel of the second subset in the three-subset partitions
+const ANCHORS_3_2: [u8; 64] = [
+    3, 3, 15, 15, 8, 3, 15, 15, 8, 8, 6, 6, 6, 5, 3, 3, // 0-15
+    3, 3, 8, 15, 3, 3, 6, 10, 5, 8, 8, 6, 8, 5, 15, 15, // 16-31
+    8, 15, 3, 5, 6, 10, 8, 15, 15, 3, 15, 5, 15, 15, 15, 15, // 32-47
+    3, 15, 5, 5, 5, 8, 5, 10, 5, 10, 8, 13, 15, 12, 3, 3, // 48-63
+];
+
+/// Anchor pixel of the third subset in the three-subset partitions
+const ANCHORS_3_3: [u8; 64] = [
+    15, 8, 8, 3, 15, 15, 3, 8, 15, 15, 15, 15, 15, 15, 15, 8, // 0-15
+    15, 8, 15, 3, 15, 8, 15, 8, 3, 15, 6, 10, 15, 15, 10, 8, // 16-31
+    15, 3, 15, 10, 10, 8, 9, 10, 6, 15, 8, 15, 3, 6, 6, 8, // 32-47
+    15, 3, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 3, 15, 15, 8, // 48-63
+];
+
+/// Interpolation weights for 2-bit indices
+const WEIGHTS_2: [u8; 4] = [0, 21, 43, 64];
+
+/// Interpolation weights for 3-bit indices
+const WEIGHTS_3: [u8; 8] = [0, 9, 18, 27, 37, 46, 55, 64];
+
+/// Interpolation weights for 4-bit indices
+const WEIGHTS_4: [u8; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+/// Returns which subset a pixel belongs to in the given partition
+pub fn subset(subsets: usize, partition: usize, pixel: usize) -> usize {
+    match subsets {
+        2 => usize::from((PARTITIONS_2[partition] >> pixel) & 1),
+        3 => ((PARTITIONS_3[partition] >> (2 * pixel)) & 3) as usize,
+        _ => 0,
+    }
+}
+
+/// Returns the index of the anchor pixel of a subset in the given partition
+pub fn anchor(subsets: usize, partition: usize, subset: usize) -> usize {
+    let anchor = match (subsets, subset) {
+        (2, 1) => ANCHORS_2[partition],
+        (3, 1) => ANCHORS_3_2[partition],
+        (3, 2) => ANCHORS_3_3[partition],
+        _ => 0,
+    };
+    usize::from(anchor)
+}
+
+/// Returns the interpolation weights for indices of the given bit width
+pub fn weights(index_bits: usize) -> &'static [u8] {
+    match index_bits {
+        2 => &WEIGHTS_2,
+        3 => &WEIGHTS_3,
+        _ => &WEIGHTS_4,
+    }
+}
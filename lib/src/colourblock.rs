@@ -20,7 +20,7 @@
 // TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION WITH THE
 // SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
 
-use core::{mem, u8};
+use core::mem;
 
 use crate::math::{f32_to_i32_clamped, Vec3};
 
@@ -33,23 +33,60 @@ fn pack_565(colour: &Vec3) -> u16 {
     (r << 11) | (g << 5) | b
 }
 
-fn write_block(a: u16, b: u16, indices: &[u8; 16], block: &mut [u8]) {
-    // write endpoints
-    let a = a.to_le_bytes();
-    block[0..2].copy_from_slice(&a[..]);
-    let b = b.to_le_bytes();
-    block[2..4].copy_from_slice(&b[..]);
-
-    // write 2-bit LUT indices
-    let mut packed = [0u8; 4];
-    for i in 0..packed.len() {
-        packed[i] = ((indices[4 * i + 3] & 0x03) << 6)
-            | ((indices[4 * i + 2] & 0x03) << 4)
-            | ((indices[4 * i + 1] & 0x03) << 2)
-            | (indices[4 * i] & 0x03);
+/// The fields of a BC1 block, also the colour half of BC2 and BC3 blocks
+///
+/// The endpoints are RGB 5:6:5 values. Blocks whose first endpoint isn't greater than the
+/// second interpolate a single colour between them and, in BC1, decode index 3 as
+/// transparent black.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Bc1Block {
+    pub colour0: u16,
+    pub colour1: u16,
+    /// 2-bit palette indices in row-major pixel order
+    pub indices: [u8; 16],
+}
+
+impl Bc1Block {
+    /// Unpacks the fields of a block
+    ///
+    /// Panics if `bytes` holds fewer than 8 bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        let mut indices = [0u8; 16];
+        for (i, index) in indices.iter_mut().enumerate() {
+            *index = (bytes[4 + i / 4] >> (2 * (i % 4))) & 0x03;
+        }
+
+        Bc1Block {
+            colour0: u16::from_le_bytes([bytes[0], bytes[1]]),
+            colour1: u16::from_le_bytes([bytes[2], bytes[3]]),
+            indices,
+        }
+    }
+
+    /// Packs the fields of a block
+    pub fn to_bytes(self) -> [u8; 8] {
+        let mut block = [0u8; 8];
+        block[0..2].copy_from_slice(&self.colour0.to_le_bytes());
+        block[2..4].copy_from_slice(&self.colour1.to_le_bytes());
+        for (i, &index) in self.indices.iter().enumerate() {
+            block[4 + i / 4] |= (index & 0x03) << (2 * (i % 4));
+        }
+        block
     }
 
-    block[4..].copy_from_slice(&packed);
+    /// Whether the block uses the three colour palette
+    pub fn is_three_colour(&self) -> bool {
+        self.colour0 <= self.colour1
+    }
+}
+
+fn write_block(a: u16, b: u16, indices: &[u8; 16], block: &mut [u8]) {
+    let fields = Bc1Block {
+        colour0: a,
+        colour1: b,
+        indices: *indices,
+    };
+    block.copy_from_slice(&fields.to_bytes());
 }
 
 pub fn write3(start: &Vec3, end: &Vec3, indices: &[u8; 16], block: &mut [u8]) {
@@ -119,11 +156,8 @@ pub fn decompress(bytes: &[u8], is_bc1: bool) -> [[u8; 4]; 16] {
     let mut codes = [0u8; 16];
 
     // unpack endpoints
-    let mut tmp = [0u8; 2];
-    tmp.copy_from_slice(&bytes[0..2]);
-    let a = u16::from_le_bytes(tmp);
-    tmp.copy_from_slice(&bytes[2..4]);
-    let b = u16::from_le_bytes(tmp);
+    let fields = Bc1Block::from_bytes(bytes);
+    let (a, b) = (fields.colour0, fields.colour1);
     codes[0..4].copy_from_slice(&unpack_565(&bytes[0..2]));
     codes[4..8].copy_from_slice(&unpack_565(&bytes[2..4]));
 
@@ -145,17 +179,7 @@ pub fn decompress(bytes: &[u8], is_bc1: bool) -> [[u8; 4]; 16] {
     codes[8 + 3] = u8::MAX;
     codes[12 + 3] = if is_bc1 && (a <= b) { 0u8 } else { u8::MAX };
 
-    // unpack LUT indices
-    let mut indices = [0u8; 16];
-    for i in 0..4 {
-        let ind = &mut indices[4 * i..4 * i + 4];
-        let packed = bytes[4 + i];
-
-        ind[0] = packed & 0x03;
-        ind[1] = (packed >> 2) & 0x03;
-        ind[2] = (packed >> 4) & 0x03;
-        ind[3] = (packed >> 6) & 0x03;
-    }
+    let indices = fields.indices;
 
     let mut rgba = [[0u8; 4]; 16];
     for i in 0..rgba.len() {
@@ -29,8 +29,13 @@ pub use self::cluster::ClusterFit;
 pub use self::range::RangeFit;
 pub use self::single::SingleColourFit;
 
+use crate::math::{linear_to_cielab, linear_to_oklab, srgb8_to_linear, Vec3};
+use crate::{ColourSpace, ErrorMetric, Params};
+
 pub trait ColourFit<'a> {
-    fn compress(&'a mut self, block: &mut [u8]);
+    /// Compresses the colours into a block, returning whether the fit chose the three colour
+    /// palette of BC1
+    fn compress(&'a mut self, block: &mut [u8]) -> bool;
 }
 
 pub trait ColourFitImpl<'a> {
@@ -38,23 +43,118 @@ pub trait ColourFitImpl<'a> {
     fn is_transparent(&self) -> bool;
     fn compress3(&mut self);
     fn compress4(&mut self);
+    fn best_error(&self) -> f32;
     fn best_compressed(&'a self) -> &'a [u8];
 }
 
+/// The space in which a fit measures colour errors, unless it uses the weighted RGB metric
+#[derive(Clone, Copy)]
+pub(crate) struct ErrorSpace {
+    metric: ErrorMetric,
+    colour_space: ColourSpace,
+}
+
+impl ErrorSpace {
+    /// Returns `None` for the weighted RGB metric, which the fits evaluate directly
+    pub(crate) fn new(params: &Params) -> Option<Self> {
+        match params.colour_error_metric() {
+            ErrorMetric::Rgb => None,
+            metric => Some(ErrorSpace {
+                metric,
+                colour_space: params.colour_space,
+            }),
+        }
+    }
+
+    /// Converts a colour to the space, where errors are plain squared distances
+    pub(crate) fn convert(&self, colour: &Vec3) -> Vec3 {
+        let linear = || match self.colour_space {
+            ColourSpace::Linear => *colour,
+            ColourSpace::Srgb => Vec3::new(
+                srgb8_to_linear(colour.x()),
+                srgb8_to_linear(colour.y()),
+                srgb8_to_linear(colour.z()),
+            ),
+        };
+
+        match self.metric {
+            ErrorMetric::Rgb => *colour,
+            ErrorMetric::YCoCg => {
+                let (r, g, b) = (colour.x(), colour.y(), colour.z());
+                Vec3::new(
+                    0.25 * (r + 2.0 * g + b),
+                    0.5 * (r - b),
+                    0.25 * (2.0 * g - r - b),
+                )
+            }
+            ErrorMetric::OkLab => linear_to_oklab(linear()),
+            ErrorMetric::CieLab => linear_to_cielab(linear()),
+        }
+    }
+}
+
+/// The error the fits minimise, measured between a source and a decoded 8-bit pixel
+///
+/// This is the weighted RGB distance or the distance in the error space, weighed by alpha if
+/// the parameters ask for it, as the colour set and the fits evaluate it.
+#[derive(Clone, Copy)]
+pub(crate) struct ColourError {
+    weights: Vec3,
+    error_space: Option<ErrorSpace>,
+    weigh_colour_by_alpha: bool,
+}
+
+impl ColourError {
+    pub(crate) fn new(params: &Params) -> Self {
+        let weights = params.weights;
+        ColourError {
+            weights: Vec3::new(weights[0], weights[1], weights[2]),
+            error_space: ErrorSpace::new(params),
+            weigh_colour_by_alpha: params.weigh_colour_by_alpha,
+        }
+    }
+
+    /// Returns the error of a decoded pixel, scaled to squared 8-bit steps
+    pub(crate) fn pixel(&self, source: [u8; 4], decoded: [u8; 4]) -> f32 {
+        let point = |[r, g, b, _]: [u8; 4]| {
+            Vec3::new(f32::from(r), f32::from(g), f32::from(b)) * (1.0 / 255.0)
+        };
+        let (source_point, decoded_point) = (point(source), point(decoded));
+        let distance = match &self.error_space {
+            Some(space) => (space.convert(&source_point) - space.convert(&decoded_point)).length2(),
+            None => (self.weights * (source_point - decoded_point)).length2(),
+        };
+
+        // the colour set weighs points by the square root, and the fits by its square
+        let weight = if self.weigh_colour_by_alpha {
+            (i32::from(source[3]) + 1) as f32 / 256.0
+        } else {
+            1.0
+        };
+        255.0 * 255.0 * weight * distance
+    }
+}
+
 impl<'a, T> ColourFit<'a> for T
 where
     T: ColourFitImpl<'a>,
 {
-    fn compress(&'a mut self, block: &mut [u8]) {
+    fn compress(&'a mut self, block: &mut [u8]) -> bool {
+        let mut three_colour = false;
         if self.is_bc1() {
             self.compress3();
+            three_colour = true;
             if !self.is_transparent() {
+                // the four colour palette only replaces the three colour one if it does better
+                let error = self.best_error();
                 self.compress4();
+                three_colour = self.best_error() >= error;
             }
         } else {
             self.compress4();
         }
 
         block.copy_from_slice(self.best_compressed());
+        three_colour
     }
 }
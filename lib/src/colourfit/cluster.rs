@@ -25,17 +25,36 @@ use core::f32;
 
 use crate::colourblock;
 use crate::colourset::ColourSet;
-use crate::math::{Sym3x3, Vec3, Vec4};
-use crate::{ColourWeights, Format};
-
-use super::ColourFitImpl;
+#[cfg(all(
+    any(target_arch = "x86", target_arch = "x86_64"),
+    any(feature = "std", target_feature = "avx2")
+))]
+use crate::math::Avx2;
+#[cfg(target_arch = "aarch64")]
+use crate::math::Neon;
+#[cfg(all(
+    any(target_arch = "x86", target_arch = "x86_64"),
+    any(feature = "std", target_feature = "sse4.1"),
+    not(target_feature = "avx2")
+))]
+use crate::math::Sse41;
+use crate::math::{Lanes, Sym3x3, Vec3, Vec4};
+use crate::{Format, Params};
+
+use super::{ColourFitImpl, ErrorSpace};
 
 const MAX_ITERATIONS: usize = 8;
 
+/// The number of partitions with the lowest RGB error that are measured again in the error
+/// space, which is too slow to measure every partition in
+const MAX_RESCORED: usize = 8;
+
 pub struct ClusterFit<'a> {
     colourset: &'a ColourSet,
     format: Format,
     weights: Vec4,
+    error_space: Option<ErrorSpace>,
+    converted_points: [Vec3; 16],
     num_iterations: usize,
     principle: Vec3,
     order: [[u8; 16]; MAX_ITERATIONS],
@@ -46,16 +65,14 @@ pub struct ClusterFit<'a> {
 }
 
 impl<'a> ClusterFit<'a> {
-    pub fn new(
-        colourset: &'a ColourSet,
-        format: Format,
-        weights: ColourWeights,
-        iterate: bool,
-    ) -> Self {
+    pub fn new(colourset: &'a ColourSet, format: Format, params: &Params, iterate: bool) -> Self {
+        let weights = params.weights;
         let mut fit = ClusterFit {
             colourset,
             format,
             weights: Vec4::new(weights[0], weights[1], weights[2], 1.0),
+            error_space: ErrorSpace::new(params),
+            converted_points: [Vec3::new(0.0, 0.0, 0.0); 16],
             num_iterations: if iterate { MAX_ITERATIONS } else { 1 },
             principle: Vec3::new(0.0, 0.0, 0.0),
             order: [[0u8; 16]; MAX_ITERATIONS],
@@ -72,9 +89,80 @@ impl<'a> ClusterFit<'a> {
         // get the principle component
         fit.principle = covariance.principle_component();
 
+        // convert the points to the error space once up front
+        if let Some(space) = &fit.error_space {
+            for (converted, point) in fit.converted_points.iter_mut().zip(colourset.points()) {
+                *converted = space.convert(point);
+            }
+        }
+
         fit
     }
 
+    /// Computes the error of an ordering in the error space, with the points between each pair
+    /// of consecutive bounds mapped to the corresponding code
+    fn converted_error(
+        &self,
+        space: &ErrorSpace,
+        iteration: usize,
+        codes: &[Vec4],
+        bounds: &[usize],
+    ) -> f32 {
+        let order = &self.order[iteration];
+        let weights = self.colourset.weights();
+
+        let mut error = 0.0;
+        for (code, bounds) in codes.iter().zip(bounds.windows(2)) {
+            let code = space.convert(&code.to_vec3());
+            for &m in &order[bounds[0]..bounds[1]] {
+                let m = m as usize;
+                let w = weights[m] * weights[m];
+                error += w * (self.converted_points[m] - code).length2();
+            }
+        }
+
+        error
+    }
+
+    /// Starts the ranking of the partitions of a partition search
+    ///
+    /// In weighted RGB a partition only has to beat the best earlier result. Other error spaces
+    /// keep the best few partitions by RGB error, to be measured again by `rescore`.
+    fn ranking(&self) -> Ranking {
+        match self.error_space {
+            Some(_) => Ranking::new(MAX_RESCORED, f32::MAX),
+            None => Ranking::new(1, self.best_error.x()),
+        }
+    }
+
+    /// Picks the ranked partition with the lowest error in the error space, with the codes of a
+    /// partition into `clusters` clusters computed from its endpoints
+    fn rescore<F>(&self, ranking: &Ranking, clusters: usize, codes: F) -> Option<(Candidate, f32)>
+    where
+        F: Fn(Vec4, Vec4) -> [Vec4; 4],
+    {
+        let Some(space) = &self.error_space else {
+            return ranking
+                .best()
+                .map(|candidate| (*candidate, candidate.error));
+        };
+
+        let mut best: Option<(Candidate, f32)> = None;
+        for candidate in ranking.candidates() {
+            let codes = codes(candidate.start, candidate.end);
+            let error = self.converted_error(
+                space,
+                candidate.iteration,
+                &codes[..clusters],
+                &candidate.bounds[..=clusters],
+            );
+            if best.map_or(true, |(_, best_error)| error < best_error) {
+                best = Some((*candidate, error));
+            }
+        }
+        best
+    }
+
     fn construct_ordering(&mut self, axis: &Vec3, iteration: usize) -> bool {
         // cache some values
         let count = self.colourset.count();
@@ -136,39 +224,240 @@ impl<'a> ClusterFit<'a> {
     }
 }
 
-impl<'a> ColourFitImpl<'a> for ClusterFit<'a> {
-    fn is_bc1(&self) -> bool {
-        self.format == Format::Bc1
+/// The partition search of one of the colour block modes
+#[derive(Clone, Copy)]
+enum Kernel {
+    Three,
+    Four,
+}
+
+/// A partition of the ordered points into clusters with the endpoints fitted to it
+#[derive(Clone, Copy)]
+struct Candidate {
+    /// The weighted RGB error, without the constant term
+    error: f32,
+    start: Vec4,
+    end: Vec4,
+    iteration: usize,
+    /// The start of every cluster and the end of the last, the three-colour search uses four
+    bounds: [usize; 5],
+}
+
+/// The candidates with the lowest errors that beat a limit, best first
+struct Ranking {
+    candidates: [Candidate; MAX_RESCORED],
+    len: usize,
+    capacity: usize,
+    limit: f32,
+}
+
+impl Ranking {
+    fn new(capacity: usize, limit: f32) -> Self {
+        let zero = Vec4::new(0.0, 0.0, 0.0, 0.0);
+        let candidate = Candidate {
+            error: f32::MAX,
+            start: zero,
+            end: zero,
+            iteration: 0,
+            bounds: [0; 5],
+        };
+        Ranking {
+            candidates: [candidate; MAX_RESCORED],
+            len: 0,
+            capacity,
+            limit,
+        }
     }
 
-    fn is_transparent(&self) -> bool {
-        self.colourset.is_transparent()
+    fn candidates(&self) -> &[Candidate] {
+        &self.candidates[..self.len]
     }
 
-    fn best_compressed(&'a self) -> &'a [u8] {
-        &self.best_compressed
+    fn best(&self) -> Option<&Candidate> {
+        self.candidates().first()
     }
 
-    fn compress3(&mut self) {
+    /// Returns the iteration of the best candidate and the axis through its endpoints, or the
+    /// first iteration and a zero axis if there is none
+    fn best_axis(&self) -> (usize, Vec3) {
+        let best = &self.candidates[0];
+        (best.iteration, (best.end - best.start).to_vec3())
+    }
+
+    /// Returns the error a candidate has to be below to enter the ranking
+    #[inline(always)]
+    fn limit(&self) -> f32 {
+        if self.len < self.capacity {
+            self.limit
+        } else {
+            self.candidates[self.len - 1].error
+        }
+    }
+
+    /// Returns the limit in every lane
+    #[inline(always)]
+    fn limit_lanes<L: Lanes>(&self) -> L {
+        let limit = self.limit();
+        L::new(limit, limit, limit, limit)
+    }
+
+    /// Adds every candidate of the lanes of a partition search that beats the limit, in order
+    ///
+    /// The candidates differ in the bound at `index`, which grows by one for each candidate.
+    /// Candidates beyond the last point are skipped.
+    #[inline(always)]
+    fn insert_lanes<L: Lanes>(
+        &mut self,
+        error: L,
+        start: L,
+        end: L,
+        iteration: usize,
+        bounds: [usize; 5],
+        index: usize,
+    ) {
+        let count = bounds[4];
+        for candidate in 0..L::CANDIDATES.min(count + 1 - bounds[index]) {
+            let error = error.candidate(candidate).x();
+            if error < self.limit() {
+                let mut bounds = bounds;
+                bounds[index] += candidate;
+                self.insert(Candidate {
+                    error,
+                    start: start.candidate(candidate),
+                    end: end.candidate(candidate),
+                    iteration,
+                    bounds,
+                });
+            }
+        }
+    }
+
+    /// Adds a candidate below the limit, after the candidates with the same error
+    #[inline(always)]
+    fn insert(&mut self, candidate: Candidate) {
+        // shift the worse candidates down, dropping the last one if the ranking is full
+        let mut position = self.len.min(self.capacity - 1);
+        while position > 0 && candidate.error < self.candidates[position - 1].error {
+            self.candidates[position] = self.candidates[position - 1];
+            position -= 1;
+        }
+        self.len = (self.len + 1).min(self.capacity);
+        self.candidates[position] = candidate;
+    }
+}
+
+impl ClusterFit<'_> {
+    /// Runs a partition search with the fastest vector backend the CPU supports
+    fn dispatch(&mut self, kernel: Kernel) {
+        #[cfg(all(
+            feature = "std",
+            any(target_arch = "x86", target_arch = "x86_64"),
+            not(target_feature = "avx2")
+        ))]
+        {
+            if std::is_x86_feature_detected!("avx2") {
+                // SAFETY: the CPU supports AVX2
+                unsafe { self.run_avx2(kernel) };
+                return;
+            }
+            #[cfg(not(target_feature = "sse4.1"))]
+            if std::is_x86_feature_detected!("sse4.1") {
+                // SAFETY: the CPU supports SSE4.1
+                unsafe { self.run_sse41(kernel) };
+                return;
+            }
+        }
+
+        // use whatever every CPU of the target supports
+        #[cfg(target_arch = "aarch64")]
+        self.run::<Neon>(kernel);
+        #[cfg(all(
+            any(target_arch = "x86", target_arch = "x86_64"),
+            target_feature = "avx2"
+        ))]
+        self.run::<Avx2>(kernel);
+        #[cfg(all(
+            any(target_arch = "x86", target_arch = "x86_64"),
+            target_feature = "sse4.1",
+            not(target_feature = "avx2")
+        ))]
+        self.run::<Sse41>(kernel);
+        #[cfg(not(any(
+            target_arch = "aarch64",
+            all(
+                any(target_arch = "x86", target_arch = "x86_64"),
+                target_feature = "sse4.1"
+            )
+        )))]
+        self.run::<Vec4>(kernel);
+    }
+
+    #[cfg(all(
+        feature = "std",
+        any(target_arch = "x86", target_arch = "x86_64"),
+        not(target_feature = "avx2")
+    ))]
+    #[target_feature(enable = "avx2")]
+    unsafe fn run_avx2(&mut self, kernel: Kernel) {
+        self.run::<Avx2>(kernel);
+    }
+
+    #[cfg(all(
+        feature = "std",
+        any(target_arch = "x86", target_arch = "x86_64"),
+        not(target_feature = "sse4.1")
+    ))]
+    #[target_feature(enable = "sse4.1")]
+    unsafe fn run_sse41(&mut self, kernel: Kernel) {
+        self.run::<Sse41>(kernel);
+    }
+
+    /// Runs the partition searches of the format with a given vector backend and returns the
+    /// block and the bits of its error, for comparing the backends
+    #[cfg(all(
+        test,
+        any(
+            target_arch = "aarch64",
+            all(
+                any(target_arch = "x86", target_arch = "x86_64"),
+                any(feature = "std", target_feature = "sse4.1")
+            )
+        )
+    ))]
+    pub(crate) fn compress_with<L: Lanes>(&mut self) -> ([u8; 8], u32) {
+        if self.format == Format::Bc1 {
+            self.run::<L>(Kernel::Three);
+        }
+        self.run::<L>(Kernel::Four);
+        (self.best_compressed, self.best_error.x().to_bits())
+    }
+
+    #[inline(always)]
+    fn run<L: Lanes>(&mut self, kernel: Kernel) {
+        match kernel {
+            Kernel::Three => self.compress3_with::<L>(),
+            Kernel::Four => self.compress4_with::<L>(),
+        }
+    }
+
+    #[inline(always)]
+    fn compress3_with<L: Lanes>(&mut self) {
         let count = self.colourset.count();
-        let two = Vec4::new(2.0, 2.0, 2.0, 2.0);
-        let one = Vec4::new(1.0, 1.0, 1.0, 1.0);
-        let half_half2 = Vec4::new(0.5, 0.5, 0.5, 0.25);
-        let zero = Vec4::new(0.0, 0.0, 0.0, 0.0);
-        let half = Vec4::new(0.5, 0.5, 0.5, 0.5);
-        let grid = Vec4::new(31.0, 63.0, 31.0, 0.0);
-        let gridrcp = Vec4::new(1.0 / 31.0, 1.0 / 63.0, 1.0 / 31.0, 0.0);
+        let two = L::new(2.0, 2.0, 2.0, 2.0);
+        let one = L::new(1.0, 1.0, 1.0, 1.0);
+        let half_half2 = L::new(0.5, 0.5, 0.5, 0.25);
+        let zero = L::new(0.0, 0.0, 0.0, 0.0);
+        let half = L::new(0.5, 0.5, 0.5, 0.5);
+        let grid = L::new(31.0, 63.0, 31.0, 0.0);
+        let gridrcp = L::new(1.0 / 31.0, 1.0 / 63.0, 1.0 / 31.0, 0.0);
 
         // check all possible clusters and iterate on the total order
-        let mut best_start = zero;
-        let mut best_end = zero;
-        let mut best_error = self.best_error;
+        let mut ranking = self.ranking();
+        let mut limit = ranking.limit_lanes::<L>();
         let mut best_indices = [0u8; 16];
-        let mut best_iteration = 0;
-        let mut best_i = 0;
-        let mut best_j = 0;
 
         // inital ordering is computed using principle axis
+        let weights = L::from_vec4(self.weights);
         let mut axis = self.principle;
 
         for iteration_index in 0..self.num_iterations {
@@ -177,16 +466,33 @@ impl<'a> ColourFitImpl<'a> for ClusterFit<'a> {
                 break;
             }
 
+            let xsum_wsum = L::from_vec4(self.xsum_wsum);
+
             // first cluster [0,i) is at the start
             let mut part0 = zero;
             for i in 0..count {
                 // second cluster [i,j) is halfway along
-                let mut part1 = if i == 0 { self.points_weights[0] } else { zero };
+                let mut sum1 = if i == 0 {
+                    L::from_vec4(self.points_weights[0])
+                } else {
+                    zero
+                };
                 let jmin = if i == 0 { 1 } else { i };
 
-                for j in jmin..=count {
+                // step over the candidates of each vector at once
+                for step in 0..=(count - jmin) / L::CANDIDATES {
+                    let j = jmin + step * L::CANDIDATES;
+
+                    // the second cluster of the next candidate takes one more point
+                    let next = if j < count {
+                        sum1 + L::from_vec4(self.points_weights[j])
+                    } else {
+                        sum1
+                    };
+                    let part1 = L::pair(sum1, next);
+
                     // last cluster [j,count) is at the end
-                    let part2 = self.xsum_wsum - part1 - part0;
+                    let part2 = xsum_wsum - part1 - part0;
 
                     // compute least squares term directly
                     let alphax_sum = part1 * half_half2 + part0;
@@ -216,86 +522,84 @@ impl<'a> ColourFitImpl<'a> for ClusterFit<'a> {
                     let e4 = two * e3 + e1;
 
                     // apply the channel weights to the error term
-                    let e5 = e4 * self.weights;
+                    let e5 = e4 * weights;
                     let error = e5.splat_x() + e5.splat_y() + e5.splat_z();
 
-                    // keep the solution if it wins
-                    if error.any_less_than(&best_error) {
-                        best_start = a;
-                        best_end = b;
-                        best_i = i;
-                        best_j = j;
-                        best_error = error;
-                        best_iteration = iteration_index;
+                    // keep the solutions that win
+                    if error.any_less_than(limit) {
+                        let bounds = [0, i, j, count, count];
+                        ranking.insert_lanes(error, a, b, iteration_index, bounds, 2);
+                        limit = ranking.limit_lanes();
                     }
 
-                    // advance
-                    if j < count {
-                        part1 += self.points_weights[j];
+                    // advance past the points of every candidate
+                    sum1 = next;
+                    for m in j + 1..(j + L::CANDIDATES).min(count) {
+                        sum1 += L::from_vec4(self.points_weights[m]);
                     }
                 }
 
                 // advance
-                part0 += self.points_weights[i];
+                part0 += L::from_vec4(self.points_weights[i]);
             }
 
             // stop if we didn't improve in this iteration
+            let (best_iteration, best_axis) = ranking.best_axis();
             if best_iteration != iteration_index {
                 break;
             }
 
             // compute new axis for next iteration
-            axis = (best_end - best_start).to_vec3();
+            axis = best_axis;
         }
 
         // save the block if necessary
-        if best_error.any_less_than(&self.best_error) {
+        let best = self.rescore(&ranking, 3, |a, b| [a, (a + b) * 0.5, b, b]);
+        if let Some((best, error)) = best.filter(|&(_, error)| error < self.best_error.x()) {
             // remap indices
-            let order = self.order[best_iteration];
+            let order = self.order[best.iteration];
+            let [_, i, j, ..] = best.bounds;
 
             let mut unordered = [0u8; 16];
-            for m in best_i..best_j {
+            for m in i..j {
                 unordered[order[m] as usize] = 2;
             }
-            for m in best_j..count {
+            for m in j..count {
                 unordered[order[m] as usize] = 1;
             }
 
             self.colourset.remap_indices(&unordered, &mut best_indices);
 
             // generate the compressed blob
-            let a = best_start.to_vec3();
-            let b = best_end.to_vec3();
+            let a = best.start.to_vec3();
+            let b = best.end.to_vec3();
             colourblock::write3(&a, &b, &best_indices, &mut self.best_compressed);
 
             // save the error
-            self.best_error = best_error;
+            self.best_error = Vec4::new(error, error, error, error);
         }
     }
 
-    fn compress4(&mut self) {
+    #[inline(always)]
+    fn compress4_with<L: Lanes>(&mut self) {
         let count = self.colourset.count();
-        let two = Vec4::new(2.0, 2.0, 2.0, 2.0);
-        let one = Vec4::new(1.0, 1.0, 1.0, 1.0);
-        let onethird_onethird2 = Vec4::new(1.0 / 3.0, 1.0 / 3.0, 1.0 / 3.0, 1.0 / 9.0);
-        let twothirds_twothirds2 = Vec4::new(2.0 / 3.0, 2.0 / 3.0, 2.0 / 3.0, 4.0 / 9.0);
-        let twoninths = Vec4::new(2.0 / 9.0, 2.0 / 9.0, 2.0 / 9.0, 2.0 / 9.0);
-        let zero = Vec4::new(0.0, 0.0, 0.0, 0.0);
-        let half = Vec4::new(0.5, 0.5, 0.5, 0.5);
-        let grid = Vec4::new(31.0, 63.0, 31.0, 0.0);
-        let gridrcp = Vec4::new(1.0 / 31.0, 1.0 / 63.0, 1.0 / 31.0, 0.0);
+        let two = L::new(2.0, 2.0, 2.0, 2.0);
+        let one = L::new(1.0, 1.0, 1.0, 1.0);
+        let onethird_onethird2 = L::new(1.0 / 3.0, 1.0 / 3.0, 1.0 / 3.0, 1.0 / 9.0);
+        let twothirds_twothirds2 = L::new(2.0 / 3.0, 2.0 / 3.0, 2.0 / 3.0, 4.0 / 9.0);
+        let twoninths = L::new(2.0 / 9.0, 2.0 / 9.0, 2.0 / 9.0, 2.0 / 9.0);
+        let zero = L::new(0.0, 0.0, 0.0, 0.0);
+        let half = L::new(0.5, 0.5, 0.5, 0.5);
+        let grid = L::new(31.0, 63.0, 31.0, 0.0);
+        let gridrcp = L::new(1.0 / 31.0, 1.0 / 63.0, 1.0 / 31.0, 0.0);
 
         // check all possible clusters and iterate on the total order
-        let mut best_start = zero;
-        let mut best_end = zero;
-        let mut best_error = self.best_error;
+        let mut ranking = self.ranking();
+        let mut limit = ranking.limit_lanes::<L>();
         let mut best_indices = [0u8; 16];
-        let mut best_iteration = 0;
-        let mut best_i = 0;
-        let mut best_j = 0;
-        let mut best_k = 0;
 
         // inital ordering is computed using principle axis
+        let weights = L::from_vec4(self.weights);
         let mut axis = self.principle;
 
         for iteration_index in 0..self.num_iterations {
@@ -304,6 +608,8 @@ impl<'a> ColourFitImpl<'a> for ClusterFit<'a> {
                 break;
             }
 
+            let xsum_wsum = L::from_vec4(self.xsum_wsum);
+
             // first cluster [0,i) is at the start
             let mut part0 = zero;
             for i in 0..count {
@@ -312,12 +618,27 @@ impl<'a> ColourFitImpl<'a> for ClusterFit<'a> {
 
                 for j in i..=count {
                     // third cluster [j, k) is two thirds along
-                    let mut part2 = if j == 0 { self.points_weights[0] } else { zero };
+                    let mut sum2 = if j == 0 {
+                        L::from_vec4(self.points_weights[0])
+                    } else {
+                        zero
+                    };
                     let kmin = if j == 0 { 1 } else { j };
 
-                    for k in kmin..=count {
+                    // step over the candidates of each vector at once
+                    for step in 0..=(count - kmin) / L::CANDIDATES {
+                        let k = kmin + step * L::CANDIDATES;
+
+                        // the third cluster of the next candidate takes one more point
+                        let next = if k < count {
+                            sum2 + L::from_vec4(self.points_weights[k])
+                        } else {
+                            sum2
+                        };
+                        let part2 = L::pair(sum2, next);
+
                         // last cluster [k, count) is at the end
-                        let part3 = self.xsum_wsum - part2 - part1 - part0;
+                        let part3 = xsum_wsum - part2 - part1 - part0;
 
                         // compute least squares terms directly
                         let alphax_sum =
@@ -349,70 +670,103 @@ impl<'a> ColourFitImpl<'a> for ClusterFit<'a> {
                         let e4 = two * e3 + e1;
 
                         // apply the channel weights to the error term
-                        let e5 = e4 * self.weights;
+                        let e5 = e4 * weights;
                         let error = e5.splat_x() + e5.splat_y() + e5.splat_z();
 
-                        // keep the solution if it wins
-                        if error.any_less_than(&best_error) {
-                            best_start = a;
-                            best_end = b;
-                            best_i = i;
-                            best_j = j;
-                            best_k = k;
-                            best_error = error;
-                            best_iteration = iteration_index;
+                        // keep the solutions that win
+                        if error.any_less_than(limit) {
+                            let bounds = [0, i, j, k, count];
+                            ranking.insert_lanes(error, a, b, iteration_index, bounds, 3);
+                            limit = ranking.limit_lanes();
                         }
 
-                        // advance
-                        if k < count {
-                            part2 += self.points_weights[k];
+                        // advance past the points of every candidate
+                        sum2 = next;
+                        for m in k + 1..(k + L::CANDIDATES).min(count) {
+                            sum2 += L::from_vec4(self.points_weights[m]);
                         }
                     }
 
                     // advance
                     if j < count {
-                        part1 += self.points_weights[j];
+                        part1 += L::from_vec4(self.points_weights[j]);
                     }
                 }
 
                 // advance
-                part0 += self.points_weights[i];
+                part0 += L::from_vec4(self.points_weights[i]);
             }
 
             // stop if we didn't improve in this iteration
+            let (best_iteration, best_axis) = ranking.best_axis();
             if best_iteration != iteration_index {
                 break;
             }
 
             // compute new axis for next iteration
-            axis = (best_end - best_start).to_vec3();
+            axis = best_axis;
         }
 
         // save the block if necessary
-        if best_error.any_less_than(&self.best_error) {
+        let best = self.rescore(&ranking, 4, |a, b| {
+            [
+                a,
+                a * (2.0 / 3.0) + b * (1.0 / 3.0),
+                a * (1.0 / 3.0) + b * (2.0 / 3.0),
+                b,
+            ]
+        });
+        if let Some((best, error)) = best.filter(|&(_, error)| error < self.best_error.x()) {
             // remap indices
-            let order = self.order[best_iteration];
+            let order = self.order[best.iteration];
+            let [_, i, j, k, _] = best.bounds;
 
             let mut unordered = [0u8; 16];
-            for m in best_i..best_j {
+            for m in i..j {
                 unordered[order[m] as usize] = 2;
             }
-            for m in best_j..count {
+            for m in j..count {
                 unordered[order[m] as usize] = 3;
             }
-            for m in best_k..count {
+            for m in k..count {
                 unordered[order[m] as usize] = 1;
             }
 
             self.colourset.remap_indices(&unordered, &mut best_indices);
 
             // generate the compressed blob
-            let a = best_start.to_vec3();
-            let b = best_end.to_vec3();
+            let a = best.start.to_vec3();
+            let b = best.end.to_vec3();
             colourblock::write4(&a, &b, &best_indices, &mut self.best_compressed);
 
             // save the error
-            self.best_error = best_error;
+            self.best_error = Vec4::new(error, error, error, error);
         }
     }
 }
+
+impl<'a> ColourFitImpl<'a> for ClusterFit<'a> {
+    fn is_bc1(&self) -> bool {
+        self.format == Format::Bc1
+    }
+
+    fn is_transparent(&self) -> bool {
+        self.colourset.is_transparent()
+    }
+
+    fn best_error(&self) -> f32 {
+        self.best_error.x()
+    }
+
+    fn best_compressed(&'a self) -> &'a [u8] {
+        &self.best_compressed
+    }
+
+    fn compress3(&mut self) {
+        self.dispatch(Kernel::Three);
+    }
+
+    fn compress4(&mut self) {
+        self.dispatch(Kernel::Four);
+    }
+}
@@ -25,14 +25,15 @@ use core::f32;
 use crate::colourblock;
 use crate::colourset::ColourSet;
 use crate::math::{Sym3x3, Vec3};
-use crate::{ColourWeights, Format};
+use crate::{Format, Params};
 
-use super::ColourFitImpl;
+use super::{ColourFitImpl, ErrorSpace};
 
 pub struct RangeFit<'a> {
     colourset: &'a ColourSet,
     format: Format,
     weights: Vec3,
+    error_space: Option<ErrorSpace>,
     start: Vec3,
     end: Vec3,
     indices: [u8; 16],
@@ -41,11 +42,13 @@ pub struct RangeFit<'a> {
 }
 
 impl<'a> RangeFit<'a> {
-    pub fn new(colourset: &'a ColourSet, format: Format, weights: ColourWeights) -> Self {
+    pub fn new(colourset: &'a ColourSet, format: Format, params: &Params) -> Self {
+        let weights = params.weights;
         let mut fit = RangeFit {
             colourset,
             format,
             weights: Vec3::new(weights[0], weights[1], weights[2]),
+            error_space: ErrorSpace::new(params),
             start: Vec3::new(0.0, 0.0, 0.0),
             end: Vec3::new(0.0, 0.0, 0.0),
             indices: [0u8; 16],
@@ -105,6 +108,14 @@ impl<'a> RangeFit<'a> {
         let count = self.colourset.count();
         let values = self.colourset.points();
 
+        // convert the codebook to the error space, where the channel weights don't apply
+        let mut converted_codes = [Vec3::new(0.0, 0.0, 0.0); 4];
+        if let Some(space) = &self.error_space {
+            for (converted, code) in converted_codes.iter_mut().zip(codes) {
+                *converted = space.convert(code);
+            }
+        }
+
         // match each point to the closest code
         let mut closest = [0u8; 16];
         let mut error = 0f32;
@@ -112,9 +123,13 @@ impl<'a> RangeFit<'a> {
             // find the closest code
             let mut dist = f32::MAX;
             let mut idx = 0;
+            let converted = self.error_space.map(|space| space.convert(&values[i]));
 
             for (j, code) in codes.iter().enumerate() {
-                let d = (self.weights * (values[i] - code)).length2();
+                let d = match converted {
+                    Some(value) => (value - converted_codes[j]).length2(),
+                    None => (self.weights * (values[i] - code)).length2(),
+                };
                 if d < dist {
                     dist = d;
                     idx = j;
@@ -152,6 +167,10 @@ impl<'a> ColourFitImpl<'a> for RangeFit<'a> {
         self.colourset.is_transparent()
     }
 
+    fn best_error(&self) -> f32 {
+        self.best_error
+    }
+
     fn best_compressed(&'a self) -> &'a [u8] {
         &self.best_compressed
     }
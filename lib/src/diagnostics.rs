@@ -0,0 +1,131 @@
+// Copyright (c) 2018-2021 Jan Solanti <jhs@psonet.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to	deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be included
+// in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.
+// IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT,
+// TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION WITH THE
+// SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Per-block records of how an image was encoded
+
+use crate::colourset::ColourSet;
+use crate::{normal, ycocg, Algorithm, Format, Params};
+
+/// The colour fits that encode the colour blocks of BC1, BC2 and BC3
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColourFitKind {
+    /// Optimal endpoints for blocks of a single colour, looked up in tables
+    SingleColour,
+    /// Endpoints at the extremes of the principal axis of the colours
+    Range,
+    /// Endpoints fitted to the best ordering of the colours along the principal axis
+    Cluster,
+}
+
+impl ColourFitKind {
+    /// Chooses the fit for a set of colours
+    pub(crate) fn select(colours: &ColourSet, params: &Params) -> Self {
+        if colours.count() == 1 {
+            // Single colour fit can't handle fully transparent blocks, hence the
+            // set has to contain at least 1 colour. It's also not very useful for
+            // anything more complex so we only use it for blocks of uniform colour.
+            ColourFitKind::SingleColour
+        } else if (params.algorithm == Algorithm::RangeFit) || (colours.count() == 0) {
+            ColourFitKind::Range
+        } else {
+            ColourFitKind::Cluster
+        }
+    }
+}
+
+/// How a block was encoded and how much it differs from the source pixels
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BlockDiagnostics {
+    /// The fit that encoded the colour block of BC1, BC2 and BC3 blocks
+    pub colour_fit: Option<ColourFitKind>,
+    /// Whether a BC1 block uses the three colour palette rather than the four colour one
+    pub three_colour: bool,
+    /// Number of interpolation steps, 5 or 7, of the alpha block of BC3 blocks or of the red
+    /// and green blocks of BC4 and BC5 blocks
+    pub alpha_steps: [Option<u8>; 2],
+    /// Squared error of the decoded block, summed over the channels of the pixels within the
+    /// image in 8-bit units
+    pub error: u32,
+}
+
+impl BlockDiagnostics {
+    /// Takes over what the record of another block says about the endpoints that
+    /// rate-distortion optimisation copied from it into an 8-byte half of this block
+    pub(crate) fn copy_endpoints(&mut self, format: Format, half: usize, origin: &Self) {
+        let colour_half = if format == Format::Bc1 { 0 } else { 1 };
+        if self.colour_fit.is_some() && half == colour_half {
+            self.colour_fit = origin.colour_fit;
+            self.three_colour = origin.three_colour;
+        } else if self.alpha_steps[half].is_some() {
+            self.alpha_steps[half] = origin.alpha_steps[half];
+        }
+    }
+}
+
+/// Returns the squared error of a compressed block of any footprint
+///
+/// `rgba` and `mask` hold the source pixels of the block in row-major order and whether they
+/// are within the image.
+pub(crate) fn block_error(
+    format: Format,
+    rgba: &[[u8; 4]],
+    mask: &[bool],
+    block: &[u8],
+    params: &Params,
+) -> u32 {
+    // DXT5nm and YCoCg blocks are measured against the converted pixels they were fitted to
+    let mut swizzled = [[0u8; 4]; 16];
+    let rgba = if format == Format::Bc3 && params.normal_map {
+        for (swizzled, &pixel) in swizzled.iter_mut().zip(rgba) {
+            *swizzled = normal::swizzle_dxt5nm(pixel);
+        }
+        &swizzled[..]
+    } else if format == Format::Bc3 && params.ycocg {
+        let mut pixels = [[0u8; 4]; 16];
+        pixels.copy_from_slice(rgba);
+        let mask = mask
+            .iter()
+            .enumerate()
+            .fold(0, |bits, (i, &valid)| bits | u32::from(valid) << i);
+        swizzled = ycocg::encode(&pixels, mask);
+        &swizzled[..]
+    } else {
+        rgba
+    };
+
+    let mut decoded = [[0u8; 4]; crate::astc::MAX_TEXELS];
+    let decoded = &mut decoded[..rgba.len()];
+    format.decompress_footprint(block, decoded);
+
+    let mut error = 0;
+    for ((source, decoded), _) in rgba
+        .iter()
+        .zip(decoded.iter())
+        .zip(mask)
+        .filter(|(_, &valid)| valid)
+    {
+        for (&a, &b) in source.iter().zip(decoded) {
+            let difference = u32::from(a.abs_diff(b));
+            error += difference * difference;
+        }
+    }
+    error
+}
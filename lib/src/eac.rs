@@ -0,0 +1,356 @@
+// Copyright (c) 2018-2021 Jan Solanti <jhs@psonet.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to	deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be included
+// in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.
+// IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT,
+// TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION WITH THE
+// SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Ericsson Alpha Compression blocks
+//!
+//! Each 64-bit big endian block holds a base value, a multiplier, a modifier table and a
+//! 3-bit modifier index for every pixel, stored column by column.
+
+use core::ops::RangeInclusive;
+
+use crate::{Algorithm, Params};
+
+/// Modifier tables shared by all EAC formats
+const MODIFIERS: [[i32; 8]; 16] = [
+    [-3, -6, -9, -15, 2, 5, 8, 14],
+    [-3, -7, -10, -13, 2, 6, 9, 12],
+    [-2, -5, -8, -13, 1, 4, 7, 12],
+    [-2, -4, -6, -13, 1, 3, 5, 12],
+    [-3, -6, -8, -12, 2, 5, 7, 11],
+    [-3, -7, -9, -11, 2, 6, 8, 10],
+    [-4, -7, -8, -11, 3, 6, 7, 10],
+    [-3, -5, -8, -11, 2, 4, 7, 10],
+    [-2, -6, -8, -10, 1, 5, 7, 9],
+    [-2, -5, -8, -10, 1, 4, 7, 9],
+    [-2, -4, -8, -10, 1, 3, 7, 9],
+    [-2, -5, -7, -10, 1, 4, 6, 9],
+    [-3, -4, -7, -10, 2, 3, 6, 9],
+    [-1, -2, -3, -10, 0, 1, 2, 9],
+    [-4, -6, -8, -9, 3, 5, 7, 8],
+    [-3, -5, -7, -9, 2, 4, 6, 8],
+];
+
+/// The fields of an EAC block, used by the R11 and RG11 formats and as the alpha half of ETC2
+/// RGBA blocks
+///
+/// Signed R11 and RG11 blocks store the base value as a signed byte, which decodes as `i8`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct EacBlock {
+    pub base: u8,
+    /// 4-bit multiplier of the modifiers
+    pub multiplier: u8,
+    /// 4-bit index of the modifier table
+    pub table: u8,
+    /// 3-bit modifier indices in row-major pixel order
+    pub indices: [u8; 16],
+}
+
+impl EacBlock {
+    /// Unpacks the fields of a block
+    ///
+    /// Panics if `bytes` holds fewer than 8 bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        let mut word = [0u8; 8];
+        word.copy_from_slice(&bytes[..8]);
+        let block = u64::from_be_bytes(word);
+
+        let mut indices = [0u8; 16];
+        for (i, index) in indices.iter_mut().enumerate() {
+            *index = ((block >> index_shift(i)) & 0x07) as u8;
+        }
+
+        EacBlock {
+            base: (block >> 56) as u8,
+            multiplier: ((block >> 52) & 0x0F) as u8,
+            table: ((block >> 48) & 0x0F) as u8,
+            indices,
+        }
+    }
+
+    /// Packs the fields of a block
+    pub fn to_bytes(self) -> [u8; 8] {
+        let mut block = u64::from(self.base) << 56
+            | u64::from(self.multiplier & 0x0F) << 52
+            | u64::from(self.table & 0x0F) << 48;
+        for (i, &index) in self.indices.iter().enumerate() {
+            block |= u64::from(index & 0x07) << index_shift(i);
+        }
+        block.to_be_bytes()
+    }
+}
+
+/// Interpretation of the block header by the different EAC formats
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Precision {
+    /// 8-bit alpha of ETC2 RGBA blocks
+    Alpha,
+    /// 11-bit unsigned R11 and RG11 channels
+    Unsigned11,
+    /// 11-bit signed R11 and RG11 channels
+    Signed11,
+}
+
+impl Precision {
+    /// Range of the decoded values
+    fn range(self) -> (i32, i32) {
+        match self {
+            Precision::Alpha => (0, 255),
+            Precision::Unsigned11 => (0, 2047),
+            Precision::Signed11 => (-1023, 1023),
+        }
+    }
+
+    /// Valid base codewords; the signed formats reserve -128
+    fn codewords(self) -> RangeInclusive<i32> {
+        match self {
+            Precision::Alpha | Precision::Unsigned11 => 0..=255,
+            Precision::Signed11 => -127..=127,
+        }
+    }
+
+    /// Valid multipliers; the 11-bit formats use 0 for their finest step
+    fn multipliers(self) -> RangeInclusive<i32> {
+        match self {
+            Precision::Alpha => 1..=15,
+            Precision::Unsigned11 | Precision::Signed11 => 0..=15,
+        }
+    }
+
+    /// Expands a base codeword to the value range
+    fn base(self, codeword: i32) -> i32 {
+        match self {
+            Precision::Alpha => codeword,
+            Precision::Unsigned11 => 8 * codeword + 4,
+            Precision::Signed11 => 8 * codeword.max(-127),
+        }
+    }
+
+    /// Expands a multiplier to the value range
+    fn step(self, multiplier: i32) -> i32 {
+        match self {
+            Precision::Alpha => multiplier,
+            _ if multiplier == 0 => 1,
+            _ => 8 * multiplier,
+        }
+    }
+
+    /// Inverse of `base`, returns the nearest valid codeword for a value
+    fn nearest_codeword(self, value: f32) -> i32 {
+        let codeword = match self {
+            Precision::Alpha => value,
+            Precision::Unsigned11 => (value - 4.0) / 8.0,
+            Precision::Signed11 => value / 8.0,
+        };
+        let codewords = self.codewords();
+        (libm::roundf(codeword) as i32).clamp(*codewords.start(), *codewords.end())
+    }
+
+    /// Inverse of `step`, returns the nearest valid multiplier for a step size
+    fn nearest_multiplier(self, step: f32) -> i32 {
+        let multiplier = match self {
+            Precision::Alpha => step,
+            Precision::Unsigned11 | Precision::Signed11 => step / 8.0,
+        };
+        let multipliers = self.multipliers();
+        (libm::roundf(multiplier) as i32).clamp(*multipliers.start(), *multipliers.end())
+    }
+}
+
+/// Returns the shift of a pixel's index bits, given its position in a row-major block
+fn index_shift(pixel: usize) -> u32 {
+    45 - 3 * (4 * (pixel % 4) + pixel / 4) as u32
+}
+
+/// Builds the eight values a block with the given header can represent
+fn codebook(precision: Precision, codeword: i32, multiplier: i32, table: usize) -> [i32; 8] {
+    let (min, max) = precision.range();
+    let base = precision.base(codeword);
+    let step = precision.step(multiplier);
+    MODIFIERS[table].map(|modifier| (base + modifier * step).clamp(min, max))
+}
+
+/// Fits each valid value to the codebook, writing the indices into `block`
+fn fit_codes(values: &[i32; 16], mask: u32, codes: &[i32; 8], block: &mut u64) -> u32 {
+    let mut err = 0;
+    for (i, &value) in values.iter().enumerate() {
+        // masked pixels keep index 0
+        if (mask & (1 << i)) == 0 {
+            continue;
+        }
+
+        let mut least = u32::MAX;
+        let mut index = 0;
+        for (j, &code) in codes.iter().enumerate() {
+            let dist = value - code;
+            let dist = (dist * dist) as u32;
+            if dist < least {
+                least = dist;
+                index = j;
+            }
+        }
+
+        *block |= (index as u64) << index_shift(i);
+        err += least;
+    }
+    err
+}
+
+/// Compresses 16 values within the range of the given precision
+fn compress(values: &[i32; 16], mask: u32, precision: Precision, params: &Params) -> u64 {
+    // get the range of the valid pixels
+    let (mut min, mut max) = (i32::MAX, i32::MIN);
+    for (i, &value) in values.iter().enumerate() {
+        if (mask & (1 << i)) != 0 {
+            min = min.min(value);
+            max = max.max(value);
+        }
+    }
+    if min > max {
+        (min, max) = (0, 0);
+    }
+
+    // how far to search around the estimated base and multiplier
+    let (base_radius, multiplier_radius) = match params.algorithm {
+        Algorithm::RangeFit => (0, 0),
+        Algorithm::ClusterFit => (2, 1),
+        Algorithm::IterativeClusterFit => (4, 2),
+    };
+
+    let codewords = precision.codewords();
+    let multipliers = precision.multipliers();
+
+    let mut best = 0;
+    let mut best_err = u32::MAX;
+    for (table, modifiers) in MODIFIERS.iter().enumerate() {
+        // estimate the parameters that stretch this table over the range
+        let low = modifiers[3];
+        let high = modifiers[7];
+        let step = (max - min) as f32 / (high - low) as f32;
+        let multiplier = precision.nearest_multiplier(step);
+        let step = precision.step(multiplier) as f32;
+        let centre = (min + max) as f32 / 2.0;
+        let codeword = precision.nearest_codeword(centre - (low + high) as f32 * step / 2.0);
+
+        for multiplier in multiplier - multiplier_radius..=multiplier + multiplier_radius {
+            if !multipliers.contains(&multiplier) {
+                continue;
+            }
+            for codeword in codeword - base_radius..=codeword + base_radius {
+                if !codewords.contains(&codeword) {
+                    continue;
+                }
+
+                let mut candidate = (codeword as u8 as u64) << 56
+                    | (multiplier as u64) << 52
+                    | (table as u64) << 48;
+                let codes = codebook(precision, codeword, multiplier, table);
+                let err = fit_codes(values, mask, &codes, &mut candidate);
+                if err < best_err {
+                    best = candidate;
+                    best_err = err;
+                }
+            }
+        }
+    }
+
+    best
+}
+
+/// Decompresses a block to 16 values within the range of the given precision
+fn decompress(bytes: &[u8], precision: Precision) -> [i32; 16] {
+    assert!(bytes.len() == 8);
+
+    // build the codebook from the header
+    let fields = EacBlock::from_bytes(bytes);
+    let codeword = if precision == Precision::Signed11 {
+        i32::from(fields.base as i8)
+    } else {
+        i32::from(fields.base)
+    };
+    let codes = codebook(
+        precision,
+        codeword,
+        i32::from(fields.multiplier),
+        usize::from(fields.table),
+    );
+
+    // look up the indexed codebook values
+    fields.indices.map(|index| codes[usize::from(index)])
+}
+
+pub fn compress_eac(
+    rgba: &[[u8; 4]; 16],
+    channel: usize,
+    mask: u32,
+    params: &Params,
+    block: &mut [u8],
+) {
+    let values = rgba.map(|pixel| i32::from(pixel[channel]));
+    let compressed = compress(&values, mask, Precision::Alpha, params);
+    block.copy_from_slice(&compressed.to_be_bytes());
+}
+
+pub fn decompress_eac(rgba: &mut [[u8; 4]; 16], channel: usize, bytes: &[u8]) {
+    let values = decompress(bytes, Precision::Alpha);
+    for (pixel, value) in rgba.iter_mut().zip(values) {
+        pixel[channel] = value as u8;
+    }
+}
+
+/// Compresses one channel of a block to an R11 or RG11 block, taking values in the range
+/// 0..1 or -1..1 if `signed` is set
+pub fn compress_eac11(
+    rgba: &[[f32; 4]; 16],
+    channel: usize,
+    mask: u32,
+    signed: bool,
+    params: &Params,
+    block: &mut [u8],
+) {
+    let precision = if signed {
+        Precision::Signed11
+    } else {
+        Precision::Unsigned11
+    };
+    let (min, max) = precision.range();
+    let values = rgba.map(|pixel| {
+        let value = libm::roundf(pixel[channel] * max as f32) as i32;
+        value.clamp(min, max)
+    });
+
+    let compressed = compress(&values, mask, precision, params);
+    block.copy_from_slice(&compressed.to_be_bytes());
+}
+
+/// Decompresses an R11 or RG11 block into one channel, producing values in the range 0..1
+/// or -1..1 if `signed` is set
+pub fn decompress_eac11(rgba: &mut [[f32; 4]; 16], channel: usize, signed: bool, bytes: &[u8]) {
+    let precision = if signed {
+        Precision::Signed11
+    } else {
+        Precision::Unsigned11
+    };
+    let (_, max) = precision.range();
+    let values = decompress(bytes, precision);
+    for (pixel, value) in rgba.iter_mut().zip(values) {
+        pixel[channel] = value as f32 / max as f32;
+    }
+}
@@ -0,0 +1,70 @@
+// Copyright (c) 2018-2021 Jan Solanti <jhs@psonet.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to	deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be included
+// in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.
+// IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT,
+// TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION WITH THE
+// SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+use core::fmt;
+
+/// Reasons the fallible image functions of `Format` reject their arguments or stop early
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum Error {
+    /// The width or height of the image is zero
+    ZeroDimensions,
+    /// The number of pixels, values or bytes of the image does not fit in a `usize`
+    SizeOverflow,
+    /// The input holds fewer values than the image requires
+    InputTooShort { expected: usize, actual: usize },
+    /// The output has room for fewer values than the image requires
+    OutputTooShort { expected: usize, actual: usize },
+    /// The distance between the starts of consecutive rows is less than a row of pixels
+    PitchTooSmall { expected: usize, actual: usize },
+    /// The cancellation flag of a `Job` was set before every block was compressed
+    Cancelled,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::ZeroDimensions => write!(f, "image width or height is zero"),
+            Error::SizeOverflow => write!(f, "image size overflows usize"),
+            Error::InputTooShort { expected, actual } => {
+                write!(
+                    f,
+                    "input too short: expected {expected} values, got {actual}"
+                )
+            }
+            Error::OutputTooShort { expected, actual } => {
+                write!(
+                    f,
+                    "output too short: expected {expected} values, got {actual}"
+                )
+            }
+            Error::PitchTooSmall { expected, actual } => {
+                write!(
+                    f,
+                    "pitch too small: expected at least {expected} values, got {actual}"
+                )
+            }
+            Error::Cancelled => write!(f, "compression was cancelled"),
+        }
+    }
+}
+
+impl core::error::Error for Error {}
@@ -0,0 +1,1221 @@
+// Copyright (c) 2018-2021 Jan Solanti <jhs@psonet.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to	deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be included
+// in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.
+// IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT,
+// TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION WITH THE
+// SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Ericsson Texture Compression 2 colour blocks
+//!
+//! Blocks are stored as big endian 64-bit words and index their pixels column by column.
+//! Besides the ETC1 compatible individual and differential modes, ETC2 reinterprets
+//! differential blocks whose second base colour overflows as T, H or planar mode blocks.
+
+use crate::math::{Sym3x3, Vec3};
+use crate::{Algorithm, Params};
+
+/// Intensity modifiers of the individual and differential modes
+const MODIFIERS: [[i32; 2]; 8] = [
+    [2, 8],
+    [5, 17],
+    [9, 29],
+    [13, 42],
+    [18, 60],
+    [24, 80],
+    [33, 106],
+    [47, 183],
+];
+
+/// Distances between the paint colours of the T and H modes
+const DISTANCES: [i32; 8] = [3, 6, 11, 16, 23, 32, 41, 64];
+
+/// Index that marks a pixel transparent in punch-through alpha blocks
+const TRANSPARENT: usize = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Mode {
+    Individual,
+    Differential,
+    T,
+    H,
+    Planar,
+}
+
+/// Extracts `count` bits starting at bit `shift`
+fn bits(block: u64, shift: u32, count: u32) -> i32 {
+    ((block >> shift) & ((1 << count) - 1)) as i32
+}
+
+/// Sign extends a 3-bit colour delta
+fn delta(value: i32) -> i32 {
+    (value << 29) >> 29
+}
+
+fn extend4(value: i32) -> i32 {
+    (value << 4) | value
+}
+
+fn extend5(value: i32) -> i32 {
+    (value << 3) | (value >> 2)
+}
+
+fn extend6(value: i32) -> i32 {
+    (value << 2) | (value >> 4)
+}
+
+fn extend7(value: i32) -> i32 {
+    (value << 1) | (value >> 6)
+}
+
+/// Returns the position of a pixel's index bits, given its position in a row-major block
+fn index_bit(pixel: usize) -> u32 {
+    (4 * (pixel % 4) + pixel / 4) as u32
+}
+
+/// Reads the 2-bit index of a pixel
+fn read_index(block: u64, pixel: usize) -> usize {
+    let bit = index_bit(pixel);
+    let msb = (block >> (16 + bit)) & 1;
+    let lsb = (block >> bit) & 1;
+    ((msb << 1) | lsb) as usize
+}
+
+/// Writes the 2-bit index of a pixel into a block with all index bits cleared
+fn write_index(block: &mut u64, pixel: usize, index: usize) {
+    let bit = index_bit(pixel);
+    let index = index as u64;
+    *block |= ((index >> 1) << (16 + bit)) | ((index & 1) << bit);
+}
+
+fn clamp_colour(colour: [i32; 3]) -> [i32; 3] {
+    colour.map(|value| value.clamp(0, 255))
+}
+
+fn offset_colour(colour: [i32; 3], offset: i32) -> [i32; 3] {
+    clamp_colour(colour.map(|value| value + offset))
+}
+
+/// Determines the mode of a block
+///
+/// Punch-through alpha blocks have no individual mode; the bit that otherwise selects it
+/// marks the block as opaque instead.
+fn mode(block: u64, punchthrough: bool) -> Mode {
+    if !punchthrough && (block >> 33) & 1 == 0 {
+        return Mode::Individual;
+    }
+
+    let overflows = |shift: u32| {
+        let value = bits(block, shift + 3, 5) + delta(bits(block, shift, 3));
+        !(0..32).contains(&value)
+    };
+
+    if overflows(56) {
+        Mode::T
+    } else if overflows(48) {
+        Mode::H
+    } else if overflows(40) {
+        Mode::Planar
+    } else {
+        Mode::Differential
+    }
+}
+
+/// Base colours of the two sub-blocks of an individual or differential block
+fn base_colours(block: u64, mode: Mode) -> [[i32; 3]; 2] {
+    let channel = |c: u32| {
+        let shift = 56 - 8 * c;
+        if mode == Mode::Individual {
+            [
+                extend4(bits(block, shift + 4, 4)),
+                extend4(bits(block, shift, 4)),
+            ]
+        } else {
+            let base = bits(block, shift + 3, 5);
+            [extend5(base), extend5(base + delta(bits(block, shift, 3)))]
+        }
+    };
+
+    let [r, g, b] = [0, 1, 2].map(channel);
+    [[r[0], g[0], b[0]], [r[1], g[1], b[1]]]
+}
+
+/// Modifier palette of a sub-block
+fn subblock_palette(base: [i32; 3], table: usize, opaque: bool) -> [[i32; 3]; 4] {
+    let [small, large] = MODIFIERS[table];
+    [
+        offset_colour(base, if opaque { small } else { 0 }),
+        offset_colour(base, large),
+        offset_colour(base, -small),
+        offset_colour(base, -large),
+    ]
+}
+
+/// The four paint colours of a T or H mode block
+fn paint_colours(block: u64, mode: Mode) -> [[i32; 3]; 4] {
+    if mode == Mode::T {
+        let c1 = [
+            (bits(block, 59, 2) << 2) | bits(block, 56, 2),
+            bits(block, 52, 4),
+            bits(block, 48, 4),
+        ]
+        .map(extend4);
+        let c2 = [bits(block, 44, 4), bits(block, 40, 4), bits(block, 36, 4)].map(extend4);
+        let distance = DISTANCES[((bits(block, 34, 2) << 1) | bits(block, 32, 1)) as usize];
+
+        [
+            c1,
+            offset_colour(c2, distance),
+            c2,
+            offset_colour(c2, -distance),
+        ]
+    } else {
+        let c1 = [
+            bits(block, 59, 4),
+            (bits(block, 56, 3) << 1) | bits(block, 52, 1),
+            (bits(block, 51, 1) << 3) | bits(block, 47, 3),
+        ];
+        let c2 = [bits(block, 43, 4), bits(block, 39, 4), bits(block, 35, 4)];
+
+        // the order of the base colours holds the lowest bit of the distance index
+        let value = |c: [i32; 3]| (c[0] << 8) | (c[1] << 4) | c[2];
+        let order = i32::from(value(c1) >= value(c2));
+        let index = (bits(block, 34, 1) << 2) | (bits(block, 32, 1) << 1) | order;
+        let distance = DISTANCES[index as usize];
+
+        let (c1, c2) = (c1.map(extend4), c2.map(extend4));
+        [
+            offset_colour(c1, distance),
+            offset_colour(c1, -distance),
+            offset_colour(c2, distance),
+            offset_colour(c2, -distance),
+        ]
+    }
+}
+
+/// Origin, horizontal and vertical colours of a planar block
+fn planar_colours(block: u64) -> [[i32; 3]; 3] {
+    let origin = [
+        extend6(bits(block, 57, 6)),
+        extend7((bits(block, 56, 1) << 6) | bits(block, 49, 6)),
+        extend6((bits(block, 48, 1) << 5) | (bits(block, 43, 2) << 3) | bits(block, 39, 3)),
+    ];
+    let horizontal = [
+        extend6((bits(block, 34, 5) << 1) | bits(block, 32, 1)),
+        extend7(bits(block, 25, 7)),
+        extend6(bits(block, 19, 6)),
+    ];
+    let vertical = [
+        extend6(bits(block, 13, 6)),
+        extend7(bits(block, 6, 7)),
+        extend6(bits(block, 0, 6)),
+    ];
+    [origin, horizontal, vertical]
+}
+
+/// Evaluates a planar block at a pixel
+fn planar_colour(colours: &[[i32; 3]; 3], pixel: usize) -> [i32; 3] {
+    let (x, y) = ((pixel % 4) as i32, (pixel / 4) as i32);
+    let [origin, horizontal, vertical] = colours;
+    clamp_colour([0, 1, 2].map(|c| {
+        (x * (horizontal[c] - origin[c]) + y * (vertical[c] - origin[c]) + 4 * origin[c] + 2) >> 2
+    }))
+}
+
+/// Decompresses an ETC2 colour block to 4x4 RGBA pixels
+///
+/// With `punchthrough` set the block is decoded as an ETC2 punch-through alpha block.
+pub fn decompress_etc2(bytes: &[u8], punchthrough: bool) -> [[u8; 4]; 16] {
+    let mut word = [0u8; 8];
+    word.copy_from_slice(&bytes[..8]);
+    let block = u64::from_be_bytes(word);
+
+    let mode = mode(block, punchthrough);
+    let opaque = !punchthrough || (block >> 33) & 1 == 1;
+
+    let mut rgba = [[0u8; 4]; 16];
+    if mode == Mode::Planar {
+        let colours = planar_colours(block);
+        for (pixel, output) in rgba.iter_mut().enumerate() {
+            let [r, g, b] = planar_colour(&colours, pixel);
+            *output = [r as u8, g as u8, b as u8, u8::MAX];
+        }
+        return rgba;
+    }
+
+    // T and H mode blocks share their palette between all pixels
+    let flip = (block >> 32) & 1 == 1;
+    let palettes = match mode {
+        Mode::T | Mode::H => [paint_colours(block, mode); 2],
+        _ => {
+            let [first, second] = base_colours(block, mode);
+            [
+                subblock_palette(first, bits(block, 37, 3) as usize, opaque),
+                subblock_palette(second, bits(block, 34, 3) as usize, opaque),
+            ]
+        }
+    };
+
+    for (pixel, output) in rgba.iter_mut().enumerate() {
+        let index = read_index(block, pixel);
+        if !opaque && index == TRANSPARENT {
+            continue;
+        }
+
+        let (x, y) = (pixel % 4, pixel / 4);
+        let subblock = if flip { y / 2 } else { x / 2 };
+        let [r, g, b] = palettes[subblock][index];
+        *output = [r as u8, g as u8, b as u8, u8::MAX];
+    }
+
+    rgba
+}
+
+/// The fields of an ETC2 colour block, also the colour half of ETC2 RGBA blocks
+///
+/// Base colours are stored at the precision of the mode, as RGB 4:4:4, 5:5:5 or 6:7:6 values.
+/// `opaque` is the flag of punch-through alpha blocks that disables the transparent index 2
+/// and always set in other blocks, although planar blocks ignore it. T, H and planar blocks
+/// leave some bits unused, which are kept in `padding`, lowest bit first. `to_bytes` writes
+/// them back if the block still decodes in its mode, and otherwise sets them to the lowest
+/// values that select the mode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Etc2Block {
+    /// Two sub-blocks with their own base colour, not available in punch-through alpha blocks
+    Individual {
+        colours: [[u8; 3]; 2],
+        /// 3-bit modifier tables of the sub-blocks
+        tables: [u8; 2],
+        /// Whether the sub-blocks are the top and bottom halves instead of left and right
+        flip: bool,
+        /// 2-bit modifier indices in row-major pixel order
+        indices: [u8; 16],
+    },
+    /// Two sub-blocks whose second base colour is a delta in -4..3 from the first, which must
+    /// stay within 0..31 for the block to keep this mode
+    Differential {
+        colour: [u8; 3],
+        delta: [i8; 3],
+        tables: [u8; 2],
+        flip: bool,
+        opaque: bool,
+        indices: [u8; 16],
+    },
+    /// A palette of the first colour and the second one moved by a distance either way
+    T {
+        colours: [[u8; 3]; 2],
+        /// 3-bit index of the distance
+        distance: u8,
+        opaque: bool,
+        /// 2-bit palette indices in row-major pixel order
+        indices: [u8; 16],
+        padding: u8,
+    },
+    /// A palette of both colours moved by a distance either way
+    H {
+        colours: [[u8; 3]; 2],
+        /// Upper two bits of the index of the distance. The lowest bit is one if the first
+        /// colour is at least the second, compared as 12-bit RGB values.
+        distance_high: u8,
+        opaque: bool,
+        indices: [u8; 16],
+        padding: u8,
+    },
+    /// A gradient given by the colours at the origin and one block width to the right and
+    /// down
+    Planar {
+        origin: [u8; 3],
+        horizontal: [u8; 3],
+        vertical: [u8; 3],
+        opaque: bool,
+        padding: u8,
+    },
+}
+
+/// The bits left unused by the T, H and planar modes, which select the mode instead
+const T_UNUSED: [u32; 4] = [58, 61, 62, 63];
+const H_UNUSED: [u32; 5] = [50, 53, 54, 55, 63];
+const PLANAR_UNUSED: [u32; 6] = [42, 45, 46, 47, 55, 63];
+
+/// Reads the 2-bit indices of all pixels in row-major order
+fn read_indices(block: u64) -> [u8; 16] {
+    let mut indices = [0u8; 16];
+    for (pixel, index) in indices.iter_mut().enumerate() {
+        *index = read_index(block, pixel) as u8;
+    }
+    indices
+}
+
+/// Writes the 2-bit indices of all pixels into a block with all index bits cleared
+fn write_indices(block: &mut u64, indices: &[u8; 16]) {
+    for (pixel, &index) in indices.iter().enumerate() {
+        write_index(block, pixel, usize::from(index & 3));
+    }
+}
+
+impl Etc2Block {
+    /// Unpacks the fields of a block, which is read as a punch-through alpha block with
+    /// `punchthrough` set
+    ///
+    /// Panics if `bytes` holds fewer than 8 bytes.
+    pub fn from_bytes(bytes: &[u8], punchthrough: bool) -> Self {
+        let mut word = [0u8; 8];
+        word.copy_from_slice(&bytes[..8]);
+        let block = u64::from_be_bytes(word);
+
+        let field = |shift, count| bits(block, shift, count) as u8;
+        let opaque = !punchthrough || (block >> 33) & 1 == 1;
+        let flip = (block >> 32) & 1 == 1;
+        let tables = [field(37, 3), field(34, 3)];
+        let indices = read_indices(block);
+        let padding = |unused: &[u32]| {
+            let bits = unused.iter().rev().map(|&bit| (block >> bit) & 1);
+            bits.fold(0, |padding, bit| padding << 1 | bit as u8)
+        };
+
+        match mode(block, punchthrough) {
+            Mode::Individual => Etc2Block::Individual {
+                colours: [
+                    [field(60, 4), field(52, 4), field(44, 4)],
+                    [field(56, 4), field(48, 4), field(40, 4)],
+                ],
+                tables,
+                flip,
+                indices,
+            },
+            Mode::Differential => Etc2Block::Differential {
+                colour: [field(59, 5), field(51, 5), field(43, 5)],
+                delta: [56, 48, 40].map(|shift| delta(bits(block, shift, 3)) as i8),
+                tables,
+                flip,
+                opaque,
+                indices,
+            },
+            Mode::T => Etc2Block::T {
+                colours: [
+                    [field(59, 2) << 2 | field(56, 2), field(52, 4), field(48, 4)],
+                    [field(44, 4), field(40, 4), field(36, 4)],
+                ],
+                distance: field(34, 2) << 1 | field(32, 1),
+                opaque,
+                indices,
+                padding: padding(&T_UNUSED),
+            },
+            Mode::H => Etc2Block::H {
+                colours: [
+                    [
+                        field(59, 4),
+                        field(56, 3) << 1 | field(52, 1),
+                        field(51, 1) << 3 | field(47, 3),
+                    ],
+                    [field(43, 4), field(39, 4), field(35, 4)],
+                ],
+                distance_high: field(34, 1) << 1 | field(32, 1),
+                opaque,
+                indices,
+                padding: padding(&H_UNUSED),
+            },
+            Mode::Planar => Etc2Block::Planar {
+                origin: [
+                    field(57, 6),
+                    field(56, 1) << 6 | field(49, 6),
+                    field(48, 1) << 5 | field(43, 2) << 3 | field(39, 3),
+                ],
+                horizontal: [field(34, 5) << 1 | field(32, 1), field(25, 7), field(19, 6)],
+                vertical: [field(13, 6), field(6, 7), field(0, 6)],
+                opaque,
+                padding: padding(&PLANAR_UNUSED),
+            },
+        }
+    }
+
+    /// Packs the fields of a block
+    pub fn to_bytes(self) -> [u8; 8] {
+        // packs the channels of a colour `step` bits apart, starting with red at `shift`
+        let colour = |c: [u8; 3], bits: u32, shift: u32, step: u32| {
+            c.iter().enumerate().fold(0, |block, (i, &value)| {
+                let mask = (1 << bits) - 1;
+                block | (u64::from(value) & mask) << (shift - step * i as u32)
+            })
+        };
+        let header = |tables: [u8; 2], flip: bool, opaque: bool| {
+            u64::from(tables[0] & 7) << 37
+                | u64::from(tables[1] & 7) << 34
+                | u64::from(opaque) << 33
+                | u64::from(flip) << 32
+        };
+
+        // the unused bits are kept if the block still decodes in the wanted mode, and can
+        // always be set so that it does otherwise
+        let force = |block: u64, free_bits: &[u32], padding: u8, wanted| {
+            let kept = free_bits
+                .iter()
+                .enumerate()
+                .fold(block, |block, (i, &bit)| {
+                    block | u64::from(padding >> i & 1) << bit
+                });
+            if mode(kept, true) == wanted {
+                kept
+            } else {
+                force_mode(block, free_bits, wanted, true).expect("mode can be selected")
+            }
+        };
+
+        let block = match self {
+            Etc2Block::Individual {
+                colours,
+                tables,
+                flip,
+                indices,
+            } => {
+                let mut block = colour(colours[0], 4, 60, 8) | colour(colours[1], 4, 56, 8);
+                block |= header(tables, flip, false);
+                write_indices(&mut block, &indices);
+                block
+            }
+            Etc2Block::Differential {
+                colour: base,
+                delta,
+                tables,
+                flip,
+                opaque,
+                indices,
+            } => {
+                let mut block = colour(base, 5, 59, 8) | colour(delta.map(|d| d as u8), 3, 56, 8);
+                block |= header(tables, flip, opaque);
+                write_indices(&mut block, &indices);
+                block
+            }
+            Etc2Block::T {
+                colours: [c1, c2],
+                distance,
+                opaque,
+                indices,
+                padding,
+            } => {
+                let c1 = c1.map(u64::from);
+                let mut block = (c1[0] >> 2 & 3) << 59 | (c1[0] & 3) << 56;
+                block |= (c1[1] & 0x0F) << 52 | (c1[2] & 0x0F) << 48;
+                block |= colour(c2, 4, 44, 4);
+                block |= u64::from(distance >> 1 & 3) << 34 | u64::from(distance & 1) << 32;
+                block |= u64::from(opaque) << 33;
+                write_indices(&mut block, &indices);
+                force(block, &T_UNUSED, padding, Mode::T)
+            }
+            Etc2Block::H {
+                colours: [c1, c2],
+                distance_high,
+                opaque,
+                indices,
+                padding,
+            } => {
+                let c1 = c1.map(u64::from);
+                let mut block = (c1[0] & 0x0F) << 59 | (c1[1] >> 1 & 7) << 56;
+                block |= (c1[1] & 1) << 52 | (c1[2] >> 3 & 1) << 51 | (c1[2] & 7) << 47;
+                block |= colour(c2, 4, 43, 4);
+                block |= u64::from(distance_high >> 1 & 1) << 34;
+                block |= u64::from(distance_high & 1) << 32;
+                block |= u64::from(opaque) << 33;
+                write_indices(&mut block, &indices);
+                force(block, &H_UNUSED, padding, Mode::H)
+            }
+            Etc2Block::Planar {
+                origin,
+                horizontal,
+                vertical,
+                opaque,
+                padding,
+            } => {
+                let colours = [origin, horizontal, vertical].map(|c| c.map(i32::from));
+                let limits = [0x3F, 0x7F, 0x3F];
+                let colours = colours.map(|c| [0, 1, 2].map(|i| c[i] & limits[i]));
+                let block = pack_planar(&colours) & !(1 << 33) | u64::from(opaque) << 33;
+                force(block, &PLANAR_UNUSED, padding, Mode::Planar)
+            }
+        };
+
+        block.to_be_bytes()
+    }
+}
+
+//--------------------------------------------------------------------------------
+// Compression
+//--------------------------------------------------------------------------------
+
+/// Search effort for the different compression algorithms
+struct Effort {
+    /// How far around the average colour to search for base colours
+    radius: i32,
+    /// Clustering iterations for the T and H modes
+    iterations: usize,
+    /// How far around the cluster averages to search for T and H mode colours
+    paint_radius: i32,
+    /// Whether to search the neighbourhood of the least squares planar mode colours
+    refine: bool,
+}
+
+impl Effort {
+    fn new(algorithm: Algorithm) -> Self {
+        match algorithm {
+            Algorithm::RangeFit => Effort {
+                radius: 0,
+                iterations: 1,
+                paint_radius: 0,
+                refine: false,
+            },
+            Algorithm::ClusterFit => Effort {
+                radius: 1,
+                iterations: 4,
+                paint_radius: 0,
+                refine: true,
+            },
+            Algorithm::IterativeClusterFit => Effort {
+                radius: 2,
+                iterations: 8,
+                paint_radius: 1,
+                refine: true,
+            },
+        }
+    }
+}
+
+/// Pixel values and error weights of the block being compressed
+struct Pixels {
+    colours: [[i32; 3]; 16],
+    /// Per-pixel weights, zero for masked and transparent pixels
+    weights: [f32; 16],
+    channel_weights: [f32; 3],
+    /// Pixels that have to decode as transparent
+    transparent: u16,
+}
+
+impl Pixels {
+    fn new(rgba: &[[u8; 4]; 16], mask: u32, punchthrough: bool, params: &Params) -> Self {
+        let mut pixels = Pixels {
+            colours: [[0; 3]; 16],
+            weights: [0.0; 16],
+            channel_weights: params.weights,
+            transparent: 0,
+        };
+
+        for (i, pixel) in rgba.iter().enumerate() {
+            if (mask & (1 << i)) == 0 {
+                continue;
+            }
+            if punchthrough && pixel[3] < params.alpha_threshold {
+                pixels.transparent |= 1 << i;
+                continue;
+            }
+
+            pixels.colours[i] = [pixel[0], pixel[1], pixel[2]].map(i32::from);
+            pixels.weights[i] = if params.weigh_colour_by_alpha {
+                (f32::from(pixel[3]) + 1.0) / 256.0
+            } else {
+                1.0
+            };
+        }
+
+        pixels
+    }
+
+    /// Weighted squared error of a pixel against a colour
+    fn error(&self, pixel: usize, colour: [i32; 3]) -> f32 {
+        let mut error = 0.0;
+        for ((&value, &target), &weight) in self.colours[pixel]
+            .iter()
+            .zip(&colour)
+            .zip(&self.channel_weights)
+        {
+            let d = (value - target) as f32;
+            error += weight * d * d;
+        }
+        self.weights[pixel] * error
+    }
+
+    /// Weighted squared error of a decoded block
+    fn block_error(&self, decoded: &[[u8; 4]; 16]) -> f32 {
+        let mut error = 0.0;
+        for (i, pixel) in decoded.iter().enumerate() {
+            if (self.transparent & (1 << i)) != 0 {
+                if pixel[3] != 0 {
+                    return f32::MAX;
+                }
+                continue;
+            }
+            if self.weights[i] > 0.0 && pixel[3] == 0 {
+                return f32::MAX;
+            }
+            error += self.error(i, [pixel[0], pixel[1], pixel[2]].map(i32::from));
+        }
+        error
+    }
+
+    /// Weighted average colour of a set of pixels
+    fn average(&self, members: u16) -> Option<[f32; 3]> {
+        let mut total = 0.0;
+        let mut sum = [0f32; 3];
+        for i in 0..16 {
+            if (members & (1 << i)) == 0 {
+                continue;
+            }
+            for (sum, &value) in sum.iter_mut().zip(&self.colours[i]) {
+                *sum += self.weights[i] * value as f32;
+            }
+            total += self.weights[i];
+        }
+
+        if total > 0.0 {
+            Some(sum.map(|value| value / total))
+        } else {
+            None
+        }
+    }
+
+    /// Centre of the bounding box of a set of pixels
+    fn midrange(&self, members: u16) -> Option<[f32; 3]> {
+        let mut min = [i32::MAX; 3];
+        let mut max = [i32::MIN; 3];
+        for i in 0..16 {
+            if (members & (1 << i)) == 0 || self.weights[i] == 0.0 {
+                continue;
+            }
+            for c in 0..3 {
+                min[c] = min[c].min(self.colours[i][c]);
+                max[c] = max[c].max(self.colours[i][c]);
+            }
+        }
+
+        if min[0] <= max[0] {
+            Some([0, 1, 2].map(|c| (min[c] + max[c]) as f32 / 2.0))
+        } else {
+            None
+        }
+    }
+
+    /// Picks the palette entry with the least error for each pixel in a set and returns the
+    /// total error. Transparent pixels are assigned the transparent index if there is one.
+    fn assign(
+        &self,
+        members: u16,
+        palette: &[[i32; 3]; 4],
+        transparent: bool,
+        block: &mut u64,
+    ) -> f32 {
+        let mut total = 0.0;
+        for i in 0..16 {
+            if (members & (1 << i)) == 0 {
+                continue;
+            }
+            if transparent && (self.transparent & (1 << i)) != 0 {
+                write_index(block, i, TRANSPARENT);
+                continue;
+            }
+
+            let mut least = f32::MAX;
+            let mut index = 0;
+            for (j, &colour) in palette.iter().enumerate() {
+                if transparent && j == TRANSPARENT {
+                    continue;
+                }
+                let error = self.error(i, colour);
+                if error < least {
+                    least = error;
+                    index = j;
+                }
+            }
+
+            write_index(block, i, index);
+            total += least;
+        }
+        total
+    }
+}
+
+/// Quantises a colour channel to the given number of bits
+fn quantise(value: f32, bits: u32) -> i32 {
+    let max = (1 << bits) - 1;
+    (libm::roundf(value * max as f32 / 255.0) as i32).clamp(0, max)
+}
+
+/// Yields the quantised colours within `radius` of a colour, clamped to `limits`
+fn neighbourhood(
+    centre: [i32; 3],
+    radius: i32,
+    limits: [(i32, i32); 3],
+) -> impl Iterator<Item = [i32; 3]> {
+    let range = move |c: usize| {
+        let (min, max) = limits[c];
+        (centre[c] - radius).clamp(min, max)..=(centre[c] + radius).clamp(min, max)
+    };
+    range(0).flat_map(move |r| range(1).flat_map(move |g| range(2).map(move |b| [r, g, b])))
+}
+
+/// Sets the bits that are ignored by a mode so that the block decodes in that mode
+fn force_mode(block: u64, free_bits: &[u32], wanted: Mode, punchthrough: bool) -> Option<u64> {
+    (0..1u64 << free_bits.len()).find_map(|combination| {
+        let mut candidate = block;
+        for (i, &bit) in free_bits.iter().enumerate() {
+            candidate |= ((combination >> i) & 1) << bit;
+        }
+        (mode(candidate, punchthrough) == wanted).then_some(candidate)
+    })
+}
+
+/// Pixels of the two sub-blocks for both flip orientations
+fn subblocks(flip: bool) -> [u16; 2] {
+    if flip {
+        [0x00FF, 0xFF00]
+    } else {
+        [0x3333, 0xCCCC]
+    }
+}
+
+/// Finds the best modifier table for a sub-block and writes its indices
+fn fit_subblock(
+    pixels: &Pixels,
+    members: u16,
+    base: [i32; 3],
+    opaque: bool,
+    block: &mut u64,
+) -> (usize, f32) {
+    let mut best = (0, f32::MAX);
+    let mut best_indices = 0;
+    for table in 0..MODIFIERS.len() {
+        let mut indices = 0;
+        let palette = subblock_palette(base, table, opaque);
+        let error = pixels.assign(members, &palette, !opaque, &mut indices);
+        if error < best.1 {
+            best = (table, error);
+            best_indices = indices;
+        }
+    }
+
+    *block |= best_indices;
+    best
+}
+
+/// Searches base colours for a sub-block around its average colour and, unless compressing
+/// with a range fit, around the centre of its bounding box
+///
+/// Returns the quantised base colour, its modifier table, the sub-block's indices and error.
+fn search_subblock(
+    pixels: &Pixels,
+    members: u16,
+    bits: u32,
+    limits: [(i32, i32); 3],
+    opaque: bool,
+    effort: &Effort,
+) -> ([i32; 3], usize, u64, f32) {
+    let extend = if bits == 4 { extend4 } else { extend5 };
+    let centres = [
+        pixels.average(members),
+        pixels.midrange(members).filter(|_| effort.radius > 0),
+    ];
+
+    let mut best = ([0; 3], 0, 0, f32::MAX);
+    for centre in centres.into_iter().flatten() {
+        let centre = centre.map(|value| quantise(value, bits));
+        for candidate in neighbourhood(centre, effort.radius, limits) {
+            let mut indices = 0;
+            let base = candidate.map(extend);
+            let (table, error) = fit_subblock(pixels, members, base, opaque, &mut indices);
+            if error < best.3 {
+                best = (candidate, table, indices, error);
+            }
+        }
+    }
+
+    best
+}
+
+/// Encodes a block in individual mode
+fn encode_individual(pixels: &Pixels, effort: &Effort) -> [u64; 2] {
+    let full = [(0, 15); 3];
+    [false, true].map(|flip| {
+        let [first, second] = subblocks(flip);
+        let a = search_subblock(pixels, first, 4, full, true, effort);
+        let b = search_subblock(pixels, second, 4, full, true, effort);
+
+        let mut block = a.2 | b.2;
+        for c in 0..3 {
+            let shift = 56 - 8 * c as u32;
+            block |= (a.0[c] as u64) << (shift + 4);
+            block |= (b.0[c] as u64) << shift;
+        }
+        block |= (a.1 as u64) << 37;
+        block |= (b.1 as u64) << 34;
+        block | (u64::from(flip) << 32)
+    })
+}
+
+/// Encodes a block in differential mode, once constraining each sub-block to the other
+fn encode_differential(pixels: &Pixels, opaque: bool, effort: &Effort) -> [u64; 4] {
+    let full = [(0, 31); 3];
+    let mut candidates = [0; 4];
+
+    for (i, candidate) in candidates.iter_mut().enumerate() {
+        let flip = i >= 2;
+        let subblocks = subblocks(flip);
+
+        // fit one sub-block freely, then the other within reach of the 3-bit deltas
+        let free = i % 2;
+        let first = search_subblock(pixels, subblocks[free], 5, full, opaque, effort);
+        let limits = first
+            .0
+            .map(|value| ((value - 4).max(0), (value + 3).min(31)));
+        let limits_back = first
+            .0
+            .map(|value| ((value - 3).max(0), (value + 4).min(31)));
+        let second = search_subblock(
+            pixels,
+            subblocks[1 - free],
+            5,
+            if free == 0 { limits } else { limits_back },
+            opaque,
+            effort,
+        );
+
+        let (a, b) = if free == 0 {
+            (first, second)
+        } else {
+            (second, first)
+        };
+
+        let mut block = a.2 | b.2;
+        for c in 0..3 {
+            let shift = 56 - 8 * c as u32;
+            block |= (a.0[c] as u64) << (shift + 3);
+            block |= ((b.0[c] - a.0[c]) as u64 & 7) << shift;
+        }
+        block |= (a.1 as u64) << 37;
+        block |= (b.1 as u64) << 34;
+        block |= u64::from(opaque) << 33;
+        *candidate = block | (u64::from(flip) << 32);
+    }
+
+    candidates
+}
+
+/// Splits the opaque pixels of a block into two clusters with k-means
+fn cluster(pixels: &Pixels, iterations: usize) -> [u16; 2] {
+    let valid = (0..16).fold(0u16, |members, i| {
+        if pixels.weights[i] > 0.0 {
+            members | (1 << i)
+        } else {
+            members
+        }
+    });
+
+    // start from a split along the principal axis
+    let points = pixels
+        .colours
+        .map(|[r, g, b]| Vec3::new(r as f32, g as f32, b as f32));
+    let covariance = Sym3x3::weighted_covariance(&points, &pixels.weights);
+    let axis = covariance.principle_component();
+    let centre = pixels.average(valid).unwrap_or([0.0; 3]);
+    let centre = Vec3::new(centre[0], centre[1], centre[2]);
+
+    let mut clusters = [0u16; 2];
+    for (i, &point) in points.iter().enumerate() {
+        if (valid & (1 << i)) != 0 {
+            let side = usize::from((point - centre).dot(&axis) > 0.0);
+            clusters[side] |= 1 << i;
+        }
+    }
+
+    for _ in 0..iterations {
+        let means = clusters.map(|members| pixels.average(members));
+        let mut next = [0u16; 2];
+        for i in 0..16 {
+            if (valid & (1 << i)) == 0 {
+                continue;
+            }
+            let distance = |mean: Option<[f32; 3]>| match mean {
+                Some(mean) => (0..3)
+                    .map(|c| {
+                        let d = pixels.colours[i][c] as f32 - mean[c];
+                        pixels.channel_weights[c] * d * d
+                    })
+                    .sum(),
+                None => f32::MAX,
+            };
+            let side = usize::from(distance(means[1]) < distance(means[0]));
+            next[side] |= 1 << i;
+        }
+
+        if next == clusters {
+            break;
+        }
+        clusters = next;
+    }
+
+    clusters
+}
+
+/// Quantised colour candidates for a cluster
+fn cluster_colours(
+    pixels: &Pixels,
+    members: u16,
+    effort: &Effort,
+) -> impl Iterator<Item = [i32; 3]> {
+    let average = pixels.average(members).unwrap_or([0.0; 3]);
+    let centre = average.map(|value| quantise(value, 4));
+
+    // step along one channel at a time to keep the number of colour pairs manageable
+    let radius = effort.paint_radius;
+    let steps = (0..3).flat_map(move |c| {
+        (-radius..=radius)
+            .filter(|&step| step != 0)
+            .map(move |step| {
+                let mut colour = centre;
+                colour[c] = (colour[c] + step).clamp(0, 15);
+                colour
+            })
+    });
+    core::iter::once(centre).chain(steps)
+}
+
+/// Encodes a block in T mode, trying both clusters as the single colour
+fn encode_t(pixels: &Pixels, opaque: bool, effort: &Effort) -> Option<u64> {
+    let clusters = cluster(pixels, effort.iterations);
+
+    let mut best = None;
+    let mut best_error = f32::MAX;
+    for single in 0..2 {
+        for c1 in cluster_colours(pixels, clusters[single], effort) {
+            for c2 in cluster_colours(pixels, clusters[1 - single], effort) {
+                for (distance_index, &distance) in DISTANCES.iter().enumerate() {
+                    let (e1, e2) = (c1.map(extend4), c2.map(extend4));
+                    let palette = [
+                        e1,
+                        offset_colour(e2, distance),
+                        e2,
+                        offset_colour(e2, -distance),
+                    ];
+
+                    let mut block = 0;
+                    let error = pixels.assign(0xFFFF, &palette, !opaque, &mut block);
+                    if error >= best_error {
+                        continue;
+                    }
+
+                    block |= ((c1[0] >> 2) as u64) << 59 | ((c1[0] & 3) as u64) << 56;
+                    block |= (c1[1] as u64) << 52 | (c1[2] as u64) << 48;
+                    block |= (c2[0] as u64) << 44 | (c2[1] as u64) << 40 | (c2[2] as u64) << 36;
+                    block |=
+                        ((distance_index >> 1) as u64) << 34 | (distance_index as u64 & 1) << 32;
+                    block |= u64::from(opaque) << 33;
+
+                    best = force_mode(block, &T_UNUSED, Mode::T, true);
+                    best_error = error;
+                }
+            }
+        }
+    }
+
+    best
+}
+
+/// Encodes a block in H mode
+fn encode_h(pixels: &Pixels, opaque: bool, effort: &Effort) -> Option<u64> {
+    let clusters = cluster(pixels, effort.iterations);
+    let value = |c: [i32; 3]| (c[0] << 8) | (c[1] << 4) | c[2];
+
+    let mut best = None;
+    let mut best_error = f32::MAX;
+    for c1 in cluster_colours(pixels, clusters[0], effort) {
+        for c2 in cluster_colours(pixels, clusters[1], effort) {
+            for (distance_index, &distance) in DISTANCES.iter().enumerate() {
+                // the lowest bit of the distance index is given by the order of the colours
+                let (c1, c2) = if (value(c1) >= value(c2)) == (distance_index & 1 == 1) {
+                    (c1, c2)
+                } else {
+                    (c2, c1)
+                };
+                if (value(c1) >= value(c2)) != (distance_index & 1 == 1) {
+                    // equal colours can only encode odd distance indices
+                    continue;
+                }
+
+                let (e1, e2) = (c1.map(extend4), c2.map(extend4));
+                let palette = [
+                    offset_colour(e1, distance),
+                    offset_colour(e1, -distance),
+                    offset_colour(e2, distance),
+                    offset_colour(e2, -distance),
+                ];
+
+                let mut block = 0;
+                let error = pixels.assign(0xFFFF, &palette, !opaque, &mut block);
+                if error >= best_error {
+                    continue;
+                }
+
+                block |= (c1[0] as u64) << 59 | ((c1[1] >> 1) as u64) << 56;
+                block |= ((c1[1] & 1) as u64) << 52 | ((c1[2] >> 3) as u64) << 51;
+                block |= ((c1[2] & 7) as u64) << 47;
+                block |= (c2[0] as u64) << 43 | (c2[1] as u64) << 39 | (c2[2] as u64) << 35;
+                block |= ((distance_index >> 2) as u64) << 34;
+                block |= ((distance_index >> 1) as u64 & 1) << 32;
+                block |= u64::from(opaque) << 33;
+
+                best = force_mode(block, &H_UNUSED, Mode::H, true);
+                best_error = error;
+            }
+        }
+    }
+
+    best
+}
+
+/// Packs the quantised origin, horizontal and vertical colours of a planar block
+fn pack_planar(colours: &[[i32; 3]; 3]) -> u64 {
+    let [origin, horizontal, vertical] = colours.map(|c| c.map(|value| value as u64));
+
+    let mut block = origin[0] << 57 | (origin[1] >> 6) << 56 | (origin[1] & 0x3F) << 49;
+    block |= (origin[2] >> 5) << 48 | ((origin[2] >> 3) & 3) << 43 | (origin[2] & 7) << 39;
+    block |= (horizontal[0] >> 1) << 34 | (horizontal[0] & 1) << 32;
+    block |= horizontal[1] << 25 | horizontal[2] << 19;
+    block |= vertical[0] << 13 | vertical[1] << 6 | vertical[2];
+    block | 1 << 33
+}
+
+/// Encodes a block in planar mode using a least squares fit of the three colours
+fn encode_planar(pixels: &Pixels, effort: &Effort) -> Option<u64> {
+    // each pixel is a bilinear blend of origin, horizontal and vertical colours
+    let mut ata = [[0f32; 3]; 3];
+    let mut atb = [[0f32; 3]; 3];
+    for i in 0..16 {
+        let w = pixels.weights[i];
+        if w == 0.0 {
+            continue;
+        }
+        let (x, y) = ((i % 4) as f32 / 4.0, (i / 4) as f32 / 4.0);
+        let basis = [1.0 - x - y, x, y];
+        for ((ata, atb), &a) in ata.iter_mut().zip(&mut atb).zip(&basis) {
+            for (ata, &b) in ata.iter_mut().zip(&basis) {
+                *ata += w * a * b;
+            }
+            for (atb, &value) in atb.iter_mut().zip(&pixels.colours[i]) {
+                *atb += w * a * value as f32;
+            }
+        }
+    }
+
+    let det = ata[0][0] * (ata[1][1] * ata[2][2] - ata[1][2] * ata[2][1])
+        - ata[0][1] * (ata[1][0] * ata[2][2] - ata[1][2] * ata[2][0])
+        + ata[0][2] * (ata[1][0] * ata[2][1] - ata[1][1] * ata[2][0]);
+    if det.abs() <= f32::EPSILON {
+        return None;
+    }
+
+    // solve with Cramer's rule
+    let mut colours = [[0i32; 3]; 3];
+    for c in 0..3 {
+        for (unknown, colour) in colours.iter_mut().enumerate() {
+            let mut m = ata;
+            for row in 0..3 {
+                m[row][unknown] = atb[row][c];
+            }
+            let value = (m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+                - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+                + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0]))
+                / det;
+            colour[c] = quantise(value.clamp(0.0, 255.0), if c == 1 { 7 } else { 6 });
+        }
+    }
+
+    let error = |colours: &[[i32; 3]; 3]| {
+        let expanded = colours.map(|c| [extend6(c[0]), extend7(c[1]), extend6(c[2])]);
+        (0..16)
+            .map(|i| pixels.error(i, planar_colour(&expanded, i)))
+            .sum::<f32>()
+    };
+
+    // nudge each quantised value while that reduces the error
+    if effort.refine {
+        let mut best_error = error(&colours);
+        let mut improved = true;
+        while improved {
+            improved = false;
+            for unknown in 0..3 {
+                for c in 0..3 {
+                    let max = if c == 1 { 127 } else { 63 };
+                    for step in [-1, 1] {
+                        let mut candidate = colours;
+                        candidate[unknown][c] = (candidate[unknown][c] + step).clamp(0, max);
+                        let candidate_error = error(&candidate);
+                        if candidate_error < best_error {
+                            best_error = candidate_error;
+                            colours = candidate;
+                            improved = true;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    force_mode(pack_planar(&colours), &PLANAR_UNUSED, Mode::Planar, true)
+}
+
+/// Compresses a 4x4 block of pixels to an ETC2 colour block
+///
+/// With `punchthrough` set, pixels with an alpha below `params.alpha_threshold` are encoded as
+/// transparent.
+pub fn compress_etc2(
+    rgba: &[[u8; 4]; 16],
+    mask: u32,
+    punchthrough: bool,
+    params: &Params,
+    block: &mut [u8],
+) {
+    let pixels = Pixels::new(rgba, mask, punchthrough, params);
+    let effort = Effort::new(params.algorithm);
+    let opaque = pixels.transparent == 0;
+
+    let mut best = 0;
+    let mut best_error = f32::MAX;
+    let mut consider = |candidate: u64| {
+        let error = pixels.block_error(&decompress_etc2(&candidate.to_be_bytes(), punchthrough));
+        if error < best_error {
+            best = candidate;
+            best_error = error;
+        }
+    };
+
+    if !punchthrough {
+        encode_individual(&pixels, &effort)
+            .into_iter()
+            .for_each(&mut consider);
+    }
+    encode_differential(&pixels, opaque, &effort)
+        .into_iter()
+        .for_each(&mut consider);
+    encode_t(&pixels, opaque, &effort)
+        .into_iter()
+        .for_each(&mut consider);
+    encode_h(&pixels, opaque, &effort)
+        .into_iter()
+        .for_each(&mut consider);
+    if opaque {
+        encode_planar(&pixels, &effort)
+            .into_iter()
+            .for_each(&mut consider);
+    }
+
+    block.copy_from_slice(&best.to_be_bytes());
+}
@@ -0,0 +1,117 @@
+// Copyright (c) 2018-2021 Jan Solanti <jhs@psonet.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to	deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be included
+// in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.
+// IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT,
+// TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION WITH THE
+// SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Control over how a large compression job is run
+
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use crate::Error;
+
+/// Thread pool, progress reporting and cancellation for the `*_with_job` functions of `Format`
+///
+/// The default job behaves like the plain compression functions: it runs on the global thread
+/// pool, reports nothing and can't be cancelled.
+#[derive(Clone, Copy, Default)]
+pub struct Job<'a> {
+    #[cfg(feature = "rayon")]
+    thread_pool: Option<&'a rayon::ThreadPool>,
+    progress: Option<&'a (dyn Fn(usize, usize) + Sync)>,
+    cancel: Option<&'a AtomicBool>,
+}
+
+impl<'a> Job<'a> {
+    /// Creates a job that behaves like the plain compression functions
+    pub fn new() -> Self {
+        Job::default()
+    }
+
+    /// Compresses the block rows on a thread pool instead of the global one
+    #[cfg(feature = "rayon")]
+    pub fn with_thread_pool(self, thread_pool: &'a rayon::ThreadPool) -> Self {
+        Job {
+            thread_pool: Some(thread_pool),
+            ..self
+        }
+    }
+
+    /// Calls `progress` with the number of finished block rows and the total number of block
+    /// rows after each row
+    ///
+    /// Rows are compressed in parallel, so this may be called from several threads at once.
+    /// Rate-distortion optimisation revisits every row after compressing it, which counts
+    /// towards the total.
+    pub fn with_progress(self, progress: &'a (dyn Fn(usize, usize) + Sync)) -> Self {
+        Job {
+            progress: Some(progress),
+            ..self
+        }
+    }
+
+    /// Stops the job before the next block row once `cancel` is set
+    ///
+    /// The output is left partially written and the job returns `Error::Cancelled`.
+    pub fn with_cancel(self, cancel: &'a AtomicBool) -> Self {
+        Job {
+            cancel: Some(cancel),
+            ..self
+        }
+    }
+
+    /// Returns the thread pool to compress on, if not the global one
+    #[cfg(feature = "rayon")]
+    pub(crate) fn thread_pool(&self) -> Option<&'a rayon::ThreadPool> {
+        self.thread_pool
+    }
+}
+
+/// A job in progress, which counts the block rows finished over all of its passes
+pub(crate) struct Run<'a> {
+    pub job: &'a Job<'a>,
+    finished: AtomicUsize,
+    total: usize,
+}
+
+impl<'a> Run<'a> {
+    /// Starts a job that works through `total` block rows
+    pub fn new(job: &'a Job<'a>, total: usize) -> Self {
+        Run {
+            job,
+            finished: AtomicUsize::new(0),
+            total,
+        }
+    }
+
+    /// Returns `Error::Cancelled` if the cancellation flag has been set
+    pub fn check(&self) -> Result<(), Error> {
+        match self.job.cancel {
+            Some(cancel) if cancel.load(Ordering::Relaxed) => Err(Error::Cancelled),
+            _ => Ok(()),
+        }
+    }
+
+    /// Reports another finished block row
+    pub fn finish_row(&self) {
+        if let Some(progress) = self.job.progress {
+            let finished = self.finished.fetch_add(1, Ordering::Relaxed) + 1;
+            progress(finished, self.total);
+        }
+    }
+}
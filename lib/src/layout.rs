@@ -0,0 +1,64 @@
+// Copyright (c) 2018-2021 Jan Solanti <jhs@psonet.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to	deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be included
+// in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.
+// IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT,
+// TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION WITH THE
+// SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+/// Arrangement of the channels of 8-bit input pixels
+///
+/// Layouts without alpha read as opaque. The single-channel `R8` and two-channel `Rg8` layouts
+/// leave the missing colour channels at zero, as graphics APIs do when sampling such textures,
+/// while the luminance layouts `L8` and `La8` replicate the value into red, green and blue.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum PixelLayout {
+    R8,
+    Rg8,
+    Rgb8,
+    #[default]
+    Rgba8,
+    Bgr8,
+    Bgra8,
+    L8,
+    La8,
+}
+
+impl PixelLayout {
+    /// Returns the number of bytes per pixel
+    pub fn channels(self) -> usize {
+        match self {
+            PixelLayout::R8 | PixelLayout::L8 => 1,
+            PixelLayout::Rg8 | PixelLayout::La8 => 2,
+            PixelLayout::Rgb8 | PixelLayout::Bgr8 => 3,
+            PixelLayout::Rgba8 | PixelLayout::Bgra8 => 4,
+        }
+    }
+
+    /// Expands the bytes of a single pixel to RGBA
+    pub fn to_rgba(self, pixel: &[u8]) -> [u8; 4] {
+        match self {
+            PixelLayout::R8 => [pixel[0], 0, 0, 255],
+            PixelLayout::Rg8 => [pixel[0], pixel[1], 0, 255],
+            PixelLayout::Rgb8 => [pixel[0], pixel[1], pixel[2], 255],
+            PixelLayout::Rgba8 => [pixel[0], pixel[1], pixel[2], pixel[3]],
+            PixelLayout::Bgr8 => [pixel[2], pixel[1], pixel[0], 255],
+            PixelLayout::Bgra8 => [pixel[2], pixel[1], pixel[0], pixel[3]],
+            PixelLayout::L8 => [pixel[0], pixel[0], pixel[0], 255],
+            PixelLayout::La8 => [pixel[0], pixel[0], pixel[0], pixel[1]],
+        }
+    }
+}
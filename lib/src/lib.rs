@@ -24,14 +24,51 @@
 
 #![no_std]
 
+#[cfg(feature = "std")]
+extern crate std;
+
 mod alpha;
+mod astc;
+mod block;
+mod bptc;
 mod colourblock;
 mod colourfit;
 mod colourset;
+mod diagnostics;
+mod eac;
+mod error;
+mod etc;
+mod job;
+mod layout;
 mod math;
+pub mod metrics;
+mod mipmap;
+mod normal;
+mod rdo;
+mod rect;
+mod ycocg;
 
+pub use crate::alpha::Bc4Block;
+pub use crate::astc::AstcBlock;
+pub use crate::block::{Bc2Block, Bc3Block, Bc5Block, EacRg11Block, Etc2RgbaBlock};
+pub use crate::bptc::{Bc6hBlock, Bc6hFields, Bc7Block, Bc7Fields};
+pub use crate::colourblock::Bc1Block;
 use crate::colourfit::{ClusterFit, ColourFit, RangeFit, SingleColourFit};
 use crate::colourset::ColourSet;
+pub use crate::diagnostics::{BlockDiagnostics, ColourFitKind};
+pub use crate::eac::EacBlock;
+pub use crate::error::Error;
+pub use crate::etc::Etc2Block;
+pub use crate::job::Job;
+use crate::job::Run;
+pub use crate::layout::PixelLayout;
+use crate::math::{f16_to_f32, f32_to_f16};
+pub use crate::mipmap::{
+    mip_chain_len, mip_dimensions, mip_levels, EdgeMode, MipFilter, MipmapParams,
+};
+pub use crate::normal::{decode_dxt5nm, reconstruct_normal_z};
+pub use crate::rect::Rect;
+pub use crate::ycocg::decode_ycocg;
 #[cfg(feature = "rayon")]
 use rayon::prelude::*;
 
@@ -43,27 +80,52 @@ pub enum Format {
     Bc3,
     Bc4,
     Bc5,
+    Bc4Snorm,
+    Bc5Snorm,
+    Bc6hUf16,
+    Bc6hSf16,
+    Bc7,
+    Etc2Rgb,
+    Etc2RgbA1,
+    Etc2Rgba,
+    EacR11,
+    EacRg11,
+    EacR11Snorm,
+    EacRg11Snorm,
+    Astc4x4,
+    Astc5x4,
+    Astc5x5,
+    Astc6x5,
+    Astc6x6,
+    Astc8x5,
+    Astc8x6,
+    Astc8x8,
+    Astc10x5,
+    Astc10x6,
+    Astc10x8,
+    Astc10x10,
+    Astc12x10,
+    Astc12x12,
 }
 
 /// Defines a compression algorithm
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
 pub enum Algorithm {
     /// Fast, low quality
     RangeFit,
 
     /// Slow, high quality
+    #[default]
     ClusterFit,
 
     /// Very slow, very high quality
+    ///
+    /// BC3 alpha, BC4 and BC5 additionally search exhaustively for the best endpoints around
+    /// the refined ones at this setting, which makes them tens of times slower than with
+    /// `ClusterFit`.
     IterativeClusterFit,
 }
 
-impl Default for Algorithm {
-    fn default() -> Self {
-        Algorithm::ClusterFit
-    }
-}
-
 /// RGB colour channel weights for use in block fitting
 pub type ColourWeights = [f32; 3];
 
@@ -73,7 +135,39 @@ pub const COLOUR_WEIGHTS_UNIFORM: ColourWeights = [1.0, 1.0, 1.0];
 /// Weights based on the perceived brightness of each colour channel
 pub const COLOUR_WEIGHTS_PERCEPTUAL: ColourWeights = [0.2126, 0.7152, 0.0722];
 
+/// Defines how the colour values of input pixels are encoded
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColourSpace {
+    /// Values are proportional to light intensity, or aren't colours at all
+    #[default]
+    Linear,
+
+    /// Values are sRGB encoded, as is common for colour textures
+    Srgb,
+}
+
+/// Defines the space in which colour fits measure the difference between colours
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorMetric {
+    /// Squared difference of the RGB values, scaled by the colour weights
+    Rgb,
+
+    /// Squared difference of the luma and chroma of the RGB values
+    YCoCg,
+
+    /// Squared difference in OKLab, a perceptually uniform space that keeps hues stable
+    OkLab,
+
+    /// Squared difference in CIE L*a*b*
+    CieLab,
+}
+
+/// Additional compressor parameters
+///
+/// New options are added in minor releases, so start from `Params::default()` and set the
+/// fields that should differ.
 #[derive(Clone, Copy)]
+#[non_exhaustive]
 pub struct Params {
     /// The compression algorithm to be used
     pub algorithm: Algorithm,
@@ -87,6 +181,65 @@ pub struct Params {
     /// This can significantly increase perceived quality for images that are rendered
     /// using alpha blending.
     pub weigh_colour_by_alpha: bool,
+
+    /// The alpha below which BC1 and ETC2 RGB A1 pixels are encoded as transparent
+    /// (defaults to 128)
+    ///
+    /// Setting this to the cutoff used for alpha testing at runtime, and to the reference
+    /// value of `MipmapParams::alpha_coverage`, keeps the punch-through alpha of every mip
+    /// level in line with the alpha test.
+    pub alpha_threshold: u8,
+
+    /// Treat the input as a tangent space normal map with X and Y in red and green (defaults
+    /// to false)
+    ///
+    /// BC5 then picks the indices of both channels together and refines the endpoints,
+    /// minimising the angles between the decoded and the source normals instead of the error of
+    /// each channel. Z is reconstructed from X and Y on both sides, so blue is ignored.
+    ///
+    /// BC3 stores normal maps in the swizzled DXT5nm layout for hardware without BC5: X in the
+    /// alpha block, Y in green and red and blue at zero, with the colour block fitted by green
+    /// alone and the indices of both blocks picked together by angle. `decode_dxt5nm` turns
+    /// the decoded pixels back into the regular layout.
+    pub normal_map: bool,
+
+    /// Store BC3 colours as scaled YCoCg for hardware without BC7 (defaults to false)
+    ///
+    /// Luma goes in the alpha block and the chroma in red and green of the colour block,
+    /// scaled up by two or four in blocks with little chroma, with the scale kept in blue. This
+    /// roughly doubles the precision of the colours, but drops alpha. The colour block is
+    /// fitted by the error of the chroma, and `decode_ycocg` turns the decoded pixels back into
+    /// RGB. Ignored for normal maps.
+    pub ycocg: bool,
+
+    /// The colour space of the input pixels (defaults to linear)
+    ///
+    /// BC1-BC3 measure the colour error of sRGB input in OKLab by default, computed from the
+    /// linear light values instead of the encoded values. This keeps dark gradients smooth.
+    ///
+    /// Measuring every candidate partition of a cluster fit in OKLab would make it many times
+    /// slower, so the cluster fits rank the partitions by weighted RGB error and only measure
+    /// the best few in OKLab. That makes sRGB input up to about one and a half times
+    /// as slow to compress as linear input.
+    pub colour_space: ColourSpace,
+
+    /// The error metric used by BC1-BC3 colour fits (defaults to picking one by colour space)
+    ///
+    /// `None` uses `ErrorMetric::Rgb` for linear input and `ErrorMetric::OkLab` for sRGB input.
+    /// Only `ErrorMetric::Rgb` applies the colour weights. OKLab and CIE Lab are computed from
+    /// linear light values, decoding sRGB input first. The cluster fits measure the other
+    /// metrics for the partitions with the lowest RGB error only, see `colour_space`.
+    pub error_metric: Option<ErrorMetric>,
+
+    /// The rate-distortion trade-off for BC1-BC5 (defaults to 0, which disables it)
+    ///
+    /// Above zero, blocks reuse the endpoints, the selectors or all of the data of recently
+    /// stored blocks, which makes the output much smaller under general purpose LZ
+    /// compressors. A change is made when the error it adds is less than `rdo_lambda` times
+    /// the number of bits of unmatched data it saves. The error is the one the blocks were
+    /// fitted by, with the colour weights and error metric or the angles of normal maps,
+    /// scaled to squared 8-bit steps. Values around 1 to 10 are a good start.
+    pub rdo_lambda: f32,
 }
 
 impl Default for Params {
@@ -95,25 +248,70 @@ impl Default for Params {
             algorithm: Algorithm::default(),
             weights: COLOUR_WEIGHTS_PERCEPTUAL,
             weigh_colour_by_alpha: false,
+            alpha_threshold: 128,
+            normal_map: false,
+            ycocg: false,
+            colour_space: ColourSpace::default(),
+            error_metric: None,
+            rdo_lambda: 0.0,
         }
     }
 }
 
-/// Returns number of blocks needed for an image of given dimension
+impl Params {
+    /// Returns the error metric for colour fits, resolving the default by colour space
+    fn colour_error_metric(&self) -> ErrorMetric {
+        self.error_metric.unwrap_or(match self.colour_space {
+            ColourSpace::Linear => ErrorMetric::Rgb,
+            ColourSpace::Srgb => ErrorMetric::OkLab,
+        })
+    }
+}
+
+/// Returns number of blocks needed for an image of given dimension, for formats with 4x4
+/// blocks
 pub fn num_blocks(size: usize) -> usize {
-    (size + 3) / 4
+    size.div_ceil(4)
 }
 
 /// BCn formats are laid out in 8-byte blocks of the following types:
 /// * BC1: colour with optional 1-bit alpha
 /// * BC2: paletted alpha, colour
 /// * BC3: gradient alpha, colour
-/// * BC4: gradient alpha
-/// * BC5: gradient alpha, gradient alpha
+/// * BC4: gradient alpha, unsigned or signed (SNORM)
+/// * BC5: gradient alpha, gradient alpha, unsigned or signed (SNORM)
+/// * BC6H: 16-byte block of half float RGB, unsigned (UF16) or signed (SF16)
+/// * BC7: 16-byte block with one of eight modes of partitioned colour and alpha
 ///
 /// BC4 and BC5 reuse the alpha compression scheme for arbitrary one- and two-channel images.
 /// Graphics APIs commonly refer to them as "grayscale", "luminance" or simply "red" for BC4 and
-/// "rg" or "luminance + alpha" for BC5 respectively.
+/// "rg" or "luminance + alpha" for BC5 respectively. The signed variants interpolate between
+/// endpoints in the range -127..127, which suits tangent space normal maps. Like the signed EAC
+/// formats, their 8-bit variants of the compression and decompression functions map the range
+/// -1..1 to 0..255.
+///
+/// BC6H stores HDR data and is best used with the floating point variants of the compression
+/// and decompression functions. The 8-bit variants map it to and from the range 0..1.
+///
+/// The ETC2 formats use the same 4x4 block layout:
+/// * ETC2 RGB: 8-byte block of colour in one of five modes, compatible with ETC1
+/// * ETC2 RGB A1: 8-byte block of colour with punch-through alpha
+/// * ETC2 RGBA: EAC alpha, ETC2 RGB colour
+/// * EAC R11: 11-bit single channel, unsigned or signed (SNORM)
+/// * EAC RG11: two 11-bit channels, unsigned or signed (SNORM)
+///
+/// Like BC4 and BC5, the EAC formats are suited for one- and two-channel images such as
+/// heightmaps and normal maps. The floating point variants of the compression and
+/// decompression functions preserve their full precision. The 8-bit variants map the range of
+/// the signed formats from -1..1 to 0..255.
+///
+/// The ASTC formats store 16-byte blocks covering anywhere from 4x4 to 12x12 pixels, as given
+/// by `block_dimensions`. Their 4x4 block functions operate on the top left 4x4 pixels of a
+/// block, the image functions handle the full footprint.
+///
+/// ASTC blocks are decoded with the HDR profile. The compressor only uses HDR endpoints for
+/// blocks of floating point pixels with colour values above one, which decoders limited to
+/// the LDR profile show as magenta.
 impl Format {
     /// Decompresses an image in memory
     ///
@@ -121,32 +319,291 @@ impl Format {
     /// * `width`  - The width of the source image
     /// * `height` - The height of the source image
     /// * `output` - Space to store the decompressed image
+    ///
+    /// Panics if `data` or `output` are too small for the image, see `try_decompress`.
     pub fn decompress(self, data: &[u8], width: usize, height: usize, output: &mut [u8]) {
-        let blocks_wide = num_blocks(width);
+        self.decompress_with_pitch(data, width, height, output, 4 * width);
+    }
+
+    /// Decompresses an image in memory into rows that are `pitch` values apart
+    ///
+    /// The values between the end of a row and the start of the next are left untouched, which
+    /// allows decompressing into padded buffers or sub-rectangles of larger images.
+    ///
+    /// * `data`   - The compressed image data
+    /// * `width`  - The width of the source image
+    /// * `height` - The height of the source image
+    /// * `output` - Space to store the decompressed image
+    /// * `pitch`  - The distance between the starts of consecutive rows in `output`, at least
+    ///   `4 * width`
+    ///
+    /// Panics if `pitch` is too small or `data` or `output` are too small for the image, see
+    /// `try_decompress_with_pitch`.
+    pub fn decompress_with_pitch(
+        self,
+        data: &[u8],
+        width: usize,
+        height: usize,
+        output: &mut [u8],
+        pitch: usize,
+    ) {
+        expect_image(self.try_decompress_with_pitch(data, width, height, output, pitch));
+    }
+
+    /// Decompresses an image in memory to floating point RGBA pixels
+    ///
+    /// * `data`   - The compressed image data
+    /// * `width`  - The width of the source image
+    /// * `height` - The height of the source image
+    /// * `output` - Space to store the decompressed image
+    ///
+    /// Panics if `data` or `output` are too small for the image, see `try_decompress_f32`.
+    pub fn decompress_f32(self, data: &[u8], width: usize, height: usize, output: &mut [f32]) {
+        self.decompress_f32_with_pitch(data, width, height, output, 4 * width);
+    }
+
+    /// Decompresses an image in memory to floating point RGBA pixels in rows that are `pitch`
+    /// values apart
+    ///
+    /// * `data`   - The compressed image data
+    /// * `width`  - The width of the source image
+    /// * `height` - The height of the source image
+    /// * `output` - Space to store the decompressed image
+    /// * `pitch`  - The distance between the starts of consecutive rows in `output`, at least
+    ///   `4 * width`
+    pub fn decompress_f32_with_pitch(
+        self,
+        data: &[u8],
+        width: usize,
+        height: usize,
+        output: &mut [f32],
+        pitch: usize,
+    ) {
+        self.decompress_blocks(data, width, height, output, pitch, |block, rgba| {
+            self.decompress_footprint_f32(block, rgba)
+        });
+    }
+
+    /// Decompresses an image in memory to half float RGBA pixels, given as their bit patterns
+    ///
+    /// * `data`   - The compressed image data
+    /// * `width`  - The width of the source image
+    /// * `height` - The height of the source image
+    /// * `output` - Space to store the decompressed image
+    ///
+    /// Panics if `data` or `output` are too small for the image, see `try_decompress_f16`.
+    pub fn decompress_f16(self, data: &[u8], width: usize, height: usize, output: &mut [u16]) {
+        self.decompress_f16_with_pitch(data, width, height, output, 4 * width);
+    }
+
+    /// Decompresses an image in memory to half float RGBA pixels, given as their bit patterns,
+    /// in rows that are `pitch` values apart
+    ///
+    /// * `data`   - The compressed image data
+    /// * `width`  - The width of the source image
+    /// * `height` - The height of the source image
+    /// * `output` - Space to store the decompressed image
+    /// * `pitch`  - The distance between the starts of consecutive rows in `output`, at least
+    ///   `4 * width`
+    pub fn decompress_f16_with_pitch(
+        self,
+        data: &[u8],
+        width: usize,
+        height: usize,
+        output: &mut [u16],
+        pitch: usize,
+    ) {
+        self.decompress_blocks(data, width, height, output, pitch, |block, rgba| {
+            let mut pixels = [[0.0; 4]; astc::MAX_TEXELS];
+            self.decompress_footprint_f32(block, &mut pixels[..rgba.len()]);
+            for (pixel, value) in rgba.iter_mut().zip(pixels) {
+                *pixel = value.map(f32_to_f16);
+            }
+        });
+    }
+
+    /// Decompresses an image in memory, checking the buffer sizes instead of panicking
+    ///
+    /// * `data`   - The compressed image data
+    /// * `width`  - The width of the source image
+    /// * `height` - The height of the source image
+    /// * `output` - Space to store the decompressed image
+    pub fn try_decompress(
+        self,
+        data: &[u8],
+        width: usize,
+        height: usize,
+        output: &mut [u8],
+    ) -> Result<(), Error> {
+        self.try_decompress_with_pitch(data, width, height, output, width.saturating_mul(4))
+    }
+
+    /// Decompresses an image in memory into rows that are `pitch` values apart, checking the
+    /// pitch and the buffer sizes instead of panicking
+    ///
+    /// * `data`   - The compressed image data
+    /// * `width`  - The width of the source image
+    /// * `height` - The height of the source image
+    /// * `output` - Space to store the decompressed image
+    /// * `pitch`  - The distance between the starts of consecutive rows in `output`, at least
+    ///   `4 * width`
+    pub fn try_decompress_with_pitch(
+        self,
+        data: &[u8],
+        width: usize,
+        height: usize,
+        output: &mut [u8],
+        pitch: usize,
+    ) -> Result<(), Error> {
+        let (data, output) = self.checked_buffers(data, width, height, output, pitch)?;
+        self.decompress_blocks(data, width, height, output, pitch, |block, rgba| {
+            self.decompress_footprint(block, rgba)
+        });
+        Ok(())
+    }
+
+    /// Decompresses an image in memory to floating point RGBA pixels, checking the buffer
+    /// sizes instead of panicking
+    ///
+    /// * `data`   - The compressed image data
+    /// * `width`  - The width of the source image
+    /// * `height` - The height of the source image
+    /// * `output` - Space to store the decompressed image
+    pub fn try_decompress_f32(
+        self,
+        data: &[u8],
+        width: usize,
+        height: usize,
+        output: &mut [f32],
+    ) -> Result<(), Error> {
+        let (data, output) =
+            self.checked_buffers(data, width, height, output, width.saturating_mul(4))?;
+        self.decompress_f32(data, width, height, output);
+        Ok(())
+    }
+
+    /// Decompresses an image in memory to half float RGBA pixels, checking the buffer sizes
+    /// instead of panicking
+    ///
+    /// * `data`   - The compressed image data
+    /// * `width`  - The width of the source image
+    /// * `height` - The height of the source image
+    /// * `output` - Space to store the decompressed image
+    pub fn try_decompress_f16(
+        self,
+        data: &[u8],
+        width: usize,
+        height: usize,
+        output: &mut [u16],
+    ) -> Result<(), Error> {
+        let (data, output) =
+            self.checked_buffers(data, width, height, output, width.saturating_mul(4))?;
+        self.decompress_f16(data, width, height, output);
+        Ok(())
+    }
+
+    /// Checks that a compressed image and a pixel buffer with rows that are `pitch` values apart
+    /// are large enough for an image and trims them to the exact size the block loops expect
+    fn checked_buffers<'a, T>(
+        self,
+        data: &'a [u8],
+        width: usize,
+        height: usize,
+        output: &'a mut [T],
+        pitch: usize,
+    ) -> Result<(&'a [u8], &'a mut [T]), Error> {
+        let (_, bytes) = self.checked_sizes(width, height)?;
+        let values = checked_image_len(width, height, pitch, 4)?;
+        let data = data.get(..bytes).ok_or(Error::InputTooShort {
+            expected: bytes,
+            actual: data.len(),
+        })?;
+        let actual = output.len();
+        let output = output.get_mut(..values).ok_or(Error::OutputTooShort {
+            expected: values,
+            actual,
+        })?;
+        Ok((data, output))
+    }
+
+    /// Computes the number of pixel values and compressed bytes of an image, rejecting empty
+    /// images and sizes that overflow
+    pub(crate) fn checked_sizes(
+        self,
+        width: usize,
+        height: usize,
+    ) -> Result<(usize, usize), Error> {
+        if width == 0 || height == 0 {
+            return Err(Error::ZeroDimensions);
+        }
+        let (block_width, block_height) = self.block_dimensions();
+        let values = width
+            .checked_mul(height)
+            .and_then(|pixels| pixels.checked_mul(4));
+        let bytes = width
+            .div_ceil(block_width)
+            .checked_mul(height.div_ceil(block_height))
+            .and_then(|blocks| blocks.checked_mul(self.block_size()));
+        values.zip(bytes).ok_or(Error::SizeOverflow)
+    }
+
+    /// Computes the number of rows a job reports progress for: one per block row, and one more
+    /// per block row if rate-distortion optimisation passes over the blocks again
+    fn job_rows(self, height: usize, params: &Params) -> usize {
+        let rows = height.div_ceil(self.block_dimensions().1);
+        if rdo::is_enabled(self, params) {
+            2 * rows
+        } else {
+            rows
+        }
+    }
+
+    /// Decodes every block of an image and writes the pixels to their place in the output
+    fn decompress_blocks<T, F>(
+        self,
+        data: &[u8],
+        width: usize,
+        height: usize,
+        output: &mut [T],
+        pitch: usize,
+        decompress_block: F,
+    ) where
+        T: Copy + Default + Send,
+        F: Fn(&[u8], &mut [[T; 4]]) + Sync,
+    {
+        assert!(pitch >= 4 * width);
+        let (block_width, block_height) = self.block_dimensions();
+        let blocks_wide = width.div_ceil(block_width);
         let block_size = self.block_size();
 
+        // only visit the rows of the image, even if the output is larger
+        let output = &mut output[..image_len(width, height, pitch)];
+
         #[cfg(feature = "rayon")]
-        let output_rows = output.par_chunks_mut(width * 4 * 4);
+        let output_rows = output.par_chunks_mut(pitch * block_height);
         #[cfg(not(feature = "rayon"))]
-        let output_rows = output.chunks_mut(width * 4 * 4);
+        let output_rows = output.chunks_mut(pitch * block_height);
 
         // loop over blocks
         output_rows.enumerate().for_each(|(y, output_row)| {
+            let mut rgba = [[T::default(); 4]; astc::MAX_TEXELS];
             for x in 0..blocks_wide {
                 // decompress the block
                 let bidx = (x + y * blocks_wide) * block_size;
-                let rgba = self.decompress_block(&data[bidx..bidx + block_size]);
+                let rgba = &mut rgba[..block_width * block_height];
+                decompress_block(&data[bidx..bidx + block_size], rgba);
 
                 // write the decompressed pixels to the correct image location
-                for py in 0..4 {
-                    for px in 0..4 {
+                for py in 0..block_height {
+                    for px in 0..block_width {
                         // get target location
-                        let sx = 4 * x + px;
+                        let sx = block_width * x + px;
                         let sy = py;
 
-                        if sx < width && (4 * y + sy) < height {
+                        if sx < width && (block_height * y + sy) < height {
                             for i in 0..4 {
-                                output_row[4 * (sx + sy * width) + i] = rgba[px + py * 4][i];
+                                output_row[4 * sx + sy * pitch + i] =
+                                    rgba[px + py * block_width][i];
                             }
                         }
                     }
@@ -155,29 +612,140 @@ impl Format {
         });
     }
 
-    /// Returns how many bytes a 4x4 block of pixels will compress into
+    /// Decompresses a block of any footprint to 8-bit pixels
+    pub(crate) fn decompress_footprint(self, block: &[u8], rgba: &mut [[u8; 4]]) {
+        if self.is_astc() {
+            let mut pixels = [[0.0; 4]; astc::MAX_TEXELS];
+            self.decompress_footprint_f32(block, &mut pixels[..rgba.len()]);
+            for (pixel, value) in rgba.iter_mut().zip(pixels) {
+                *pixel = value.map(unorm8);
+            }
+        } else {
+            rgba.copy_from_slice(&self.decompress_block(block));
+        }
+    }
+
+    /// Decompresses a block of any footprint to floating point pixels
+    fn decompress_footprint_f32(self, block: &[u8], rgba: &mut [[f32; 4]]) {
+        if self.is_astc() {
+            let (width, height) = self.block_dimensions();
+            astc::decompress_astc(&block[..16], width, height, rgba);
+        } else {
+            rgba.copy_from_slice(&self.decompress_block_f32(block));
+        }
+    }
+
+    /// Returns the width and height in pixels of the blocks of the format
+    pub fn block_dimensions(self) -> (usize, usize) {
+        match self {
+            Format::Astc4x4 => (4, 4),
+            Format::Astc5x4 => (5, 4),
+            Format::Astc5x5 => (5, 5),
+            Format::Astc6x5 => (6, 5),
+            Format::Astc6x6 => (6, 6),
+            Format::Astc8x5 => (8, 5),
+            Format::Astc8x6 => (8, 6),
+            Format::Astc8x8 => (8, 8),
+            Format::Astc10x5 => (10, 5),
+            Format::Astc10x6 => (10, 6),
+            Format::Astc10x8 => (10, 8),
+            Format::Astc10x10 => (10, 10),
+            Format::Astc12x10 => (12, 10),
+            Format::Astc12x12 => (12, 12),
+            _ => (4, 4),
+        }
+    }
+
+    /// Converts a float channel value to the 8-bit value the format is compressed from, which
+    /// maps -1..1 to 0..255 for the signed formats
+    fn to_8bit(self, value: f32) -> u8 {
+        match self {
+            Format::Bc4Snorm | Format::Bc5Snorm | Format::EacR11Snorm | Format::EacRg11Snorm => {
+                snorm8(value)
+            }
+            _ => unorm8(value),
+        }
+    }
+
+    /// Whether the format is one of the ASTC formats
+    fn is_astc(self) -> bool {
+        matches!(
+            self,
+            Format::Astc4x4
+                | Format::Astc5x4
+                | Format::Astc5x5
+                | Format::Astc6x5
+                | Format::Astc6x6
+                | Format::Astc8x5
+                | Format::Astc8x6
+                | Format::Astc8x8
+                | Format::Astc10x5
+                | Format::Astc10x6
+                | Format::Astc10x8
+                | Format::Astc10x10
+                | Format::Astc12x10
+                | Format::Astc12x12
+        )
+    }
+
+    /// Returns how many bytes a block of pixels will compress into
     pub fn block_size(self) -> usize {
         // Compressed block size in bytes
         match self {
             Format::Bc1 => 8,
             Format::Bc2 => 16,
             Format::Bc3 => 16,
-            Format::Bc4 => 8,
-            Format::Bc5 => 16,
+            Format::Bc4 | Format::Bc4Snorm => 8,
+            Format::Bc5 | Format::Bc5Snorm => 16,
+            Format::Bc6hUf16 | Format::Bc6hSf16 => 16,
+            Format::Bc7 => 16,
+            Format::Etc2Rgb | Format::Etc2RgbA1 => 8,
+            Format::Etc2Rgba => 16,
+            Format::EacR11 | Format::EacR11Snorm => 8,
+            Format::EacRg11 | Format::EacRg11Snorm => 16,
+            Format::Astc4x4
+            | Format::Astc5x4
+            | Format::Astc5x5
+            | Format::Astc6x5
+            | Format::Astc6x6
+            | Format::Astc8x5
+            | Format::Astc8x6
+            | Format::Astc8x8
+            | Format::Astc10x5
+            | Format::Astc10x6
+            | Format::Astc10x8
+            | Format::Astc10x10
+            | Format::Astc12x10
+            | Format::Astc12x12 => 16,
         }
     }
 
     /// Computes the amount of space in bytes needed for an image of given size,
-    /// accounting for padding to a multiple of 4x4 pixels
+    /// accounting for padding to a multiple of the block dimensions
     ///
     /// * `width`  - Width of the uncompressed image
     /// * `height` - Height of the uncompressed image
     pub fn compressed_size(self, width: usize, height: usize) -> usize {
         // Number of blocks required for image of given dimensions
-        let blocks = num_blocks(width) * num_blocks(height);
+        let (block_width, block_height) = self.block_dimensions();
+        let blocks = width.div_ceil(block_width) * height.div_ceil(block_height);
         blocks * self.block_size()
     }
 
+    /// Computes the amount of space in bytes needed for every level of a full mip chain of an
+    /// image of given size, stored one after the other
+    ///
+    /// * `width`  - Width of the full size image
+    /// * `height` - Height of the full size image
+    pub fn compressed_mip_chain_size(self, width: usize, height: usize) -> usize {
+        (0..mip_levels(width, height))
+            .map(|level| {
+                let (width, height) = mip_dimensions(width, height, level);
+                self.compressed_size(width, height)
+            })
+            .sum()
+    }
+
     /// Compresses a 4x4 block of pixels, masking out some pixels e.g. for padding the
     /// image to a multiple of the block size.
     ///
@@ -192,45 +760,231 @@ impl Format {
         params: Params,
         output: &mut [u8],
     ) {
+        self.compress_block_recorded(rgba, mask, params, output);
+    }
+
+    /// Compresses a 4x4 block of pixels like `compress_block_masked`, returning how it was
+    /// encoded, without the error
+    fn compress_block_recorded(
+        self,
+        rgba: [[u8; 4]; 16],
+        mask: u32,
+        params: Params,
+        output: &mut [u8],
+    ) -> BlockDiagnostics {
+        let mut record = BlockDiagnostics::default();
+
+        // DXT5nm moves X to alpha and Y to green, and fits the colours by green alone. YCoCg
+        // moves luma to alpha and the chroma to red and green, and fits the colours by chroma.
+        let dxt5nm = self == Format::Bc3 && params.normal_map;
+        let ycocg = self == Format::Bc3 && params.ycocg && !params.normal_map;
+        let (rgba, params) = if dxt5nm {
+            (
+                rgba.map(normal::swizzle_dxt5nm),
+                normal::dxt5nm_params(&params),
+            )
+        } else if ycocg {
+            (ycocg::encode(&rgba, mask), ycocg::params(&params))
+        } else {
+            (rgba, params)
+        };
+
         // compress alpha block(s)
         match self {
-            Format::Bc1 => {}
+            Format::Bc1 | Format::Bc6hUf16 | Format::Bc6hSf16 | Format::Bc7 => {}
+            Format::Etc2Rgb | Format::Etc2RgbA1 => {}
+            Format::EacR11 | Format::EacRg11 | Format::EacR11Snorm | Format::EacRg11Snorm => {}
+            Format::Bc4Snorm | Format::Bc5Snorm => {}
+            Format::Astc4x4
+            | Format::Astc5x4
+            | Format::Astc5x5
+            | Format::Astc6x5
+            | Format::Astc6x6
+            | Format::Astc8x5
+            | Format::Astc8x6
+            | Format::Astc8x8
+            | Format::Astc10x5
+            | Format::Astc10x6
+            | Format::Astc10x8
+            | Format::Astc10x10
+            | Format::Astc12x10
+            | Format::Astc12x12 => {}
             Format::Bc2 => alpha::compress_bc2(&rgba, mask, &mut output[..8]),
-            Format::Bc3 => alpha::compress_bc3(&rgba, 3, mask, &mut output[..8]),
-            Format::Bc4 => alpha::compress_bc3(&rgba, 0, mask, &mut output[..8]),
+            Format::Bc3 | Format::Bc4 => {
+                let channel = if self == Format::Bc3 { 3 } else { 0 };
+                let steps = alpha::compress_bc3(&rgba, channel, mask, &params, &mut output[..8]);
+                record.alpha_steps[0] = Some(steps);
+            }
             Format::Bc5 => {
-                alpha::compress_bc3(&rgba, 0, mask, &mut output[0..8]);
-                alpha::compress_bc3(&rgba, 1, mask, &mut output[8..16]);
+                let red = alpha::compress_bc3(&rgba, 0, mask, &params, &mut output[0..8]);
+                let green = alpha::compress_bc3(&rgba, 1, mask, &params, &mut output[8..16]);
+                let mut steps = [red, green];
+                if params.normal_map {
+                    let normals = rgba.map(|[x, y, _, _]| [x, y].map(normal::from_unorm8));
+                    steps =
+                        normal::fit_bc5(&normals, mask, false, params.algorithm, &mut output[..16]);
+                }
+                record.alpha_steps = steps.map(Some);
             }
+            Format::Etc2Rgba => eac::compress_eac(&rgba, 3, mask, &params, &mut output[..8]),
         }
 
         // compress colour block if the format has one
         match self {
             Format::Bc1 | Format::Bc2 | Format::Bc3 => {
                 // create the minimal point set
-                let colours = ColourSet::new(&rgba, mask, self, params.weigh_colour_by_alpha);
+                let colours = ColourSet::new(&rgba, mask, self, &params);
 
                 let colour_offset = if self == Format::Bc1 { 0 } else { 8 };
                 let colour_block = &mut output[colour_offset..colour_offset + 8];
 
                 // compress with appropriate compression algorithm
-                if colours.count() == 1 {
-                    // Single colour fit can't handle fully transparent blocks, hence the
-                    // set has to contain at least 1 colour. It's also not very useful for
-                    // anything more complex so we only use it for blocks of uniform colour.
-                    let mut fit = SingleColourFit::new(&colours, self);
-                    fit.compress(colour_block);
-                } else if (params.algorithm == Algorithm::RangeFit) || (colours.count() == 0) {
-                    let mut fit = RangeFit::new(&colours, self, params.weights);
-                    fit.compress(colour_block);
-                } else {
-                    let iterate = params.algorithm == Algorithm::IterativeClusterFit;
-                    let mut fit = ClusterFit::new(&colours, self, params.weights, iterate);
-                    fit.compress(colour_block);
-                }
+                let kind = ColourFitKind::select(&colours, &params);
+                record.colour_fit = Some(kind);
+                record.three_colour = match kind {
+                    ColourFitKind::SingleColour => {
+                        let mut fit = SingleColourFit::new(&colours, self);
+                        fit.compress(colour_block)
+                    }
+                    ColourFitKind::Range => {
+                        let mut fit = RangeFit::new(&colours, self, &params);
+                        fit.compress(colour_block)
+                    }
+                    ColourFitKind::Cluster => {
+                        let iterate = params.algorithm == Algorithm::IterativeClusterFit;
+                        let mut fit = ClusterFit::new(&colours, self, &params, iterate);
+                        fit.compress(colour_block)
+                    }
+                };
             }
             Format::Bc4 | Format::Bc5 => {}
+            Format::Bc6hUf16 | Format::Bc6hSf16 => {
+                let rgba = rgba.map(|pixel| pixel.map(|value| f32::from(value) / 255.0));
+                self.compress_block_masked_f32(rgba, mask, params, output);
+            }
+            Format::Bc7 => bptc::compress_bc7(&rgba, mask, &params, &mut output[..16]),
+            Format::Etc2Rgb | Format::Etc2RgbA1 => {
+                let punchthrough = self == Format::Etc2RgbA1;
+                etc::compress_etc2(&rgba, mask, punchthrough, &params, &mut output[..8]);
+            }
+            Format::Etc2Rgba => etc::compress_etc2(&rgba, mask, false, &params, &mut output[8..16]),
+            Format::EacR11 | Format::EacRg11 => {
+                let rgba = rgba.map(|pixel| pixel.map(|value| f32::from(value) / 255.0));
+                self.compress_block_masked_f32(rgba, mask, params, output);
+            }
+            Format::Astc4x4
+            | Format::Astc5x4
+            | Format::Astc5x5
+            | Format::Astc6x5
+            | Format::Astc6x6
+            | Format::Astc8x5
+            | Format::Astc8x6
+            | Format::Astc8x8
+            | Format::Astc10x5
+            | Format::Astc10x6
+            | Format::Astc10x8
+            | Format::Astc10x10
+            | Format::Astc12x10
+            | Format::Astc12x12 => {
+                let rgba = rgba.map(|pixel| pixel.map(|value| f32::from(value) / 255.0));
+                self.compress_block_masked_f32(rgba, mask, params, output);
+            }
+            Format::Bc4Snorm | Format::Bc5Snorm | Format::EacR11Snorm | Format::EacRg11Snorm => {
+                let rgba = rgba.map(|pixel| pixel.map(from_snorm8));
+                record = self.compress_block_recorded_f32(rgba, mask, params, output);
+            }
+        }
+
+        if dxt5nm {
+            let normals = rgba.map(|[_, y, _, x]| [x, y].map(normal::from_unorm8));
+            normal::select_dxt5nm(&normals, mask, &mut output[..16]);
+        }
+        if ycocg {
+            ycocg::store_scale(&mut output[8..16], rgba[0][2]);
+        }
+        record
+    }
+
+    /// Compresses a 4x4 block of floating point pixels, masking out some pixels e.g. for
+    /// padding the image to a multiple of the block size.
+    ///
+    /// The signed BC4 and BC5 formats compress values in the range -1..1. The EAC formats
+    /// compress the values at their full precision. ASTC does the same for
+    /// values in the range 0..1 and switches to HDR endpoints for blocks with colour values
+    /// above one. Other formats except BC6H clamp the values to 0..1 and compress them as
+    /// 8-bit pixels.
+    ///
+    /// * `rgba`   - The uncompressed block of pixels
+    /// * `mask`   - The valid pixel mask
+    /// * `params` - Additional compressor parameters
+    /// * `output` - Storage for the compressed block
+    pub fn compress_block_masked_f32(
+        self,
+        rgba: [[f32; 4]; 16],
+        mask: u32,
+        params: Params,
+        output: &mut [u8],
+    ) {
+        self.compress_block_recorded_f32(rgba, mask, params, output);
+    }
+
+    /// Compresses a 4x4 block of floating point pixels like `compress_block_masked_f32`,
+    /// returning how it was encoded, without the error
+    fn compress_block_recorded_f32(
+        self,
+        rgba: [[f32; 4]; 16],
+        mask: u32,
+        params: Params,
+        output: &mut [u8],
+    ) -> BlockDiagnostics {
+        let mut record = BlockDiagnostics::default();
+        match self {
+            Format::Bc6hUf16 | Format::Bc6hSf16 => {
+                let signed = self == Format::Bc6hSf16;
+                bptc::compress_bc6h(&rgba, mask, signed, &params, &mut output[..16]);
+            }
+            Format::Bc4Snorm => {
+                let steps = alpha::compress_bc4_snorm(&rgba, 0, mask, &params, &mut output[..8]);
+                record.alpha_steps[0] = Some(steps);
+            }
+            Format::Bc5Snorm => {
+                let red = alpha::compress_bc4_snorm(&rgba, 0, mask, &params, &mut output[0..8]);
+                let green = alpha::compress_bc4_snorm(&rgba, 1, mask, &params, &mut output[8..16]);
+                let mut steps = [red, green];
+                if params.normal_map {
+                    let normals = rgba.map(|[x, y, _, _]| [x, y]);
+                    steps =
+                        normal::fit_bc5(&normals, mask, true, params.algorithm, &mut output[..16]);
+                }
+                record.alpha_steps = steps.map(Some);
+            }
+            Format::EacR11 | Format::EacR11Snorm => {
+                let signed = self == Format::EacR11Snorm;
+                eac::compress_eac11(&rgba, 0, mask, signed, &params, &mut output[..8]);
+            }
+            Format::EacRg11 | Format::EacRg11Snorm => {
+                let signed = self == Format::EacRg11Snorm;
+                eac::compress_eac11(&rgba, 0, mask, signed, &params, &mut output[..8]);
+                eac::compress_eac11(&rgba, 1, mask, signed, &params, &mut output[8..16]);
+            }
+            _ if self.is_astc() => {
+                // the block occupies the top left corner of the footprint
+                let (width, _) = self.block_dimensions();
+                let mut pixels = [[0.0; 4]; astc::MAX_TEXELS];
+                let mut valid = [false; astc::MAX_TEXELS];
+                for (i, pixel) in rgba.iter().enumerate() {
+                    let index = i % 4 + (i / 4) * width;
+                    pixels[index] = *pixel;
+                    valid[index] = (mask & (1 << i)) != 0;
+                }
+                self.compress_footprint_f32(&pixels, &valid, params, output);
+            }
+            _ => {
+                let rgba = rgba.map(|pixel| pixel.map(unorm8));
+                record = self.compress_block_recorded(rgba, mask, params, output);
+            }
         }
+        record
     }
 
     /// Decompresses a 4x4 block of pixels
@@ -249,6 +1003,35 @@ impl Format {
                 // decompress colour block
                 rgba = colourblock::decompress(colour_block, self == Format::Bc1);
             }
+            Format::Bc6hUf16 | Format::Bc6hSf16 => {
+                rgba = self
+                    .decompress_block_f32(block)
+                    .map(|pixel| pixel.map(unorm8));
+            }
+            Format::Bc7 => {
+                rgba = bptc::decompress_bc7(&block[..16]);
+            }
+            Format::Etc2Rgb | Format::Etc2RgbA1 => {
+                rgba = etc::decompress_etc2(&block[..8], self == Format::Etc2RgbA1);
+            }
+            Format::Etc2Rgba => {
+                rgba = etc::decompress_etc2(&block[8..16], false);
+            }
+            Format::EacR11 | Format::EacRg11 => {
+                rgba = self
+                    .decompress_block_f32(block)
+                    .map(|pixel| pixel.map(unorm8));
+            }
+            Format::Bc4Snorm | Format::Bc5Snorm | Format::EacR11Snorm | Format::EacRg11Snorm => {
+                rgba = self
+                    .decompress_block_f32(block)
+                    .map(|pixel| pixel.map(snorm8));
+            }
+            _ if self.is_astc() => {
+                rgba = self
+                    .decompress_block_f32(block)
+                    .map(|pixel| pixel.map(unorm8));
+            }
             _ => {
                 rgba = [[0, 0, 0, 0xFF]; 16];
             }
@@ -256,7 +1039,24 @@ impl Format {
 
         // decompress alpha block(s)
         match self {
-            Format::Bc1 => (),
+            Format::Bc1 | Format::Bc6hUf16 | Format::Bc6hSf16 | Format::Bc7 => (),
+            Format::Etc2Rgb | Format::Etc2RgbA1 => (),
+            Format::EacR11 | Format::EacRg11 | Format::EacR11Snorm | Format::EacRg11Snorm => (),
+            Format::Bc4Snorm | Format::Bc5Snorm => (),
+            Format::Astc4x4
+            | Format::Astc5x4
+            | Format::Astc5x5
+            | Format::Astc6x5
+            | Format::Astc6x6
+            | Format::Astc8x5
+            | Format::Astc8x6
+            | Format::Astc8x8
+            | Format::Astc10x5
+            | Format::Astc10x6
+            | Format::Astc10x8
+            | Format::Astc10x10
+            | Format::Astc12x10
+            | Format::Astc12x12 => (),
             Format::Bc2 => alpha::decompress_bc2(&mut rgba, &block[..8]),
             Format::Bc3 => alpha::decompress_bc3(&mut rgba, 3, &block[..8]),
             Format::Bc4 => {
@@ -271,235 +1071,3494 @@ impl Format {
                 alpha::decompress_bc3(&mut rgba, 0, &block[..8]);
                 alpha::decompress_bc3(&mut rgba, 1, &block[8..16]);
             }
+            Format::Etc2Rgba => eac::decompress_eac(&mut rgba, 3, &block[..8]),
         }
 
         rgba
     }
 
-    /// Compresses an image in memory
+    /// Decompresses a 4x4 block of pixels to floating point values
     ///
-    /// * `rgba`   - The uncompressed pixel data
-    /// * `width`  - The width of the source image
-    /// * `height` - The height of the source image
-    /// * `params` - Additional compressor parameters
-    /// * `output` - Output buffer for the compressed image. Ensure that this has
-    /// at least as much space available as `compute_compressed_size` suggests.
-    pub fn compress(
-        self,
-        rgba: &[u8],
-        width: usize,
-        height: usize,
-        params: Params,
-        output: &mut [u8],
-    ) {
-        assert!(output.len() >= self.compressed_size(width, height));
-
-        let block_size = self.block_size();
-        let blocks_wide = num_blocks(width);
+    /// The signed BC4 and BC5 formats are decompressed to -1..1. The EAC formats are decompressed
+    /// at their full precision, to 0..1 or -1..1 for the signed variants. ASTC blocks are
+    /// decompressed to 0..1, or to half float values where they use HDR endpoints. Other formats
+    /// except BC6H are decompressed as 8-bit pixels and scaled to 0..1.
+    ///
+    /// * `block`  - The compressed block of pixels
+    pub fn decompress_block_f32(self, block: &[u8]) -> [[f32; 4]; 16] {
+        match self {
+            Format::Bc6hUf16 | Format::Bc6hSf16 => {
+                let signed = self == Format::Bc6hSf16;
+                bptc::decompress_bc6h(&block[..16], signed).map(|pixel| pixel.map(f16_to_f32))
+            }
+            Format::Bc4Snorm => {
+                let mut rgba = [[0.0, 0.0, 0.0, 1.0]; 16];
+                alpha::decompress_bc4_snorm(&mut rgba, 0, &block[..8]);
+                // splat decompressed value into g and b channels
+                for pixel in &mut rgba {
+                    pixel[1] = pixel[0];
+                    pixel[2] = pixel[0];
+                }
+                rgba
+            }
+            Format::Bc5Snorm => {
+                let mut rgba = [[0.0, 0.0, 0.0, 1.0]; 16];
+                alpha::decompress_bc4_snorm(&mut rgba, 0, &block[..8]);
+                alpha::decompress_bc4_snorm(&mut rgba, 1, &block[8..16]);
+                rgba
+            }
+            Format::EacR11 | Format::EacR11Snorm => {
+                let mut rgba = [[0.0, 0.0, 0.0, 1.0]; 16];
+                eac::decompress_eac11(&mut rgba, 0, self == Format::EacR11Snorm, &block[..8]);
+                // splat decompressed value into g and b channels
+                for pixel in &mut rgba {
+                    pixel[1] = pixel[0];
+                    pixel[2] = pixel[0];
+                }
+                rgba
+            }
+            Format::EacRg11 | Format::EacRg11Snorm => {
+                let signed = self == Format::EacRg11Snorm;
+                let mut rgba = [[0.0, 0.0, 0.0, 1.0]; 16];
+                eac::decompress_eac11(&mut rgba, 0, signed, &block[..8]);
+                eac::decompress_eac11(&mut rgba, 1, signed, &block[8..16]);
+                rgba
+            }
+            _ if self.is_astc() => {
+                // the block occupies the top left corner of the footprint
+                let (width, height) = self.block_dimensions();
+                let mut pixels = [[0.0; 4]; astc::MAX_TEXELS];
+                astc::decompress_astc(&block[..16], width, height, &mut pixels);
+                let mut rgba = [[0.0; 4]; 16];
+                for (i, pixel) in rgba.iter_mut().enumerate() {
+                    *pixel = pixels[i % 4 + (i / 4) * width];
+                }
+                rgba
+            }
+            _ => self
+                .decompress_block(block)
+                .map(|pixel| pixel.map(|value| f32::from(value) / 255.0)),
+        }
+    }
+
+    /// Compresses an image in memory
+    ///
+    /// * `rgba`   - The uncompressed pixel data
+    /// * `width`  - The width of the source image
+    /// * `height` - The height of the source image
+    /// * `params` - Additional compressor parameters
+    /// * `output` - Output buffer for the compressed image. Ensure that this has
+    ///   at least as much space available as `compute_compressed_size` suggests.
+    ///
+    /// Panics if `rgba` or `output` are too small for the image, see `try_compress`.
+    pub fn compress(
+        self,
+        rgba: &[u8],
+        width: usize,
+        height: usize,
+        params: Params,
+        output: &mut [u8],
+    ) {
+        self.compress_with_pitch(rgba, width, height, 4 * width, params, output);
+    }
+
+    /// Compresses an image in memory whose rows are `pitch` values apart
+    ///
+    /// This allows compressing padded buffers or sub-rectangles of larger images in place.
+    ///
+    /// * `rgba`   - The uncompressed pixel data
+    /// * `width`  - The width of the source image
+    /// * `height` - The height of the source image
+    /// * `pitch`  - The distance between the starts of consecutive rows in `rgba`, at least
+    ///   `4 * width`
+    /// * `params` - Additional compressor parameters
+    /// * `output` - Output buffer for the compressed image
+    ///
+    /// Panics if `pitch` is too small or `rgba` or `output` are too small for the image, see
+    /// `try_compress_with_pitch`.
+    pub fn compress_with_pitch(
+        self,
+        rgba: &[u8],
+        width: usize,
+        height: usize,
+        pitch: usize,
+        params: Params,
+        output: &mut [u8],
+    ) {
+        expect_image(self.try_compress_with_pitch(rgba, width, height, pitch, params, output));
+    }
+
+    /// Compresses an image of 8-bit pixels in any layout, reading the pixels directly instead
+    /// of requiring them to be expanded to RGBA first
+    ///
+    /// * `pixels` - The uncompressed pixel data, with rows of `width * layout.channels()` bytes
+    /// * `layout` - The arrangement of the channels of each pixel
+    /// * `width`  - The width of the source image
+    /// * `height` - The height of the source image
+    /// * `params` - Additional compressor parameters
+    /// * `output` - Output buffer for the compressed image
+    ///
+    /// Panics if `pixels` or `output` are too small for the image, see
+    /// `try_compress_with_layout`.
+    pub fn compress_with_layout(
+        self,
+        pixels: &[u8],
+        layout: PixelLayout,
+        width: usize,
+        height: usize,
+        params: Params,
+        output: &mut [u8],
+    ) {
+        let read_pixel = layout_reader(pixels, layout, width, layout.channels() * width);
+        self.compress_pixels(width, height, read_pixel, params, output);
+    }
+
+    /// Compresses an image of 8-bit pixels in any layout whose rows are `pitch` bytes apart,
+    /// checking the pitch and the buffer sizes instead of panicking
+    ///
+    /// * `pixels` - The uncompressed pixel data
+    /// * `layout` - The arrangement of the channels of each pixel
+    /// * `width`  - The width of the source image
+    /// * `height` - The height of the source image
+    /// * `pitch`  - The distance between the starts of consecutive rows in `pixels`, at least
+    ///   `width * layout.channels()`
+    /// * `params` - Additional compressor parameters
+    /// * `output` - Output buffer for the compressed image
+    #[allow(clippy::too_many_arguments)]
+    pub fn try_compress_with_layout(
+        self,
+        pixels: &[u8],
+        layout: PixelLayout,
+        width: usize,
+        height: usize,
+        pitch: usize,
+        params: Params,
+        output: &mut [u8],
+    ) -> Result<(), Error> {
+        let (_, bytes) = self.checked_sizes(width, height)?;
+        let values = checked_image_len(width, height, pitch, layout.channels())?;
+        let pixels = pixels.get(..values).ok_or(Error::InputTooShort {
+            expected: values,
+            actual: pixels.len(),
+        })?;
+        let actual = output.len();
+        let output = output.get_mut(..bytes).ok_or(Error::OutputTooShort {
+            expected: bytes,
+            actual,
+        })?;
+
+        let read_pixel = layout_reader(pixels, layout, width, pitch);
+        self.compress_pixels(width, height, read_pixel, params, output);
+        Ok(())
+    }
+
+    /// Compresses the blocks of an image of 8-bit pixels and optimises them, reading the
+    /// pixels through a function
+    fn compress_pixels<R>(
+        self,
+        width: usize,
+        height: usize,
+        read_pixel: R,
+        params: Params,
+        output: &mut [u8],
+    ) where
+        R: Fn(usize, usize) -> [u8; 4] + Sync,
+    {
+        // the default job can't be cancelled, so every block gets compressed
+        let job = Job::default();
+        let run = Run::new(&job, 0);
+        let _ = self.compress_blocks(
+            width,
+            height,
+            output,
+            &read_pixel,
+            &run,
+            |block, mask, output| self.compress_footprint(block, mask, params, output),
+        );
+        let _ = rdo::optimise(self, width, height, read_pixel, &params, &run, output);
+    }
+
+    /// Compresses an image of floating point RGBA pixels in memory
+    ///
+    /// * `rgba`   - The uncompressed pixel data
+    /// * `width`  - The width of the source image
+    /// * `height` - The height of the source image
+    /// * `params` - Additional compressor parameters
+    /// * `output` - Output buffer for the compressed image. Ensure that this has
+    ///   at least as much space available as `compute_compressed_size` suggests.
+    ///
+    /// Panics if `rgba` or `output` are too small for the image, see `try_compress_f32`.
+    pub fn compress_f32(
+        self,
+        rgba: &[f32],
+        width: usize,
+        height: usize,
+        params: Params,
+        output: &mut [u8],
+    ) {
+        self.compress_f32_with_pitch(rgba, width, height, 4 * width, params, output);
+    }
+
+    /// Compresses an image of floating point RGBA pixels in memory whose rows are `pitch`
+    /// values apart
+    ///
+    /// * `rgba`   - The uncompressed pixel data
+    /// * `width`  - The width of the source image
+    /// * `height` - The height of the source image
+    /// * `pitch`  - The distance between the starts of consecutive rows in `rgba`, at least
+    ///   `4 * width`
+    /// * `params` - Additional compressor parameters
+    /// * `output` - Output buffer for the compressed image
+    pub fn compress_f32_with_pitch(
+        self,
+        rgba: &[f32],
+        width: usize,
+        height: usize,
+        pitch: usize,
+        params: Params,
+        output: &mut [u8],
+    ) {
+        let read_pixel = rgba_reader(rgba, width, pitch);
+        let job = Job::default();
+        let run = Run::new(&job, 0);
+        let _ = self.compress_blocks(
+            width,
+            height,
+            output,
+            &read_pixel,
+            &run,
+            |block, mask, output| self.compress_footprint_f32(block, mask, params, output),
+        );
+        let read_8bit = |x, y| read_pixel(x, y).map(|value| self.to_8bit(value));
+        let _ = rdo::optimise(self, width, height, read_8bit, &params, &run, output);
+    }
+
+    /// Compresses an image of half float RGBA pixels, given as their bit patterns, in memory
+    ///
+    /// * `rgba`   - The uncompressed pixel data
+    /// * `width`  - The width of the source image
+    /// * `height` - The height of the source image
+    /// * `params` - Additional compressor parameters
+    /// * `output` - Output buffer for the compressed image. Ensure that this has
+    ///   at least as much space available as `compute_compressed_size` suggests.
+    ///
+    /// Panics if `rgba` or `output` are too small for the image, see `try_compress_f16`.
+    pub fn compress_f16(
+        self,
+        rgba: &[u16],
+        width: usize,
+        height: usize,
+        params: Params,
+        output: &mut [u8],
+    ) {
+        self.compress_f16_with_pitch(rgba, width, height, 4 * width, params, output);
+    }
+
+    /// Compresses an image of half float RGBA pixels, given as their bit patterns, in memory
+    /// whose rows are `pitch` values apart
+    ///
+    /// * `rgba`   - The uncompressed pixel data
+    /// * `width`  - The width of the source image
+    /// * `height` - The height of the source image
+    /// * `pitch`  - The distance between the starts of consecutive rows in `rgba`, at least
+    ///   `4 * width`
+    /// * `params` - Additional compressor parameters
+    /// * `output` - Output buffer for the compressed image
+    pub fn compress_f16_with_pitch(
+        self,
+        rgba: &[u16],
+        width: usize,
+        height: usize,
+        pitch: usize,
+        params: Params,
+        output: &mut [u8],
+    ) {
+        let read_pixel = rgba_reader(rgba, width, pitch);
+        let job = Job::default();
+        let run = Run::new(&job, 0);
+        let _ = self.compress_blocks(
+            width,
+            height,
+            output,
+            &read_pixel,
+            &run,
+            |block, mask, output| self.compress_footprint_f16(block, mask, params, output),
+        );
+        let read_8bit = |x, y| read_pixel(x, y).map(|value| self.to_8bit(f16_to_f32(value)));
+        let _ = rdo::optimise(self, width, height, read_8bit, &params, &run, output);
+    }
+
+    /// Compresses an image in memory, checking the buffer sizes instead of panicking
+    ///
+    /// * `rgba`   - The uncompressed pixel data
+    /// * `width`  - The width of the source image
+    /// * `height` - The height of the source image
+    /// * `params` - Additional compressor parameters
+    /// * `output` - Output buffer for the compressed image
+    pub fn try_compress(
+        self,
+        rgba: &[u8],
+        width: usize,
+        height: usize,
+        params: Params,
+        output: &mut [u8],
+    ) -> Result<(), Error> {
+        self.try_compress_with_pitch(rgba, width, height, width.saturating_mul(4), params, output)
+    }
+
+    /// Compresses an image in memory whose rows are `pitch` values apart, checking the pitch
+    /// and the buffer sizes instead of panicking
+    ///
+    /// * `rgba`   - The uncompressed pixel data
+    /// * `width`  - The width of the source image
+    /// * `height` - The height of the source image
+    /// * `pitch`  - The distance between the starts of consecutive rows in `rgba`, at least
+    ///   `4 * width`
+    /// * `params` - Additional compressor parameters
+    /// * `output` - Output buffer for the compressed image
+    pub fn try_compress_with_pitch(
+        self,
+        rgba: &[u8],
+        width: usize,
+        height: usize,
+        pitch: usize,
+        params: Params,
+        output: &mut [u8],
+    ) -> Result<(), Error> {
+        let (rgba, output) = self.checked_image(rgba, width, height, pitch, output)?;
+        let read_pixel = rgba_reader(rgba, width, pitch);
+        self.compress_pixels(width, height, read_pixel, params, output);
+        Ok(())
+    }
+
+    /// Compresses an image of floating point RGBA pixels in memory, checking the buffer sizes
+    /// instead of panicking
+    ///
+    /// * `rgba`   - The uncompressed pixel data
+    /// * `width`  - The width of the source image
+    /// * `height` - The height of the source image
+    /// * `params` - Additional compressor parameters
+    /// * `output` - Output buffer for the compressed image
+    pub fn try_compress_f32(
+        self,
+        rgba: &[f32],
+        width: usize,
+        height: usize,
+        params: Params,
+        output: &mut [u8],
+    ) -> Result<(), Error> {
+        let (rgba, output) =
+            self.checked_image(rgba, width, height, width.saturating_mul(4), output)?;
+        self.compress_f32(rgba, width, height, params, output);
+        Ok(())
+    }
+
+    /// Compresses an image of half float RGBA pixels, given as their bit patterns, in memory,
+    /// checking the buffer sizes instead of panicking
+    ///
+    /// * `rgba`   - The uncompressed pixel data
+    /// * `width`  - The width of the source image
+    /// * `height` - The height of the source image
+    /// * `params` - Additional compressor parameters
+    /// * `output` - Output buffer for the compressed image
+    pub fn try_compress_f16(
+        self,
+        rgba: &[u16],
+        width: usize,
+        height: usize,
+        params: Params,
+        output: &mut [u8],
+    ) -> Result<(), Error> {
+        let (rgba, output) =
+            self.checked_image(rgba, width, height, width.saturating_mul(4), output)?;
+        self.compress_f16(rgba, width, height, params, output);
+        Ok(())
+    }
+
+    /// Compresses an image in memory as a job that can run on a specific thread pool, report
+    /// its progress and be cancelled
+    ///
+    /// * `rgba`   - The uncompressed pixel data
+    /// * `width`  - The width of the source image
+    /// * `height` - The height of the source image
+    /// * `params` - Additional compressor parameters
+    /// * `job`    - The thread pool, progress callback and cancellation flag of the job
+    /// * `output` - Output buffer for the compressed image
+    ///
+    /// Returns `Error::Cancelled` if the cancellation flag was set before the job finished.
+    pub fn compress_with_job(
+        self,
+        rgba: &[u8],
+        width: usize,
+        height: usize,
+        params: Params,
+        job: &Job,
+        output: &mut [u8],
+    ) -> Result<(), Error> {
+        let (rgba, output) =
+            self.checked_image(rgba, width, height, width.saturating_mul(4), output)?;
+        let read_pixel = rgba_reader(rgba, width, 4 * width);
+        let run = Run::new(job, self.job_rows(height, &params));
+        self.compress_blocks(
+            width,
+            height,
+            output,
+            &read_pixel,
+            &run,
+            |block, mask, output| self.compress_footprint(block, mask, params, output),
+        )?;
+        rdo::optimise(self, width, height, read_pixel, &params, &run, output)
+    }
+
+    /// Compresses an image of floating point RGBA pixels in memory as a job that can run on a
+    /// specific thread pool, report its progress and be cancelled
+    ///
+    /// * `rgba`   - The uncompressed pixel data
+    /// * `width`  - The width of the source image
+    /// * `height` - The height of the source image
+    /// * `params` - Additional compressor parameters
+    /// * `job`    - The thread pool, progress callback and cancellation flag of the job
+    /// * `output` - Output buffer for the compressed image
+    ///
+    /// Returns `Error::Cancelled` if the cancellation flag was set before the job finished.
+    pub fn compress_f32_with_job(
+        self,
+        rgba: &[f32],
+        width: usize,
+        height: usize,
+        params: Params,
+        job: &Job,
+        output: &mut [u8],
+    ) -> Result<(), Error> {
+        let (rgba, output) =
+            self.checked_image(rgba, width, height, width.saturating_mul(4), output)?;
+        let read_pixel = rgba_reader(rgba, width, 4 * width);
+        let run = Run::new(job, self.job_rows(height, &params));
+        self.compress_blocks(
+            width,
+            height,
+            output,
+            &read_pixel,
+            &run,
+            |block, mask, output| self.compress_footprint_f32(block, mask, params, output),
+        )?;
+        let read_8bit = |x, y| read_pixel(x, y).map(|value| self.to_8bit(value));
+        rdo::optimise(self, width, height, read_8bit, &params, &run, output)
+    }
+
+    /// Compresses an image of half float RGBA pixels, given as their bit patterns, in memory
+    /// as a job that can run on a specific thread pool, report its progress and be cancelled
+    ///
+    /// * `rgba`   - The uncompressed pixel data
+    /// * `width`  - The width of the source image
+    /// * `height` - The height of the source image
+    /// * `params` - Additional compressor parameters
+    /// * `job`    - The thread pool, progress callback and cancellation flag of the job
+    /// * `output` - Output buffer for the compressed image
+    ///
+    /// Returns `Error::Cancelled` if the cancellation flag was set before the job finished.
+    pub fn compress_f16_with_job(
+        self,
+        rgba: &[u16],
+        width: usize,
+        height: usize,
+        params: Params,
+        job: &Job,
+        output: &mut [u8],
+    ) -> Result<(), Error> {
+        let (rgba, output) =
+            self.checked_image(rgba, width, height, width.saturating_mul(4), output)?;
+        let read_pixel = rgba_reader(rgba, width, 4 * width);
+        let run = Run::new(job, self.job_rows(height, &params));
+        self.compress_blocks(
+            width,
+            height,
+            output,
+            &read_pixel,
+            &run,
+            |block, mask, output| self.compress_footprint_f16(block, mask, params, output),
+        )?;
+        let read_8bit = |x, y| read_pixel(x, y).map(|value| self.to_8bit(f16_to_f32(value)));
+        rdo::optimise(self, width, height, read_8bit, &params, &run, output)
+    }
+
+    /// Compresses an image in memory and records how each block was encoded
+    ///
+    /// The blocks are compressed as by `compress`, including rate-distortion optimisation, and
+    /// each record is taken while its block is compressed. Where the optimisation copies the
+    /// endpoints of a block into another, the record of the other block takes over the colour
+    /// fit, palette and alpha steps that produced them.
+    ///
+    /// * `rgba`        - The uncompressed pixel data
+    /// * `width`       - The width of the source image
+    /// * `height`      - The height of the source image
+    /// * `params`      - Additional compressor parameters
+    /// * `output`      - Output buffer for the compressed image
+    /// * `diagnostics` - Output buffer for the records of the blocks, in row-major order
+    pub fn compress_with_diagnostics(
+        self,
+        rgba: &[u8],
+        width: usize,
+        height: usize,
+        params: Params,
+        output: &mut [u8],
+        diagnostics: &mut [BlockDiagnostics],
+    ) -> Result<(), Error> {
+        let (rgba, output) =
+            self.checked_image(rgba, width, height, width.saturating_mul(4), output)?;
+        let (block_width, block_height) = self.block_dimensions();
+        let blocks_wide = width.div_ceil(block_width);
+        let blocks = blocks_wide * height.div_ceil(block_height);
+        let actual = diagnostics.len();
+        let diagnostics = diagnostics.get_mut(..blocks).ok_or(Error::OutputTooShort {
+            expected: blocks,
+            actual,
+        })?;
+
+        // each block is optimised right after it is fitted, which gives the same output as
+        // optimising the image afterwards, since only the blocks before it are looked at
+        let read_pixel = rgba_reader(rgba, width, 4 * width);
+        let optimiser = rdo::Optimiser::new(self, width, height, &read_pixel, &params);
+
+        let block_size = self.block_size();
+        let pixels = block_width * block_height;
+        let mut source_rgba = [[0u8; 4]; astc::MAX_TEXELS];
+        let mut mask = [false; astc::MAX_TEXELS];
+        for index in 0..blocks {
+            let (x, y) = (index % blocks_wide, index / blocks_wide);
+            for (i, (pixel, valid)) in source_rgba.iter_mut().zip(&mut mask).enumerate() {
+                let sx = block_width * x + i % block_width;
+                let sy = block_height * y + i / block_width;
+                *valid = i < pixels && sx < width && sy < height;
+                if *valid {
+                    *pixel = read_pixel(sx, sy);
+                }
+            }
+            let (source_rgba, mask) = (&source_rgba[..pixels], &mask[..pixels]);
+
+            let start = index * block_size;
+            let block = &mut output[start..start + block_size];
+            let mut record = if self.is_astc() {
+                self.compress_footprint(source_rgba, mask, params, block);
+                BlockDiagnostics::default()
+            } else {
+                let (pixels, mask) = pack_4x4(source_rgba, mask);
+                self.compress_block_recorded(pixels, mask, params, block)
+            };
+
+            if let Some(optimiser) = &optimiser {
+                let origins = optimiser.optimise_block(index, output);
+                for (half, origin) in origins.into_iter().enumerate() {
+                    if origin != index {
+                        record.copy_endpoints(self, half, &diagnostics[origin]);
+                    }
+                }
+            }
+
+            let block = &output[start..start + block_size];
+            record.error = diagnostics::block_error(self, source_rgba, mask, block, &params);
+            diagnostics[index] = record;
+        }
+
+        Ok(())
+    }
+
+    /// Compresses every level of a mip chain, as generated by `MipmapParams::generate`
+    ///
+    /// The compressed levels are stored one after the other in
+    /// `compressed_mip_chain_size(width, height)` bytes. Levels smaller than a block are padded
+    /// like any other image.
+    ///
+    /// * `chain`  - The uncompressed levels, from the full size image down to 1x1
+    /// * `width`  - The width of the full size image
+    /// * `height` - The height of the full size image
+    /// * `params` - Additional compressor parameters
+    /// * `output` - Output buffer for the compressed levels
+    pub fn compress_mipmaps(
+        self,
+        chain: &[u8],
+        width: usize,
+        height: usize,
+        params: Params,
+        output: &mut [u8],
+    ) -> Result<(), Error> {
+        // check the whole chain before compressing any level
+        let (mut values, mut bytes) = self.checked_sizes(width, height)?;
+        for level in 1..mip_levels(width, height) {
+            let (width, height) = mip_dimensions(width, height, level);
+            let (level_values, level_bytes) = self.checked_sizes(width, height)?;
+            values = values
+                .checked_add(level_values)
+                .ok_or(Error::SizeOverflow)?;
+            bytes = bytes.checked_add(level_bytes).ok_or(Error::SizeOverflow)?;
+        }
+        let mut chain = chain.get(..values).ok_or(Error::InputTooShort {
+            expected: values,
+            actual: chain.len(),
+        })?;
+        let actual = output.len();
+        let mut output = output.get_mut(..bytes).ok_or(Error::OutputTooShort {
+            expected: bytes,
+            actual,
+        })?;
+
+        for level in 0..mip_levels(width, height) {
+            let (width, height) = mip_dimensions(width, height, level);
+            let (level_values, level_bytes) = self.checked_sizes(width, height)?;
+            let (rgba, rest) = chain.split_at(level_values);
+            let (level_output, rest_output) = output.split_at_mut(level_bytes);
+            self.compress(rgba, width, height, params, level_output);
+            (chain, output) = (rest, rest_output);
+        }
+        Ok(())
+    }
+
+    /// Recompresses the blocks of a compressed image that overlap a rectangle of changed pixels
+    ///
+    /// Each block touching `rect` is compressed again from all of its pixels in the updated
+    /// image, and the other blocks in `output` are left untouched. Parts of the rectangle
+    /// outside the image are ignored. Rate-distortion optimisation is not applied, as it trades
+    /// quality between neighbouring blocks that are not recompressed.
+    ///
+    /// * `rgba`   - The updated uncompressed pixel data of the whole image
+    /// * `width`  - The width of the source image
+    /// * `height` - The height of the source image
+    /// * `rect`   - The rectangle of pixels that changed
+    /// * `params` - Additional compressor parameters
+    /// * `output` - The compressed image to update
+    pub fn recompress_rect(
+        self,
+        rgba: &[u8],
+        width: usize,
+        height: usize,
+        rect: Rect,
+        params: Params,
+        output: &mut [u8],
+    ) -> Result<(), Error> {
+        let (rgba, output) =
+            self.checked_image(rgba, width, height, width.saturating_mul(4), output)?;
+        let (block_width, block_height) = self.block_dimensions();
+        let (columns, rows) = rect.blocks(width, height, block_width, block_height);
+        let block_size = self.block_size();
+        let row_size = width.div_ceil(block_width) * block_size;
+        let read_pixel = rgba_reader(rgba, width, 4 * width);
+        let pixels = block_width * block_height;
+
+        let recompress_row = |y: usize, output_row: &mut [u8]| {
+            let mut source_rgba = [[0u8; 4]; astc::MAX_TEXELS];
+            let mut mask = [false; astc::MAX_TEXELS];
+            for x in columns.clone() {
+                for (i, (pixel, valid)) in source_rgba.iter_mut().zip(&mut mask).enumerate() {
+                    let sx = block_width * x + i % block_width;
+                    let sy = block_height * y + i / block_width;
+                    *valid = i < pixels && sx < width && sy < height;
+                    if *valid {
+                        *pixel = read_pixel(sx, sy);
+                    }
+                }
+
+                let output_block = &mut output_row[block_size * x..][..block_size];
+                self.compress_footprint(
+                    &source_rgba[..pixels],
+                    &mask[..pixels],
+                    params,
+                    output_block,
+                );
+            }
+        };
+
+        let output = &mut output[row_size * rows.start..row_size * rows.end];
+        #[cfg(feature = "rayon")]
+        let output_rows = output.par_chunks_mut(row_size);
+        #[cfg(not(feature = "rayon"))]
+        let output_rows = output.chunks_mut(row_size);
+
+        output_rows
+            .enumerate()
+            .for_each(|(y, output_row)| recompress_row(rows.start + y, output_row));
+        Ok(())
+    }
+
+    /// Checks that a pixel buffer with rows that are `pitch` values apart and a compressed
+    /// image are large enough for an image and trims them to the exact size the block loops
+    /// expect
+    fn checked_image<'a, T>(
+        self,
+        rgba: &'a [T],
+        width: usize,
+        height: usize,
+        pitch: usize,
+        output: &'a mut [u8],
+    ) -> Result<(&'a [T], &'a mut [u8]), Error> {
+        let (_, bytes) = self.checked_sizes(width, height)?;
+        let values = checked_image_len(width, height, pitch, 4)?;
+        let rgba = rgba.get(..values).ok_or(Error::InputTooShort {
+            expected: values,
+            actual: rgba.len(),
+        })?;
+        let actual = output.len();
+        let output = output.get_mut(..bytes).ok_or(Error::OutputTooShort {
+            expected: bytes,
+            actual,
+        })?;
+        Ok((rgba, output))
+    }
+
+    /// Gathers every block of an image and compresses it into its place in the output
+    fn compress_blocks<T, R, F>(
+        self,
+        width: usize,
+        height: usize,
+        output: &mut [u8],
+        read_pixel: R,
+        run: &Run,
+        compress_block: F,
+    ) -> Result<(), Error>
+    where
+        T: Copy + Default,
+        R: Fn(usize, usize) -> [T; 4] + Sync,
+        F: Fn(&[[T; 4]], &[bool], &mut [u8]) + Sync,
+    {
+        let output = &mut output[..self.compressed_size(width, height)];
+
+        let block_size = self.block_size();
+        let (block_width, block_height) = self.block_dimensions();
+        let blocks_wide = width.div_ceil(block_width);
+
+        let compress_row = |(y, output_row): (usize, &mut [u8])| {
+            run.check()?;
+
+            let mut source_rgba = [[T::default(); 4]; astc::MAX_TEXELS];
+            let mut mask = [false; astc::MAX_TEXELS];
+            let output_blocks = output_row.chunks_mut(block_size);
+
+            output_blocks.enumerate().for_each(|(x, output_block)| {
+                // build the block of pixels
+                for py in 0..block_height {
+                    for px in 0..block_width {
+                        let index = block_width * py + px;
+
+                        // get position in source image
+                        let sx = block_width * x + px;
+                        let sy = block_height * y + py;
+
+                        // enable pixel if within bounds
+                        mask[index] = sx < width && sy < height;
+                        if mask[index] {
+                            // copy pixel value
+                            source_rgba[index] = read_pixel(sx, sy);
+                        }
+                    }
+                }
+
+                let pixels = block_width * block_height;
+                compress_block(&source_rgba[..pixels], &mask[..pixels], output_block);
+            });
+
+            run.finish_row();
+            Ok(())
+        };
+
+        let row_size = blocks_wide * block_size;
+        #[cfg(feature = "rayon")]
+        if let Some(thread_pool) = run.job.thread_pool() {
+            return thread_pool.install(|| {
+                output
+                    .par_chunks_mut(row_size)
+                    .enumerate()
+                    .try_for_each(compress_row)
+            });
+        }
+
+        #[cfg(feature = "rayon")]
+        let output_rows = output.par_chunks_mut(row_size);
+        #[cfg(not(feature = "rayon"))]
+        let output_rows = output.chunks_mut(row_size);
+
+        output_rows.enumerate().try_for_each(compress_row)
+    }
+
+    /// Compresses a block of 8-bit pixels of any footprint
+    fn compress_footprint(
+        self,
+        rgba: &[[u8; 4]],
+        mask: &[bool],
+        params: Params,
+        output: &mut [u8],
+    ) {
+        if self.is_astc() {
+            let mut pixels = [[0.0; 4]; astc::MAX_TEXELS];
+            for (pixel, value) in pixels.iter_mut().zip(rgba) {
+                *pixel = value.map(|value| f32::from(value) / 255.0);
+            }
+            self.compress_footprint_f32(&pixels[..rgba.len()], mask, params, output);
+        } else {
+            let (block, mask) = pack_4x4(rgba, mask);
+            self.compress_block_masked(block, mask, params, output);
+        }
+    }
+
+    /// Compresses a block of half float pixels of any footprint
+    fn compress_footprint_f16(
+        self,
+        rgba: &[[u16; 4]],
+        mask: &[bool],
+        params: Params,
+        output: &mut [u8],
+    ) {
+        let mut pixels = [[0.0; 4]; astc::MAX_TEXELS];
+        for (pixel, value) in pixels.iter_mut().zip(rgba) {
+            *pixel = value.map(f16_to_f32);
+        }
+        self.compress_footprint_f32(&pixels[..rgba.len()], mask, params, output)
+    }
+
+    /// Compresses a block of floating point pixels of any footprint
+    fn compress_footprint_f32(
+        self,
+        rgba: &[[f32; 4]],
+        mask: &[bool],
+        params: Params,
+        output: &mut [u8],
+    ) {
+        if self.is_astc() {
+            let (width, height) = self.block_dimensions();
+            astc::compress_astc(rgba, mask, width, height, &params, &mut output[..16]);
+        } else {
+            let (block, mask) = pack_4x4(rgba, mask);
+            self.compress_block_masked_f32(block, mask, params, output);
+        }
+    }
+}
+
+/// Returns a function reading the RGBA pixel at a position in an image with rows that are
+/// `pitch` values apart
+fn rgba_reader<T: Copy + Sync>(
+    rgba: &[T],
+    width: usize,
+    pitch: usize,
+) -> impl Fn(usize, usize) -> [T; 4] + Sync + '_ {
+    assert!(pitch >= 4 * width);
+    move |x, y| {
+        let index = pitch * y + 4 * x;
+        core::array::from_fn(|i| rgba[index + i])
+    }
+}
+
+/// Returns a function that reads an 8-bit pixel in any layout and expands it to RGBA
+fn layout_reader(
+    pixels: &[u8],
+    layout: PixelLayout,
+    width: usize,
+    pitch: usize,
+) -> impl Fn(usize, usize) -> [u8; 4] + Sync + '_ {
+    let channels = layout.channels();
+    assert!(pitch >= channels * width);
+    move |x, y| {
+        let index = pitch * y + channels * x;
+        layout.to_rgba(&pixels[index..index + channels])
+    }
+}
+
+/// Returns the number of values spanned by an image with rows that are `pitch` values apart
+fn image_len(width: usize, height: usize, pitch: usize) -> usize {
+    match height {
+        0 => 0,
+        _ => pitch * (height - 1) + 4 * width,
+    }
+}
+
+/// Computes the number of values spanned by an image of `channels` values per pixel with rows
+/// that are `pitch` values apart, rejecting pitches shorter than a row
+///
+/// Only called after `Format::checked_sizes` has rejected empty images.
+fn checked_image_len(
+    width: usize,
+    height: usize,
+    pitch: usize,
+    channels: usize,
+) -> Result<usize, Error> {
+    let row = width.checked_mul(channels).ok_or(Error::SizeOverflow)?;
+    if pitch < row {
+        return Err(Error::PitchTooSmall {
+            expected: row,
+            actual: pitch,
+        });
+    }
+    pitch
+        .checked_mul(height - 1)
+        .and_then(|rows| rows.checked_add(row))
+        .ok_or(Error::SizeOverflow)
+}
+
+/// Panics with the error of a checked image function, except for images without pixels, which
+/// leave the output untouched
+fn expect_image(result: Result<(), Error>) {
+    match result {
+        Ok(()) | Err(Error::ZeroDimensions) => {}
+        Err(error) => panic!("{}", error),
+    }
+}
+
+/// Converts the pixels and mask of a 4x4 footprint to a block and its pixel mask
+fn pack_4x4<T: Copy>(rgba: &[[T; 4]], mask: &[bool]) -> ([[T; 4]; 16], u32) {
+    let block = core::array::from_fn(|i| rgba[i]);
+    let mask = mask
+        .iter()
+        .enumerate()
+        .fold(0, |bits, (i, &valid)| bits | u32::from(valid) << i);
+    (block, mask)
+}
+
+/// Converts a float in the range 0..1 to an 8-bit value
+fn unorm8(value: f32) -> u8 {
+    libm::roundf(value.clamp(0.0, 1.0) * 255.0) as u8
+}
+
+/// Converts a float in the range -1..1 to an 8-bit value
+fn snorm8(value: f32) -> u8 {
+    unorm8(value * 0.5 + 0.5)
+}
+
+/// Converts an 8-bit value to a float in the range -1..1
+fn from_snorm8(value: u8) -> f32 {
+    f32::from(value) / 127.5 - 1.0
+}
+
+//--------------------------------------------------------------------------------
+// Unit tests
+//--------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod test_data;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_storage_requirements() {
+        assert_eq!(Format::Bc1.compressed_size(16, 32), 256);
+        assert_eq!(Format::Bc1.compressed_size(15, 32), 256);
+        assert_eq!(Format::Bc2.compressed_size(16, 32), 512);
+        assert_eq!(Format::Bc2.compressed_size(15, 32), 512);
+        assert_eq!(Format::Bc3.compressed_size(16, 32), 512);
+        assert_eq!(Format::Bc3.compressed_size(15, 32), 512);
+        assert_eq!(Format::Bc4.compressed_size(16, 32), 256);
+        assert_eq!(Format::Bc4.compressed_size(15, 32), 256);
+        assert_eq!(Format::Bc5.compressed_size(16, 32), 512);
+        assert_eq!(Format::Bc5.compressed_size(15, 32), 512);
+        assert_eq!(Format::Bc4Snorm.compressed_size(16, 32), 256);
+        assert_eq!(Format::Bc5Snorm.compressed_size(15, 32), 512);
+        assert_eq!(Format::Bc6hUf16.compressed_size(16, 32), 512);
+        assert_eq!(Format::Bc6hSf16.compressed_size(15, 32), 512);
+        assert_eq!(Format::Bc7.compressed_size(16, 32), 512);
+        assert_eq!(Format::Bc7.compressed_size(15, 32), 512);
+        assert_eq!(Format::Astc4x4.compressed_size(16, 32), 512);
+        assert_eq!(Format::Astc5x4.compressed_size(15, 32), 384);
+        assert_eq!(Format::Astc8x6.compressed_size(16, 32), 192);
+        assert_eq!(Format::Astc12x12.compressed_size(15, 32), 96);
+    }
+
+    #[test]
+    fn test_try_compress_errors() {
+        let image = [0u8; 5 * 5 * 4];
+        let mut output = [0u8; 4 * 16];
+        let params = Params::default();
+        let format = Format::Bc3;
+
+        assert_eq!(
+            format.try_compress(&image, 0, 5, params, &mut output),
+            Err(Error::ZeroDimensions)
+        );
+        assert_eq!(
+            format.try_compress(&image, 6, 5, params, &mut output),
+            Err(Error::InputTooShort {
+                expected: 120,
+                actual: 100
+            })
+        );
+        assert_eq!(
+            format.try_compress(&image, 5, 5, params, &mut output[..63]),
+            Err(Error::OutputTooShort {
+                expected: 64,
+                actual: 63
+            })
+        );
+        assert_eq!(
+            format.try_compress(&image, usize::MAX, 2, params, &mut output),
+            Err(Error::SizeOverflow)
+        );
+        assert_eq!(
+            format.try_compress(&image, 5, 5, params, &mut output),
+            Ok(())
+        );
+
+        // rows must not overlap and the last row only needs its pixels
+        assert_eq!(
+            format.try_compress_with_pitch(&image, 5, 5, 19, params, &mut output),
+            Err(Error::PitchTooSmall {
+                expected: 20,
+                actual: 19
+            })
+        );
+        assert_eq!(
+            format.try_compress_with_pitch(&image, 5, 5, 24, params, &mut output),
+            Err(Error::InputTooShort {
+                expected: 116,
+                actual: 100
+            })
+        );
+        assert_eq!(
+            format.try_compress_with_pitch(&image, 4, 5, 21, params, &mut output),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_try_decompress_errors() {
+        let data = [0u8; 4 * 16];
+        let mut output = [0u8; 5 * 5 * 4];
+        let format = Format::Bc3;
+
+        assert_eq!(
+            format.try_decompress(&data, 5, 0, &mut output),
+            Err(Error::ZeroDimensions)
+        );
+        assert_eq!(
+            format.try_decompress(&data[..48], 5, 5, &mut output),
+            Err(Error::InputTooShort {
+                expected: 64,
+                actual: 48
+            })
+        );
+        assert_eq!(
+            format.try_decompress(&data, 5, 5, &mut output[..99]),
+            Err(Error::OutputTooShort {
+                expected: 100,
+                actual: 99
+            })
+        );
+        assert_eq!(
+            format.try_decompress(&data, usize::MAX / 2, usize::MAX / 2, &mut output),
+            Err(Error::SizeOverflow)
+        );
+
+        // oversized buffers are fine, only the image is written
+        let mut output = [0xAAu8; 6 * 5 * 4];
+        assert_eq!(format.try_decompress(&data, 5, 5, &mut output), Ok(()));
+        assert_eq!(output[100..], [0xAA; 20]);
+
+        assert_eq!(
+            format.try_decompress_with_pitch(&data, 5, 5, &mut output, 19),
+            Err(Error::PitchTooSmall {
+                expected: 20,
+                actual: 19
+            })
+        );
+        assert_eq!(
+            format.try_decompress_with_pitch(&data, 5, 5, &mut output, 32),
+            Err(Error::OutputTooShort {
+                expected: 148,
+                actual: 120
+            })
+        );
+        assert_eq!(
+            format.try_decompress_with_pitch(&data, 5, 5, &mut output, 24),
+            Ok(())
+        );
+        assert_eq!(output[116..], [0xAA; 4]);
+    }
+
+    #[test]
+    fn test_pitch() {
+        let image = test_image(true);
+        let (x, y, width, height) = (3, 5, 9, 7);
+        let pitch = 16 * 4;
+        let offset = y * pitch + 4 * x;
+
+        // copy the sub-rectangle into a tightly packed image for reference
+        let mut packed = [0u8; 9 * 7 * 4];
+        for (row, packed_row) in packed.chunks_mut(width * 4).enumerate() {
+            let start = offset + row * pitch;
+            packed_row.copy_from_slice(&image[start..start + width * 4]);
+        }
+
+        for format in [Format::Bc3, Format::Astc5x4] {
+            let size = format.compressed_size(width, height);
+            let mut expected = [0u8; 64 * 16];
+            format.compress(
+                &packed,
+                width,
+                height,
+                Params::default(),
+                &mut expected[..size],
+            );
+
+            // compress straight from the larger image
+            let mut compressed = [0u8; 64 * 16];
+            let rgba = &image[offset..];
+            let output = &mut compressed[..size];
+            format.compress_with_pitch(rgba, width, height, pitch, Params::default(), output);
+            assert_eq!(compressed, expected);
+
+            // decompress into a padded buffer, leaving the padding untouched
+            let padded_pitch = width * 4 + 12;
+            let mut decoded = [0xAAu8; 7 * (9 * 4 + 12)];
+            format.decompress_with_pitch(&compressed, width, height, &mut decoded, padded_pitch);
+            let mut reference = [0u8; 9 * 7 * 4];
+            format.decompress(&compressed, width, height, &mut reference);
+            for (row, reference_row) in decoded
+                .chunks(padded_pitch)
+                .zip(reference.chunks(width * 4))
+            {
+                assert_eq!(&row[..width * 4], reference_row);
+                assert_eq!(row[width * 4..], [0xAA; 12]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_pixel_layouts() {
+        let image = test_image(true);
+        for (layout, channels) in [
+            (PixelLayout::R8, [Some(0), None, None, None]),
+            (PixelLayout::Rg8, [Some(0), Some(1), None, None]),
+            (PixelLayout::Rgb8, [Some(0), Some(1), Some(2), None]),
+            (PixelLayout::Rgba8, [Some(0), Some(1), Some(2), Some(3)]),
+            (PixelLayout::Bgr8, [Some(2), Some(1), Some(0), None]),
+            (PixelLayout::Bgra8, [Some(2), Some(1), Some(0), Some(3)]),
+            (PixelLayout::L8, [Some(0), Some(0), Some(0), None]),
+            (PixelLayout::La8, [Some(0), Some(0), Some(0), Some(1)]),
+        ] {
+            // pack the image into the layout and expand it back by hand
+            let mut pixels = [0u8; 16 * 16 * 4];
+            let mut expanded = [0u8; 16 * 16 * 4];
+            let mut count = 0;
+            for (pixel, expanded) in image.chunks(4).zip(expanded.chunks_mut(4)) {
+                let stored = &pixel[..layout.channels()];
+                pixels[count..count + stored.len()].copy_from_slice(stored);
+                count += stored.len();
+                for (value, channel) in expanded.iter_mut().zip(channels) {
+                    *value = channel.map_or(0, |c| stored[c]);
+                }
+                if channels[3].is_none() {
+                    expanded[3] = 255;
+                }
+            }
+            let pixels = &pixels[..count];
+
+            for format in [Format::Bc3, Format::Bc5] {
+                let mut expected = [0u8; 16 * 16];
+                format.compress(&expanded, 16, 16, Params::default(), &mut expected);
+
+                let mut compressed = [0u8; 16 * 16];
+                let output = &mut compressed;
+                format.compress_with_layout(pixels, layout, 16, 16, Params::default(), output);
+                assert_eq!(compressed, expected, "{:?}", layout);
+
+                // the same rows with padding between them
+                let row = 16 * layout.channels();
+                let pitch = row + 3;
+                let mut padded = [0xAAu8; 16 * (16 * 4 + 3)];
+                for (padded, row) in padded.chunks_mut(pitch).zip(pixels.chunks(row)) {
+                    padded[..row.len()].copy_from_slice(row);
+                }
+                let padded = &padded[..15 * pitch + row];
+                let mut compressed = [0u8; 16 * 16];
+                let params = Params::default();
+                format
+                    .try_compress_with_layout(
+                        padded,
+                        layout,
+                        16,
+                        16,
+                        pitch,
+                        params,
+                        &mut compressed,
+                    )
+                    .unwrap();
+                assert_eq!(compressed, expected, "{:?}", layout);
+            }
+
+            // mip chains expand the pixels of the first level just the same
+            let mut expected = [0u8; 4 * (256 + 64 + 16 + 4 + 1)];
+            let mut chain = [0u8; 4 * (256 + 64 + 16 + 4 + 1)];
+            let mipmaps = MipmapParams::default();
+            mipmaps.generate(&expanded, 16, 16, &mut expected).unwrap();
+            mipmaps
+                .generate_with_layout(pixels, layout, 16, 16, &mut chain)
+                .unwrap();
+            assert_eq!(chain, expected, "{:?}", layout);
+            assert_eq!(
+                mipmaps.generate_with_layout(&pixels[..count - 1], layout, 16, 16, &mut chain),
+                Err(Error::InputTooShort {
+                    expected: count,
+                    actual: count - 1
+                })
+            );
+        }
+
+        let pixels = [0u8; 5 * 5 * 3];
+        let mut output = [0u8; 4 * 16];
+        let params = Params::default();
+        let (format, layout) = (Format::Bc3, PixelLayout::Rgb8);
+        assert_eq!(
+            format.try_compress_with_layout(&pixels, layout, 5, 5, 14, params, &mut output),
+            Err(Error::PitchTooSmall {
+                expected: 15,
+                actual: 14
+            })
+        );
+        assert_eq!(
+            format.try_compress_with_layout(&pixels, layout, 5, 5, 16, params, &mut output),
+            Err(Error::InputTooShort {
+                expected: 79,
+                actual: 75
+            })
+        );
+        assert_eq!(
+            format.try_compress_with_layout(&pixels, layout, 5, 5, 15, params, &mut output[..63]),
+            Err(Error::OutputTooShort {
+                expected: 64,
+                actual: 63
+            })
+        );
+        assert_eq!(
+            format.try_compress_with_layout(&pixels, layout, 0, 5, 15, params, &mut output),
+            Err(Error::ZeroDimensions)
+        );
+        assert_eq!(
+            format.try_compress_with_layout(&pixels, layout, 5, 5, 15, params, &mut output),
+            Ok(())
+        );
+    }
+
+    /// Generates a 16x16 test image with gradients, hard edges and some noise
+    fn test_image(with_alpha: bool) -> [u8; 16 * 16 * 4] {
+        let mut image = [0u8; 16 * 16 * 4];
+        let mut seed = 0x1234_5678u32;
+        for y in 0..16 {
+            for x in 0..16 {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                let noise = ((seed >> 16) & 0x0F) as usize;
+                let checker = (x / 4 + y / 4) % 2 == 1;
+
+                let pixel = &mut image[4 * (16 * y + x)..4 * (16 * y + x) + 4];
+                pixel[0] = (15 * x + noise) as u8;
+                pixel[1] = (16 * y) as u8;
+                pixel[2] = if checker {
+                    200 - 2 * x as u8
+                } else {
+                    40 + 3 * y as u8
+                };
+                pixel[3] = if with_alpha {
+                    (255 - 12 * x) as u8
+                } else {
+                    255
+                };
+            }
+        }
+        image
+    }
+
+    /// Peak signal to noise ratio between two images in dB
+    fn psnr(original: &[u8], decoded: &[u8]) -> f32 {
+        let error: u64 = original
+            .iter()
+            .zip(decoded)
+            .map(|(&a, &b)| {
+                let d = i64::from(a) - i64::from(b);
+                (d * d) as u64
+            })
+            .sum();
+        let mse = error as f32 / original.len() as f32;
+        10.0 * libm::log10f(255.0 * 255.0 / mse)
+    }
+
+    fn execute_roundtrip_test(format: Format, image: &[u8], min_psnr: f32) {
+        let size = format.compressed_size(16, 16);
+        for algorithm in [
+            Algorithm::RangeFit,
+            Algorithm::ClusterFit,
+            Algorithm::IterativeClusterFit,
+        ] {
+            let mut compressed = [0u8; 16 * 16];
+            let params = Params {
+                algorithm,
+                ..Params::default()
+            };
+            format.compress(image, 16, 16, params, &mut compressed[..size]);
+
+            let mut decoded = [0u8; 16 * 16 * 4];
+            format.decompress(&compressed[..size], 16, 16, &mut decoded);
+
+            let psnr = psnr(image, &decoded);
+            assert!(psnr >= min_psnr, "PSNR too low: {} dB", psnr);
+        }
+    }
+
+    fn execute_decompression_test(format: Format, data: &test_data::TestDataSet) {
+        let mut output_actual = [0u8; 4 * 4 * 4];
+        format.decompress(data.encoded, 4, 4, &mut output_actual);
+        assert_eq!(output_actual, data.decoded);
+    }
+
+    fn execute_compression_test(format: Format, data: &test_data::TestDataSet) {
+        let test = |algorithm: Algorithm| {
+            // As no std is available, use a buffer of huge static size
+            // and slice it into the dynamic size of the format.
+            let mut output_buffer = [0u8; 128];
+            let output_actual: &mut [u8] = &mut output_buffer[0..format.block_size()];
+            format.compress(
+                data.decoded,
+                4,
+                4,
+                Params {
+                    algorithm,
+                    weights: COLOUR_WEIGHTS_UNIFORM,
+                    weigh_colour_by_alpha: false,
+                    ..Params::default()
+                },
+                output_actual,
+            );
+            assert_eq!(output_actual, data.encoded);
+        };
+
+        // all algorithms should result in the same expected output
+        test(Algorithm::ClusterFit);
+        test(Algorithm::RangeFit);
+        test(Algorithm::IterativeClusterFit);
+    }
+
+    #[test]
+    fn test_bc1_decompression_gray() {
+        execute_decompression_test(Format::Bc1, &test_data::BC1_GRAY);
+    }
+
+    #[test]
+    fn test_bc1_compression_gray() {
+        execute_compression_test(Format::Bc1, &test_data::BC1_GRAY);
+    }
+
+    #[test]
+    fn test_bc1_decompression_colour() {
+        execute_decompression_test(Format::Bc1, &test_data::BC1_COLOUR);
+    }
+
+    #[test]
+    fn test_bc1_compression_colour() {
+        execute_compression_test(Format::Bc1, &test_data::BC1_COLOUR);
+    }
+
+    #[test]
+    fn test_bc1_decompression_height_not_multiple_of_4() {
+        #[derive(Debug, PartialEq)]
+        struct Pixel<'a> {
+            x: usize,
+            y: usize,
+            data: &'a [u8],
+        }
+
+        let encoded = [
+            0x8E, 0x73, 0x71, 0x8C, 0xAA, 0xAA, 0xAA, 0xAA, 0x8E, 0x73, 0x71, 0x8C, 0xAA, 0xAA,
+            0xFF, 0xFF,
+        ];
+        let mut output = [0u8; 4 * 4 * 6];
+        Format::Bc1.decompress(&encoded, 4, 6, &mut output);
+        const REFERENCE: [u8; 4] = [0x7F, 0x7F, 0x7F, 0xFF];
+        for (pixel_n, pixel) in output.chunks(4).enumerate() {
+            let x = pixel_n % 4;
+            let y = pixel_n / 4;
+            let decoded = Pixel { x, y, data: pixel };
+            let expected = Pixel {
+                x,
+                y,
+                data: &REFERENCE,
+            };
+            assert_eq!(decoded, expected);
+        }
+    }
+
+    #[test]
+    fn test_bc2_decompression_gray() {
+        execute_decompression_test(Format::Bc2, &test_data::BC2_GRAY);
+    }
+
+    #[test]
+    fn test_bc2_compression_gray() {
+        execute_compression_test(Format::Bc2, &test_data::BC2_GRAY);
+    }
+
+    #[test]
+    fn test_bc2_decompression_colour() {
+        execute_decompression_test(Format::Bc2, &test_data::BC2_COLOUR);
+    }
+
+    #[test]
+    fn test_bc2_compression_colour() {
+        execute_compression_test(Format::Bc2, &test_data::BC2_COLOUR);
+    }
+
+    #[test]
+    fn test_bc3_decompression_gray() {
+        execute_decompression_test(Format::Bc3, &test_data::BC3_GRAY);
+    }
+
+    #[test]
+    fn test_bc3_compression_gray() {
+        execute_compression_test(Format::Bc3, &test_data::BC3_GRAY);
+    }
+
+    #[test]
+    fn test_bc3_decompression_colour() {
+        execute_decompression_test(Format::Bc3, &test_data::BC3_COLOUR);
+    }
+
+    #[test]
+    fn test_bc3_compression_colour() {
+        execute_compression_test(Format::Bc3, &test_data::BC3_COLOUR);
+    }
+
+    #[test]
+    fn test_bc4_decompression_gray() {
+        execute_decompression_test(Format::Bc4, &test_data::BC4_GRAY);
+    }
+
+    #[test]
+    fn test_bc4_compression_gray() {
+        execute_compression_test(Format::Bc4, &test_data::BC4_GRAY);
+    }
+
+    #[test]
+    fn test_bc4_compression_seven_value_ramp() {
+        // exactly the 7-value codebook between 255 and 0, which includes both extremes
+        let ramp = [0u8, 36, 72, 109, 145, 182, 218, 255];
+        let mut image = [0u8; 4 * 4 * 4];
+        for (i, pixel) in image.chunks_mut(4).enumerate() {
+            pixel[0] = ramp[i % 8];
+        }
+
+        let mut compressed = [0u8; 8];
+        Format::Bc4.compress(&image, 4, 4, Params::default(), &mut compressed);
+        let mut decoded = [0u8; 4 * 4 * 4];
+        Format::Bc4.decompress(&compressed, 4, 4, &mut decoded);
+        for (original, decoded) in image.chunks(4).zip(decoded.chunks(4)) {
+            assert_eq!(original[0], decoded[0]);
+        }
+    }
+
+    #[test]
+    fn test_bc5_decompression_gray() {
+        execute_decompression_test(Format::Bc5, &test_data::BC5_GRAY);
+    }
+
+    #[test]
+    fn test_bc5_compression_gray() {
+        execute_compression_test(Format::Bc5, &test_data::BC5_GRAY);
+    }
+
+    #[test]
+    fn test_bc5_compression_effort() {
+        // curved slopes, so the endpoints of the value range aren't the best fit
+        let mut image = [0u8; 16 * 16 * 4];
+        for (i, pixel) in image.chunks_mut(4).enumerate() {
+            let (x, y) = ((i % 16) as f32, (i / 16) as f32);
+            pixel[0] = (x * x + 3.0 * y) as u8;
+            pixel[1] = (255.0 - 0.6 * y * y - 2.0 * x) as u8;
+            pixel[3] = 255;
+        }
+
+        let quality = |algorithm| {
+            let params = Params {
+                algorithm,
+                ..Params::default()
+            };
+            let mut compressed = [0u8; 16 * 16];
+            Format::Bc5.compress(&image, 16, 16, params, &mut compressed);
+            let mut decoded = [0u8; 16 * 16 * 4];
+            Format::Bc5.decompress(&compressed, 16, 16, &mut decoded);
+            psnr(&image, &decoded)
+        };
+
+        // the default effort keeps the endpoints of the value range
+        let range = quality(Algorithm::RangeFit);
+        let cluster = quality(Algorithm::ClusterFit);
+        let iterative = quality(Algorithm::IterativeClusterFit);
+        assert_eq!(cluster, range);
+        assert!(iterative > cluster, "{} <= {}", iterative, cluster);
+    }
+
+    #[test]
+    fn test_srgb_colour_space() {
+        // dark, slightly tinted gradient where gamma space error misjudges the visible steps
+        let mut image = [0u8; 16 * 16 * 4];
+        for (i, pixel) in image.chunks_mut(4).enumerate() {
+            let (x, y) = (i % 16, i / 16);
+            pixel[0] = (x / 2 + y / 4) as u8;
+            pixel[1] = (3 * x / 4) as u8;
+            pixel[2] = (x + y / 2) as u8;
+            pixel[3] = 255;
+        }
+
+        // summed squared error in OKLab
+        let oklab = |pixel: &[u8]| {
+            let linear = |value: u8| math::srgb8_to_linear(f32::from(value) / 255.0);
+            math::linear_to_oklab(math::Vec3::new(
+                linear(pixel[0]),
+                linear(pixel[1]),
+                linear(pixel[2]),
+            ))
+        };
+        let error = |colour_space| {
+            let params = Params {
+                algorithm: Algorithm::ClusterFit,
+                colour_space,
+                ..Params::default()
+            };
+            let mut compressed = [0u8; 8 * 4 * 4];
+            Format::Bc1.compress(&image, 16, 16, params, &mut compressed);
+            let mut decoded = [0u8; 16 * 16 * 4];
+            Format::Bc1.decompress(&compressed, 16, 16, &mut decoded);
+            image
+                .chunks(4)
+                .zip(decoded.chunks(4))
+                .map(|(a, b)| (oklab(a) - oklab(b)).length2())
+                .sum::<f32>()
+        };
+
+        let linear = error(ColourSpace::Linear);
+        let srgb = error(ColourSpace::Srgb);
+        assert!(srgb < linear, "{} >= {}", srgb, linear);
+    }
+
+    #[test]
+    fn test_error_metrics() {
+        // noisy skin tones, where weighted RGB errors tend to shift the hue
+        let mut image = [0u8; 16 * 16 * 4];
+        let mut seed = 0x1234_5678u32;
+        for (i, pixel) in image.chunks_mut(4).enumerate() {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            let noise = ((seed >> 16) & 0x0F) as usize;
+            let (x, y) = (i % 16, i / 16);
+            pixel[0] = (180 + 4 * x - noise) as u8;
+            pixel[1] = (120 + 2 * x + y + noise) as u8;
+            pixel[2] = (90 + x + 2 * y) as u8;
+            pixel[3] = 255;
+        }
+
+        // summed squared error in the space of each metric
+        let error = |compressed_with, measured_with| {
+            let params = Params {
+                algorithm: Algorithm::ClusterFit,
+                colour_space: ColourSpace::Srgb,
+                error_metric: Some(compressed_with),
+                ..Params::default()
+            };
+            let mut compressed = [0u8; 8 * 4 * 4];
+            Format::Bc1.compress(&image, 16, 16, params, &mut compressed);
+            let mut decoded = [0u8; 16 * 16 * 4];
+            Format::Bc1.decompress(&compressed, 16, 16, &mut decoded);
+
+            let space = colourfit::ErrorSpace::new(&Params {
+                error_metric: Some(measured_with),
+                ..params
+            })
+            .unwrap();
+            let convert = |pixel: &[u8]| {
+                let colour = math::Vec3::new(
+                    f32::from(pixel[0]) / 255.0,
+                    f32::from(pixel[1]) / 255.0,
+                    f32::from(pixel[2]) / 255.0,
+                );
+                space.convert(&colour)
+            };
+            image
+                .chunks(4)
+                .zip(decoded.chunks(4))
+                .map(|(a, b)| (convert(a) - convert(b)).length2())
+                .sum::<f32>()
+        };
+
+        // each metric must beat the weighted RGB fit in its own space
+        for metric in [ErrorMetric::YCoCg, ErrorMetric::OkLab, ErrorMetric::CieLab] {
+            let rgb = error(ErrorMetric::Rgb, metric);
+            let own = error(metric, metric);
+            assert!(own < rgb, "{:?}: {} >= {}", metric, own, rgb);
+        }
+    }
+
+    /// Checks that a vector backend rounds exactly like the portable one, including for the
+    /// NaNs and infinities that degenerate cluster fits produce, and that every candidate of
+    /// a wide backend gets the same result
+    #[cfg(any(
+        target_arch = "aarch64",
+        all(
+            any(target_arch = "x86", target_arch = "x86_64"),
+            any(feature = "std", target_feature = "sse4.1")
+        )
+    ))]
+    fn execute_lanes_test<L: math::Lanes>() {
+        use math::Vec4;
+
+        let values = [
+            0.0,
+            -0.0,
+            0.5,
+            1.0 / 3.0,
+            -2.7,
+            63.5,
+            1e-40,
+            f32::INFINITY,
+            f32::NEG_INFINITY,
+            f32::NAN,
+        ];
+        // any NaN will do, the fits never store them
+        let bits = |v: Vec4| {
+            [v.x(), v.y(), v.z(), v.w()].map(|value| {
+                if value.is_nan() {
+                    f32::NAN.to_bits()
+                } else {
+                    value.to_bits()
+                }
+            })
+        };
+
+        for (i, &x) in values.iter().enumerate() {
+            for &y in &values[i..] {
+                let a = Vec4::new(x, y, -y, 1.0);
+                let b = Vec4::new(y, x, 0.25, -x);
+                let c = Vec4::new(-x, 2.0, y, x);
+                let d = Vec4::new(x, -y, y, 0.5);
+                let pair = |first, second| L::pair(L::from_vec4(first), L::from_vec4(second));
+                let (la, lb) = (pair(a, c), pair(b, d));
+
+                let pairs = [(a, b), (c, d)];
+                for (candidate, (a, b)) in pairs.into_iter().enumerate().take(L::CANDIDATES) {
+                    let lanes = |v: L| bits(v.candidate(candidate));
+                    assert_eq!(lanes(la + lb), bits(a + b));
+                    assert_eq!(lanes(la - lb), bits(a - b));
+                    assert_eq!(lanes(la * lb), bits(a * b));
+                    // f32::max and f32::min may pick either zero if the signs differ
+                    let zeroed = |v: Vec4| bits(v + Vec4::new(0.0, 0.0, 0.0, 0.0));
+                    assert_eq!(zeroed(la.max(lb).candidate(candidate)), zeroed(a.max(b)));
+                    assert_eq!(zeroed(la.min(lb).candidate(candidate)), zeroed(a.min(b)));
+                    assert_eq!(lanes(la.reciprocal()), bits(a.reciprocal()));
+                    assert_eq!(lanes(la.truncate()), bits(a.truncate()));
+                    assert_eq!(lanes(la.splat_y()), bits(a.splat_y()));
+                    assert_eq!(lanes(la.splat_w()), bits(a.splat_w()));
+                }
+
+                let less = pairs[..L::CANDIDATES]
+                    .iter()
+                    .any(|(a, b)| a.any_less_than(b));
+                assert_eq!(la.any_less_than(lb), less);
+                assert_eq!(bits(L::from_vec4(a).candidate(L::CANDIDATES - 1)), bits(a));
+            }
+        }
+    }
+
+    /// Checks that cluster fit finds exactly the same blocks with a vector backend as with the
+    /// portable one, for every number of distinct colours
+    #[cfg(any(
+        target_arch = "aarch64",
+        all(
+            any(target_arch = "x86", target_arch = "x86_64"),
+            any(feature = "std", target_feature = "sse4.1")
+        )
+    ))]
+    fn execute_cluster_lanes_test<L: math::Lanes>() {
+        let mut seed = 7u32;
+        let mut random = move || {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (seed >> 16) as u8
+        };
+
+        for block in 0..256 {
+            // pick the pixels from a palette to vary the number of points
+            let palette: [[u8; 4]; 16] = core::array::from_fn(|_| {
+                let base = random();
+                [base, base.wrapping_add(random() % 24), random(), 255]
+            });
+            let colours = block % 16 + 1;
+            let rgba: [[u8; 4]; 16] =
+                core::array::from_fn(|_| palette[usize::from(random()) % colours]);
+
+            for (format, algorithm, colour_space) in [
+                (Format::Bc1, Algorithm::ClusterFit, ColourSpace::Linear),
+                (
+                    Format::Bc3,
+                    Algorithm::IterativeClusterFit,
+                    ColourSpace::Linear,
+                ),
+                (
+                    Format::Bc1,
+                    Algorithm::IterativeClusterFit,
+                    ColourSpace::Srgb,
+                ),
+            ] {
+                let params = Params {
+                    algorithm,
+                    colour_space,
+                    ..Params::default()
+                };
+                let set = ColourSet::new(&rgba, 0xFFFF, format, &params);
+                let iterate = algorithm == Algorithm::IterativeClusterFit;
+                let fit = || ClusterFit::new(&set, format, &params, iterate);
+                assert_eq!(
+                    fit().compress_with::<L>(),
+                    fit().compress_with::<math::Vec4>(),
+                    "block {}",
+                    block
+                );
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(all(
+        any(target_arch = "x86", target_arch = "x86_64"),
+        any(feature = "std", target_feature = "sse4.1"),
+        not(target_feature = "avx2")
+    ))]
+    fn test_sse41_lanes() {
+        #[cfg(not(target_feature = "sse4.1"))]
+        if !std::is_x86_feature_detected!("sse4.1") {
+            return;
+        }
+        execute_lanes_test::<math::Sse41>();
+        execute_cluster_lanes_test::<math::Sse41>();
+    }
+
+    #[test]
+    #[cfg(all(
+        any(target_arch = "x86", target_arch = "x86_64"),
+        any(feature = "std", target_feature = "avx2")
+    ))]
+    fn test_avx2_lanes() {
+        #[cfg(not(target_feature = "avx2"))]
+        if !std::is_x86_feature_detected!("avx2") {
+            return;
+        }
+        execute_lanes_test::<math::Avx2>();
+        execute_cluster_lanes_test::<math::Avx2>();
+    }
+
+    #[test]
+    #[cfg(target_arch = "aarch64")]
+    fn test_neon_lanes() {
+        execute_lanes_test::<math::Neon>();
+        execute_cluster_lanes_test::<math::Neon>();
+    }
+
+    #[test]
+    fn test_rdo() {
+        let image = test_image(true);
+
+        for format in [
+            Format::Bc1,
+            Format::Bc2,
+            Format::Bc3,
+            Format::Bc4,
+            Format::Bc4Snorm,
+            Format::Bc5,
+            Format::Bc5Snorm,
+        ] {
+            let compress = |rdo_lambda| {
+                let params = Params {
+                    rdo_lambda,
+                    ..Params::default()
+                };
+                let mut compressed = [0u8; 16 * 16];
+                let size = format.compressed_size(16, 16);
+                format.compress(&image, 16, 16, params, &mut compressed[..size]);
+                let mut decoded = [0u8; 16 * 16 * 4];
+                format.decompress(&compressed[..size], 16, 16, &mut decoded);
+                (compressed, psnr(&image, &decoded))
+            };
+
+            // count the distinct 4-byte chunks, a rough measure of what LZ can't match
+            let distinct = |compressed: &[u8]| {
+                let mut chunks = [[0u8; 4]; 16 * 16 / 4];
+                for (chunk, bytes) in chunks.iter_mut().zip(compressed.chunks(4)) {
+                    chunk.copy_from_slice(bytes);
+                }
+                chunks.sort_unstable();
+                1 + chunks.windows(2).filter(|pair| pair[0] != pair[1]).count()
+            };
+
+            let (reference, reference_psnr) = compress(0.0);
+            let (optimised, optimised_psnr) = compress(16.0);
+            assert!(
+                distinct(&optimised) < distinct(&reference),
+                "{:?}: {} >= {}",
+                format,
+                distinct(&optimised),
+                distinct(&reference)
+            );
+            assert!(
+                optimised_psnr > reference_psnr - 3.0,
+                "{:?}: {} dB vs {} dB",
+                format,
+                optimised_psnr,
+                reference_psnr
+            );
+        }
+    }
+
+    #[test]
+    fn test_rdo_error_metric() {
+        let image = test_image(false);
+
+        // with a lambda too small to pay for any error, blocks only change where the error the
+        // colours were fitted by stays the same, here that of red alone
+        let compress = |rdo_lambda| {
+            let params = Params {
+                weights: [1.0, 0.0, 0.0],
+                colour_space: ColourSpace::Linear,
+                error_metric: Some(ErrorMetric::Rgb),
+                rdo_lambda,
+                ..Params::default()
+            };
+            let mut compressed = [0u8; 16 * 16 / 2];
+            Format::Bc1.compress(&image, 16, 16, params, &mut compressed);
+            let mut decoded = [0u8; 16 * 16 * 4];
+            Format::Bc1.decompress(&compressed, 16, 16, &mut decoded);
+            let metrics = metrics::compare(&image, &decoded, 16, 16, COLOUR_WEIGHTS_UNIFORM);
+            (compressed, metrics.unwrap().mse)
+        };
+        let (reference, reference_mse) = compress(0.0);
+        let (optimised, optimised_mse) = compress(0.01);
+        assert_ne!(optimised, reference);
+        assert!(
+            optimised_mse[0] <= reference_mse[0],
+            "{optimised_mse:?} {reference_mse:?}"
+        );
+    }
+
+    #[test]
+    fn test_job() {
+        use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+        let image = test_image(true);
+        let size = Format::Bc3.compressed_size(16, 16);
+        let mut reference = [0u8; 16 * 16];
+        Format::Bc3.compress(&image, 16, 16, Params::default(), &mut reference[..size]);
+
+        // every block row is reported once and the output matches the plain function
+        let reported = AtomicUsize::new(0);
+        let progress = |finished: usize, total: usize| {
+            assert_eq!(total, 4);
+            assert!(finished >= 1 && finished <= total);
+            reported.fetch_add(1, Ordering::Relaxed);
+        };
+        let job = Job::new().with_progress(&progress);
+        let mut compressed = [0u8; 16 * 16];
+        Format::Bc3
+            .compress_with_job(&image, 16, 16, Params::default(), &job, &mut compressed)
+            .unwrap();
+        assert_eq!(reported.load(Ordering::Relaxed), 4);
+        assert_eq!(compressed[..size], reference[..size]);
+
+        // a cancelled job stops before the next block row
+        let cancel = AtomicBool::new(true);
+        let job = Job::new().with_cancel(&cancel);
+        let mut compressed = [0u8; 16 * 16];
+        assert_eq!(
+            Format::Bc3.compress_with_job(&image, 16, 16, Params::default(), &job, &mut compressed),
+            Err(Error::Cancelled)
+        );
+        assert!(compressed.iter().all(|&byte| byte == 0));
+
+        // rate-distortion optimisation reports its block rows after the compressed ones
+        let params = Params {
+            rdo_lambda: 16.0,
+            ..Params::default()
+        };
+        let mut optimised = [0u8; 16 * 16];
+        Format::Bc3.compress(&image, 16, 16, params, &mut optimised[..size]);
+        let reported = AtomicUsize::new(0);
+        let progress = |finished: usize, total: usize| {
+            assert_eq!(total, 8);
+            assert!(finished >= 1 && finished <= total);
+            reported.fetch_add(1, Ordering::Relaxed);
+        };
+        let job = Job::new().with_progress(&progress);
+        let mut compressed = [0u8; 16 * 16];
+        Format::Bc3
+            .compress_with_job(&image, 16, 16, params, &job, &mut compressed)
+            .unwrap();
+        assert_eq!(reported.load(Ordering::Relaxed), 8);
+        assert_eq!(compressed[..size], optimised[..size]);
+
+        // and stops before the next block row once the job is cancelled
+        let cancel = AtomicBool::new(false);
+        let progress = |finished: usize, _total: usize| {
+            if finished == 6 {
+                cancel.store(true, Ordering::Relaxed);
+            }
+        };
+        let job = Job::new().with_progress(&progress).with_cancel(&cancel);
+        let mut compressed = [0u8; 16 * 16];
+        assert_eq!(
+            Format::Bc3.compress_with_job(&image, 16, 16, params, &job, &mut compressed),
+            Err(Error::Cancelled)
+        );
+
+        #[cfg(feature = "rayon")]
+        {
+            let thread_pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(2)
+                .build()
+                .unwrap();
+            let job = Job::new().with_thread_pool(&thread_pool);
+            let mut compressed = [0u8; 16 * 16];
+            Format::Bc3
+                .compress_with_job(&image, 16, 16, Params::default(), &job, &mut compressed)
+                .unwrap();
+            assert_eq!(compressed[..size], reference[..size]);
+        }
+    }
+
+    #[test]
+    fn test_metrics() {
+        let image = test_image(false);
+
+        let identical = metrics::compare(&image, &image, 16, 16, COLOUR_WEIGHTS_UNIFORM).unwrap();
+        assert_eq!(identical.mse, [0.0; 4]);
+        assert_eq!(identical.psnr, [f32::INFINITY; 4]);
+        assert_eq!(identical.ssim, [1.0; 4]);
+        assert_eq!(identical.max_error, [0; 4]);
+
+        // the 5x5 footprint doesn't divide the image, so some blocks are only partly inside
+        for format in [Format::Bc1, Format::Bc3, Format::Astc5x5] {
+            let mut compressed = [0u8; 16 * 16 * 4];
+            let size = format.compressed_size(16, 16);
+            let compressed = &mut compressed[..size];
+            format.compress(&image, 16, 16, Params::default(), compressed);
+            let mut decoded = [0u8; 16 * 16 * 4];
+            format.decompress(compressed, 16, 16, &mut decoded);
+
+            let weights = COLOUR_WEIGHTS_UNIFORM;
+            let reference = metrics::compare(&image, &decoded, 16, 16, weights).unwrap();
+            let measured =
+                metrics::compare_compressed(format, &image, compressed, 16, 16, weights).unwrap();
+            assert_eq!(measured, reference, "{:?}", format);
+            assert!((measured.weighted_psnr - psnr(&image, &decoded)).abs() < 1e-3);
+            assert!(measured.ssim[..3]
+                .iter()
+                .all(|&ssim| ssim > 0.5 && ssim < 1.0));
+            assert!(measured.max_error[..3].iter().all(|&error| error > 0));
+
+            // the errors of full blocks average to the error of the image
+            if format.block_dimensions() == (4, 4) {
+                let mut errors = [0.0; 16];
+                metrics::block_errors(format, &image, compressed, 16, 16, weights, &mut errors)
+                    .unwrap();
+                let mean = errors.iter().sum::<f32>() / 16.0;
+                assert!((mean - measured.weighted_mse).abs() < 1e-3);
+                assert_eq!(
+                    metrics::block_errors(
+                        format,
+                        &image,
+                        compressed,
+                        16,
+                        16,
+                        weights,
+                        &mut [0.0; 8]
+                    ),
+                    Err(Error::OutputTooShort {
+                        expected: 16,
+                        actual: 8
+                    })
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_ssim() {
+        /// SSIM of one channel over every position of an 11x11 Gaussian window, evaluated
+        /// directly
+        fn reference(original: &[u8], decoded: &[u8], width: usize, height: usize) -> f64 {
+            let (window_width, window_height) = (width.min(11), height.min(11));
+            let weight = |size: usize, i: usize| {
+                let distance = i as f64 - (size - 1) as f64 / 2.0;
+                libm::exp(-distance * distance / 4.5)
+            };
+            let total_x: f64 = (0..window_width).map(|i| weight(window_width, i)).sum();
+            let total_y: f64 = (0..window_height).map(|j| weight(window_height, j)).sum();
+            let (c1, c2) = (6.5025, 58.5225);
+
+            let mut sum = 0.0;
+            let mut count = 0;
+            for y in 0..=height - window_height {
+                for x in 0..=width - window_width {
+                    let mut moments = [0.0; 5];
+                    for j in 0..window_height {
+                        for i in 0..window_width {
+                            let w = weight(window_width, i) * weight(window_height, j)
+                                / (total_x * total_y);
+                            let index = 4 * (width * (y + j) + x + i);
+                            let (a, b) = (f64::from(original[index]), f64::from(decoded[index]));
+                            for (moment, value) in
+                                moments.iter_mut().zip([a, b, a * a, b * b, a * b])
+                            {
+                                *moment += w * value;
+                            }
+                        }
+                    }
+                    let [mean_a, mean_b, square_a, square_b, product] = moments;
+                    let (var_a, var_b) = (square_a - mean_a * mean_a, square_b - mean_b * mean_b);
+                    let covariance = product - mean_a * mean_b;
+                    sum += ((2.0 * mean_a * mean_b + c1) * (2.0 * covariance + c2))
+                        / ((mean_a * mean_a + mean_b * mean_b + c1) * (var_a + var_b + c2));
+                    count += 1;
+                }
+            }
+            sum / f64::from(count)
+        }
+
+        // larger than a tile of the metrics, and smaller than a window
+        let mut seed = 0x1234_5678u32;
+        for (width, height) in [(40, 37), (7, 5)] {
+            let mut image = [0u8; 40 * 37 * 4];
+            let image = &mut image[..4 * width * height];
+            for (i, pixel) in image.chunks_mut(4).enumerate() {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                let (x, y) = (i % width, i / width);
+                let noise = (seed >> 16) as usize % 32;
+                pixel.copy_from_slice(&[(6 * x + noise) as u8, (5 * y) as u8, noise as u8, 255]);
+            }
+
+            let format = Format::Bc1;
+            let mut compressed = [0u8; 8 * 10 * 10];
+            let compressed = &mut compressed[..format.compressed_size(width, height)];
+            format.compress(image, width, height, Params::default(), compressed);
+            let mut decoded = [0u8; 40 * 37 * 4];
+            let decoded = &mut decoded[..4 * width * height];
+            format.decompress(compressed, width, height, decoded);
+
+            let weights = COLOUR_WEIGHTS_UNIFORM;
+            let measured = metrics::compare(image, decoded, width, height, weights).unwrap();
+            let expected = reference(image, decoded, width, height);
+            assert!(
+                (f64::from(measured.ssim[0]) - expected).abs() < 1e-4,
+                "{width}x{height}: {} vs {expected}",
+                measured.ssim[0]
+            );
+            assert!(measured.ssim[0] < 1.0);
+            assert_eq!(
+                metrics::compare_compressed(format, image, compressed, width, height, weights),
+                Ok(measured)
+            );
+        }
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        // the first block is a single colour
+        let mut image = test_image(true);
+        for y in 0..4 {
+            image[64 * y..64 * y + 16].copy_from_slice(&[90, 120, 150, 255].repeat(4));
+        }
+
+        for (format, algorithm, rdo_lambda) in [
+            (Format::Bc1, Algorithm::ClusterFit, 0.0),
+            (Format::Bc3, Algorithm::RangeFit, 0.0),
+            (Format::Bc5, Algorithm::ClusterFit, 0.0),
+            (Format::Bc1, Algorithm::ClusterFit, 64.0),
+            (Format::Bc3, Algorithm::RangeFit, 64.0),
+        ] {
+            let params = Params {
+                algorithm,
+                rdo_lambda,
+                ..Params::default()
+            };
+            let mut compressed = [0u8; 16 * 16];
+            let compressed = &mut compressed[..format.compressed_size(16, 16)];
+            let mut diagnostics = [BlockDiagnostics::default(); 16];
+            format
+                .compress_with_diagnostics(&image, 16, 16, params, compressed, &mut diagnostics)
+                .unwrap();
+
+            // the records are taken while compressing, which must not change the output
+            let mut reference = [0u8; 16 * 16];
+            let reference = &mut reference[..compressed.len()];
+            format.compress(&image, 16, 16, params, reference);
+            assert_eq!(compressed, reference);
+
+            let mut decoded = [0u8; 16 * 16 * 4];
+            format.decompress(compressed, 16, 16, &mut decoded);
+            let error: u32 = image
+                .iter()
+                .zip(&decoded)
+                .map(|(&a, &b)| u32::from(a.abs_diff(b)).pow(2))
+                .sum();
+            assert_eq!(
+                diagnostics.iter().map(|record| record.error).sum::<u32>(),
+                error
+            );
+
+            if rdo_lambda > 0.0 {
+                // the optimisation copies endpoints between blocks, and the records with them
+                let mut fitted = [0u8; 16 * 16];
+                let fitted = &mut fitted[..compressed.len()];
+                let unoptimised = Params {
+                    rdo_lambda: 0.0,
+                    ..params
+                };
+                format.compress(&image, 16, 16, unoptimised, fitted);
+                assert_ne!(compressed, fitted);
+
+                for (record, block) in diagnostics.iter().zip(compressed.chunks(16)) {
+                    assert!(record.colour_fit.is_some());
+                    if format == Format::Bc3 {
+                        let steps = Bc4Block::from_bytes(block).steps(false);
+                        assert_eq!(record.alpha_steps, [Some(steps), None]);
+                    }
+                }
+                continue;
+            }
+
+            for (i, (record, block)) in diagnostics
+                .iter()
+                .zip(compressed.chunks(format.block_size()))
+                .enumerate()
+            {
+                match format {
+                    Format::Bc1 => {
+                        let (x, y) = (4 * (i % 4), 4 * (i / 4));
+                        let transparent = (0..16)
+                            .filter(|p| image[4 * (16 * (y + p / 4) + x + p % 4) + 3] < 128)
+                            .count();
+
+                        // fully transparent blocks have no colours to fit a cluster to
+                        let fit = match (i, transparent) {
+                            (0, _) => ColourFitKind::SingleColour,
+                            (_, 16) => ColourFitKind::Range,
+                            _ => ColourFitKind::Cluster,
+                        };
+                        assert_eq!(record.colour_fit, Some(fit));
+
+                        // transparent pixels need the three colour palette
+                        assert!(record.three_colour || transparent == 0);
+                        assert_eq!(record.alpha_steps, [None, None]);
+                    }
+                    Format::Bc3 => {
+                        let fit = if i == 0 {
+                            ColourFitKind::SingleColour
+                        } else {
+                            ColourFitKind::Range
+                        };
+                        assert_eq!(record.colour_fit, Some(fit));
+                        assert!(!record.three_colour);
+                        let steps = Bc3Block::from_bytes(block).alpha.steps(false);
+                        assert_eq!(record.alpha_steps, [Some(steps), None]);
+                    }
+                    _ => {
+                        assert_eq!(record.colour_fit, None);
+                        assert!(record.alpha_steps.iter().all(Option::is_some));
+                    }
+                }
+            }
+
+            assert_eq!(
+                format.compress_with_diagnostics(
+                    &image,
+                    16,
+                    16,
+                    params,
+                    compressed,
+                    &mut diagnostics[..4]
+                ),
+                Err(Error::OutputTooShort {
+                    expected: 16,
+                    actual: 4
+                })
+            );
+        }
+
+        // blocks close to the uniform first one take its endpoints and the record of its fit
+        let mut image = [0u8; 16 * 16 * 4];
+        let mut seed = 0x1234_5678u32;
+        for (i, pixel) in image.chunks_mut(4).enumerate() {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            let noise = if i % 16 < 4 && i / 16 < 4 {
+                0
+            } else {
+                (seed >> 16) as u8 % 3
+            };
+            pixel.copy_from_slice(&[90 + noise, 120 + noise, 150, 255]);
+        }
+        let params = Params {
+            rdo_lambda: 64.0,
+            ..Params::default()
+        };
+        let mut compressed = [0u8; 8 * 16];
+        let mut diagnostics = [BlockDiagnostics::default(); 16];
+        Format::Bc1
+            .compress_with_diagnostics(&image, 16, 16, params, &mut compressed, &mut diagnostics)
+            .unwrap();
+        let copies = || {
+            compressed
+                .chunks(8)
+                .zip(&diagnostics)
+                .skip(1)
+                .filter(|(block, _)| *block == &compressed[..8])
+        };
+        assert!(copies().count() > 0);
+        assert!(copies().all(|(_, record)| record.colour_fit == Some(ColourFitKind::SingleColour)));
+    }
+
+    #[test]
+    fn test_recompress_rect() {
+        let original = test_image(true);
+        for (format, rect) in [
+            (
+                Format::Bc3,
+                Rect {
+                    x: 5,
+                    y: 3,
+                    width: 4,
+                    height: 3,
+                },
+            ),
+            // partly outside the image
+            (
+                Format::Astc6x6,
+                Rect {
+                    x: 11,
+                    y: 13,
+                    width: 8,
+                    height: 8,
+                },
+            ),
+        ] {
+            let params = Params::default();
+            let size = format.compressed_size(16, 16);
+            let mut before = [0u8; 16 * 16];
+            format.compress(&original, 16, 16, params, &mut before[..size]);
+
+            let inside = |x: usize, y: usize| {
+                (rect.x..rect.x + rect.width).contains(&x)
+                    && (rect.y..rect.y + rect.height).contains(&y)
+            };
+            let mut updated = original;
+            for y in 0..16 {
+                for x in 0..16 {
+                    if inside(x, y) {
+                        updated[4 * (16 * y + x)..][..4].copy_from_slice(&[255, 40, 200, 255]);
+                    }
+                }
+            }
+            let mut expected = [0u8; 16 * 16];
+            format.compress(&updated, 16, 16, params, &mut expected[..size]);
+
+            let mut recompressed = before;
+            format
+                .recompress_rect(&updated, 16, 16, rect, params, &mut recompressed[..size])
+                .unwrap();
+
+            let (block_width, block_height) = format.block_dimensions();
+            let blocks_wide = 16usize.div_ceil(block_width);
+            let block_size = format.block_size();
+            for (i, block) in recompressed[..size].chunks(block_size).enumerate() {
+                let (x, y) = (i % blocks_wide, i / blocks_wide);
+                let touched = (0..block_width * block_height).any(|p| {
+                    inside(
+                        block_width * x + p % block_width,
+                        block_height * y + p / block_width,
+                    )
+                });
+                let reference = if touched { &expected } else { &before };
+                assert_eq!(block, &reference[block_size * i..][..block_size]);
+            }
+
+            // rectangles outside the image change nothing
+            let outside = Rect { x: 16, ..rect };
+            let mut unchanged = before;
+            format
+                .recompress_rect(&updated, 16, 16, outside, params, &mut unchanged[..size])
+                .unwrap();
+            assert_eq!(unchanged, before);
+        }
+    }
+
+    #[test]
+    fn test_mip_dimensions() {
+        assert_eq!(mip_levels(16, 16), 5);
+        assert_eq!(mip_levels(13, 5), 4);
+        assert_eq!(mip_levels(1, 1), 1);
+        assert_eq!(mip_dimensions(13, 5, 2), (3, 1));
+        assert_eq!(mip_dimensions(13, 5, 3), (1, 1));
+        assert_eq!(mip_chain_len(4, 2), 4 * (8 + 2 + 1));
+        assert_eq!(
+            Format::Bc1.compressed_mip_chain_size(16, 8),
+            8 * (8 + 2 + 1 + 1 + 1)
+        );
+    }
+
+    #[test]
+    fn test_mipmaps() {
+        let filters = [
+            MipFilter::Box,
+            MipFilter::Triangle,
+            MipFilter::Kaiser,
+            MipFilter::Lanczos,
+        ];
+
+        // normalised filters keep flat images flat, in either colour space
+        let flat = [90, 120, 150, 200].repeat(13 * 7);
+        for filter in filters {
+            for edge_mode in [EdgeMode::Clamp, EdgeMode::Wrap] {
+                for colour_space in [ColourSpace::Linear, ColourSpace::Srgb] {
+                    let mipmaps = MipmapParams {
+                        filter,
+                        edge_mode,
+                        colour_space,
+                        ..MipmapParams::default()
+                    };
+                    let mut chain = [0u8; 4 * 13 * 7 * 2];
+                    let chain = &mut chain[..mip_chain_len(13, 7)];
+                    mipmaps.generate(&flat, 13, 7, chain).unwrap();
+                    for pixel in chain.chunks(4) {
+                        assert_eq!(pixel, [90, 120, 150, 200]);
+                    }
+                }
+            }
+        }
+
+        // sRGB colours are averaged as linear light, alpha as is
+        let checker = [
+            [0, 0, 0, 0],
+            [255, 255, 255, 255],
+            [255, 255, 255, 255],
+            [0, 0, 0, 0],
+        ]
+        .concat();
+        for (colour_space, expected) in [
+            (ColourSpace::Linear, [128, 128, 128, 128]),
+            (ColourSpace::Srgb, [188, 188, 188, 128]),
+        ] {
+            let mipmaps = MipmapParams {
+                colour_space,
+                ..MipmapParams::default()
+            };
+            let mut output = [0u8; 4];
+            mipmaps.downsample(&checker, 2, 2, &mut output).unwrap();
+            assert_eq!(output, expected);
+        }
+
+        // the filters reach across the edges only when wrapping
+        let stripe = [
+            [255, 255, 255, 255],
+            [0, 0, 0, 255],
+            [0, 0, 0, 255],
+            [0, 0, 0, 255],
+        ]
+        .concat();
+        for filter in &filters[1..] {
+            let downsample = |edge_mode| {
+                let mipmaps = MipmapParams {
+                    filter: *filter,
+                    edge_mode,
+                    ..MipmapParams::default()
+                };
+                let mut output = [0u8; 8];
+                mipmaps.downsample(&stripe, 4, 1, &mut output).unwrap();
+                output
+            };
+            let clamped = downsample(EdgeMode::Clamp);
+            let wrapped = downsample(EdgeMode::Wrap);
+            assert!(clamped[4] < wrapped[4], "{filter:?}");
+        }
+
+        // every level is compressed like a separate image
+        let image = test_image(true);
+        let mut chain = [0u8; 2 * 16 * 16 * 4];
+        let chain = &mut chain[..mip_chain_len(16, 16)];
+        MipmapParams::default()
+            .generate(&image, 16, 16, chain)
+            .unwrap();
+        assert_eq!(chain[..image.len()], image);
+
+        let format = Format::Bc3;
+        let params = Params::default();
+        let mut compressed = [0u8; 512];
+        let compressed = &mut compressed[..format.compressed_mip_chain_size(16, 16)];
+        format
+            .compress_mipmaps(chain, 16, 16, params, compressed)
+            .unwrap();
+
+        let (mut rgba, mut blocks) = (&chain[..], &compressed[..]);
+        for level in 0..mip_levels(16, 16) {
+            let (width, height) = mip_dimensions(16, 16, level);
+            let mut expected = [0u8; 256];
+            let expected = &mut expected[..format.compressed_size(width, height)];
+            format.compress(&rgba[..4 * width * height], width, height, params, expected);
+            assert_eq!(&blocks[..expected.len()], expected);
+            rgba = &rgba[4 * width * height..];
+            blocks = &blocks[expected.len()..];
+        }
+
+        assert_eq!(
+            format.compress_mipmaps(chain, 16, 16, params, &mut [0u8; 256]),
+            Err(Error::OutputTooShort {
+                expected: compressed.len(),
+                actual: 256
+            })
+        );
+    }
+
+    #[test]
+    fn test_alpha_coverage() {
+        // sparse opaque pixels, as in foliage
+        let mut image = [0u8; 32 * 32 * 4];
+        let mut seed = 0x1234_5678u32;
+        for pixel in image.chunks_mut(4) {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            let alpha = if (seed >> 16) % 5 == 0 { 255 } else { 0 };
+            pixel.copy_from_slice(&[40, 160, 30, alpha]);
+        }
+
+        let coverage = |rgba: &[u8]| {
+            let covered = rgba.chunks(4).filter(|pixel| pixel[3] >= 128).count();
+            covered as f32 / (rgba.len() / 4) as f32
+        };
+        let generate = |alpha_coverage| {
+            let mipmaps = MipmapParams {
+                filter: MipFilter::Triangle,
+                alpha_coverage,
+                ..MipmapParams::default()
+            };
+            let mut chain = [0u8; 5460];
+            mipmaps.generate(&image, 32, 32, &mut chain).unwrap();
+            chain
+        };
+        fn level(chain: &[u8], level: usize) -> &[u8] {
+            let offset = mip_chain_len(32, 32) - mip_chain_len(32 >> level, 32 >> level);
+            let (width, height) = mip_dimensions(32, 32, level);
+            &chain[offset..offset + 4 * width * height]
+        }
+
+        let top = coverage(&image);
+        let filtered = generate(None);
+        let preserved = generate(Some(128));
+        assert!(coverage(level(&filtered, 2)) < top / 2.0);
+        for index in 1..4 {
+            let coverage = coverage(level(&preserved, index));
+            assert!((coverage - top).abs() < 0.05, "{coverage} vs {top}");
+
+            // each level is filtered from the unscaled one above and then scaled on its own
+            let alphas = |chain| {
+                level(chain, index)
+                    .chunks(4)
+                    .map(|pixel| usize::from(pixel[3]))
+            };
+            assert!(
+                (1..256).any(|split| alphas(&filtered)
+                    .zip(alphas(&preserved))
+                    .all(|(alpha, scaled)| scaled == (alpha * 128 / split).min(255))),
+                "level {index}"
+            );
+        }
+
+        // BC1 keeps the coverage when its alpha threshold is the reference value
+        for reference in [128, 100] {
+            let mipmaps = MipmapParams {
+                filter: MipFilter::Triangle,
+                alpha_coverage: Some(reference),
+                ..MipmapParams::default()
+            };
+            let mut chain = [0u8; 5460];
+            mipmaps.generate(&image, 32, 32, &mut chain).unwrap();
+            let params = Params {
+                alpha_threshold: reference,
+                ..Params::default()
+            };
+            let mut compressed = [0u8; 704];
+            Format::Bc1
+                .compress_mipmaps(&chain, 32, 32, params, &mut compressed)
+                .unwrap();
+
+            let mut offset = 0;
+            for index in 0..4 {
+                let (width, height) = mip_dimensions(32, 32, index);
+                let size = Format::Bc1.compressed_size(width, height);
+                let mut decoded = [0u8; 32 * 32 * 4];
+                let decoded = &mut decoded[..4 * width * height];
+                Format::Bc1.decompress(&compressed[offset..offset + size], width, height, decoded);
+                offset += size;
+
+                let opaque = coverage(decoded);
+                assert!(
+                    (opaque - top).abs() < 0.05,
+                    "{reference}: {opaque} vs {top}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_alpha_threshold() {
+        let image = [200, 100, 50, 100].repeat(16);
+        for format in [Format::Bc1, Format::Etc2RgbA1] {
+            for (alpha_threshold, alpha) in [(128, 0), (64, 255)] {
+                let params = Params {
+                    alpha_threshold,
+                    ..Params::default()
+                };
+                let mut block = [0u8; 8];
+                format.compress(&image, 4, 4, params, &mut block);
+                let decoded = format.decompress_block(&block);
+                assert!(decoded.iter().all(|pixel| pixel[3] == alpha), "{format:?}");
+            }
+        }
+    }
+
+    /// Bumpy 16x16 normal map with Z in blue
+    fn normal_map_image() -> [u8; 16 * 16 * 4] {
+        let mut image = [0u8; 16 * 16 * 4];
+        let mut seed = 0x1234_5678u32;
+        for pixel in image.chunks_mut(4) {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            let x = ((seed >> 16) % 256) as f32 / 255.0 * 1.2 - 0.6;
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            let y = ((seed >> 16) % 256) as f32 / 255.0 * 1.2 - 0.6;
+            let normal = normal::from_xy(x, y);
+            let [x, y, z] = [normal.x(), normal.y(), normal.z()].map(normal::to_unorm8);
+            pixel.copy_from_slice(&[x, y, z, 255]);
+        }
+        image
+    }
+
+    #[test]
+    fn test_normal_map() {
+        let image = normal_map_image();
+
+        // fitting by angle does better than fitting each channel
+        for format in [Format::Bc5, Format::Bc5Snorm] {
+            let angles = |normal_map, rdo_lambda| {
+                let params = Params {
+                    normal_map,
+                    rdo_lambda,
+                    ..Params::default()
+                };
+                let mut compressed = [0u8; 16 * 16];
+                format.compress(&image, 16, 16, params, &mut compressed);
+                let mut decoded = [0u8; 16 * 16 * 4];
+                format.decompress(&compressed, 16, 16, &mut decoded);
+                if format == Format::Bc5 {
+                    assert!(decoded.chunks(4).all(|pixel| pixel[2] == 0));
+                }
+
+                reconstruct_normal_z(&mut decoded);
+                for (original, decoded) in image.chunks(4).zip(decoded.chunks(4)) {
+                    assert!(original[2].abs_diff(decoded[2]) < 24);
+                }
+                metrics::angular_error(&image, &decoded, 16, 16).unwrap()
+            };
+            let per_channel = angles(false, 0.0);
+            let angular = angles(true, 0.0);
+            assert!(angular.mean < per_channel.mean, "{format:?}");
+            assert!(angular.max < 8.0, "{format:?}");
+
+            // rate-distortion optimisation trades the angles as well
+            let per_channel = angles(false, 4.0);
+            let angular = angles(true, 4.0);
+            assert!(
+                angular.mean < per_channel.mean,
+                "{format:?} {angular:?} {per_channel:?}"
+            );
+        }
+        let identical = metrics::angular_error(&image, &image, 16, 16).unwrap();
+        assert!(identical.max < 0.05);
+
+        // filtering shortens normals, which the normal map mode undoes
+        let length = |pixel: &[u8]| {
+            let [x, y, z] = [pixel[0], pixel[1], pixel[2]].map(normal::from_unorm8);
+            libm::sqrtf(x * x + y * y + z * z)
+        };
+        for normal_map in [false, true] {
+            let mipmaps = MipmapParams {
+                filter: MipFilter::Kaiser,
+                normal_map,
+                ..MipmapParams::default()
+            };
+            let mut chain = [0u8; 2 * 16 * 16 * 4];
+            let chain = &mut chain[..mip_chain_len(16, 16)];
+            mipmaps.generate(&image, 16, 16, chain).unwrap();
+            let shortest = chain[image.len()..]
+                .chunks(4)
+                .map(length)
+                .fold(f32::MAX, f32::min);
+            if normal_map {
+                assert!(shortest > 0.98, "{shortest}");
+            } else {
+                assert!(shortest < 0.9, "{shortest}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_dxt5nm() {
+        let image = normal_map_image();
+        let angles = |params: Params| {
+            let mut compressed = [0u8; 16 * 16];
+            Format::Bc3.compress(&image, 16, 16, params, &mut compressed);
+            let mut decoded = [0u8; 16 * 16 * 4];
+            Format::Bc3.decompress(&compressed, 16, 16, &mut decoded);
+            if params.normal_map {
+                // X in alpha, Y in green, nothing in red and blue
+                for (original, decoded) in image.chunks(4).zip(decoded.chunks(4)) {
+                    assert_eq!([decoded[0], decoded[2]], [0, 0]);
+                    assert!(original[0].abs_diff(decoded[3]) < 16);
+                    assert!(original[1].abs_diff(decoded[1]) < 48);
+                }
+                decode_dxt5nm(&mut decoded);
+            }
+            metrics::angular_error(&image, &decoded, 16, 16).unwrap()
+        };
+
+        let plain = angles(Params::default());
+        let dxt5nm = angles(Params {
+            normal_map: true,
+            ..Params::default()
+        });
+        let optimised = angles(Params {
+            normal_map: true,
+            rdo_lambda: 2.0,
+            ..Params::default()
+        });
+        assert!(dxt5nm.mean < plain.mean, "{dxt5nm:?} {plain:?}");
+        assert!(optimised.mean < plain.mean, "{optimised:?} {plain:?}");
+    }
+
+    #[test]
+    fn test_ycocg() {
+        let image = test_image(false);
+        let roundtrip = |params: Params| {
+            let mut compressed = [0u8; 16 * 16];
+            Format::Bc3.compress(&image, 16, 16, params, &mut compressed);
+            let mut decoded = [0u8; 16 * 16 * 4];
+            Format::Bc3.decompress(&compressed, 16, 16, &mut decoded);
+            if params.ycocg {
+                // the scale is one of 1, 2 or 4 in every pixel
+                for pixel in decoded.chunks(4) {
+                    assert!([0, 8, 24].contains(&pixel[2]), "{pixel:?}");
+                }
+                decode_ycocg(&mut decoded);
+            }
+            psnr(&image, &decoded)
+        };
+
+        let plain = roundtrip(Params {
+            weights: COLOUR_WEIGHTS_UNIFORM,
+            ..Params::default()
+        });
+        let ycocg = roundtrip(Params {
+            ycocg: true,
+            ..Params::default()
+        });
+        let optimised = roundtrip(Params {
+            ycocg: true,
+            rdo_lambda: 16.0,
+            ..Params::default()
+        });
+        assert!(ycocg > plain + 1.0, "{ycocg} {plain}");
+        assert!(optimised > plain, "{optimised} {plain}");
+    }
+
+    #[test]
+    fn test_block_fields_roundtrip() {
+        let mut seed = 0x1234_5678u32;
+        let mut reserved = [0; 2];
+        let mut void_extents = 0;
+        for round in 0..4096 {
+            let mut bytes = [0u8; 16];
+            for byte in &mut bytes {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                *byte = (seed >> 16) as u8;
+            }
+
+            // every block round trips exactly, whatever mode it is in
+            assert_eq!(Bc1Block::from_bytes(&bytes).to_bytes(), bytes[..8]);
+            assert_eq!(Bc2Block::from_bytes(&bytes).to_bytes(), bytes);
+            assert_eq!(Bc3Block::from_bytes(&bytes).to_bytes(), bytes);
+            assert_eq!(Bc4Block::from_bytes(&bytes).to_bytes(), bytes[..8]);
+            assert_eq!(Bc5Block::from_bytes(&bytes).to_bytes(), bytes);
+            assert_eq!(EacBlock::from_bytes(&bytes).to_bytes(), bytes[..8]);
+            assert_eq!(EacRg11Block::from_bytes(&bytes).to_bytes(), bytes);
+            assert_eq!(Etc2RgbaBlock::from_bytes(&bytes).to_bytes(), bytes);
+            for punchthrough in [false, true] {
+                let block = Etc2Block::from_bytes(&bytes, punchthrough);
+                assert_eq!(block.to_bytes(), bytes[..8]);
+            }
+
+            // make the reserved and void extent modes common enough to be tested
+            if round % 4 == 0 {
+                bytes[0] = 0;
+            }
+            if round % 4 == 1 {
+                bytes[0] = 0xFC;
+                bytes[1] = (bytes[1] & 0xF0) | 0x0D;
+            }
+
+            let bc6h = Bc6hBlock::from_bytes(&bytes);
+            assert_eq!(bc6h.to_bytes(), bytes);
+            let bc7 = Bc7Block::from_bytes(&bytes);
+            assert_eq!(bc7.to_bytes(), bytes);
+            let astc = AstcBlock::from_bytes(&bytes);
+            assert_eq!(astc.to_bytes(), bytes);
+
+            reserved[0] += usize::from(matches!(bc6h, Bc6hBlock::Reserved(_)));
+            reserved[1] += usize::from(matches!(bc7, Bc7Block::Reserved(_)));
+            void_extents += usize::from(matches!(astc, AstcBlock::VoidExtent { .. }));
+        }
+        assert!(reserved.iter().all(|&count| count > 0), "{reserved:?}");
+        assert!(void_extents > 0);
+
+        // ETC2 blocks built from their fields decode in the mode of the fields, even when the
+        // unused bits of T, H and planar blocks don't select it
+        let planar = Etc2Block::Planar {
+            origin: [0; 3],
+            horizontal: [0; 3],
+            vertical: [0; 3],
+            opaque: true,
+            padding: 0,
+        };
+        let unpacked = Etc2Block::from_bytes(&planar.to_bytes(), false);
+        assert!(
+            matches!(unpacked, Etc2Block::Planar { padding, .. } if padding != 0),
+            "{unpacked:?}"
+        );
+
+        // void extent blocks decode to their colour
+        let block = AstcBlock::VoidExtent {
+            hdr: false,
+            extent: AstcBlock::NO_EXTENT,
+            colour: [0xFFFF, 0x8080, 0, 0xFFFF],
+        };
+        let rgba = Format::Astc4x4.decompress_block(&block.to_bytes());
+        assert_eq!(rgba, [[255, 128, 0, 255]; 16]);
+        assert_eq!(AstcBlock::from_bytes(&block.to_bytes()), block);
+
+        // blocks written by the compressor round trip exactly
+        let image = test_image(true);
+        for (format, punchthrough) in [(Format::Etc2Rgb, false), (Format::Etc2RgbA1, true)] {
+            let mut compressed = [0u8; 16 * 16 / 2];
+            format.compress(&image, 16, 16, Params::default(), &mut compressed);
+            for bytes in compressed.chunks(8) {
+                let block = Etc2Block::from_bytes(bytes, punchthrough);
+                assert_eq!(block.to_bytes(), bytes);
+            }
+        }
+
+        // indices are given in row-major order even where they are stored column by column
+        let mut block = EacBlock {
+            base: 128,
+            multiplier: 8,
+            ..EacBlock::default()
+        };
+        block.indices[1] = 3;
+        let rgba = Format::EacR11.decompress_block(&block.to_bytes());
+        assert!(rgba[1][0] < rgba[0][0]);
+        assert_eq!(rgba[4][0], rgba[0][0]);
+    }
+
+    /// Decompresses a signed BC4 or BC5 block to floats and checks the interpolated values
+    fn execute_snorm_decompression_test(format: Format, data: &test_data::SnormTestDataSet) {
+        let channels = data.decoded.len() / 16;
+
+        let mut output = [0f32; 4 * 4 * 4];
+        format.decompress_f32(data.encoded, 4, 4, &mut output);
+        for (pixel, expected) in output.chunks(4).zip(data.decoded.chunks(channels)) {
+            for (&value, &expected) in pixel.iter().zip(expected) {
+                assert_eq!(value, f32::from(expected) / 127.0);
+            }
+        }
+    }
+
+    fn execute_snorm_roundtrip_test(format: Format, max_error: f32) {
+        let image = eac_test_image(true);
+        let channels = if format == Format::Bc4Snorm { 1 } else { 2 };
+
+        let mut compressed = [0u8; 16 * 16];
+        format.compress_f32(&image, 16, 16, Params::default(), &mut compressed);
+
+        let mut decoded = [0f32; 16 * 16 * 4];
+        format.decompress_f32(&compressed, 16, 16, &mut decoded);
+        for (original, decoded) in image.chunks(4).zip(decoded.chunks(4)) {
+            for c in 0..channels {
+                let error = (original[c] - decoded[c]).abs();
+                assert!(error <= max_error, "Error too high: {}", error);
+            }
+        }
+    }
+
+    #[test]
+    fn test_bc4_decompression_signed() {
+        execute_snorm_decompression_test(Format::Bc4Snorm, &test_data::BC4_SNORM);
+    }
+
+    #[test]
+    fn test_bc5_decompression_signed() {
+        execute_snorm_decompression_test(Format::Bc5Snorm, &test_data::BC5_SNORM);
+    }
+
+    #[test]
+    fn test_bc4_compression_signed() {
+        execute_snorm_roundtrip_test(Format::Bc4Snorm, 1.0 / 127.0);
+    }
+
+    #[test]
+    fn test_bc5_compression_signed() {
+        execute_snorm_roundtrip_test(Format::Bc5Snorm, 0.04);
+    }
+
+    #[test]
+    fn test_bc5_compression_signed_extremes() {
+        // -1, 0 and 1 are exactly representable, as expected of normal map components
+        for value in [-1.0, 0.0, 1.0] {
+            let mut image = [0f32; 4 * 4 * 4];
+            for pixel in image.chunks_mut(4) {
+                pixel.copy_from_slice(&[value, -value, 0.0, 1.0]);
+            }
+            let mut compressed = [0u8; 16];
+            Format::Bc5Snorm.compress_f32(&image, 4, 4, Params::default(), &mut compressed);
+            assert_ne!(compressed[0], 0x80);
+            assert_ne!(compressed[1], 0x80);
+
+            let mut decoded = [0f32; 4 * 4 * 4];
+            Format::Bc5Snorm.decompress_f32(&compressed, 4, 4, &mut decoded);
+            for pixel in decoded.chunks(4) {
+                assert_eq!(pixel, [value, -value, 0.0, 1.0]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_bc7_decompression_mode0() {
+        execute_decompression_test(Format::Bc7, &test_data::BC7_MODE0);
+    }
+
+    #[test]
+    fn test_bc7_decompression_mode1() {
+        execute_decompression_test(Format::Bc7, &test_data::BC7_MODE1);
+    }
+
+    #[test]
+    fn test_bc7_decompression_mode2() {
+        execute_decompression_test(Format::Bc7, &test_data::BC7_MODE2);
+    }
+
+    #[test]
+    fn test_bc7_decompression_mode3() {
+        execute_decompression_test(Format::Bc7, &test_data::BC7_MODE3);
+    }
+
+    #[test]
+    fn test_bc7_decompression_mode4() {
+        execute_decompression_test(Format::Bc7, &test_data::BC7_MODE4);
+    }
+
+    #[test]
+    fn test_bc7_decompression_mode5() {
+        execute_decompression_test(Format::Bc7, &test_data::BC7_MODE5);
+    }
+
+    #[test]
+    fn test_bc7_decompression_mode6() {
+        execute_decompression_test(Format::Bc7, &test_data::BC7_MODE6);
+    }
+
+    #[test]
+    fn test_bc7_decompression_mode7() {
+        execute_decompression_test(Format::Bc7, &test_data::BC7_MODE7);
+    }
+
+    #[test]
+    fn test_bc7_decompression_reserved_mode() {
+        let rgba = Format::Bc7.decompress_block(&[0u8; 16]);
+        assert_eq!(rgba, [[0u8; 4]; 16]);
+    }
+
+    #[test]
+    fn test_bc7_compression_opaque() {
+        execute_roundtrip_test(Format::Bc7, &test_image(false), 35.0);
+    }
+
+    #[test]
+    fn test_bc7_compression_alpha() {
+        execute_roundtrip_test(Format::Bc7, &test_image(true), 38.0);
+    }
+
+    #[test]
+    fn test_bc7_compression_partial_blocks() {
+        // crop the test image to 6x5 pixels so that most blocks are masked
+        let image = test_image(true);
+        let mut cropped = [0u8; 6 * 5 * 4];
+        for (y, row) in cropped.chunks_mut(6 * 4).enumerate() {
+            row.copy_from_slice(&image[y * 16 * 4..y * 16 * 4 + 6 * 4]);
+        }
+
+        let mut compressed = [0u8; 4 * 16];
+        Format::Bc7.compress(&cropped, 6, 5, Params::default(), &mut compressed);
 
-        #[cfg(feature = "rayon")]
-        let output_rows = output.par_chunks_mut(blocks_wide * block_size);
-        #[cfg(not(feature = "rayon"))]
-        let output_rows = output.chunks_mut(blocks_wide * block_size);
+        let mut decoded = [0u8; 6 * 5 * 4];
+        Format::Bc7.decompress(&compressed, 6, 5, &mut decoded);
+        assert!(psnr(&cropped, &decoded) > 38.0);
+    }
 
-        output_rows.enumerate().for_each(|(y, output_row)| {
-            let mut source_rgba = [[0u8; 4]; 16];
-            let output_blocks = output_row.chunks_mut(block_size);
+    #[test]
+    fn test_bc7_compression_decompression_roundtrip() {
+        for data in [
+            &test_data::BC7_MODE0,
+            &test_data::BC7_MODE1,
+            &test_data::BC7_MODE2,
+            &test_data::BC7_MODE3,
+            &test_data::BC7_MODE4,
+            &test_data::BC7_MODE5,
+            &test_data::BC7_MODE6,
+            &test_data::BC7_MODE7,
+        ] {
+            let mut encoded = [0u8; 16];
+            let params = Params {
+                algorithm: Algorithm::IterativeClusterFit,
+                ..Params::default()
+            };
+            Format::Bc7.compress(data.decoded, 4, 4, params, &mut encoded);
 
-            output_blocks.enumerate().for_each(|(x, output_block)| {
-                // build the 4x4 block of pixels
-                let mut mask = 0u32;
-                for py in 0..4 {
-                    for px in 0..4 {
-                        let index = 4 * py + px;
+            let mut decoded = [0u8; 4 * 4 * 4];
+            Format::Bc7.decompress(&encoded, 4, 4, &mut decoded);
+            assert!(psnr(data.decoded, &decoded) > 38.0);
+        }
+    }
 
-                        // get position in source image
-                        let sx = 4 * x + px;
-                        let sy = 4 * y + py;
+    /// Generates a 16x16 HDR test image with tinted gradients spanning several orders of
+    /// magnitude and some noise
+    fn hdr_test_image(signed: bool) -> [f32; 16 * 16 * 4] {
+        let mut image = [0f32; 16 * 16 * 4];
+        let mut seed = 0x1234_5678u32;
+        for y in 0..16 {
+            for x in 0..16 {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                let noise = ((seed >> 16) & 0xFF) as f32 / 8192.0;
+                let checker = (x / 4 + y / 4) % 2 == 1;
 
-                        // enable pixel if within bounds
-                        if sx < width && sy < height {
-                            // copy pixel value
-                            let src_index = 4 * (width * sy + sx);
-                            source_rgba[index].copy_from_slice(&rgba[src_index..src_index + 4]);
+                let intensity = libm::exp2f((x + y) as f32 / 4.0 - 3.0) * (1.0 + noise);
+                let tint = if checker {
+                    [1.0, 0.6, 0.2]
+                } else {
+                    [0.3, 0.5, 1.0]
+                };
 
-                            // enable pixel
-                            mask |= 1 << index;
-                        }
-                    }
+                let pixel = &mut image[4 * (16 * y + x)..4 * (16 * y + x) + 4];
+                for c in 0..3 {
+                    pixel[c] = intensity * tint[c];
                 }
+                pixel[3] = 1.0;
+                if signed && checker {
+                    pixel[1] = -pixel[1];
+                }
+            }
+        }
+        image
+    }
 
-                self.compress_block_masked(source_rgba, mask, params, output_block);
-            });
-        });
+    /// Root mean square difference of the RGB half float bit patterns of two HDR images,
+    /// taken as sign-magnitude integers. A difference of 1024 is roughly a factor of two.
+    fn hdr_error(original: &[f32], decoded: &[f32]) -> f32 {
+        let to_int = |value: f32| {
+            let half = f32_to_f16(value);
+            let magnitude = i32::from(half & 0x7FFF);
+            if (half & 0x8000) != 0 {
+                -magnitude
+            } else {
+                magnitude
+            }
+        };
+
+        let mut error = 0.0;
+        for (a, b) in original.chunks(4).zip(decoded.chunks(4)) {
+            for c in 0..3 {
+                let d = (to_int(a[c]) - to_int(b[c])) as f32;
+                error += d * d;
+            }
+        }
+        libm::sqrtf(error / (3 * original.len() / 4) as f32)
     }
-}
 
-//--------------------------------------------------------------------------------
-// Unit tests
-//--------------------------------------------------------------------------------
+    fn execute_hdr_roundtrip_test(format: Format, image: &[f32], max_error: f32) {
+        for algorithm in [
+            Algorithm::RangeFit,
+            Algorithm::ClusterFit,
+            Algorithm::IterativeClusterFit,
+        ] {
+            let mut compressed = [0u8; 16 * 16];
+            let params = Params {
+                algorithm,
+                ..Params::default()
+            };
+            format.compress_f32(image, 16, 16, params, &mut compressed);
 
-#[cfg(test)]
-mod test_data;
+            let mut decoded = [0f32; 16 * 16 * 4];
+            format.decompress_f32(&compressed, 16, 16, &mut decoded);
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+            let error = hdr_error(image, &decoded);
+            assert!(error <= max_error, "error too high: {}", error);
+        }
+    }
+
+    fn execute_hdr_decompression_test(format: Format, data: &test_data::HdrTestDataSet) {
+        let mut output_actual = [0u16; 4 * 4 * 4];
+        format.decompress_f16(data.encoded, 4, 4, &mut output_actual);
+        assert_eq!(output_actual, data.decoded);
+    }
 
     #[test]
-    fn test_storage_requirements() {
-        assert_eq!(Format::Bc1.compressed_size(16, 32), 256);
-        assert_eq!(Format::Bc1.compressed_size(15, 32), 256);
-        assert_eq!(Format::Bc2.compressed_size(16, 32), 512);
-        assert_eq!(Format::Bc2.compressed_size(15, 32), 512);
-        assert_eq!(Format::Bc3.compressed_size(16, 32), 512);
-        assert_eq!(Format::Bc3.compressed_size(15, 32), 512);
-        assert_eq!(Format::Bc4.compressed_size(16, 32), 256);
-        assert_eq!(Format::Bc4.compressed_size(15, 32), 256);
-        assert_eq!(Format::Bc5.compressed_size(16, 32), 512);
-        assert_eq!(Format::Bc5.compressed_size(15, 32), 512);
+    fn test_half_float_conversion() {
+        assert_eq!(f32_to_f16(1.0), 0x3C00);
+        assert_eq!(f32_to_f16(-2.0), 0xC000);
+        assert_eq!(f32_to_f16(65504.0), 0x7BFF);
+        assert_eq!(f32_to_f16(65520.0), 0x7C00);
+        assert_eq!(f32_to_f16(f32::NEG_INFINITY), 0xFC00);
+        assert_eq!(f32_to_f16(5.960_464_5e-8), 0x0001);
+        assert_eq!(f32_to_f16(1e-9), 0x0000);
+        // ties round to even
+        assert_eq!(f32_to_f16(1.0 + 1.0 / 2048.0), 0x3C00);
+        assert_eq!(f32_to_f16(1.0 + 3.0 / 2048.0), 0x3C02);
+        assert!(f16_to_f32(f32_to_f16(f32::NAN)).is_nan());
+
+        for half in 0..0x7C00u16 {
+            assert_eq!(f32_to_f16(f16_to_f32(half)), half);
+            assert_eq!(f32_to_f16(f16_to_f32(half | 0x8000)), half | 0x8000);
+        }
     }
 
-    fn execute_decompression_test(format: Format, data: &test_data::TestDataSet) {
-        let mut output_actual = [0u8; 4 * 4 * 4];
-        format.decompress(data.encoded, 4, 4, &mut output_actual);
-        assert_eq!(output_actual, data.decoded);
+    #[test]
+    fn test_bc6h_decompression_mode11() {
+        execute_hdr_decompression_test(Format::Bc6hUf16, &test_data::BC6H_UF16_MODE11);
     }
 
-    fn execute_compression_test(format: Format, data: &test_data::TestDataSet) {
-        let test = |algorithm: Algorithm| {
-            // As no std is available, use a buffer of huge static size
-            // and slice it into the dynamic size of the format.
-            let mut output_buffer = [0u8; 128];
-            let mut output_actual: &mut [u8] = &mut output_buffer[0..format.block_size()];
-            format.compress(
-                data.decoded,
-                4,
-                4,
-                Params {
-                    algorithm,
-                    weights: COLOUR_WEIGHTS_UNIFORM,
-                    weigh_colour_by_alpha: false,
-                },
-                &mut output_actual,
-            );
-            assert_eq!(output_actual, data.encoded);
-        };
+    #[test]
+    fn test_bc6h_decompression_mode1_signed() {
+        execute_hdr_decompression_test(Format::Bc6hSf16, &test_data::BC6H_SF16_MODE1);
+    }
 
-        // all algorithms should result in the same expected output
-        test(Algorithm::ClusterFit);
-        test(Algorithm::RangeFit);
-        test(Algorithm::IterativeClusterFit);
+    #[test]
+    fn test_bc6h_decompression_mode14() {
+        execute_hdr_decompression_test(Format::Bc6hUf16, &test_data::BC6H_UF16_MODE14);
     }
 
     #[test]
-    fn test_bc1_decompression_gray() {
-        execute_decompression_test(Format::Bc1, &test_data::BC1_GRAY);
+    fn test_bc6h_decompression_reserved_mode() {
+        let mut block = [0u8; 16];
+        block[0] = 0b10011;
+        let rgba = Format::Bc6hUf16.decompress_block_f32(&block);
+        assert_eq!(rgba, [[0.0, 0.0, 0.0, 1.0]; 16]);
     }
 
     #[test]
-    fn test_bc1_compression_gray() {
-        execute_compression_test(Format::Bc1, &test_data::BC1_GRAY);
+    fn test_bc6h_compression_unsigned() {
+        execute_hdr_roundtrip_test(Format::Bc6hUf16, &hdr_test_image(false), 45.0);
     }
 
     #[test]
-    fn test_bc1_decompression_colour() {
-        execute_decompression_test(Format::Bc1, &test_data::BC1_COLOUR);
+    fn test_bc6h_compression_signed() {
+        execute_hdr_roundtrip_test(Format::Bc6hSf16, &hdr_test_image(true), 45.0);
     }
 
     #[test]
-    fn test_bc1_compression_colour() {
-        execute_compression_test(Format::Bc1, &test_data::BC1_COLOUR);
+    fn test_bc6h_compression_half_input() {
+        let image = hdr_test_image(false);
+        let mut half_image = [0u16; 16 * 16 * 4];
+        for (half, &value) in half_image.iter_mut().zip(image.iter()) {
+            *half = f32_to_f16(value);
+        }
+
+        let mut from_f32 = [0u8; 16 * 16];
+        let mut from_f16 = [0u8; 16 * 16];
+        let params = Params::default();
+        Format::Bc6hUf16.compress_f32(&image, 16, 16, params, &mut from_f32);
+        Format::Bc6hUf16.compress_f16(&half_image, 16, 16, params, &mut from_f16);
+        assert_eq!(from_f32, from_f16);
     }
 
     #[test]
-    fn test_bc1_decompression_height_not_multiple_of_4() {
-        #[derive(Debug, PartialEq)]
-        struct Pixel<'a> {
-            x: usize,
-            y: usize,
-            data: &'a [u8],
+    fn test_bc6h_compression_ldr() {
+        execute_roundtrip_test(Format::Bc6hUf16, &test_image(false), 30.0);
+    }
+
+    #[test]
+    fn test_bc6h_compression_decompression_roundtrip() {
+        for data in [&test_data::BC6H_UF16_MODE11, &test_data::BC6H_UF16_MODE14] {
+            let mut image = [0f32; 4 * 4 * 4];
+            for (value, &half) in image.iter_mut().zip(data.decoded) {
+                *value = f16_to_f32(half);
+            }
+
+            let mut encoded = [0u8; 16];
+            let params = Params {
+                algorithm: Algorithm::IterativeClusterFit,
+                ..Params::default()
+            };
+            Format::Bc6hUf16.compress_f32(&image, 4, 4, params, &mut encoded);
+
+            let mut decoded = [0f32; 4 * 4 * 4];
+            Format::Bc6hUf16.decompress_f32(&encoded, 4, 4, &mut decoded);
+            assert!(hdr_error(&image, &decoded) <= 1.0);
         }
+    }
 
-        let encoded = [
-            0x8E, 0x73, 0x71, 0x8C, 0xAA, 0xAA, 0xAA, 0xAA, 0x8E, 0x73, 0x71, 0x8C, 0xAA, 0xAA,
-            0xFF, 0xFF,
-        ];
-        let mut output = [0u8; 4 * 4 * 6];
-        Format::Bc1.decompress(&encoded, 4, 6, &mut output);
-        let mut pixel_n = 0;
-        const REFERENCE: [u8; 4] = [0x7F, 0x7F, 0x7F, 0xFF];
-        for pixel in output.chunks(4) {
-            let x = pixel_n % 4;
-            let y = pixel_n / 4;
-            let decoded = Pixel { x, y, data: pixel };
-            let expected = Pixel {
-                x,
-                y,
-                data: &REFERENCE,
+    #[test]
+    fn test_etc2_decompression_individual() {
+        execute_decompression_test(Format::Etc2Rgb, &test_data::ETC2_INDIVIDUAL);
+    }
+
+    #[test]
+    fn test_etc2_decompression_differential() {
+        execute_decompression_test(Format::Etc2Rgb, &test_data::ETC2_DIFFERENTIAL);
+    }
+
+    #[test]
+    fn test_etc2_decompression_t_mode() {
+        execute_decompression_test(Format::Etc2Rgb, &test_data::ETC2_T_MODE);
+    }
+
+    #[test]
+    fn test_etc2_decompression_h_mode() {
+        execute_decompression_test(Format::Etc2Rgb, &test_data::ETC2_H_MODE);
+    }
+
+    #[test]
+    fn test_etc2_decompression_planar() {
+        execute_decompression_test(Format::Etc2Rgb, &test_data::ETC2_PLANAR);
+    }
+
+    #[test]
+    fn test_etc2_decompression_punchthrough() {
+        execute_decompression_test(
+            Format::Etc2RgbA1,
+            &test_data::ETC2_PUNCHTHROUGH_DIFFERENTIAL,
+        );
+        execute_decompression_test(Format::Etc2RgbA1, &test_data::ETC2_PUNCHTHROUGH_T_MODE);
+    }
+
+    #[test]
+    fn test_etc2_decompression_eac() {
+        execute_decompression_test(Format::Etc2Rgba, &test_data::ETC2_EAC);
+    }
+
+    #[test]
+    fn test_etc2_compression_opaque() {
+        execute_roundtrip_test(Format::Etc2Rgb, &test_image(false), 33.0);
+    }
+
+    #[test]
+    fn test_etc2_compression_alpha() {
+        execute_roundtrip_test(Format::Etc2Rgba, &test_image(true), 33.0);
+    }
+
+    #[test]
+    fn test_etc2_compression_punchthrough() {
+        // cut holes into the test image wherever its alpha drops below half
+        let mut image = test_image(true);
+        for pixel in image.chunks_mut(4) {
+            pixel[3] = if pixel[3] < 128 { 0 } else { 255 };
+        }
+
+        let mut compressed = [0u8; 8 * 16];
+        Format::Etc2RgbA1.compress(&image, 16, 16, Params::default(), &mut compressed);
+
+        let mut decoded = [0u8; 16 * 16 * 4];
+        Format::Etc2RgbA1.decompress(&compressed, 16, 16, &mut decoded);
+        for (original, decoded) in image.chunks(4).zip(decoded.chunks(4)) {
+            assert_eq!(original[3], decoded[3]);
+            if original[3] == 0 {
+                assert_eq!(decoded, [0, 0, 0, 0]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_etc2_compression_decompression_roundtrip() {
+        for data in [
+            &test_data::ETC2_INDIVIDUAL,
+            &test_data::ETC2_T_MODE,
+            &test_data::ETC2_H_MODE,
+            &test_data::ETC2_PLANAR,
+        ] {
+            let mut encoded = [0u8; 8];
+            let params = Params {
+                algorithm: Algorithm::IterativeClusterFit,
+                ..Params::default()
             };
-            assert_eq!(decoded, expected);
-            pixel_n += 1;
+            Format::Etc2Rgb.compress(data.decoded, 4, 4, params, &mut encoded);
+
+            let mut decoded = [0u8; 4 * 4 * 4];
+            Format::Etc2Rgb.decompress(&encoded, 4, 4, &mut decoded);
+            let psnr = psnr(data.decoded, &decoded);
+            assert!(psnr > 40.0, "PSNR too low: {} dB", psnr);
+        }
+    }
+
+    /// Decompresses an EAC block to floats and checks that they carry the exact 11-bit values
+    fn execute_eac_decompression_test(format: Format, data: &test_data::EacTestDataSet) {
+        let signed = matches!(format, Format::EacR11Snorm | Format::EacRg11Snorm);
+        let scale = if signed { 1023.0 } else { 2047.0 };
+        let channels = data.decoded.len() / 16;
+
+        let mut output = [0f32; 4 * 4 * 4];
+        format.decompress_f32(data.encoded, 4, 4, &mut output);
+        for (pixel, expected) in output.chunks(4).zip(data.decoded.chunks(channels)) {
+            for (&value, &expected) in pixel.iter().zip(expected) {
+                assert_eq!(value * scale, f32::from(expected));
+            }
+        }
+    }
+
+    /// Generates a 16x16 image with a shallow red slope and a steep green slope, in 0..1 or
+    /// -1..1 if `signed` is set
+    fn eac_test_image(signed: bool) -> [f32; 16 * 16 * 4] {
+        let mut image = [0f32; 16 * 16 * 4];
+        for (i, pixel) in image.chunks_mut(4).enumerate() {
+            let (x, y) = ((i % 16) as f32, (i / 16) as f32);
+            pixel[0] = 0.4 + 0.002 * x + 0.001 * y;
+            pixel[1] = 0.9 - 0.05 * y - 0.01 * x;
+            pixel[3] = 1.0;
+            if signed {
+                pixel[0] = 2.0 * pixel[0] - 1.0;
+                pixel[1] = 2.0 * pixel[1] - 1.0;
+            }
+        }
+        image
+    }
+
+    fn execute_eac_roundtrip_test(format: Format, max_error: f32) {
+        let signed = matches!(format, Format::EacR11Snorm | Format::EacRg11Snorm);
+        let image = eac_test_image(signed);
+        let channels = if matches!(format, Format::EacR11 | Format::EacR11Snorm) {
+            1
+        } else {
+            2
+        };
+
+        for algorithm in [
+            Algorithm::RangeFit,
+            Algorithm::ClusterFit,
+            Algorithm::IterativeClusterFit,
+        ] {
+            let params = Params {
+                algorithm,
+                ..Params::default()
+            };
+            let mut compressed = [0u8; 16 * 16];
+            format.compress_f32(&image, 16, 16, params, &mut compressed);
+
+            let mut decoded = [0f32; 16 * 16 * 4];
+            format.decompress_f32(&compressed, 16, 16, &mut decoded);
+            for (original, decoded) in image.chunks(4).zip(decoded.chunks(4)) {
+                for c in 0..channels {
+                    let error = (original[c] - decoded[c]).abs();
+                    assert!(error <= max_error, "Error too high: {}", error);
+                }
+            }
         }
     }
 
     #[test]
-    fn test_bc2_decompression_gray() {
-        execute_decompression_test(Format::Bc2, &test_data::BC2_GRAY);
+    fn test_eac_decompression_r11() {
+        execute_eac_decompression_test(Format::EacR11, &test_data::EAC_R11);
     }
 
     #[test]
-    fn test_bc2_compression_gray() {
-        execute_compression_test(Format::Bc2, &test_data::BC2_GRAY);
+    fn test_eac_decompression_r11_signed() {
+        execute_eac_decompression_test(Format::EacR11Snorm, &test_data::EAC_R11_SNORM);
     }
 
     #[test]
-    fn test_bc2_decompression_colour() {
-        execute_decompression_test(Format::Bc2, &test_data::BC2_COLOUR);
+    fn test_eac_decompression_rg11() {
+        execute_eac_decompression_test(Format::EacRg11, &test_data::EAC_RG11);
     }
 
     #[test]
-    fn test_bc2_compression_colour() {
-        execute_compression_test(Format::Bc2, &test_data::BC2_COLOUR);
+    fn test_eac_decompression_rg11_signed() {
+        execute_eac_decompression_test(Format::EacRg11Snorm, &test_data::EAC_RG11_SNORM);
     }
 
     #[test]
-    fn test_bc3_decompression_gray() {
-        execute_decompression_test(Format::Bc3, &test_data::BC3_GRAY);
+    fn test_eac_decompression_8bit() {
+        let mut output = [0u8; 4 * 4 * 4];
+        Format::EacR11.decompress(test_data::EAC_R11.encoded, 4, 4, &mut output);
+        for (pixel, &expected) in output.chunks(4).zip(test_data::EAC_R11.decoded) {
+            let expected = unorm8(f32::from(expected) / 2047.0);
+            assert_eq!(pixel, [expected, expected, expected, 255]);
+        }
     }
 
     #[test]
-    fn test_bc3_compression_gray() {
-        execute_compression_test(Format::Bc3, &test_data::BC3_GRAY);
+    fn test_eac_compression_r11() {
+        // finer than the 8-bit precision of BC4
+        execute_eac_roundtrip_test(Format::EacR11, 0.5 / 255.0);
     }
 
     #[test]
-    fn test_bc3_decompression_colour() {
-        execute_decompression_test(Format::Bc3, &test_data::BC3_COLOUR);
+    fn test_eac_compression_rg11() {
+        execute_eac_roundtrip_test(Format::EacRg11, 0.02);
     }
 
     #[test]
-    fn test_bc3_compression_colour() {
-        execute_compression_test(Format::Bc3, &test_data::BC3_COLOUR);
+    fn test_eac_compression_signed() {
+        execute_eac_roundtrip_test(Format::EacR11Snorm, 1.0 / 255.0);
+        execute_eac_roundtrip_test(Format::EacRg11Snorm, 0.04);
+    }
+
+    fn execute_astc_decompression_test(format: Format, data: &test_data::AstcTestDataSet) {
+        assert_eq!(format.block_dimensions(), (data.width, data.height));
+        let mut output = [0u8; 12 * 12 * 4];
+        let output = &mut output[..data.width * data.height * 4];
+        format.decompress(data.encoded, data.width, data.height, output);
+        assert_eq!(output, data.decoded);
     }
 
     #[test]
-    fn test_bc4_decompression_gray() {
-        execute_decompression_test(Format::Bc4, &test_data::BC4_GRAY);
+    fn test_astc_decompression_void_extent() {
+        execute_astc_decompression_test(Format::Astc4x4, &test_data::ASTC_VOID_EXTENT);
     }
 
     #[test]
-    fn test_bc4_compression_gray() {
-        execute_compression_test(Format::Bc4, &test_data::BC4_GRAY);
+    fn test_astc_decompression_rgb_quints() {
+        execute_astc_decompression_test(Format::Astc4x4, &test_data::ASTC_RGB_QUINTS);
     }
 
     #[test]
-    fn test_bc5_decompression_gray() {
-        execute_decompression_test(Format::Bc5, &test_data::BC5_GRAY);
+    fn test_astc_decompression_dual_plane() {
+        execute_astc_decompression_test(Format::Astc4x4, &test_data::ASTC_DUAL_PLANE);
     }
 
     #[test]
-    fn test_bc5_compression_gray() {
-        execute_compression_test(Format::Bc5, &test_data::BC5_GRAY);
+    fn test_astc_decompression_two_partitions() {
+        execute_astc_decompression_test(Format::Astc4x4, &test_data::ASTC_TWO_PARTITIONS);
+    }
+
+    #[test]
+    fn test_astc_decompression_infill() {
+        execute_astc_decompression_test(Format::Astc6x6, &test_data::ASTC_6X6_INFILL);
+    }
+
+    #[test]
+    fn test_astc_decompression_reserved_mode() {
+        // block mode 0 is reserved, which decodes to the error colour
+        let mut output = [0u8; 5 * 5 * 4];
+        Format::Astc5x5.decompress(&[0; 16], 5, 5, &mut output);
+        for pixel in output.chunks(4) {
+            assert_eq!(pixel, [255, 0, 255, 255]);
+        }
+    }
+
+    #[test]
+    fn test_astc_decompression_partial_block() {
+        let data = &test_data::ASTC_6X6_INFILL;
+        let mut output = [0u8; 5 * 3 * 4];
+        Format::Astc6x6.decompress(data.encoded, 5, 3, &mut output);
+        for (y, row) in output.chunks(5 * 4).enumerate() {
+            assert_eq!(row, &data.decoded[y * 6 * 4..y * 6 * 4 + 5 * 4]);
+        }
+    }
+
+    #[test]
+    fn test_astc_compression_opaque() {
+        // larger footprints trade quality for bit rate, and the hard edges of the test image
+        // don't line up with their blocks
+        let image = test_image(false);
+        execute_roundtrip_test(Format::Astc4x4, &image, 39.0);
+        execute_roundtrip_test(Format::Astc5x5, &image, 23.5);
+        execute_roundtrip_test(Format::Astc8x5, &image, 21.5);
+        execute_roundtrip_test(Format::Astc12x12, &image, 18.0);
+    }
+
+    #[test]
+    fn test_astc_compression_alpha() {
+        let image = test_image(true);
+        execute_roundtrip_test(Format::Astc4x4, &image, 36.0);
+        execute_roundtrip_test(Format::Astc6x6, &image, 21.5);
+        execute_roundtrip_test(Format::Astc10x8, &image, 18.0);
+    }
+
+    #[test]
+    fn test_astc_compression_constant() {
+        // uniform blocks are stored losslessly as void extent blocks
+        let mut image = [0u8; 7 * 9 * 4];
+        for pixel in image.chunks_mut(4) {
+            pixel.copy_from_slice(&[12, 34, 56, 78]);
+        }
+
+        let size = Format::Astc5x4.compressed_size(7, 9);
+        let mut compressed = [0u8; 16 * 6];
+        Format::Astc5x4.compress(&image, 7, 9, Params::default(), &mut compressed[..size]);
+
+        let mut decoded = [0u8; 7 * 9 * 4];
+        Format::Astc5x4.decompress(&compressed[..size], 7, 9, &mut decoded);
+        assert_eq!(decoded, image);
+    }
+
+    #[test]
+    fn test_astc_decompression_hdr_void_extent() {
+        execute_hdr_decompression_test(Format::Astc4x4, &test_data::ASTC_HDR_VOID_EXTENT);
+    }
+
+    #[test]
+    fn test_astc_decompression_hdr_rgb_direct() {
+        execute_hdr_decompression_test(Format::Astc4x4, &test_data::ASTC_HDR_RGB_DIRECT);
+    }
+
+    #[test]
+    fn test_astc_decompression_hdr_two_partitions() {
+        execute_hdr_decompression_test(Format::Astc4x4, &test_data::ASTC_HDR_TWO_PARTITIONS);
+    }
+
+    #[test]
+    fn test_astc_decompression_hdr_alpha() {
+        execute_hdr_decompression_test(Format::Astc4x4, &test_data::ASTC_HDR_ALPHA);
+    }
+
+    #[test]
+    fn test_astc_compression_hdr() {
+        execute_hdr_roundtrip_test(Format::Astc4x4, &hdr_test_image(false), 55.0);
+    }
+
+    #[test]
+    fn test_astc_compression_hdr_constant() {
+        // uniform HDR blocks are stored as void extent blocks of half floats
+        let mut image = [0f32; 7 * 9 * 4];
+        for pixel in image.chunks_mut(4) {
+            pixel.copy_from_slice(&[4.5, 1.25, 0.125, 0.5]);
+        }
+
+        let size = Format::Astc5x4.compressed_size(7, 9);
+        let mut compressed = [0u8; 16 * 6];
+        Format::Astc5x4.compress_f32(&image, 7, 9, Params::default(), &mut compressed[..size]);
+
+        let mut decoded = [0f32; 7 * 9 * 4];
+        Format::Astc5x4.decompress_f32(&compressed[..size], 7, 9, &mut decoded);
+        assert_eq!(decoded, image);
+    }
+
+    #[test]
+    fn test_astc_block_functions() {
+        // the 4x4 block functions cover the top left corner of larger footprints
+        let rgba = test_image(true);
+        let mut block = [[0u8; 4]; 16];
+        for (i, pixel) in block.iter_mut().enumerate() {
+            let offset = 4 * (16 * (i / 4) + i % 4);
+            pixel.copy_from_slice(&rgba[offset..offset + 4]);
+        }
+
+        let mut compressed = [0u8; 16];
+        Format::Astc8x8.compress_block_masked(block, 0xFFFF, Params::default(), &mut compressed);
+        let decoded = Format::Astc8x8.decompress_block(&compressed);
+        assert!(psnr(block.as_flattened(), decoded.as_flattened()) >= 30.0);
     }
 }
@@ -22,6 +22,21 @@
 
 use core::f32;
 
+mod cielab;
+pub use self::cielab::*;
+
+mod half;
+pub use self::half::*;
+
+mod oklab;
+pub use self::oklab::*;
+
+mod simd;
+pub use self::simd::*;
+
+mod srgb;
+pub use self::srgb::*;
+
 mod vec3;
 pub use self::vec3::*;
 
@@ -0,0 +1,46 @@
+// Copyright (c) 2018-2021 Jan Solanti <jhs@psonet.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to	deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be included
+// in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.
+// IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT,
+// TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION WITH THE
+// SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Conversion from linear light RGB to the CIE L*a*b* colour space
+
+use super::Vec3;
+
+/// Converts a linear light sRGB colour to CIE L*a*b*, relative to the D65 white point
+pub fn linear_to_cielab(rgb: Vec3) -> Vec3 {
+    let (r, g, b) = (rgb.x(), rgb.y(), rgb.z());
+
+    // CIE XYZ, divided by the white point
+    let x = 0.43394994 * r + 0.37620977 * g + 0.18984029 * b;
+    let y = 0.2126729 * r + 0.7151522 * g + 0.072175 * b;
+    let z = 0.017756583 * r + 0.10946796 * g + 0.87277546 * b;
+
+    // cube root with a linear segment near black
+    let f = |t: f32| {
+        if t > 0.008856452 {
+            libm::cbrtf(t)
+        } else {
+            t * 7.787037 + 4.0 / 29.0
+        }
+    };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+
+    Vec3::new(116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz))
+}
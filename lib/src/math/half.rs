@@ -0,0 +1,85 @@
+// Copyright (c) 2018-2021 Jan Solanti <jhs@psonet.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to	deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be included
+// in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.
+// IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT,
+// TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION WITH THE
+// SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Conversions between single and half precision floats
+//!
+//! Half floats are passed around as their IEEE 754 binary16 bit patterns.
+
+/// Converts a half float to single precision
+pub fn f16_to_f32(half: u16) -> f32 {
+    let sign = u32::from(half & 0x8000) << 16;
+    let exponent = u32::from((half >> 10) & 0x1F);
+    let mantissa = u32::from(half & 0x03FF);
+
+    let bits = match exponent {
+        // zero or subnormal
+        0 => {
+            let magnitude = mantissa as f32 / (1 << 24) as f32;
+            magnitude.to_bits()
+        }
+        // infinity or NaN
+        0x1F => 0x7F80_0000 | (mantissa << 13),
+        _ => ((exponent + 127 - 15) << 23) | (mantissa << 13),
+    };
+
+    f32::from_bits(sign | bits)
+}
+
+/// Converts a single precision float to a half float, rounding to nearest even
+pub fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xFF) as i32;
+    let mantissa = bits & 0x007F_FFFF;
+
+    // infinity or NaN, keeping NaNs quiet
+    if exponent == 0xFF {
+        let nan = if mantissa != 0 { 0x0200 } else { 0 };
+        return sign | 0x7C00 | nan;
+    }
+
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1F {
+        // too large, round to infinity
+        return sign | 0x7C00;
+    }
+
+    // the 10 highest mantissa bits end up in the half, the rest are rounded off
+    let (value, shift) = if exponent > 0 {
+        ((exponent as u32) << 23 | mantissa, 13)
+    } else if exponent >= -10 {
+        // subnormal, the implicit leading bit becomes explicit
+        (mantissa | 0x0080_0000, (14 - exponent) as u32)
+    } else {
+        // too small, round to zero
+        return sign;
+    };
+
+    let halfway = 1 << (shift - 1);
+    let remainder = value & ((1 << shift) - 1);
+    let mut half = value >> shift;
+    if remainder > halfway || (remainder == halfway && (half & 1) == 1) {
+        // a carry into the exponent is still the correctly rounded value
+        half += 1;
+    }
+
+    sign | half as u16
+}
@@ -0,0 +1,42 @@
+// Copyright (c) 2018-2021 Jan Solanti <jhs@psonet.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to	deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be included
+// in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.
+// IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT,
+// TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION WITH THE
+// SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Conversion from linear light RGB to the OKLab perceptual colour space
+
+use super::Vec3;
+
+/// Converts a linear light sRGB colour to OKLab
+///
+/// See <https://bottosson.github.io/posts/oklab/> for the derivation of the matrices.
+pub fn linear_to_oklab(rgb: Vec3) -> Vec3 {
+    let (r, g, b) = (rgb.x(), rgb.y(), rgb.z());
+
+    // cone responses, compressed by a cube root
+    let l = libm::cbrtf(0.41222147 * r + 0.53633254 * g + 0.051445993 * b);
+    let m = libm::cbrtf(0.2119035 * r + 0.6806995 * g + 0.10739696 * b);
+    let s = libm::cbrtf(0.08830246 * r + 0.28171884 * g + 0.6299787 * b);
+
+    Vec3::new(
+        0.21045426 * l + 0.7936178 * m - 0.004072047 * s,
+        1.9779985 * l - 2.4285922 * m + 0.4505937 * s,
+        0.025904037 * l + 0.78277177 * m - 0.80867577 * s,
+    )
+}
@@ -0,0 +1,168 @@
+// Copyright (c) 2018-2021 Jan Solanti <jhs@psonet.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to	deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be included
+// in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.
+// IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT,
+// TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION WITH THE
+// SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Four-lane float vectors with SIMD backends
+//!
+//! `Lanes` abstracts the operations of the cluster fit inner loops so they can be compiled
+//! for several instruction sets. Every backend rounds exactly like `Vec4`, which keeps the
+//! compressed output identical whichever backend runs. Backends with wider registers hold the
+//! vectors of several candidates side by side and apply every operation to each of them.
+
+use core::ops::{Add, AddAssign, Mul, Sub};
+
+use super::Vec4;
+
+#[cfg(target_arch = "aarch64")]
+mod neon;
+#[cfg(target_arch = "aarch64")]
+pub use self::neon::*;
+
+#[cfg(all(
+    any(target_arch = "x86", target_arch = "x86_64"),
+    any(feature = "std", target_feature = "avx2")
+))]
+mod avx2;
+#[cfg(all(
+    any(target_arch = "x86", target_arch = "x86_64"),
+    any(feature = "std", target_feature = "avx2")
+))]
+pub use self::avx2::*;
+
+#[cfg(all(
+    any(target_arch = "x86", target_arch = "x86_64"),
+    any(feature = "std", target_feature = "sse4.1"),
+    not(target_feature = "avx2")
+))]
+mod sse41;
+#[cfg(all(
+    any(target_arch = "x86", target_arch = "x86_64"),
+    any(feature = "std", target_feature = "sse4.1"),
+    not(target_feature = "avx2")
+))]
+pub use self::sse41::*;
+
+/// A vector of four floats for each of `CANDIDATES` candidates
+///
+/// `new` and `from_vec4` set the same vector for every candidate, and `to_vec4` returns the
+/// vector of the first.
+pub trait Lanes:
+    Copy + Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self> + AddAssign
+{
+    /// The number of candidates processed at once, at most two
+    const CANDIDATES: usize = 1;
+
+    fn new(x: f32, y: f32, z: f32, w: f32) -> Self;
+    fn from_vec4(v: Vec4) -> Self;
+    fn to_vec4(self) -> Vec4;
+
+    /// Takes the first candidate from `first` and the second from `second`, backends with one
+    /// candidate return `first`
+    #[inline(always)]
+    fn pair(first: Self, _second: Self) -> Self {
+        first
+    }
+
+    /// Returns the vector of a candidate
+    #[inline(always)]
+    fn candidate(self, _index: usize) -> Vec4 {
+        self.to_vec4()
+    }
+
+    fn splat_x(self) -> Self;
+    fn splat_y(self) -> Self;
+    fn splat_z(self) -> Self;
+    fn splat_w(self) -> Self;
+
+    /// Lane-wise `f32::max`, NaN lanes yield the other value
+    fn max(self, other: Self) -> Self;
+
+    /// Lane-wise `f32::min`, NaN lanes yield the other value
+    fn min(self, other: Self) -> Self;
+
+    fn reciprocal(self) -> Self;
+    fn truncate(self) -> Self;
+
+    /// Returns true if any lane of any candidate is less than the same lane of `other`
+    fn any_less_than(self, other: Self) -> bool;
+}
+
+/// The portable fallback
+impl Lanes for Vec4 {
+    #[inline(always)]
+    fn new(x: f32, y: f32, z: f32, w: f32) -> Self {
+        Vec4::new(x, y, z, w)
+    }
+
+    #[inline(always)]
+    fn from_vec4(v: Vec4) -> Self {
+        v
+    }
+
+    #[inline(always)]
+    fn to_vec4(self) -> Vec4 {
+        self
+    }
+
+    #[inline(always)]
+    fn splat_x(self) -> Self {
+        Vec4::splat_x(&self)
+    }
+
+    #[inline(always)]
+    fn splat_y(self) -> Self {
+        Vec4::splat_y(&self)
+    }
+
+    #[inline(always)]
+    fn splat_z(self) -> Self {
+        Vec4::splat_z(&self)
+    }
+
+    #[inline(always)]
+    fn splat_w(self) -> Self {
+        Vec4::splat_w(&self)
+    }
+
+    #[inline(always)]
+    fn max(self, other: Self) -> Self {
+        Vec4::max(&self, other)
+    }
+
+    #[inline(always)]
+    fn min(self, other: Self) -> Self {
+        Vec4::min(&self, other)
+    }
+
+    #[inline(always)]
+    fn reciprocal(self) -> Self {
+        Vec4::reciprocal(&self)
+    }
+
+    #[inline(always)]
+    fn truncate(self) -> Self {
+        Vec4::truncate(&self)
+    }
+
+    #[inline(always)]
+    fn any_less_than(self, other: Self) -> bool {
+        Vec4::any_less_than(&self, &other)
+    }
+}
@@ -0,0 +1,168 @@
+// Copyright (c) 2018-2021 Jan Solanti <jhs@psonet.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to	deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be included
+// in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.
+// IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT,
+// TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION WITH THE
+// SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! AVX2 backend of `Lanes`, holding two candidates in the halves of a 256-bit register
+
+use core::ops::{Add, AddAssign, Mul, Sub};
+
+#[cfg(target_arch = "x86")]
+use core::arch::x86::*;
+#[cfg(target_arch = "x86_64")]
+use core::arch::x86_64::*;
+
+use super::Lanes;
+use crate::math::Vec4;
+
+/// AVX2 lanes, only to be used where the CPU is known to support AVX2
+///
+/// Every operation stays within the 128-bit half of each candidate, so the shuffles of the
+/// splats are the in-lane AVX permutes.
+#[derive(Clone, Copy)]
+pub struct Avx2(__m256);
+
+impl Avx2 {
+    #[inline(always)]
+    fn to_array(self) -> [f32; 8] {
+        let mut values = [0.0; 8];
+        unsafe { _mm256_storeu_ps(values.as_mut_ptr(), self.0) };
+        values
+    }
+}
+
+impl Lanes for Avx2 {
+    const CANDIDATES: usize = 2;
+
+    #[inline(always)]
+    fn new(x: f32, y: f32, z: f32, w: f32) -> Self {
+        Avx2(unsafe { _mm256_setr_ps(x, y, z, w, x, y, z, w) })
+    }
+
+    #[inline(always)]
+    fn from_vec4(v: Vec4) -> Self {
+        Self::new(v.x(), v.y(), v.z(), v.w())
+    }
+
+    #[inline(always)]
+    fn to_vec4(self) -> Vec4 {
+        self.candidate(0)
+    }
+
+    #[inline(always)]
+    fn pair(first: Self, second: Self) -> Self {
+        Avx2(unsafe { _mm256_blend_ps::<0xF0>(first.0, second.0) })
+    }
+
+    #[inline(always)]
+    fn candidate(self, index: usize) -> Vec4 {
+        let values = self.to_array();
+        let v = &values[4 * index..4 * index + 4];
+        Vec4::new(v[0], v[1], v[2], v[3])
+    }
+
+    #[inline(always)]
+    fn splat_x(self) -> Self {
+        Avx2(unsafe { _mm256_permute_ps::<0x00>(self.0) })
+    }
+
+    #[inline(always)]
+    fn splat_y(self) -> Self {
+        Avx2(unsafe { _mm256_permute_ps::<0x55>(self.0) })
+    }
+
+    #[inline(always)]
+    fn splat_z(self) -> Self {
+        Avx2(unsafe { _mm256_permute_ps::<0xAA>(self.0) })
+    }
+
+    #[inline(always)]
+    fn splat_w(self) -> Self {
+        Avx2(unsafe { _mm256_permute_ps::<0xFF>(self.0) })
+    }
+
+    #[inline(always)]
+    fn max(self, other: Self) -> Self {
+        // vmaxps returns its second operand if either is NaN, so fix up NaNs in self
+        unsafe {
+            let max = _mm256_max_ps(other.0, self.0);
+            let nan = _mm256_cmp_ps::<_CMP_UNORD_Q>(self.0, self.0);
+            Avx2(_mm256_blendv_ps(max, other.0, nan))
+        }
+    }
+
+    #[inline(always)]
+    fn min(self, other: Self) -> Self {
+        // vminps returns its second operand if either is NaN, so fix up NaNs in self
+        unsafe {
+            let min = _mm256_min_ps(other.0, self.0);
+            let nan = _mm256_cmp_ps::<_CMP_UNORD_Q>(self.0, self.0);
+            Avx2(_mm256_blendv_ps(min, other.0, nan))
+        }
+    }
+
+    #[inline(always)]
+    fn reciprocal(self) -> Self {
+        Avx2(unsafe { _mm256_div_ps(_mm256_set1_ps(1.0), self.0) })
+    }
+
+    #[inline(always)]
+    fn truncate(self) -> Self {
+        Avx2(unsafe { _mm256_round_ps::<{ _MM_FROUND_TO_ZERO | _MM_FROUND_NO_EXC }>(self.0) })
+    }
+
+    #[inline(always)]
+    fn any_less_than(self, other: Self) -> bool {
+        unsafe { _mm256_movemask_ps(_mm256_cmp_ps::<_CMP_LT_OQ>(self.0, other.0)) != 0 }
+    }
+}
+
+impl Add for Avx2 {
+    type Output = Avx2;
+
+    #[inline(always)]
+    fn add(self, other: Avx2) -> Avx2 {
+        Avx2(unsafe { _mm256_add_ps(self.0, other.0) })
+    }
+}
+
+impl AddAssign for Avx2 {
+    #[inline(always)]
+    fn add_assign(&mut self, other: Avx2) {
+        *self = *self + other;
+    }
+}
+
+impl Sub for Avx2 {
+    type Output = Avx2;
+
+    #[inline(always)]
+    fn sub(self, other: Avx2) -> Avx2 {
+        Avx2(unsafe { _mm256_sub_ps(self.0, other.0) })
+    }
+}
+
+impl Mul for Avx2 {
+    type Output = Avx2;
+
+    #[inline(always)]
+    fn mul(self, other: Avx2) -> Avx2 {
+        Avx2(unsafe { _mm256_mul_ps(self.0, other.0) })
+    }
+}
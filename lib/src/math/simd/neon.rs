@@ -0,0 +1,133 @@
+// Copyright (c) 2018-2021 Jan Solanti <jhs@psonet.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to	deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be included
+// in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.
+// IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT,
+// TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION WITH THE
+// SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! NEON backend of `Lanes`
+
+use core::arch::aarch64::*;
+use core::ops::{Add, AddAssign, Mul, Sub};
+
+use super::Lanes;
+use crate::math::Vec4;
+
+/// NEON lanes, which every aarch64 CPU supports
+#[derive(Clone, Copy)]
+pub struct Neon(float32x4_t);
+
+impl Lanes for Neon {
+    #[inline(always)]
+    fn new(x: f32, y: f32, z: f32, w: f32) -> Self {
+        let values = [x, y, z, w];
+        Neon(unsafe { vld1q_f32(values.as_ptr()) })
+    }
+
+    #[inline(always)]
+    fn from_vec4(v: Vec4) -> Self {
+        Self::new(v.x(), v.y(), v.z(), v.w())
+    }
+
+    #[inline(always)]
+    fn to_vec4(self) -> Vec4 {
+        let mut values = [0.0; 4];
+        unsafe { vst1q_f32(values.as_mut_ptr(), self.0) };
+        Vec4::new(values[0], values[1], values[2], values[3])
+    }
+
+    #[inline(always)]
+    fn splat_x(self) -> Self {
+        Neon(unsafe { vdupq_laneq_f32::<0>(self.0) })
+    }
+
+    #[inline(always)]
+    fn splat_y(self) -> Self {
+        Neon(unsafe { vdupq_laneq_f32::<1>(self.0) })
+    }
+
+    #[inline(always)]
+    fn splat_z(self) -> Self {
+        Neon(unsafe { vdupq_laneq_f32::<2>(self.0) })
+    }
+
+    #[inline(always)]
+    fn splat_w(self) -> Self {
+        Neon(unsafe { vdupq_laneq_f32::<3>(self.0) })
+    }
+
+    #[inline(always)]
+    fn max(self, other: Self) -> Self {
+        // fmaxnm has the same NaN handling as f32::max
+        Neon(unsafe { vmaxnmq_f32(self.0, other.0) })
+    }
+
+    #[inline(always)]
+    fn min(self, other: Self) -> Self {
+        // fminnm has the same NaN handling as f32::min
+        Neon(unsafe { vminnmq_f32(self.0, other.0) })
+    }
+
+    #[inline(always)]
+    fn reciprocal(self) -> Self {
+        Neon(unsafe { vdivq_f32(vdupq_n_f32(1.0), self.0) })
+    }
+
+    #[inline(always)]
+    fn truncate(self) -> Self {
+        Neon(unsafe { vrndq_f32(self.0) })
+    }
+
+    #[inline(always)]
+    fn any_less_than(self, other: Self) -> bool {
+        unsafe { vmaxvq_u32(vcltq_f32(self.0, other.0)) != 0 }
+    }
+}
+
+impl Add for Neon {
+    type Output = Neon;
+
+    #[inline(always)]
+    fn add(self, other: Neon) -> Neon {
+        Neon(unsafe { vaddq_f32(self.0, other.0) })
+    }
+}
+
+impl AddAssign for Neon {
+    #[inline(always)]
+    fn add_assign(&mut self, other: Neon) {
+        *self = *self + other;
+    }
+}
+
+impl Sub for Neon {
+    type Output = Neon;
+
+    #[inline(always)]
+    fn sub(self, other: Neon) -> Neon {
+        Neon(unsafe { vsubq_f32(self.0, other.0) })
+    }
+}
+
+impl Mul for Neon {
+    type Output = Neon;
+
+    #[inline(always)]
+    fn mul(self, other: Neon) -> Neon {
+        Neon(unsafe { vmulq_f32(self.0, other.0) })
+    }
+}
@@ -0,0 +1,144 @@
+// Copyright (c) 2018-2021 Jan Solanti <jhs@psonet.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to	deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be included
+// in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.
+// IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT,
+// TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION WITH THE
+// SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! SSE4.1 backend of `Lanes`
+
+use core::ops::{Add, AddAssign, Mul, Sub};
+
+#[cfg(target_arch = "x86")]
+use core::arch::x86::*;
+#[cfg(target_arch = "x86_64")]
+use core::arch::x86_64::*;
+
+use super::Lanes;
+use crate::math::Vec4;
+
+/// SSE4.1 lanes, only to be used where the CPU is known to support SSE4.1
+#[derive(Clone, Copy)]
+pub struct Sse41(__m128);
+
+impl Lanes for Sse41 {
+    #[inline(always)]
+    fn new(x: f32, y: f32, z: f32, w: f32) -> Self {
+        Sse41(unsafe { _mm_setr_ps(x, y, z, w) })
+    }
+
+    #[inline(always)]
+    fn from_vec4(v: Vec4) -> Self {
+        Self::new(v.x(), v.y(), v.z(), v.w())
+    }
+
+    #[inline(always)]
+    fn to_vec4(self) -> Vec4 {
+        let mut values = [0.0; 4];
+        unsafe { _mm_storeu_ps(values.as_mut_ptr(), self.0) };
+        Vec4::new(values[0], values[1], values[2], values[3])
+    }
+
+    #[inline(always)]
+    fn splat_x(self) -> Self {
+        Sse41(unsafe { _mm_shuffle_ps::<0x00>(self.0, self.0) })
+    }
+
+    #[inline(always)]
+    fn splat_y(self) -> Self {
+        Sse41(unsafe { _mm_shuffle_ps::<0x55>(self.0, self.0) })
+    }
+
+    #[inline(always)]
+    fn splat_z(self) -> Self {
+        Sse41(unsafe { _mm_shuffle_ps::<0xAA>(self.0, self.0) })
+    }
+
+    #[inline(always)]
+    fn splat_w(self) -> Self {
+        Sse41(unsafe { _mm_shuffle_ps::<0xFF>(self.0, self.0) })
+    }
+
+    #[inline(always)]
+    fn max(self, other: Self) -> Self {
+        // maxps returns its second operand if either is NaN, so fix up NaNs in self
+        unsafe {
+            let max = _mm_max_ps(other.0, self.0);
+            let nan = _mm_cmpunord_ps(self.0, self.0);
+            Sse41(_mm_blendv_ps(max, other.0, nan))
+        }
+    }
+
+    #[inline(always)]
+    fn min(self, other: Self) -> Self {
+        // minps returns its second operand if either is NaN, so fix up NaNs in self
+        unsafe {
+            let min = _mm_min_ps(other.0, self.0);
+            let nan = _mm_cmpunord_ps(self.0, self.0);
+            Sse41(_mm_blendv_ps(min, other.0, nan))
+        }
+    }
+
+    #[inline(always)]
+    fn reciprocal(self) -> Self {
+        Sse41(unsafe { _mm_div_ps(_mm_set1_ps(1.0), self.0) })
+    }
+
+    #[inline(always)]
+    fn truncate(self) -> Self {
+        Sse41(unsafe { _mm_round_ps::<{ _MM_FROUND_TO_ZERO | _MM_FROUND_NO_EXC }>(self.0) })
+    }
+
+    #[inline(always)]
+    fn any_less_than(self, other: Self) -> bool {
+        unsafe { _mm_movemask_ps(_mm_cmplt_ps(self.0, other.0)) != 0 }
+    }
+}
+
+impl Add for Sse41 {
+    type Output = Sse41;
+
+    #[inline(always)]
+    fn add(self, other: Sse41) -> Sse41 {
+        Sse41(unsafe { _mm_add_ps(self.0, other.0) })
+    }
+}
+
+impl AddAssign for Sse41 {
+    #[inline(always)]
+    fn add_assign(&mut self, other: Sse41) {
+        *self = *self + other;
+    }
+}
+
+impl Sub for Sse41 {
+    type Output = Sse41;
+
+    #[inline(always)]
+    fn sub(self, other: Sse41) -> Sse41 {
+        Sse41(unsafe { _mm_sub_ps(self.0, other.0) })
+    }
+}
+
+impl Mul for Sse41 {
+    type Output = Sse41;
+
+    #[inline(always)]
+    fn mul(self, other: Sse41) -> Sse41 {
+        Sse41(unsafe { _mm_mul_ps(self.0, other.0) })
+    }
+}
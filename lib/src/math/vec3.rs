@@ -129,7 +129,7 @@ impl Add<f32> for Vec3 {
     }
 }
 
-impl<'a> Add<f32> for &'a Vec3 {
+impl Add<f32> for &Vec3 {
     type Output = Vec3;
 
     fn add(self, other: f32) -> Vec3 {
@@ -193,7 +193,7 @@ impl Sub<f32> for Vec3 {
     }
 }
 
-impl<'a> Sub<f32> for &'a Vec3 {
+impl Sub<f32> for &Vec3 {
     type Output = Vec3;
 
     fn sub(self, other: f32) -> Vec3 {
@@ -257,7 +257,7 @@ impl Mul<f32> for Vec3 {
     }
 }
 
-impl<'a> Mul<f32> for &'a Vec3 {
+impl Mul<f32> for &Vec3 {
     type Output = Vec3;
 
     fn mul(self, other: f32) -> Vec3 {
@@ -321,7 +321,7 @@ impl Div<f32> for Vec3 {
     }
 }
 
-impl<'a> Div<f32> for &'a Vec3 {
+impl Div<f32> for &Vec3 {
     type Output = Vec3;
 
     fn div(self, other: f32) -> Vec3 {
@@ -53,7 +53,7 @@ impl Vec4 {
         self.w
     }
 
-    pub fn to_vec3(&self) -> Vec3 {
+    pub fn to_vec3(self) -> Vec3 {
         Vec3::new(self.x, self.y, self.z)
     }
 
@@ -135,7 +135,7 @@ impl<'a> Add for &'a Vec4 {
     }
 }
 
-impl<'a> Add<Vec4> for &'a Vec4 {
+impl Add<Vec4> for &Vec4 {
     type Output = Vec4;
 
     fn add(self, other: Vec4) -> Vec4 {
@@ -174,7 +174,7 @@ impl Add<f32> for Vec4 {
     }
 }
 
-impl<'a> Add<f32> for &'a Vec4 {
+impl Add<f32> for &Vec4 {
     type Output = Vec4;
 
     fn add(self, other: f32) -> Vec4 {
@@ -240,7 +240,7 @@ impl<'a> Sub for &'a Vec4 {
     }
 }
 
-impl<'a> Sub<Vec4> for &'a Vec4 {
+impl Sub<Vec4> for &Vec4 {
     type Output = Vec4;
 
     fn sub(self, other: Vec4) -> Vec4 {
@@ -279,7 +279,7 @@ impl Sub<f32> for Vec4 {
     }
 }
 
-impl<'a> Sub<f32> for &'a Vec4 {
+impl Sub<f32> for &Vec4 {
     type Output = Vec4;
 
     fn sub(self, other: f32) -> Vec4 {
@@ -345,7 +345,7 @@ impl<'a> Mul for &'a Vec4 {
     }
 }
 
-impl<'a> Mul<Vec4> for &'a Vec4 {
+impl Mul<Vec4> for &Vec4 {
     type Output = Vec4;
 
     fn mul(self, other: Vec4) -> Vec4 {
@@ -384,7 +384,7 @@ impl Mul<f32> for Vec4 {
     }
 }
 
-impl<'a> Mul<f32> for &'a Vec4 {
+impl Mul<f32> for &Vec4 {
     type Output = Vec4;
 
     fn mul(self, other: f32) -> Vec4 {
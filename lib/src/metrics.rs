@@ -0,0 +1,498 @@
+// Copyright (c) 2018-2021 Jan Solanti <jhs@psonet.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to	deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be included
+// in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.
+// IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT,
+// TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION WITH THE
+// SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Quality metrics of compressed images
+//!
+//! The functions compare an original RGBA image with a decoded one, or with a compressed one
+//! that is decoded a tile at a time. Errors are measured in 8-bit units. SSIM follows Wang et
+//! al.: it is averaged over every position of an 11x11 window with Gaussian weights of
+//! standard deviation 1.5 that lies within the image. Images narrower or shorter than the
+//! window use it cut down to the size of the image along that side.
+
+use crate::astc::MAX_TEXELS;
+use crate::normal;
+use crate::{ColourWeights, Error, Format};
+
+/// Size of the square windows SSIM is computed over
+const WINDOW: usize = 11;
+
+/// Standard deviation of the Gaussian weights of the SSIM windows
+const SIGMA: f32 = 1.5;
+
+/// Number of SSIM window positions along each side of a tile. Each tile is read with the
+/// pixels its windows reach beyond it.
+const TILE: usize = 32;
+
+/// Size of the pixels read for a tile along each side
+const REGION: usize = TILE + WINDOW - 1;
+
+/// Stabilising constants of SSIM for 8-bit values
+const C1: f32 = (0.01 * 255.0) * (0.01 * 255.0);
+const C2: f32 = (0.03 * 255.0) * (0.03 * 255.0);
+
+/// Error statistics of a decoded image, with the channels in RGBA order
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Metrics {
+    /// Mean squared error of each channel
+    pub mse: [f32; 4],
+    /// Peak signal to noise ratio of each channel in dB, infinite for identical channels
+    pub psnr: [f32; 4],
+    /// Mean structural similarity of each channel, one for identical channels
+    pub ssim: [f32; 4],
+    /// Largest absolute difference of each channel
+    pub max_error: [u8; 4],
+    /// Mean squared error over all channels, with red, green and blue weighted by the colour
+    /// weights scaled to sum to three
+    pub weighted_mse: f32,
+    /// Peak signal to noise ratio of the weighted mean squared error in dB
+    pub weighted_psnr: f32,
+}
+
+/// Angles between the normals of two normal maps, in degrees
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AngularError {
+    /// Mean angle over all pixels
+    pub mean: f32,
+    /// Largest angle of any pixel
+    pub max: f32,
+}
+
+/// Running sums of the pixel pairs of an image or block
+#[derive(Clone, Copy, Default)]
+struct Sums {
+    pixels: u64,
+    squared_error: [u64; 4],
+    max_error: [u8; 4],
+}
+
+impl Sums {
+    fn add(&mut self, original: [u8; 4], decoded: [u8; 4]) {
+        self.pixels += 1;
+        for c in 0..4 {
+            let error = original[c].abs_diff(decoded[c]);
+            self.squared_error[c] += u64::from(error) * u64::from(error);
+            self.max_error[c] = self.max_error[c].max(error);
+        }
+    }
+
+    fn mse(&self) -> [f32; 4] {
+        let pixels = self.pixels.max(1) as f64;
+        self.squared_error.map(|sum| (sum as f64 / pixels) as f32)
+    }
+}
+
+/// Converts a mean squared error to a peak signal to noise ratio in dB
+fn psnr(mse: f32) -> f32 {
+    if mse > 0.0 {
+        10.0 * libm::log10f(255.0 * 255.0 / mse)
+    } else {
+        f32::INFINITY
+    }
+}
+
+/// Returns the weights of the channels in the weighted mean squared error
+fn channel_weights(weights: ColourWeights) -> [f32; 4] {
+    let sum: f32 = weights.iter().sum();
+    let scale = if sum > 0.0 { 3.0 / sum } else { 1.0 };
+    [
+        weights[0] * scale,
+        weights[1] * scale,
+        weights[2] * scale,
+        1.0,
+    ]
+}
+
+/// Combines the mean squared errors of the channels using the channel weights
+fn weighted_mse(mse: [f32; 4], weights: [f32; 4]) -> f32 {
+    mse.iter()
+        .zip(weights)
+        .map(|(mse, weight)| mse * weight)
+        .sum::<f32>()
+        / 4.0
+}
+
+/// Returns the Gaussian weights of a window of the given size, which sum to one
+fn gaussian(size: usize) -> [f32; WINDOW] {
+    let centre = (size - 1) as f32 / 2.0;
+    let mut weights = [0.0; WINDOW];
+    for (i, weight) in weights[..size].iter_mut().enumerate() {
+        let distance = i as f32 - centre;
+        *weight = libm::expf(-distance * distance / (2.0 * SIGMA * SIGMA));
+    }
+    let sum: f32 = weights.iter().sum();
+    weights.map(|weight| weight / sum)
+}
+
+/// Sums the structural similarity of one channel over the window positions of a tile
+///
+/// `original` and `decoded` hold the pixels of the tile region in rows of `region_width`.
+/// `columns` and `rows` are the numbers of window positions, and `weights` the weights of
+/// the window along each axis with their sizes.
+fn ssim_tile(
+    original: &[[u8; 4]],
+    decoded: &[[u8; 4]],
+    region_width: usize,
+    (columns, rows): (usize, usize),
+    weights: &[&[f32]; 2],
+    channel: usize,
+) -> f64 {
+    let [weights_x, weights_y] = *weights;
+    if columns == 0 || rows == 0 {
+        return 0.0;
+    }
+
+    // weighted sums of x, y, x², y² and xy along the rows of the region
+    let mut moments = [[0.0f32; 5]; REGION * TILE];
+    for row in 0..rows + weights_y.len() - 1 {
+        for column in 0..columns {
+            let sums = &mut moments[TILE * row + column];
+            for (i, &weight) in weights_x.iter().enumerate() {
+                let index = region_width * row + column + i;
+                let x = f32::from(original[index][channel]);
+                let y = f32::from(decoded[index][channel]);
+                for (sum, value) in sums.iter_mut().zip([x, y, x * x, y * y, x * y]) {
+                    *sum += weight * value;
+                }
+            }
+        }
+    }
+
+    // and down the columns, giving the weighted moments of each window
+    let mut total = 0.0;
+    for row in 0..rows {
+        for column in 0..columns {
+            let mut sums = [0.0f32; 5];
+            for (j, &weight) in weights_y.iter().enumerate() {
+                let row_sums = &moments[TILE * (row + j) + column];
+                for (sum, value) in sums.iter_mut().zip(row_sums) {
+                    *sum += weight * value;
+                }
+            }
+
+            let [mean_x, mean_y, square_x, square_y, product] = sums;
+            let var_x = square_x - mean_x * mean_x;
+            let var_y = square_y - mean_y * mean_y;
+            let covariance = product - mean_x * mean_y;
+            let ssim = ((2.0 * mean_x * mean_y + C1) * (2.0 * covariance + C2))
+                / ((mean_x * mean_x + mean_y * mean_y + C1) * (var_x + var_y + C2));
+            total += f64::from(ssim);
+        }
+    }
+    total
+}
+
+/// Measures an image tile by tile
+///
+/// `fill_region` writes the decoded pixels of the region at `(x, y)` with the given size in
+/// row-major order.
+fn measure<F>(
+    original: &[u8],
+    width: usize,
+    height: usize,
+    weights: ColourWeights,
+    mut fill_region: F,
+) -> Metrics
+where
+    F: FnMut(usize, usize, usize, usize, &mut [[u8; 4]]),
+{
+    let mut sums = Sums::default();
+    let mut ssim_sums = [0.0f64; 4];
+    let mut source = [[0u8; 4]; REGION * REGION];
+    let mut decoded = [[0u8; 4]; REGION * REGION];
+
+    // the windows that fit in the image, cut down for small images
+    let (window_width, window_height) = (WINDOW.min(width), WINDOW.min(height));
+    let (weights_x, weights_y) = (gaussian(window_width), gaussian(window_height));
+    let window_weights = [&weights_x[..window_width], &weights_y[..window_height]];
+    let positions = (width - window_width + 1, height - window_height + 1);
+
+    for y in (0..height).step_by(TILE) {
+        for x in (0..width).step_by(TILE) {
+            let region_width = (TILE + window_width - 1).min(width - x);
+            let region_height = (TILE + window_height - 1).min(height - y);
+            let pixels = region_width * region_height;
+            for (i, pixel) in source[..pixels].iter_mut().enumerate() {
+                let index = 4 * (width * (y + i / region_width) + x + i % region_width);
+                pixel.copy_from_slice(&original[index..index + 4]);
+            }
+            fill_region(x, y, region_width, region_height, &mut decoded[..pixels]);
+
+            // the errors count each pixel once, in the tile it lies in
+            for row in 0..TILE.min(height - y) {
+                let start = region_width * row;
+                let end = start + TILE.min(width - x);
+                for (&original, &decoded) in source[start..end].iter().zip(&decoded[start..end]) {
+                    sums.add(original, decoded);
+                }
+            }
+
+            let columns = TILE.min(positions.0.saturating_sub(x));
+            let rows = TILE.min(positions.1.saturating_sub(y));
+            for (c, sum) in ssim_sums.iter_mut().enumerate() {
+                *sum += ssim_tile(
+                    &source[..pixels],
+                    &decoded[..pixels],
+                    region_width,
+                    (columns, rows),
+                    &window_weights,
+                    c,
+                );
+            }
+        }
+    }
+
+    let mse = sums.mse();
+    let weighted_mse = weighted_mse(mse, channel_weights(weights));
+    let windows = (positions.0 * positions.1) as f64;
+    Metrics {
+        mse,
+        psnr: mse.map(psnr),
+        ssim: ssim_sums.map(|sum| (sum / windows) as f32),
+        max_error: sums.max_error,
+        weighted_mse,
+        weighted_psnr: psnr(weighted_mse),
+    }
+}
+
+/// Checks the dimensions of an image and that `original` holds all of its pixels, returning
+/// the number of values of the image
+fn checked_original(original: &[u8], width: usize, height: usize) -> Result<usize, Error> {
+    if width == 0 || height == 0 {
+        return Err(Error::ZeroDimensions);
+    }
+    let values = width
+        .checked_mul(height)
+        .and_then(|pixels| pixels.checked_mul(4))
+        .ok_or(Error::SizeOverflow)?;
+    if original.len() < values {
+        return Err(Error::InputTooShort {
+            expected: values,
+            actual: original.len(),
+        });
+    }
+    Ok(values)
+}
+
+/// Compares an original RGBA image with a decoded one
+///
+/// * `original` - The uncompressed pixel data
+/// * `decoded`  - The pixel data decoded from the compressed image
+/// * `width`    - The width of the images
+/// * `height`   - The height of the images
+/// * `weights`  - The colour weights of the weighted mean squared error
+pub fn compare(
+    original: &[u8],
+    decoded: &[u8],
+    width: usize,
+    height: usize,
+    weights: ColourWeights,
+) -> Result<Metrics, Error> {
+    let values = checked_original(original, width, height)?;
+    if decoded.len() < values {
+        return Err(Error::InputTooShort {
+            expected: values,
+            actual: decoded.len(),
+        });
+    }
+
+    Ok(measure(
+        original,
+        width,
+        height,
+        weights,
+        |x, y, w, _, region| {
+            for (i, pixel) in region.iter_mut().enumerate() {
+                let index = 4 * (width * (y + i / w) + x + i % w);
+                pixel.copy_from_slice(&decoded[index..index + 4]);
+            }
+        },
+    ))
+}
+
+/// Compares an original RGBA image with its compressed version
+///
+/// * `format`     - The format of the compressed image
+/// * `original`   - The uncompressed pixel data
+/// * `compressed` - The compressed image data
+/// * `width`      - The width of the image
+/// * `height`     - The height of the image
+/// * `weights`    - The colour weights of the weighted mean squared error
+pub fn compare_compressed(
+    format: Format,
+    original: &[u8],
+    compressed: &[u8],
+    width: usize,
+    height: usize,
+    weights: ColourWeights,
+) -> Result<Metrics, Error> {
+    checked_original(original, width, height)?;
+    let compressed = checked_compressed(format, compressed, width, height)?;
+
+    let (block_width, block_height) = format.block_dimensions();
+    let blocks_wide = width.div_ceil(block_width);
+    let block_size = format.block_size();
+    let mut block = [[0u8; 4]; MAX_TEXELS];
+
+    Ok(measure(
+        original,
+        width,
+        height,
+        weights,
+        |x, y, w, h, region| {
+            // decode every block overlapping the region
+            for by in y / block_height..(y + h).div_ceil(block_height) {
+                for bx in x / block_width..(x + w).div_ceil(block_width) {
+                    let offset = (blocks_wide * by + bx) * block_size;
+                    let pixels = &mut block[..block_width * block_height];
+                    format.decompress_footprint(&compressed[offset..offset + block_size], pixels);
+
+                    for (i, pixel) in pixels.iter().enumerate() {
+                        let (px, py) = (
+                            block_width * bx + i % block_width,
+                            block_height * by + i / block_width,
+                        );
+                        if (x..x + w).contains(&px) && (y..y + h).contains(&py) {
+                            region[w * (py - y) + px - x] = *pixel;
+                        }
+                    }
+                }
+            }
+        },
+    ))
+}
+
+/// Checks that a compressed image holds all blocks of the image, returning exactly those
+fn checked_compressed(
+    format: Format,
+    compressed: &[u8],
+    width: usize,
+    height: usize,
+) -> Result<&[u8], Error> {
+    let (_, bytes) = format.checked_sizes(width, height)?;
+    compressed.get(..bytes).ok_or(Error::InputTooShort {
+        expected: bytes,
+        actual: compressed.len(),
+    })
+}
+
+/// Writes the weighted mean squared error of every block of a compressed image to a map with
+/// one value per block in row-major order
+///
+/// Only the pixels within the image count towards the errors of the blocks at its edges.
+///
+/// * `format`     - The format of the compressed image
+/// * `original`   - The uncompressed pixel data
+/// * `compressed` - The compressed image data
+/// * `width`      - The width of the image
+/// * `height`     - The height of the image
+/// * `weights`    - The colour weights of the weighted mean squared error
+/// * `errors`     - Output buffer for the errors of the blocks
+pub fn block_errors(
+    format: Format,
+    original: &[u8],
+    compressed: &[u8],
+    width: usize,
+    height: usize,
+    weights: ColourWeights,
+    errors: &mut [f32],
+) -> Result<(), Error> {
+    checked_original(original, width, height)?;
+    let compressed = checked_compressed(format, compressed, width, height)?;
+
+    let (block_width, block_height) = format.block_dimensions();
+    let blocks_wide = width.div_ceil(block_width);
+    let blocks = blocks_wide * height.div_ceil(block_height);
+    let actual = errors.len();
+    let errors = errors.get_mut(..blocks).ok_or(Error::OutputTooShort {
+        expected: blocks,
+        actual,
+    })?;
+
+    let weights = channel_weights(weights);
+    let mut block = [[0u8; 4]; MAX_TEXELS];
+    for ((index, data), error) in compressed
+        .chunks(format.block_size())
+        .enumerate()
+        .zip(errors)
+    {
+        let pixels = &mut block[..block_width * block_height];
+        format.decompress_footprint(data, pixels);
+
+        let mut sums = Sums::default();
+        for (i, &decoded) in pixels.iter().enumerate() {
+            let x = block_width * (index % blocks_wide) + i % block_width;
+            let y = block_height * (index / blocks_wide) + i / block_width;
+            if x < width && y < height {
+                let offset = 4 * (width * y + x);
+                let mut pixel = [0u8; 4];
+                pixel.copy_from_slice(&original[offset..offset + 4]);
+                sums.add(pixel, decoded);
+            }
+        }
+        *error = weighted_mse(sums.mse(), weights);
+    }
+
+    Ok(())
+}
+
+/// Compares an original normal map with a decoded one by the angles between their normals
+///
+/// X and Y are read from red and green, mapping 0..255 to -1..1. Z is reconstructed from
+/// them in both images, as for normal maps stored in two channels.
+///
+/// * `original` - The uncompressed pixel data
+/// * `decoded`  - The pixel data decoded from the compressed image
+/// * `width`    - The width of the images
+/// * `height`   - The height of the images
+pub fn angular_error(
+    original: &[u8],
+    decoded: &[u8],
+    width: usize,
+    height: usize,
+) -> Result<AngularError, Error> {
+    let values = checked_original(original, width, height)?;
+    if decoded.len() < values {
+        return Err(Error::InputTooShort {
+            expected: values,
+            actual: decoded.len(),
+        });
+    }
+
+    let mut sum = 0.0f64;
+    let mut max = 0.0f32;
+    let pixels = original[..values]
+        .chunks_exact(4)
+        .zip(decoded.chunks_exact(4));
+    for (original, decoded) in pixels {
+        let normal = |pixel: &[u8]| {
+            normal::from_xy(normal::from_unorm8(pixel[0]), normal::from_unorm8(pixel[1]))
+        };
+        let cosine = normal(original).dot(&normal(decoded)).clamp(-1.0, 1.0);
+        let angle = libm::acosf(cosine).to_degrees();
+        sum += f64::from(angle);
+        max = max.max(angle);
+    }
+
+    Ok(AngularError {
+        mean: (sum / (values / 4) as f64) as f32,
+        max,
+    })
+}
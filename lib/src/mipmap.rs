@@ -0,0 +1,492 @@
+// Copyright (c) 2018-2021 Jan Solanti <jhs@psonet.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to	deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be included
+// in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.
+// IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT,
+// TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION WITH THE
+// SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Generation of mip chains from 8-bit RGBA images
+
+use core::f32::consts::PI;
+
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+use crate::math::{linear_to_srgb, srgb8_to_linear, Vec3};
+use crate::{ColourSpace, Error, PixelLayout};
+
+/// Most source pixels a filter reads along each axis. Every level is at least a third of the
+/// size of the one above it, so the widest filter covers 19 source pixels.
+const MAX_TAPS: usize = 20;
+
+/// Shape parameter of the Kaiser window, trading sharpness for less ringing
+const KAISER_ALPHA: f32 = 4.0;
+
+/// Filter used to downsample each mip level from the one above it
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MipFilter {
+    /// Averages the source pixels under each destination pixel, fast but prone to aliasing
+    #[default]
+    Box,
+
+    /// Tent filter reaching one destination pixel out, smoother than box
+    Triangle,
+
+    /// Sinc filter with a Kaiser window reaching three destination pixels out, sharp with
+    /// little ringing
+    Kaiser,
+
+    /// Three-lobe Lanczos filter, the sharpest with some ringing around hard edges
+    Lanczos,
+}
+
+impl MipFilter {
+    /// Returns how far the filter reaches, in destination pixels
+    fn support(self) -> f32 {
+        match self {
+            MipFilter::Box => 0.5,
+            MipFilter::Triangle => 1.0,
+            MipFilter::Kaiser | MipFilter::Lanczos => 3.0,
+        }
+    }
+
+    /// Returns the unnormalised weight of a source pixel at a distance in destination pixels
+    fn weight(self, distance: f32) -> f32 {
+        let distance = distance.abs();
+        if distance > self.support() {
+            return 0.0;
+        }
+
+        match self {
+            MipFilter::Box if distance == 0.5 => 0.5,
+            MipFilter::Box => 1.0,
+            MipFilter::Triangle => 1.0 - distance,
+            MipFilter::Kaiser => {
+                let t = distance / 3.0;
+                let window = bessel_i0(KAISER_ALPHA * libm::sqrtf(1.0 - t * t));
+                sinc(distance) * window / bessel_i0(KAISER_ALPHA)
+            }
+            MipFilter::Lanczos => sinc(distance) * sinc(distance / 3.0),
+        }
+    }
+}
+
+/// Defines how filters read pixels beyond the edges of an image
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EdgeMode {
+    /// Repeats the pixels along the edges, for textures that are not tiled
+    #[default]
+    Clamp,
+
+    /// Continues from the opposite edge, for textures that tile
+    Wrap,
+}
+
+/// Parameters for generating mip levels
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MipmapParams {
+    /// The filter used to downsample each level (defaults to box)
+    pub filter: MipFilter,
+
+    /// How the filter reads pixels beyond the edges of the image (defaults to clamp)
+    pub edge_mode: EdgeMode,
+
+    /// The colour space of the input pixels (defaults to linear)
+    ///
+    /// sRGB encoded colours are filtered as linear light values and encoded again, which keeps
+    /// the brightness of the smaller levels consistent. Alpha is always filtered as is.
+    pub colour_space: ColourSpace,
+
+    /// Scales the alpha of each level so that as many of its pixels as of the full size image
+    /// have an alpha at or above this reference value (defaults to `None`, which leaves the
+    /// filtered alpha as is)
+    ///
+    /// Alpha-tested textures such as foliage otherwise thin out in the smaller levels, as
+    /// filtering pulls the alpha of sparse details below the cutoff. The reference value is
+    /// usually the cutoff of the alpha test.
+    ///
+    /// BC1 and ETC2 RGB A1 make the pixels below `Params::alpha_threshold` transparent, so set
+    /// both to the same value to keep the fraction of opaque pixels of every compressed level.
+    /// Pixels between two different values would be counted as covered here but encoded as
+    /// transparent, or the other way around.
+    pub alpha_coverage: Option<u8>,
+
+    /// Treat the colours as tangent space normals, mapping each channel from 0..255 to -1..1
+    /// (defaults to false)
+    ///
+    /// Filtering shortens the normals, so they are normalised again after each downsample.
+    /// The colour space is ignored for normal maps.
+    pub normal_map: bool,
+}
+
+impl MipmapParams {
+    /// Downsamples an image to the next mip level, of the size given by
+    /// `mip_dimensions(width, height, 1)`
+    ///
+    /// With `alpha_coverage` set, the alpha coverage of the image is preserved in the next level.
+    /// Chaining calls measures each level against the adjusted one above it, `generate` keeps
+    /// the coverage of the full size image instead.
+    ///
+    /// * `rgba`   - The uncompressed pixel data
+    /// * `width`  - The width of the source image
+    /// * `height` - The height of the source image
+    /// * `output` - Output buffer for the next level
+    pub fn downsample(
+        &self,
+        rgba: &[u8],
+        width: usize,
+        height: usize,
+        output: &mut [u8],
+    ) -> Result<(), Error> {
+        let rgba = checked_input(rgba, image_len(width, height)?)?;
+        let (next_width, next_height) = mip_dimensions(width, height, 1);
+        let output = checked_output(output, 4 * next_width * next_height)?;
+        self.downsample_level(rgba, width, height, output);
+        if let Some(reference) = self.alpha_coverage {
+            preserve_coverage(output, reference, coverage(rgba, reference));
+        }
+        Ok(())
+    }
+
+    /// Generates a full mip chain, from the full size image down to 1x1
+    ///
+    /// Each level is downsampled from the one above it. The levels are stored one after the
+    /// other in `mip_chain_len(width, height)` values, starting with a copy of the image. With
+    /// `alpha_coverage` set, the alpha of every level is then scaled to the coverage of the full
+    /// size image. Levels are filtered from the unscaled level above, so the scaling of one
+    /// level does not carry over into the next.
+    ///
+    /// * `rgba`   - The uncompressed pixel data
+    /// * `width`  - The width of the source image
+    /// * `height` - The height of the source image
+    /// * `output` - Output buffer for the mip chain
+    pub fn generate(
+        &self,
+        rgba: &[u8],
+        width: usize,
+        height: usize,
+        output: &mut [u8],
+    ) -> Result<(), Error> {
+        self.generate_with_layout(rgba, PixelLayout::Rgba8, width, height, output)
+    }
+
+    /// Generates a full mip chain of 8-bit RGBA pixels from an image of 8-bit pixels in any
+    /// layout, like `generate`
+    ///
+    /// The first level of the chain holds the image expanded to RGBA.
+    ///
+    /// * `pixels` - The uncompressed pixel data, with rows of `width * layout.channels()` bytes
+    /// * `layout` - The arrangement of the channels of each pixel
+    /// * `width`  - The width of the source image
+    /// * `height` - The height of the source image
+    /// * `output` - Output buffer for the mip chain
+    pub fn generate_with_layout(
+        &self,
+        pixels: &[u8],
+        layout: PixelLayout,
+        width: usize,
+        height: usize,
+        output: &mut [u8],
+    ) -> Result<(), Error> {
+        let values = image_len(width, height)?;
+        let pixels = checked_input(pixels, values / 4 * layout.channels())?;
+        let chain_len = (1..mip_levels(width, height)).try_fold(values, |total, level| {
+            let (width, height) = mip_dimensions(width, height, level);
+            total.checked_add(4 * width * height)
+        });
+        let output = checked_output(output, chain_len.ok_or(Error::SizeOverflow)?)?;
+
+        let (mut previous, mut rest) = output.split_at_mut(values);
+        for (pixel, stored) in previous
+            .chunks_exact_mut(4)
+            .zip(pixels.chunks_exact(layout.channels()))
+        {
+            pixel.copy_from_slice(&layout.to_rgba(stored));
+        }
+        for level in 1..mip_levels(width, height) {
+            let (width, height) = mip_dimensions(width, height, level - 1);
+            let (next_width, next_height) = mip_dimensions(width, height, 1);
+            let (current, remaining) =
+                core::mem::take(&mut rest).split_at_mut(4 * next_width * next_height);
+            self.downsample_level(previous, width, height, current);
+            (previous, rest) = (current, remaining);
+        }
+
+        // every level is scaled to the coverage of the full size image
+        if let Some(reference) = self.alpha_coverage {
+            let (first, mut rest) = output.split_at_mut(values);
+            let target = coverage(first, reference);
+            for level in 1..mip_levels(width, height) {
+                let (width, height) = mip_dimensions(width, height, level);
+                let (current, remaining) =
+                    core::mem::take(&mut rest).split_at_mut(4 * width * height);
+                preserve_coverage(current, reference, target);
+                rest = remaining;
+            }
+        }
+        Ok(())
+    }
+
+    /// Filters an image of checked size into the next level
+    fn downsample_level(&self, rgba: &[u8], width: usize, height: usize, output: &mut [u8]) {
+        let (next_width, next_height) = mip_dimensions(width, height, 1);
+        let srgb = self.colour_space == ColourSpace::Srgb && !self.normal_map;
+        let colours: [f32; 256] = core::array::from_fn(|value| {
+            let value = value as f32 / 255.0;
+            if srgb {
+                srgb8_to_linear(value)
+            } else {
+                value
+            }
+        });
+
+        let filter_row = |(y, output_row): (usize, &mut [u8])| {
+            let rows = self.taps(y, height, next_height);
+            for (x, output_pixel) in output_row.chunks_exact_mut(4).enumerate() {
+                let columns = self.taps(x, width, next_width);
+                let mut sum = [0.0f32; 4];
+                for (&sy, &row_weight) in rows.indices().iter().zip(rows.weights()) {
+                    for (&sx, &column_weight) in columns.indices().iter().zip(columns.weights()) {
+                        let weight = row_weight * column_weight;
+                        let pixel = &rgba[4 * (width * sy + sx)..][..4];
+                        for (i, (total, &value)) in sum.iter_mut().zip(pixel).enumerate() {
+                            let value = if i < 3 {
+                                colours[usize::from(value)]
+                            } else {
+                                f32::from(value) / 255.0
+                            };
+                            *total += weight * value;
+                        }
+                    }
+                }
+
+                if self.normal_map {
+                    sum = renormalise(sum);
+                }
+
+                for (i, (value, total)) in output_pixel.iter_mut().zip(sum).enumerate() {
+                    let total = if srgb && i < 3 {
+                        linear_to_srgb(total)
+                    } else {
+                        total.clamp(0.0, 1.0)
+                    };
+                    *value = (total * 255.0 + 0.5) as u8;
+                }
+            }
+        };
+
+        #[cfg(feature = "rayon")]
+        let output_rows = output.par_chunks_mut(4 * next_width);
+        #[cfg(not(feature = "rayon"))]
+        let output_rows = output.chunks_mut(4 * next_width);
+
+        output_rows.enumerate().for_each(filter_row);
+    }
+
+    /// Returns the source pixels and weights of the filter for one destination pixel along an
+    /// axis
+    fn taps(&self, index: usize, source_len: usize, destination_len: usize) -> Taps {
+        let scale = source_len as f32 / destination_len as f32;
+        let centre = (index as f32 + 0.5) * scale;
+        let radius = self.filter.support() * scale;
+        let first = libm::ceilf(centre - radius - 0.5) as isize;
+        let last = libm::floorf(centre + radius - 0.5) as isize;
+
+        let mut taps = Taps {
+            indices: [0; MAX_TAPS],
+            weights: [0.0; MAX_TAPS],
+            len: 0,
+        };
+        let source_len = source_len as isize;
+        for i in first..=last {
+            let weight = self.filter.weight((i as f32 + 0.5 - centre) / scale);
+            if weight == 0.0 || taps.len == MAX_TAPS {
+                continue;
+            }
+
+            let source = match self.edge_mode {
+                EdgeMode::Clamp => i.clamp(0, source_len - 1),
+                EdgeMode::Wrap => i.rem_euclid(source_len),
+            };
+            taps.indices[taps.len] = source as usize;
+            taps.weights[taps.len] = weight;
+            taps.len += 1;
+        }
+
+        let total: f32 = taps.weights().iter().sum();
+        for weight in &mut taps.weights[..taps.len] {
+            *weight /= total;
+        }
+        taps
+    }
+}
+
+/// Source pixels along one axis and their normalised filter weights
+struct Taps {
+    indices: [usize; MAX_TAPS],
+    weights: [f32; MAX_TAPS],
+    len: usize,
+}
+
+impl Taps {
+    fn indices(&self) -> &[usize] {
+        &self.indices[..self.len]
+    }
+
+    fn weights(&self) -> &[f32] {
+        &self.weights[..self.len]
+    }
+}
+
+/// Returns the number of levels in a full mip chain, from the full size image down to 1x1
+pub fn mip_levels(width: usize, height: usize) -> usize {
+    (usize::BITS - width.max(height).leading_zeros()) as usize
+}
+
+/// Returns the size of a mip level, halving the size of the image once per level, rounding
+/// down but never below one pixel
+pub fn mip_dimensions(width: usize, height: usize, level: usize) -> (usize, usize) {
+    let shift = u32::try_from(level).unwrap_or(u32::MAX);
+    let halve = |size: usize| size.checked_shr(shift).unwrap_or(0).max(1);
+    (halve(width), halve(height))
+}
+
+/// Returns the number of values in a full mip chain of 8-bit RGBA pixels
+pub fn mip_chain_len(width: usize, height: usize) -> usize {
+    (0..mip_levels(width, height))
+        .map(|level| {
+            let (width, height) = mip_dimensions(width, height, level);
+            4 * width * height
+        })
+        .sum()
+}
+
+/// Normalises the normal held in the colours of a filtered pixel, with channels in 0..1
+fn renormalise(pixel: [f32; 4]) -> [f32; 4] {
+    let [x, y, z, alpha] = pixel;
+    let normal = Vec3::new(x, y, z) * 2.0 - 1.0;
+    let length = libm::sqrtf(normal.length2());
+    if length <= 0.0 {
+        return pixel;
+    }
+
+    let normal = normal * (0.5 / length) + 0.5;
+    [normal.x(), normal.y(), normal.z(), alpha]
+}
+
+/// Number of pixels of an image with an alpha at or above a reference value
+#[derive(Clone, Copy)]
+struct Coverage {
+    covered: usize,
+    pixels: usize,
+}
+
+/// Measures the alpha coverage of an image
+fn coverage(rgba: &[u8], reference: u8) -> Coverage {
+    let alphas = rgba.chunks_exact(4).map(|pixel| pixel[3]);
+    Coverage {
+        covered: alphas.filter(|&alpha| alpha >= reference).count(),
+        pixels: rgba.len() / 4,
+    }
+}
+
+/// Scales the alpha of an image so that its coverage comes as close as possible to a target
+///
+/// The alpha that best splits the image into covered and uncovered pixels is mapped to the
+/// reference value, rounding down so that no pixel below the split ends up covered.
+fn preserve_coverage(rgba: &mut [u8], reference: u8, target: Coverage) {
+    if reference == 0 {
+        // every pixel is covered anyway
+        return;
+    }
+
+    let mut histogram = [0usize; 256];
+    for pixel in rgba.chunks_exact(4) {
+        histogram[usize::from(pixel[3])] += 1;
+    }
+
+    // compare covered fractions by cross-multiplying the pixel counts
+    let pixels = (rgba.len() / 4) as u128;
+    let wanted = target.covered as u128 * pixels;
+    let mut covered = 0;
+    let mut best = (u128::MAX, usize::MAX);
+    let mut split = usize::from(reference);
+    for (alpha, &count) in histogram.iter().enumerate().skip(1).rev() {
+        covered += count;
+        let error = (covered as u128 * target.pixels as u128).abs_diff(wanted);
+        let candidate = (error, alpha.abs_diff(usize::from(reference)));
+        if candidate < best {
+            best = candidate;
+            split = alpha;
+        }
+    }
+
+    if split != usize::from(reference) {
+        for alpha in rgba.iter_mut().skip(3).step_by(4) {
+            *alpha = (usize::from(*alpha) * usize::from(reference) / split).min(255) as u8;
+        }
+    }
+}
+
+/// Normalised sinc function
+fn sinc(x: f32) -> f32 {
+    if x.abs() < 1e-6 {
+        1.0
+    } else {
+        libm::sinf(PI * x) / (PI * x)
+    }
+}
+
+/// Modified Bessel function of the first kind and order zero, summed from its power series
+fn bessel_i0(x: f32) -> f32 {
+    let quarter_square = x * x / 4.0;
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    for k in 1..32 {
+        term *= quarter_square / (k * k) as f32;
+        sum += term;
+        if term < sum * 1e-7 {
+            break;
+        }
+    }
+    sum
+}
+
+/// Returns the number of values in an RGBA image, rejecting empty and oversized images
+fn image_len(width: usize, height: usize) -> Result<usize, Error> {
+    if width == 0 || height == 0 {
+        return Err(Error::ZeroDimensions);
+    }
+    width
+        .checked_mul(height)
+        .and_then(|pixels| pixels.checked_mul(4))
+        .ok_or(Error::SizeOverflow)
+}
+
+fn checked_input(rgba: &[u8], expected: usize) -> Result<&[u8], Error> {
+    rgba.get(..expected).ok_or(Error::InputTooShort {
+        expected,
+        actual: rgba.len(),
+    })
+}
+
+fn checked_output(output: &mut [u8], expected: usize) -> Result<&mut [u8], Error> {
+    let actual = output.len();
+    output
+        .get_mut(..expected)
+        .ok_or(Error::OutputTooShort { expected, actual })
+}
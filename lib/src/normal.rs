@@ -0,0 +1,267 @@
+// Copyright (c) 2018-2021 Jan Solanti <jhs@psonet.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to	deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be included
+// in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.
+// IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT,
+// TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION WITH THE
+// SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Tangent space normal maps
+//!
+//! Normals are stored with X and Y in two channels, each mapping -1..1 to the range of the
+//! channel. Z points out of the surface and is reconstructed from X and Y.
+
+use crate::alpha;
+use crate::alpha::Bc4Block;
+use crate::colourblock;
+use crate::colourblock::Bc1Block;
+use crate::math::Vec3;
+use crate::{Algorithm, ColourSpace, ErrorMetric, Params};
+
+/// Returns the unit normal with the given X and Y, reconstructing a non-negative Z
+///
+/// X and Y are scaled down if they point outside the unit circle.
+pub fn from_xy(x: f32, y: f32) -> Vec3 {
+    let length2 = x * x + y * y;
+    if length2 > 1.0 {
+        let scale = 1.0 / libm::sqrtf(length2);
+        Vec3::new(x * scale, y * scale, 0.0)
+    } else {
+        Vec3::new(x, y, libm::sqrtf(1.0 - length2))
+    }
+}
+
+/// Maps an 8-bit channel value to -1..1
+pub fn from_unorm8(value: u8) -> f32 {
+    f32::from(value) / 255.0 * 2.0 - 1.0
+}
+
+/// Maps a value in -1..1 to an 8-bit channel value
+pub fn to_unorm8(value: f32) -> u8 {
+    ((value.clamp(-1.0, 1.0) * 0.5 + 0.5) * 255.0 + 0.5) as u8
+}
+
+/// Fits a BC5 block to normals by the angles between the decoded and the source normals
+///
+/// Starting from the block as fitted channel by channel, the indices of both channels are
+/// picked together and the endpoints are moved one step at a time while that lowers the
+/// summed angular error. The algorithm sets the number of rounds of endpoint steps.
+///
+/// * `normals`   - The X and Y of the source normals in the range -1..1
+/// * `mask`      - The valid pixel mask
+/// * `signed`    - Whether the block is BC5 SNORM
+/// * `algorithm` - The compression algorithm
+/// * `block`     - The compressed block to refine
+///
+/// Returns the number of interpolation steps of the refined red and green blocks.
+pub fn fit_bc5(
+    normals: &[[f32; 2]; 16],
+    mask: u32,
+    signed: bool,
+    algorithm: Algorithm,
+    block: &mut [u8],
+) -> [u8; 2] {
+    let normals = normals.map(|[x, y]| from_xy(x, y));
+    let mut best: [u8; 16] = block[..16].try_into().unwrap();
+    let mut best_error = select_bc5(&normals, mask, signed, &mut best);
+
+    let rounds = match algorithm {
+        Algorithm::RangeFit => 0,
+        Algorithm::ClusterFit => 2,
+        Algorithm::IterativeClusterFit => 8,
+    };
+    for _ in 0..rounds {
+        let mut improved = false;
+        for endpoint in [0, 1, 8, 9] {
+            for step in [-1, 1] {
+                let mut candidate = best;
+                let Some(value) = step_endpoint(candidate[endpoint], step, signed) else {
+                    continue;
+                };
+                candidate[endpoint] = value;
+
+                let error = select_bc5(&normals, mask, signed, &mut candidate);
+                if error < best_error {
+                    best = candidate;
+                    best_error = error;
+                    improved = true;
+                }
+            }
+        }
+        if !improved {
+            break;
+        }
+    }
+
+    block[..16].copy_from_slice(&best);
+    [&best[..8], &best[8..]].map(|bytes| Bc4Block::from_bytes(bytes).steps(signed))
+}
+
+/// Picks the indices of both channels of a BC5 block together, returning the summed
+/// angular error
+fn select_bc5(normals: &[Vec3; 16], mask: u32, signed: bool, block: &mut [u8; 16]) -> f32 {
+    let mut red = Bc4Block::from_bytes(&block[..8]);
+    let mut green = Bc4Block::from_bytes(&block[8..]);
+    let xs = alpha::normal_codes(&block[..8], signed);
+    let ys = alpha::normal_codes(&block[8..], signed);
+    let error = select_pairs(
+        normals,
+        mask,
+        &xs,
+        &ys,
+        &mut red.indices,
+        &mut green.indices,
+    );
+
+    block[..8].copy_from_slice(&red.to_bytes());
+    block[8..].copy_from_slice(&green.to_bytes());
+    error
+}
+
+/// Picks the indices of a DXT5nm block, with X in the alpha block and Y in the green of the
+/// colour block, together by the angles between the decoded and the source normals
+///
+/// * `normals` - The X and Y of the source normals in the range -1..1
+/// * `mask`    - The valid pixel mask
+/// * `block`   - The compressed BC3 block to update
+pub fn select_dxt5nm(normals: &[[f32; 2]; 16], mask: u32, block: &mut [u8]) {
+    let normals = normals.map(|[x, y]| from_xy(x, y));
+    let mut alpha = Bc4Block::from_bytes(&block[..8]);
+    let mut colour = Bc1Block::from_bytes(&block[8..16]);
+    let xs = alpha::normal_codes(&block[..8], false);
+
+    // decode a block that lists the codebook in its first row
+    let mut probe = colour;
+    probe.indices = core::array::from_fn(|i| (i % 4) as u8);
+    let codes = colourblock::decompress(&probe.to_bytes(), false);
+    let ys: [f32; 4] = core::array::from_fn(|i| from_unorm8(codes[i][1]));
+
+    select_pairs(
+        &normals,
+        mask,
+        &xs,
+        &ys,
+        &mut alpha.indices,
+        &mut colour.indices,
+    );
+    block[..8].copy_from_slice(&alpha.to_bytes());
+    block[8..16].copy_from_slice(&colour.to_bytes());
+}
+
+/// Picks the pair of X and Y codes closest in angle to each valid normal, returning the
+/// summed angular error as one minus the cosine of each angle
+///
+/// Only the two codes nearest to the source value of each channel are considered, which keeps
+/// the search cheap and rarely misses the closest normal.
+fn select_pairs(
+    normals: &[Vec3; 16],
+    mask: u32,
+    xs: &[f32],
+    ys: &[f32],
+    x_indices: &mut [u8; 16],
+    y_indices: &mut [u8; 16],
+) -> f32 {
+    let mut error = 0.0;
+    for (i, source) in normals.iter().enumerate() {
+        if (mask & (1 << i)) == 0 {
+            continue;
+        }
+
+        let mut best = f32::MIN;
+        for x_index in nearest_two(xs, source.x()) {
+            for y_index in nearest_two(ys, source.y()) {
+                let similarity = source.dot(&from_xy(xs[x_index], ys[y_index]));
+                if similarity > best {
+                    best = similarity;
+                    x_indices[i] = x_index as u8;
+                    y_indices[i] = y_index as u8;
+                }
+            }
+        }
+        error += 1.0 - best;
+    }
+    error
+}
+
+/// Returns the indices of the two codes nearest to a value
+fn nearest_two(codes: &[f32], value: f32) -> [usize; 2] {
+    let mut nearest = [0, 0];
+    let mut distances = [f32::MAX; 2];
+    for (i, code) in codes.iter().enumerate() {
+        let distance = (code - value).abs();
+        if distance < distances[0] {
+            nearest = [i, nearest[0]];
+            distances = [distance, distances[0]];
+        } else if distance < distances[1] {
+            nearest[1] = i;
+            distances[1] = distance;
+        }
+    }
+    nearest
+}
+
+/// Moves a stored endpoint by one step, or returns `None` at the end of its range
+fn step_endpoint(endpoint: u8, step: i8, signed: bool) -> Option<u8> {
+    if signed {
+        // -128 is reserved and decodes like -127
+        let value = (endpoint as i8).checked_add(step)?;
+        (value != i8::MIN).then_some(value as u8)
+    } else {
+        endpoint.checked_add_signed(step)
+    }
+}
+
+/// Reconstructs the blue channel of 8-bit RGBA pixels from normals stored in red and green
+///
+/// BC5 and other two-channel formats decode with blue at zero. This fills it in with Z,
+/// mapping -1..1 to 0..255 as for X and Y, so that decoded normal maps can be viewed and used
+/// as regular RGB normal maps.
+pub fn reconstruct_normal_z(rgba: &mut [u8]) {
+    for pixel in rgba.chunks_exact_mut(4) {
+        let normal = from_xy(from_unorm8(pixel[0]), from_unorm8(pixel[1]));
+        pixel[2] = to_unorm8(normal.z());
+    }
+}
+
+/// Moves the X of DXT5nm pixels from alpha to red and rebuilds the regular normal map layout
+///
+/// DXT5nm blocks decode with X in alpha, Y in green and red and blue at zero. This turns each
+/// decoded pixel into X, Y and the reconstructed Z in red, green and blue with opaque alpha.
+pub fn decode_dxt5nm(rgba: &mut [u8]) {
+    for pixel in rgba.chunks_exact_mut(4) {
+        let (x, y) = (pixel[3], pixel[1]);
+        let normal = from_xy(from_unorm8(x), from_unorm8(y));
+        pixel.copy_from_slice(&[x, y, to_unorm8(normal.z()), 255]);
+    }
+}
+
+/// Rearranges a normal map pixel into the DXT5nm layout, with X in alpha, Y in green and red
+/// and blue at zero
+pub fn swizzle_dxt5nm([x, y, _, _]: [u8; 4]) -> [u8; 4] {
+    [0, y, 0, x]
+}
+
+/// Returns the parameters the colour block of a DXT5nm block is fitted with, which measure
+/// the error of green alone
+pub fn dxt5nm_params(params: &Params) -> Params {
+    Params {
+        weights: [0.0, 1.0, 0.0],
+        weigh_colour_by_alpha: false,
+        colour_space: ColourSpace::Linear,
+        error_metric: Some(ErrorMetric::Rgb),
+        ..*params
+    }
+}
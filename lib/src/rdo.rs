@@ -0,0 +1,437 @@
+// Copyright (c) 2018-2021 Jan Solanti <jhs@psonet.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to	deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be included
+// in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.
+// IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT,
+// TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION WITH THE
+// SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Rate-distortion optimisation of BC1-BC5 images
+//!
+//! After every block has been compressed on its own, the blocks are revisited in the order
+//! they are stored. Each block may copy the endpoints, the selectors or all of a recently
+//! stored block, which makes the output easier to compress for general purpose LZ
+//! compressors. A change is kept when the error it adds is less than `Params::rdo_lambda`
+//! times the number of bits of unmatched data it saves. The error is measured as the blocks
+//! were fitted, so the colour weights, the error metric and the angles of normal maps are
+//! kept to.
+
+use core::ops::Range;
+
+use crate::colourfit::ColourError;
+use crate::job::Run;
+use crate::{alpha, colourblock, normal, ycocg, Error, Format, Params};
+
+/// The number of preceding blocks to borrow data from, in addition to the block above
+const WINDOW: usize = 16;
+
+/// The error of a normal whose angle is off by one 8-bit step in X or Y, as one minus the
+/// cosine, which is how the normal map fits measure it
+const ANGULAR_STEP: f32 = 2.0 / (255.0 * 255.0);
+
+/// An 8-byte part of a block that is optimised on its own
+#[derive(Clone, Copy)]
+enum Part {
+    /// A BC1-BC3 colour block, which has punch-through alpha in BC1
+    Colour { bc1: bool },
+
+    /// A BC2 explicit alpha block, which has no endpoints to share
+    ExplicitAlpha,
+
+    /// A BC3-BC5 interpolated block of a channel, which is signed in BC4 and BC5 SNORM
+    Interpolated { channel: usize },
+}
+
+impl Part {
+    /// Returns the byte offsets and kinds of the parts of a block
+    fn of(format: Format) -> &'static [(usize, Part)] {
+        match format {
+            Format::Bc1 => &[(0, Part::Colour { bc1: true })],
+            Format::Bc2 => &[(0, Part::ExplicitAlpha), (8, Part::Colour { bc1: false })],
+            Format::Bc3 => &[
+                (0, Part::Interpolated { channel: 3 }),
+                (8, Part::Colour { bc1: false }),
+            ],
+            Format::Bc4 | Format::Bc4Snorm => &[(0, Part::Interpolated { channel: 0 })],
+            Format::Bc5 | Format::Bc5Snorm => &[
+                (0, Part::Interpolated { channel: 0 }),
+                (8, Part::Interpolated { channel: 1 }),
+            ],
+            _ => &[],
+        }
+    }
+
+    /// Returns the number of leading bytes that hold the endpoints
+    fn endpoint_bytes(self) -> usize {
+        match self {
+            Part::Colour { .. } => 4,
+            Part::ExplicitAlpha => 0,
+            Part::Interpolated { .. } => 2,
+        }
+    }
+
+    /// Picks the code with the least error for each valid pixel, given the endpoints of the
+    /// part
+    fn select(self, measure: &Measure, rgba: &[[u8; 4]; 16], mask: u32, bytes: &mut [u8; 8]) {
+        match self {
+            Part::Colour { bc1 } => {
+                // decode a block that lists the codebook in its first row
+                let mut probe = *bytes;
+                probe[4..].fill(0b11_10_01_00);
+                let codes = colourblock::decompress(&probe, bc1);
+
+                bytes[4..].fill(0);
+                for (i, &source) in rgba.iter().enumerate() {
+                    if (mask & (1 << i)) == 0 {
+                        continue;
+                    }
+
+                    // transparent pixels need a transparent code and vice versa
+                    let transparent = bc1 && source[3] < measure.alpha_threshold;
+                    let error = |j: usize| measure.colour.pixel(source, codes[j]);
+                    let index = (0..4)
+                        .filter(|&j| (codes[j][3] == 0) == transparent)
+                        .min_by(|&a, &b| error(a).total_cmp(&error(b)))
+                        .unwrap_or(0);
+                    bytes[4 + i / 4] |= (index as u8) << (2 * (i % 4));
+                }
+            }
+            Part::ExplicitAlpha => {}
+            Part::Interpolated { channel } => {
+                // the codebook and the source values, both mapped to -1..1
+                let signed = matches!(measure.format, Format::Bc4Snorm | Format::Bc5Snorm);
+                let codes = alpha::normal_codes(bytes, signed);
+
+                let mut indices = 0u64;
+                for (i, source) in rgba.iter().enumerate() {
+                    if (mask & (1 << i)) == 0 {
+                        continue;
+                    }
+
+                    let value = normal::from_unorm8(source[channel]);
+                    let error = |j: usize| (codes[j] - value).abs();
+                    let index = (0..8)
+                        .min_by(|&a, &b| error(a).total_cmp(&error(b)))
+                        .unwrap_or(0);
+                    indices |= (index as u64) << (3 * i);
+                }
+                bytes[2..].copy_from_slice(&indices.to_le_bytes()[..6]);
+            }
+        }
+    }
+
+    /// Estimates the bits needed for the part, counting the endpoints and the selectors as
+    /// free if they repeat those of a part in the history
+    fn rate(self, bytes: &[u8; 8], history: &[[u8; 8]]) -> u32 {
+        let split = self.endpoint_bytes();
+        [0..split, split..8]
+            .into_iter()
+            .filter(|range| !range.is_empty())
+            .map(|range| {
+                let matched = history
+                    .iter()
+                    .any(|old| old[range.clone()] == bytes[range.clone()]);
+                if matched {
+                    0
+                } else {
+                    8 * range.len() as u32
+                }
+            })
+            .sum()
+    }
+}
+
+/// The error the blocks of a format were fitted by, in squared 8-bit steps so that the lambda
+/// means the same for every format and metric
+struct Measure {
+    format: Format,
+    alpha_threshold: u8,
+
+    /// The error of the colour block, with the parameters the colour fit used
+    colour: ColourError,
+
+    /// The channels that hold X and Y of normals, which are measured by angle
+    normals: Option<(usize, usize)>,
+
+    /// The channels measured by their squared error, apart from the colour and the normals
+    channels: Range<usize>,
+}
+
+impl Measure {
+    fn new(format: Format, params: &Params) -> Self {
+        let dxt5nm = format == Format::Bc3 && params.normal_map;
+        let ycocg = format == Format::Bc3 && params.ycocg && !params.normal_map;
+        let colour_params = if dxt5nm {
+            normal::dxt5nm_params(params)
+        } else if ycocg {
+            ycocg::params(params)
+        } else {
+            *params
+        };
+
+        let bc5 = matches!(format, Format::Bc5 | Format::Bc5Snorm);
+        let normals = match format {
+            _ if dxt5nm => Some((3, 1)),
+            _ if bc5 && params.normal_map => Some((0, 1)),
+            _ => None,
+        };
+        let channels = match format {
+            _ if normals.is_some() => 0..0,
+            Format::Bc2 | Format::Bc3 => 3..4,
+            Format::Bc4 | Format::Bc4Snorm => 0..1,
+            Format::Bc5 | Format::Bc5Snorm => 0..2,
+            _ => 0..0,
+        };
+
+        Measure {
+            format,
+            alpha_threshold: params.alpha_threshold,
+            colour: ColourError::new(&colour_params),
+            normals,
+            channels,
+        }
+    }
+
+    /// Returns the error of a block over the valid pixels, or `None` if it changes which
+    /// pixels of a BC1 block are transparent, as given by the alpha threshold
+    fn distortion(&self, rgba: &[[u8; 4]; 16], mask: u32, block: &[u8]) -> Option<f32> {
+        let decoded = self.format.decompress_block(block);
+        let has_colour = matches!(self.format, Format::Bc1 | Format::Bc2 | Format::Bc3);
+
+        let mut error = 0.0;
+        for (i, (&source, &decoded)) in rgba.iter().zip(&decoded).enumerate() {
+            if (mask & (1 << i)) == 0 {
+                continue;
+            }
+
+            if self.format == Format::Bc1 {
+                let transparent = source[3] < self.alpha_threshold;
+                if transparent != (decoded[3] == 0) {
+                    return None;
+                }
+                if transparent {
+                    continue;
+                }
+            }
+
+            match self.normals {
+                // the 8-bit encodings of unsigned and signed normals map to -1..1 alike
+                Some((x, y)) => {
+                    let normal = |pixel: [u8; 4]| {
+                        normal::from_xy(
+                            normal::from_unorm8(pixel[x]),
+                            normal::from_unorm8(pixel[y]),
+                        )
+                    };
+                    error += (1.0 - normal(source).dot(&normal(decoded))) / ANGULAR_STEP;
+                }
+                None if has_colour => error += self.colour.pixel(source, decoded),
+                None => {}
+            }
+
+            for c in self.channels.clone() {
+                let d = f32::from(source[c]) - f32::from(decoded[c]);
+                error += d * d;
+            }
+        }
+
+        Some(error)
+    }
+}
+
+/// Returns whether the blocks of a format are optimised with the given parameters
+pub fn is_enabled(format: Format, params: &Params) -> bool {
+    params.rdo_lambda > 0.0 && !Part::of(format).is_empty()
+}
+
+/// Replaces parts of compressed BC1-BC5 blocks, signed or not, with data from preceding
+/// blocks where that costs little enough error, does nothing for other formats
+///
+/// * `format`     - The format of the compressed image
+/// * `width`      - The width of the source image
+/// * `height`     - The height of the source image
+/// * `read_pixel` - Returns the source pixel at a position
+/// * `params`     - The compressor parameters, including the lambda
+/// * `run`        - The job, which is told about every optimised block row
+/// * `output`     - The compressed image
+///
+/// Returns `Error::Cancelled` if the job was cancelled before the next block row.
+pub fn optimise<R>(
+    format: Format,
+    width: usize,
+    height: usize,
+    read_pixel: R,
+    params: &Params,
+    run: &Run,
+    output: &mut [u8],
+) -> Result<(), Error>
+where
+    R: Fn(usize, usize) -> [u8; 4],
+{
+    let Some(optimiser) = Optimiser::new(format, width, height, read_pixel, params) else {
+        return Ok(());
+    };
+
+    let blocks_wide = width.div_ceil(4);
+    for row in 0..height.div_ceil(4) {
+        run.check()?;
+        for block in blocks_wide * row..blocks_wide * (row + 1) {
+            optimiser.optimise_block(block, output);
+        }
+        run.finish_row();
+    }
+    Ok(())
+}
+
+/// Optimises the blocks of an image one at a time, in the order they are stored
+pub struct Optimiser<R> {
+    format: Format,
+    width: usize,
+    height: usize,
+    read_pixel: R,
+    lambda: f32,
+    measure: Measure,
+
+    /// DXT5nm blocks hold the swizzled pixels and YCoCg blocks the converted ones
+    dxt5nm: bool,
+    ycocg: bool,
+}
+
+impl<R> Optimiser<R>
+where
+    R: Fn(usize, usize) -> [u8; 4],
+{
+    /// Returns `None` if the blocks of the format are not optimised with the given parameters
+    pub fn new(
+        format: Format,
+        width: usize,
+        height: usize,
+        read_pixel: R,
+        params: &Params,
+    ) -> Option<Self> {
+        is_enabled(format, params).then(|| Optimiser {
+            format,
+            width,
+            height,
+            read_pixel,
+            lambda: params.rdo_lambda,
+            measure: Measure::new(format, params),
+            dxt5nm: format == Format::Bc3 && params.normal_map,
+            ycocg: format == Format::Bc3 && params.ycocg && !params.normal_map,
+        })
+    }
+
+    /// Optimises a block of the image, given that the blocks before it are final
+    ///
+    /// Returns the index of the block whose endpoints each 8-byte half of the block ends up
+    /// with, which is the block itself unless the endpoints were copied from another one.
+    pub fn optimise_block(&self, block: usize, output: &mut [u8]) -> [usize; 2] {
+        let block_size = self.format.block_size();
+        let blocks_wide = self.width.div_ceil(4);
+        let mut origins = [block; 2];
+
+        // gather the source pixels
+        let (bx, by) = (4 * (block % blocks_wide), 4 * (block / blocks_wide));
+        let mut rgba = [[0u8; 4]; 16];
+        let mut mask = 0u32;
+        for (i, pixel) in rgba.iter_mut().enumerate() {
+            let (x, y) = (bx + i % 4, by + i / 4);
+            if x < self.width && y < self.height {
+                *pixel = (self.read_pixel)(x, y);
+                if self.dxt5nm {
+                    *pixel = normal::swizzle_dxt5nm(*pixel);
+                }
+                mask |= 1 << i;
+            }
+        }
+        if self.ycocg {
+            rgba = ycocg::encode(&rgba, mask);
+        }
+
+        // the preceding blocks, which are final by now, and the block above
+        let mut sources = [0usize; WINDOW + 1];
+        let mut count = 0;
+        for offset in 1..=WINDOW.min(block) {
+            sources[count] = block - offset;
+            count += 1;
+        }
+        if block >= blocks_wide && blocks_wide > WINDOW {
+            sources[count] = block - blocks_wide;
+            count += 1;
+        }
+        let sources = &sources[..count];
+
+        // the block as optimised so far, which the error of each part is measured in
+        let start = block * block_size;
+        let mut current_block = [0u8; 16];
+        current_block[..block_size].copy_from_slice(&output[start..start + block_size]);
+
+        for &(offset, part) in Part::of(self.format) {
+            let read = |block: usize| -> [u8; 8] {
+                let start = block * block_size + offset;
+                output[start..start + 8].try_into().unwrap()
+            };
+
+            let mut history = [[0u8; 8]; WINDOW + 1];
+            for (old, &source) in history.iter_mut().zip(sources) {
+                *old = read(source);
+            }
+            let history = &history[..count];
+
+            let cost = |bytes: &[u8; 8]| {
+                // YCoCg colour blocks must keep the scale of their own chroma
+                if self.ycocg && offset == 8 && !ycocg::has_scale(bytes, rgba[0][2]) {
+                    return None;
+                }
+                let mut candidate = current_block;
+                candidate[offset..offset + 8].copy_from_slice(bytes);
+                let distortion = self
+                    .measure
+                    .distortion(&rgba, mask, &candidate[..block_size])?;
+                Some(distortion + self.lambda * part.rate(bytes, history) as f32)
+            };
+
+            // try whole copies, shared endpoints and shared selectors
+            let current = read(block);
+            let mut best = current;
+            let mut best_cost = cost(&current).unwrap_or(f32::MAX);
+            let split = part.endpoint_bytes();
+            for (old, &source) in history.iter().zip(sources) {
+                let mut candidates = [*old; 3];
+                let mut tried = 1;
+                if split > 0 {
+                    part.select(&self.measure, &rgba, mask, &mut candidates[1]);
+                    candidates[2][..split].copy_from_slice(&current[..split]);
+                    tried = 3;
+                }
+
+                for (i, candidate) in candidates[..tried].iter().enumerate() {
+                    if let Some(cost) = cost(candidate) {
+                        if cost < best_cost {
+                            best = *candidate;
+                            best_cost = cost;
+                            origins[offset / 8] = if i < 2 { source } else { block };
+                        }
+                    }
+                }
+            }
+
+            current_block[offset..offset + 8].copy_from_slice(&best);
+            output[start + offset..start + offset + 8].copy_from_slice(&best);
+        }
+        origins
+    }
+}
@@ -0,0 +1,65 @@
+// Copyright (c) 2018-2021 Jan Solanti <jhs@psonet.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to	deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be included
+// in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.
+// IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT,
+// TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION WITH THE
+// SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Rectangles of pixels within an image
+
+use core::ops::Range;
+
+/// A rectangle of pixels, given by its top-left corner and its size
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Rect {
+    /// Column of the leftmost pixels
+    pub x: usize,
+    /// Row of the topmost pixels
+    pub y: usize,
+    /// Number of pixel columns
+    pub width: usize,
+    /// Number of pixel rows
+    pub height: usize,
+}
+
+impl Rect {
+    /// Returns the columns and rows of blocks that the part of the rectangle inside an image
+    /// of the given size overlaps
+    pub(crate) fn blocks(
+        self,
+        image_width: usize,
+        image_height: usize,
+        block_width: usize,
+        block_height: usize,
+    ) -> (Range<usize>, Range<usize>) {
+        let span = |start: usize, len: usize, size: usize, block: usize| {
+            let end = start.saturating_add(len).min(size);
+            if start >= end {
+                0..0
+            } else {
+                start / block..end.div_ceil(block)
+            }
+        };
+        let columns = span(self.x, self.width, image_width, block_width);
+        let rows = span(self.y, self.height, image_height, block_height);
+        if columns.is_empty() || rows.is_empty() {
+            (0..0, 0..0)
+        } else {
+            (columns, rows)
+        }
+    }
+}
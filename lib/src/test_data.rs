@@ -9,6 +9,37 @@ pub struct TestDataSet {
     pub decoded: &'static [u8],
 }
 
+/// Like `TestDataSet`, but decoded to half float RGBA pixels given as their bit patterns
+#[derive(Debug)]
+pub struct HdrTestDataSet {
+    pub encoded: &'static [u8],
+    pub decoded: &'static [u16],
+}
+
+/// Like `TestDataSet`, but decoded to the 11-bit values of each channel of an EAC block
+#[derive(Debug)]
+pub struct EacTestDataSet {
+    pub encoded: &'static [u8],
+    pub decoded: &'static [i16],
+}
+
+/// Like `TestDataSet`, but decoded to the values of each channel of a signed BC4 or BC5 block,
+/// in units of 1/127
+#[derive(Debug)]
+pub struct SnormTestDataSet {
+    pub encoded: &'static [u8],
+    pub decoded: &'static [i8],
+}
+
+/// Like `TestDataSet`, but for a single ASTC block of the given footprint
+#[derive(Debug)]
+pub struct AstcTestDataSet {
+    pub width: usize,
+    pub height: usize,
+    pub encoded: &'static [u8],
+    pub decoded: &'static [u8],
+}
+
 /// The test-pattern is a gray-scale checkerboard of size 4x4 starting with 0xFF in the top-left.
 /// On top of that, the four middle pixels are set to 0x7F.
 /// BC1 data created with AMD Compressonator v4.1.5083.
@@ -134,8 +165,468 @@ pub const BC5_GRAY: TestDataSet = TestDataSet {
     ),
 };
 
+/// A BC7 mode 0 block: three subsets with 4-bit endpoints and a p-bit per endpoint.
+/// Random payload behind the mode bits, decoded with Mesa 22.3.6 (llvmpipe).
+pub const BC7_MODE0: TestDataSet = TestDataSet {
+    encoded: &[
+        0x79, 0x2E, 0xBA, 0x94, 0x4D, 0x33, 0xE3, 0xB9, 0x68, 0xC1, 0xB7, 0xC2, 0x43, 0x88, 0x3E,
+        0xA2,
+    ],
+    decoded: &[
+        70, 172, 235, 255, 166, 161, 79, 255, 80, 168, 86, 255, 90, 156, 189, 255, // row 0
+        123, 107, 206, 255, 52, 171, 88, 255, 24, 173, 90, 255, 102, 137, 164, 255, // row 1
+        92, 145, 223, 255, 24, 173, 90, 255, 166, 161, 79, 255, 173, 24, 8, 255, // row 2
+        59, 185, 241, 255, 80, 168, 86, 255, 138, 163, 81, 255, 113, 119, 138, 255, // row 3
+    ],
+};
+
+/// A BC7 mode 1 block: two subsets with 6-bit endpoints and a shared p-bit per subset.
+/// Random payload behind the mode bits, decoded with Mesa 22.3.6 (llvmpipe).
+pub const BC7_MODE1: TestDataSet = TestDataSet {
+    encoded: &[
+        0xD2, 0xBC, 0x7F, 0x5A, 0x6A, 0x86, 0xBA, 0x9D, 0xF6, 0x37, 0x4F, 0x8B, 0xB4, 0x54, 0x84,
+        0x13,
+    ],
+    decoded: &[
+        246, 142, 113, 255, 119, 177, 139, 255, 100, 184, 82, 255, 245, 152, 115,
+        255, // row 0
+        119, 177, 139, 255, 119, 177, 139, 255, 250, 112, 108, 255, 245, 152, 115,
+        255, // row 1
+        140, 170, 198, 255, 249, 121, 109, 255, 243, 171, 118, 255, 149, 166, 227,
+        255, // row 2
+        248, 131, 111, 255, 246, 142, 113, 255, 140, 170, 198, 255, 159, 163, 255,
+        255, // row 3
+    ],
+};
+
+/// A BC7 mode 2 block: three subsets with 5-bit endpoints.
+/// Random payload behind the mode bits, decoded with Mesa 22.3.6 (llvmpipe).
+pub const BC7_MODE2: TestDataSet = TestDataSet {
+    encoded: &[
+        0xBC, 0xC6, 0xFF, 0xDD, 0x34, 0xB0, 0xC0, 0xBA, 0x77, 0xEC, 0xB5, 0xD4, 0xDF, 0xA7, 0x25,
+        0x88,
+    ],
+    decoded: &[
+        100, 30, 89, 255, 100, 30, 89, 255, 255, 90, 222, 255, 255, 90, 222, 255, // row 0
+        250, 30, 90, 255, 244, 60, 90, 255, 179, 60, 157, 255, 100, 30, 89, 255, // row 1
+        68, 189, 139, 255, 49, 189, 82, 255, 250, 30, 90, 255, 24, 0, 24, 255, // row 2
+        49, 189, 82, 255, 88, 189, 198, 255, 255, 0, 90, 255, 179, 60, 157, 255, // row 3
+    ],
+};
+
+/// A BC7 mode 3 block: two subsets with 7-bit endpoints and a p-bit per endpoint.
+/// Random payload behind the mode bits, decoded with Mesa 22.3.6 (llvmpipe).
+pub const BC7_MODE3: TestDataSet = TestDataSet {
+    encoded: &[
+        0x38, 0xDE, 0x69, 0xFA, 0x0E, 0xC5, 0x59, 0xA0, 0x6A, 0x77, 0x1F, 0xB9, 0xBE, 0x23, 0xC3,
+        0x53,
+    ],
+    decoded: &[
+        194, 78, 160, 255, 105, 157, 119, 255, 183, 62, 117, 255, 59, 169, 229, 255, // row 0
+        194, 78, 160, 255, 238, 40, 180, 255, 183, 62, 117, 255, 120, 117, 174, 255, // row 1
+        183, 62, 117, 255, 244, 10, 62, 255, 238, 40, 180, 255, 105, 157, 119, 255, // row 2
+        59, 169, 229, 255, 244, 10, 62, 255, 194, 78, 160, 255, 194, 78, 160, 255, // row 3
+    ],
+};
+
+/// A BC7 mode 4 block: separate colour and alpha indices and a channel rotation.
+/// Random payload behind the mode bits, decoded with Mesa 22.3.6 (llvmpipe).
+pub const BC7_MODE4: TestDataSet = TestDataSet {
+    encoded: &[
+        0x70, 0x54, 0x58, 0xCB, 0x33, 0x53, 0x6D, 0x6A, 0x51, 0x91, 0x36, 0xE7, 0xDE, 0x68, 0x3A,
+        0x34,
+    ],
+    decoded: &[
+        116, 181, 64, 223, 116, 181, 80, 223, 16, 181, 69, 206, 165, 181, 64, 231, // row 0
+        116, 181, 80, 223, 116, 181, 75, 223, 16, 181, 85, 206, 65, 181, 80, 214, // row 1
+        165, 181, 48, 231, 65, 181, 75, 214, 65, 181, 53, 214, 65, 181, 75, 214, // row 2
+        165, 181, 64, 231, 65, 181, 48, 214, 165, 181, 75, 231, 116, 181, 53, 223, // row 3
+    ],
+};
+
+/// A BC7 mode 5 block: separate colour and alpha endpoints and a channel rotation.
+/// Random payload behind the mode bits, decoded with Mesa 22.3.6 (llvmpipe).
+pub const BC7_MODE5: TestDataSet = TestDataSet {
+    encoded: &[
+        0x20, 0xBF, 0x39, 0xC3, 0x04, 0xF8, 0xDD, 0x42, 0xD8, 0x81, 0x51, 0xC5, 0xF5, 0x91, 0xCD,
+        0xB4,
+    ],
+    decoded: &[
+        126, 24, 0, 183, 231, 76, 126, 128, 197, 59, 85, 16, 231, 76, 126, 16, // row 0
+        126, 24, 0, 128, 126, 24, 0, 183, 126, 24, 0, 128, 231, 76, 126, 71, // row 1
+        126, 24, 0, 128, 197, 59, 85, 16, 197, 59, 85, 183, 197, 59, 85, 16, // row 2
+        197, 59, 85, 183, 126, 24, 0, 128, 197, 59, 85, 16, 231, 76, 126, 71, // row 3
+    ],
+};
+
+/// A BC7 mode 6 block: a single subset with 7-bit endpoints and 4-bit indices.
+/// Random payload behind the mode bits, decoded with Mesa 22.3.6 (llvmpipe).
+pub const BC7_MODE6: TestDataSet = TestDataSet {
+    encoded: &[
+        0x40, 0x9D, 0x1C, 0x54, 0xD9, 0xA7, 0x9B, 0xC7, 0x3B, 0x3C, 0xFE, 0x76, 0x5D, 0x22, 0x33,
+        0x5E,
+    ],
+    decoded: &[
+        154, 58, 235, 151, 140, 61, 240, 153, 206, 47, 218, 145, 140, 61, 240, 153, // row 0
+        222, 44, 213, 144, 229, 43, 211, 143, 163, 56, 232, 150, 170, 55, 230, 149, // row 1
+        213, 46, 216, 145, 154, 58, 235, 151, 133, 62, 242, 153, 133, 62, 242, 153, // row 2
+        140, 61, 240, 153, 140, 61, 240, 153, 222, 44, 213, 144, 154, 58, 235, 151, // row 3
+    ],
+};
+
+/// A BC7 mode 7 block: two subsets with colour and alpha endpoints.
+/// Random payload behind the mode bits, decoded with Mesa 22.3.6 (llvmpipe).
+pub const BC7_MODE7: TestDataSet = TestDataSet {
+    encoded: &[
+        0x80, 0x98, 0xD6, 0xA0, 0x24, 0x43, 0x63, 0x9F, 0x56, 0x55, 0xF0, 0xB5, 0xFF, 0xB6, 0x77,
+        0xDC,
+    ],
+    decoded: &[
+        212, 66, 210, 116, 215, 52, 158, 4, 17, 158, 150, 242, 44, 142, 85, 215, // row 0
+        214, 59, 183, 58, 212, 66, 210, 116, 215, 52, 158, 4, 31, 150, 117, 228, // row 1
+        215, 52, 158, 4, 212, 66, 210, 116, 215, 52, 158, 4, 17, 158, 150, 242, // row 2
+        211, 73, 235, 170, 215, 52, 158, 4, 212, 66, 210, 116, 215, 52, 158, 4, // row 3
+    ],
+};
+
+/// A single-region BC6H mode 11 block with untransformed 10-bit endpoints.
+/// Hand-assembled from the bit layout in the BPTC specification.
+pub const BC6H_UF16_MODE11: HdrTestDataSet = HdrTestDataSet {
+    encoded: &[
+        0x03, 0x00, 0x90, 0xFE, 0xFF, 0xBF, 0x54, 0x20, 0x06, 0x9F, 0x21, 0x54, 0x76, 0xA8, 0xCB,
+        0xED,
+    ],
+    decoded: &[
+        0x1930, 0x2C80, 0x6465, 0x3C00, 0x0000, 0x22EF, 0x7BFF, 0x3C00, 0x7BFF, 0x520A, 0x07CF,
+        0x3C00, 0x499F, 0x3EE7, 0x3703, 0x3C00, // row 0
+        0x07C0, 0x25E1, 0x74BC, 0x3C00, 0x1170, 0x298F, 0x6BA8, 0x3C00, 0x20F0, 0x2F72, 0x5D22,
+        0x3C00, 0x28B0, 0x3264, 0x55DF, 0x3C00, // row 1
+        0x3260, 0x3612, 0x4CCB, 0x3C00, 0x3A20, 0x3904, 0x4588, 0x3C00, 0x41DF, 0x3BF5, 0x3E46,
+        0x3C00, 0x534F, 0x4295, 0x2DEF, 0x3C00, // row 2
+        0x5B0F, 0x4587, 0x26AC, 0x3C00, 0x62CF, 0x4879, 0x1F69, 0x3C00, 0x6A8F, 0x4B6A, 0x1826,
+        0x3C00, 0x743F, 0x4F18, 0x0F12, 0x3C00, // row 3
+    ],
+};
+
+/// A signed two-region BC6H mode 1 block using partition 17, with delta endpoints that
+/// wrap around. Hand-assembled from the bit layout in the BPTC specification.
+pub const BC6H_SF16_MODE1: HdrTestDataSet = HdrTestDataSet {
+    encoded: &[
+        0x08, 0xBE, 0x02, 0x21, 0x78, 0x27, 0xCA, 0x12, 0xBC, 0x33, 0xF2, 0x23, 0xBA, 0x0B, 0x7E,
+        0xAB,
+    ],
+    decoded: &[
+        0x783F, 0xFAE9, 0x03FF, 0x3C00, 0x79F1, 0x7BA3, 0x066B, 0x3C00, 0x78AE, 0x927A, 0x02B5,
+        0x3C00, 0x7811, 0xD79D, 0x00E7, 0x3C00, // row 0
+        0x794D, 0xB61D, 0x0456, 0x3C00, 0x7A6A, 0x1281, 0x04B2, 0x3C00, 0x7B78, 0x574D, 0x0509,
+        0x3C00, 0x7954, 0x367F, 0x049C, 0x3C00, // row 1
+        0x79D3, 0x93B7, 0x0481, 0x3C00, 0x78C5, 0xD883, 0x042A, 0x3C00, 0x783F, 0xFAE9, 0x03FF,
+        0x3C00, 0x7BFF, 0x79B3, 0x0535, 0x3C00, // row 2
+        0x7BFF, 0x79B3, 0x0535, 0x3C00, 0x7B78, 0x574D, 0x0509, 0x3C00, 0x794D, 0xB61D, 0x0456,
+        0x3C00, 0x7AF0, 0x34E7, 0x04DD, 0x3C00, // row 3
+    ],
+};
+
+/// A single-region BC6H mode 14 block, whose 16-bit base endpoint has its high bits
+/// stored in reverse order. Hand-assembled from the bit layout in the BPTC specification.
+pub const BC6H_UF16_MODE14: HdrTestDataSet = HdrTestDataSet {
+    encoded: &[
+        0xEF, 0x5D, 0x1A, 0x01, 0x9E, 0x9E, 0x11, 0xF4, 0xFB, 0x10, 0x32, 0x54, 0x76, 0x98, 0xBA,
+        0xDC,
+    ],
+    decoded: &[
+        0x5C7C, 0x08D0, 0x3D82, 0x3C00, 0x5C7D, 0x08CF, 0x3D80, 0x3C00, 0x5C7B, 0x08D1, 0x3D84,
+        0x3C00, 0x5C7B, 0x08D1, 0x3D84, 0x3C00, // row 0
+        0x5C7B, 0x08D0, 0x3D83, 0x3C00, 0x5C7C, 0x08D0, 0x3D83, 0x3C00, 0x5C7C, 0x08D0, 0x3D83,
+        0x3C00, 0x5C7C, 0x08D0, 0x3D82, 0x3C00, // row 1
+        0x5C7C, 0x08D0, 0x3D82, 0x3C00, 0x5C7C, 0x08D0, 0x3D82, 0x3C00, 0x5C7C, 0x08D0, 0x3D82,
+        0x3C00, 0x5C7C, 0x08D0, 0x3D81, 0x3C00, // row 2
+        0x5C7C, 0x08CF, 0x3D81, 0x3C00, 0x5C7C, 0x08CF, 0x3D81, 0x3C00, 0x5C7C, 0x08CF, 0x3D81,
+        0x3C00, 0x5C7D, 0x08CF, 0x3D80, 0x3C00, // row 3
+    ],
+};
+
+/// An ETC1 compatible individual mode block with side-by-side sub-blocks.
+/// Hand-assembled from the bit layout in the ETC2 specification.
+pub const ETC2_INDIVIDUAL: TestDataSet = TestDataSet {
+    encoded: &[0xC3, 0x5A, 0x1E, 0x58, 0x63, 0x9C, 0x5A, 0x5A],
+    decoded: &[
+        213, 94, 26, 255, 175, 56, 0, 255, 18, 137, 205, 255, 157, 255, 255, 255, // row 0
+        233, 114, 46, 255, 213, 94, 26, 255, 0, 64, 132, 255, 18, 137, 205, 255, // row 1
+        195, 76, 8, 255, 233, 114, 46, 255, 84, 203, 255, 255, 0, 64, 132, 255, // row 2
+        175, 56, 0, 255, 195, 76, 8, 255, 157, 255, 255, 255, 84, 203, 255, 255, // row 3
+    ],
+};
+
+/// A differential mode block with stacked sub-blocks and a negative red delta.
+/// Hand-assembled from the bit layout in the ETC2 specification.
+pub const ETC2_DIFFERENTIAL: TestDataSet = TestDataSet {
+    encoded: &[0xA5, 0x42, 0xE3, 0x3F, 0x63, 0x9C, 0x5A, 0x5A],
+    decoded: &[
+        170, 71, 236, 255, 148, 49, 214, 255, 160, 61, 226, 255, 182, 83, 248, 255, // row 0
+        182, 83, 248, 255, 170, 71, 236, 255, 148, 49, 214, 255, 160, 61, 226, 255, // row 1
+        93, 35, 208, 255, 255, 255, 255, 255, 187, 129, 255, 255, 0, 0, 72, 255, // row 2
+        0, 0, 72, 255, 93, 35, 208, 255, 255, 255, 255, 255, 187, 129, 255, 255, // row 3
+    ],
+};
+
+/// A T mode block, selected by overflowing the red channel of a differential block.
+/// Hand-assembled from the bit layout in the ETC2 specification.
+pub const ETC2_T_MODE: TestDataSet = TestDataSet {
+    encoded: &[0xF3, 0x94, 0xB6, 0xDB, 0x63, 0x9C, 0x5A, 0x5A],
+    decoded: &[
+        187, 153, 68, 255, 155, 70, 189, 255, 187, 102, 221, 255, 219, 134, 253, 255, // row 0
+        219, 134, 253, 255, 187, 153, 68, 255, 155, 70, 189, 255, 187, 102, 221, 255, // row 1
+        187, 102, 221, 255, 219, 134, 253, 255, 187, 153, 68, 255, 155, 70, 189, 255, // row 2
+        155, 70, 189, 255, 187, 102, 221, 255, 219, 134, 253, 255, 187, 153, 68, 255, // row 3
+    ],
+};
+
+/// An H mode block, selected by overflowing the green channel of a differential block.
+/// Hand-assembled from the bit layout in the ETC2 specification.
+pub const ETC2_H_MODE: TestDataSet = TestDataSet {
+    encoded: &[0x22, 0xFA, 0x54, 0x9E, 0x63, 0x9C, 0x5A, 0x5A],
+    decoded: &[
+        91, 108, 227, 255, 147, 130, 28, 255, 193, 176, 74, 255, 45, 62, 181, 255, // row 0
+        45, 62, 181, 255, 91, 108, 227, 255, 147, 130, 28, 255, 193, 176, 74, 255, // row 1
+        193, 176, 74, 255, 45, 62, 181, 255, 91, 108, 227, 255, 147, 130, 28, 255, // row 2
+        147, 130, 28, 255, 193, 176, 74, 255, 45, 62, 181, 255, 91, 108, 227, 255, // row 3
+    ],
+};
+
+/// A planar mode block, selected by overflowing the blue channel of a differential block.
+/// Hand-assembled from the bit layout in the ETC2 specification.
+pub const ETC2_PLANAR: TestDataSet = TestDataSet {
+    encoded: &[0x51, 0x48, 0x0D, 0x7A, 0x29, 0x90, 0xBF, 0xFF],
+    decoded: &[
+        162, 201, 40, 255, 182, 161, 81, 255, 203, 121, 122, 255, 223, 80, 162, 255, // row 0
+        127, 215, 94, 255, 147, 174, 135, 255, 167, 134, 175, 255, 187, 94, 216, 255, // row 1
+        91, 228, 148, 255, 111, 188, 188, 255, 132, 148, 229, 255, 152, 107, 255,
+        255, // row 2
+        56, 242, 201, 255, 76, 201, 242, 255, 96, 161, 255, 255, 116, 121, 255, 255, // row 3
+    ],
+};
+
+/// A non-opaque punch-through alpha block in differential mode.
+/// Hand-assembled from the bit layout in the ETC2 specification.
+pub const ETC2_PUNCHTHROUGH_DIFFERENTIAL: TestDataSet = TestDataSet {
+    encoded: &[0xA5, 0x42, 0xE3, 0x3C, 0x63, 0x9C, 0x5A, 0x5A],
+    decoded: &[
+        165, 66, 231, 255, 148, 49, 214, 255, 0, 0, 0, 0, 255, 255, 255, 255, // row 0
+        182, 83, 248, 255, 165, 66, 231, 255, 0, 0, 72, 255, 0, 0, 0, 0, // row 1
+        0, 0, 0, 0, 182, 83, 248, 255, 140, 82, 255, 255, 0, 0, 72, 255, // row 2
+        148, 49, 214, 255, 0, 0, 0, 0, 255, 255, 255, 255, 140, 82, 255, 255, // row 3
+    ],
+};
+
+/// A non-opaque punch-through alpha block in T mode.
+/// Hand-assembled from the bit layout in the ETC2 specification.
+pub const ETC2_PUNCHTHROUGH_T_MODE: TestDataSet = TestDataSet {
+    encoded: &[0xF3, 0x94, 0xB6, 0xD9, 0x63, 0x9C, 0x5A, 0x5A],
+    decoded: &[
+        187, 153, 68, 255, 155, 70, 189, 255, 0, 0, 0, 0, 219, 134, 253, 255, // row 0
+        219, 134, 253, 255, 187, 153, 68, 255, 155, 70, 189, 255, 0, 0, 0, 0, // row 1
+        0, 0, 0, 0, 219, 134, 253, 255, 187, 153, 68, 255, 155, 70, 189, 255, // row 2
+        155, 70, 189, 255, 0, 0, 0, 0, 219, 134, 253, 255, 187, 153, 68, 255, // row 3
+    ],
+};
+
+/// The ETC2_INDIVIDUAL block preceded by an EAC alpha block using modifier table 3.
+/// Hand-assembled from the bit layout in the ETC2 specification.
+pub const ETC2_EAC: TestDataSet = TestDataSet {
+    encoded: &[
+        0x78, 0x73, 0x10, 0x4A, 0x69, 0x59, 0x6E, 0xFB, 0xC3, 0x5A, 0x1E, 0x58, 0x63, 0x9C, 0x5A,
+        0x5A,
+    ],
+    decoded: &[
+        213, 94, 26, 106, 175, 56, 0, 141, 18, 137, 205, 78, 157, 255, 255, 204, // row 0
+        233, 114, 46, 127, 213, 94, 26, 92, 0, 64, 132, 155, 18, 137, 205, 29, // row 1
+        195, 76, 8, 106, 233, 114, 46, 141, 84, 203, 255, 78, 0, 64, 132, 204, // row 2
+        175, 56, 0, 127, 195, 76, 8, 92, 157, 255, 255, 155, 84, 203, 255, 29, // row 3
+    ],
+};
+
+/// A signed BC4 block with the endpoints 100 and -40, using the 7-value codebook.
+/// Hand-assembled from the bit layout in the Direct3D 11 specification.
+pub const BC4_SNORM: SnormTestDataSet = SnormTestDataSet {
+    encoded: &[0x64, 0xD8, 0x10, 0x9D, 0xF5, 0xEF, 0x62, 0x0A],
+    decoded: &[
+        100, 80, 40, 0, // row 0
+        -40, 60, 20, -20, // row 1
+        -20, 20, 60, -40, // row 2
+        0, 40, 80, 100, // row 3
+    ],
+};
+
+/// The BC4_SNORM block followed by a green channel block using the 5-value codebook with the
+/// reserved endpoint -128, which decodes like -127. Hand-assembled from the bit layout in the
+/// Direct3D 11 specification.
+pub const BC5_SNORM: SnormTestDataSet = SnormTestDataSet {
+    encoded: &[
+        0x64, 0xD8, 0x10, 0x9D, 0xF5, 0xEF, 0x62, 0x0A, // Red
+        0x80, 0x86, 0x86, 0x98, 0xF5, 0xEF, 0x42, 0xC1, // Green
+    ],
+    decoded: &[
+        100, -127, 80, -127, 40, -126, 0, -124, // row 0
+        -40, -122, 60, -125, 20, -123, -20, 127, // row 1
+        -20, 127, 20, -123, 60, -125, -40, -122, // row 2
+        0, -124, 40, -126, 80, -127, 100, -127, // row 3
+    ],
+};
+
+/// An unsigned R11 block with the indices counting up from 0 to 7 twice, clamping at the top.
+/// Hand-assembled from the bit layout in the ETC2 specification.
+pub const EAC_R11: EacTestDataSet = EacTestDataSet {
+    encoded: &[0xC8, 0x92, 0x10, 0x43, 0x4D, 0x59, 0x67, 0xDF],
+    decoded: &[
+        1460, 1244, 1028, 668, // row 0
+        1676, 1892, 2047, 2047, // row 1
+        1460, 1244, 1028, 668, // row 2
+        1676, 1892, 2047, 2047, // row 3
+    ],
+};
+
+/// A signed R11 block with a negative base and multiplier 0, which selects the finest step.
+/// Hand-assembled from the bit layout in the ETC2 specification.
+pub const EAC_R11_SNORM: EacTestDataSet = EacTestDataSet {
+    encoded: &[0x9C, 0x0D, 0x10, 0x47, 0xDF, 0xCB, 0x23, 0x4D],
+    decoded: &[
+        -801, -810, -798, -802, // row 0
+        -800, -791, -803, -799, // row 1
+        -801, -810, -798, -802, // row 2
+        -800, -791, -803, -799, // row 3
+    ],
+};
+
+/// The EAC_R11 block followed by an unsigned green channel block with the largest multiplier.
+/// Hand-assembled from the bit layout in the ETC2 specification.
+pub const EAC_RG11: EacTestDataSet = EacTestDataSet {
+    encoded: &[
+        0xC8, 0x92, 0x10, 0x43, 0x4D, 0x59, 0x67, 0xDF, 0xFA, 0xF0, 0xEF, 0xBC, 0xB2, 0xA6, 0x98,
+        0x20,
+    ],
+    decoded: &[
+        1460, 2047, 1244, 2047, 1028, 2047, 668, 2047, // row 0
+        1676, 204, 1892, 924, 2047, 1284, 2047, 1644, // row 1
+        1460, 2047, 1244, 2047, 1028, 2047, 668, 2047, // row 2
+        1676, 204, 1892, 924, 2047, 1284, 2047, 1644, // row 3
+    ],
+};
+
+/// The EAC_R11_SNORM block followed by a signed green channel block using the reserved base
+/// codeword -128, which decodes like -127. Hand-assembled from the bit layout in the ETC2
+/// specification.
+pub const EAC_RG11_SNORM: EacTestDataSet = EacTestDataSet {
+    encoded: &[
+        0x9C, 0x0D, 0x10, 0x47, 0xDF, 0xCB, 0x23, 0x4D, 0x80, 0xC5, 0x10, 0x4A, 0x69, 0x59, 0x6E,
+        0xFB,
+    ],
+    decoded: &[
+        -801, -1023, -810, -440, -798, -1023, -802, -56, // row 0
+        -800, -824, -791, -1023, -803, -248, -799, -1023, // row 1
+        -801, -1023, -810, -440, -798, -1023, -802, -56, // row 2
+        -800, -824, -791, -1023, -803, -248, -799, -1023, // row 3
+    ],
+};
+
+/// A 4x4 void extent block of a single colour without extent coordinates.
+/// Hand-assembled from the bit layout in the ASTC specification.
+pub const ASTC_VOID_EXTENT: AstcTestDataSet = AstcTestDataSet {
+    width: 4,
+    height: 4,
+    encoded: &[
+        0xFC, 0xFD, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0x40, 0x80, 0x80, 0xC0, 0xC0, 0xFF,
+        0xFF,
+    ],
+    decoded: &[
+        64, 128, 192, 255, 64, 128, 192, 255, 64, 128, 192, 255, 64, 128, 192, 255, // row 0
+        64, 128, 192, 255, 64, 128, 192, 255, 64, 128, 192, 255, 64, 128, 192, 255, // row 1
+        64, 128, 192, 255, 64, 128, 192, 255, 64, 128, 192, 255, 64, 128, 192, 255, // row 2
+        64, 128, 192, 255, 64, 128, 192, 255, 64, 128, 192, 255, 64, 128, 192, 255, // row 3
+    ],
+};
+
+/// A single-partition 4x4 block with RGB endpoints and a full grid of weights quantised
+/// to quints. Hand-assembled from the bit layout in the ASTC specification.
+pub const ASTC_RGB_QUINTS: AstcTestDataSet = AstcTestDataSet {
+    width: 4,
+    height: 4,
+    encoded: &[
+        0x52, 0x00, 0x29, 0xCC, 0x91, 0x51, 0x14, 0x00, 0x01, 0x00, 0x00, 0x08, 0x76, 0x44, 0x7D,
+        0x13,
+    ],
+    decoded: &[
+        20, 200, 10, 255, 73, 160, 40, 255, 125, 120, 69, 255, 178, 80, 99, 255, // row 0
+        230, 40, 128, 255, 178, 80, 99, 255, 125, 120, 69, 255, 73, 160, 40, 255, // row 1
+        20, 200, 10, 255, 73, 160, 40, 255, 125, 120, 69, 255, 178, 80, 99, 255, // row 2
+        230, 40, 128, 255, 230, 40, 128, 255, 20, 200, 10, 255, 125, 120, 69, 255, // row 3
+    ],
+};
+
+/// A 4x4 block with RGBA endpoints and a 3x3 grid of trit weights, using a second
+/// weight plane for alpha. Hand-assembled from the bit layout in the ASTC specification.
+pub const ASTC_DUAL_PLANE: AstcTestDataSet = AstcTestDataSet {
+    width: 4,
+    height: 4,
+    encoded: &[
+        0xAF, 0x85, 0x01, 0x02, 0x40, 0x21, 0x01, 0xE0, 0x01, 0xA2, 0xE5, 0xDB, 0x08, 0xB9, 0x8B,
+        0x8C,
+    ],
+    decoded: &[
+        255, 191, 0, 156, 147, 137, 108, 55, 84, 106, 171, 55, 48, 88, 207, 156, // row 0
+        223, 175, 32, 119, 139, 133, 116, 168, 92, 110, 163, 194, 84, 106, 171, 220, // row 1
+        207, 167, 48, 134, 131, 129, 124, 171, 120, 124, 135, 186, 147, 137, 108,
+        205, // row 2
+        207, 167, 48, 205, 131, 129, 124, 70, 147, 137, 108, 40, 255, 191, 0, 104, // row 3
+    ],
+};
+
+/// A 4x4 block with two partitions from seed 39, using direct and base+offset RGB
+/// endpoints. Hand-assembled from the bit layout in the ASTC specification.
+pub const ASTC_TWO_PARTITIONS: AstcTestDataSet = AstcTestDataSet {
+    width: 4,
+    height: 4,
+    encoded: &[
+        0x42, 0xE8, 0x84, 0x21, 0x00, 0x00, 0x00, 0x41, 0x65, 0x1B, 0xD5, 0x49, 0x1F, 0x21, 0xAC,
+        0xFE,
+    ],
+    decoded: &[
+        88, 129, 157, 255, 88, 129, 157, 255, 88, 129, 157, 255, 80, 130, 154, 255, // row 0
+        80, 130, 154, 255, 80, 130, 154, 255, 88, 129, 157, 255, 76, 131, 153, 255, // row 1
+        76, 131, 153, 255, 80, 130, 154, 255, 76, 131, 153, 255, 84, 130, 156, 255, // row 2
+        255, 0, 0, 255, 84, 0, 171, 255, 0, 0, 255, 255, 0, 0, 255, 255, // row 3
+    ],
+};
+
+/// A 6x6 block with luminance and alpha endpoints, whose 3x4 weight grid is
+/// interpolated to the footprint. Hand-assembled from the bit layout in the ASTC
+/// specification.
+pub const ASTC_6X6_INFILL: AstcTestDataSet = AstcTestDataSet {
+    width: 6,
+    height: 6,
+    encoded: &[
+        0xDF, 0x81, 0x14, 0xE0, 0xFF, 0x01, 0x01, 0x00, 0x00, 0x00, 0x00, 0xB0, 0xF8, 0x02, 0xAB,
+        0x6F,
+    ],
+    decoded: &[
+        208, 208, 208, 146, 208, 208, 208, 146, 208, 208, 208, 146, 215, 215, 215, 142, 229, 229,
+        229, 134, 240, 240, 240, 128, // row 0
+        125, 125, 125, 192, 150, 150, 150, 178, 175, 175, 175, 164, 172, 172, 172, 166, 147, 147,
+        147, 180, 118, 118, 118, 195, // row 1
+        64, 64, 64, 225, 100, 100, 100, 205, 139, 139, 139, 184, 150, 150, 150, 178, 111, 111, 111,
+        199, 78, 78, 78, 217, // row 2
+        21, 21, 21, 249, 50, 50, 50, 233, 78, 78, 78, 217, 107, 107, 107, 201, 161, 161, 161, 172,
+        204, 204, 204, 148, // row 3
+        46, 46, 46, 235, 68, 68, 68, 223, 89, 89, 89, 211, 125, 125, 125, 192, 179, 179, 179, 162,
+        229, 229, 229, 134, // row 4
+        107, 107, 107, 201, 121, 121, 121, 193, 136, 136, 136, 186, 154, 154, 154, 176, 183, 183,
+        183, 160, 208, 208, 208, 146, // row 5
+    ],
+};
+
 /// Expands an array with a single value per pixel to an array with this value expanded
 /// into the RGB channels.
+#[allow(clippy::identity_op)]
 const fn expand_single_to_rgb(input: &[u8; 4 * 4]) -> [u8; 4 * 4 * 3] {
     let mut output = [0u8; 4 * 4 * 3];
     let mut i = 0;
@@ -157,6 +648,7 @@ const LINEAR_RAMP: [u8; 4 * 4] = [
 
 /// Appends a list of 16 alpha values to the RGB values.
 /// I.e. each RGB is extended to RGBA with the alpha value from the list.
+#[allow(clippy::identity_op)]
 const fn add_alpha_to_rgb(input: &[u8; 4 * 4 * 3], alpha_values: &[u8; 4 * 4]) -> [u8; 4 * 4 * 4] {
     let mut output = [0u8; 4 * 4 * 4];
     let mut i = 0;
@@ -170,3 +662,80 @@ const fn add_alpha_to_rgb(input: &[u8; 4 * 4 * 3], alpha_values: &[u8; 4 * 4]) -
     }
     output
 }
+/// A 4x4 HDR void extent block of a single half float colour.
+/// Hand-assembled from the bit layout in the ASTC specification.
+pub const ASTC_HDR_VOID_EXTENT: HdrTestDataSet = HdrTestDataSet {
+    encoded: &[
+        0xFC, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0x49, 0x00, 0x3C, 0x66, 0x2E, 0x00,
+        0x38,
+    ],
+    decoded: &[
+        0x4900, 0x3C00, 0x2E66, 0x3800, 0x4900, 0x3C00, 0x2E66, 0x3800, 0x4900, 0x3C00, 0x2E66,
+        0x3800, 0x4900, 0x3C00, 0x2E66, 0x3800, // row 0
+        0x4900, 0x3C00, 0x2E66, 0x3800, 0x4900, 0x3C00, 0x2E66, 0x3800, 0x4900, 0x3C00, 0x2E66,
+        0x3800, 0x4900, 0x3C00, 0x2E66, 0x3800, // row 1
+        0x4900, 0x3C00, 0x2E66, 0x3800, 0x4900, 0x3C00, 0x2E66, 0x3800, 0x4900, 0x3C00, 0x2E66,
+        0x3800, 0x4900, 0x3C00, 0x2E66, 0x3800, // row 2
+        0x4900, 0x3C00, 0x2E66, 0x3800, 0x4900, 0x3C00, 0x2E66, 0x3800, 0x4900, 0x3C00, 0x2E66,
+        0x3800, 0x4900, 0x3C00, 0x2E66, 0x3800, // row 3
+    ],
+};
+
+/// A 4x4 block with HDR RGB endpoints stored directly in submode 2 with green as the
+/// major component, and a 3x3 weight grid. Hand-assembled from the bit layout in the ASTC
+/// specification.
+pub const ASTC_HDR_RGB_DIRECT: HdrTestDataSet = HdrTestDataSet {
+    encoded: &[
+        0xBF, 0x61, 0x4F, 0xAB, 0x86, 0x3D, 0x72, 0x9B, 0x00, 0x00, 0x00, 0x00, 0xE0, 0xD4, 0xE5,
+        0xFF,
+    ],
+    decoded: &[
+        0x7460, 0x74A8, 0x70E0, 0x3C00, 0x7460, 0x74A8, 0x70E0, 0x3C00, 0x7460, 0x74A8, 0x70E0,
+        0x3C00, 0x7460, 0x74A8, 0x70E0, 0x3C00, // row 0
+        0x7084, 0x73CF, 0x7257, 0x3C00, 0x6FE9, 0x738E, 0x72A2, 0x3C00, 0x70BF, 0x73EA, 0x7239,
+        0x3C00, 0x7460, 0x74A8, 0x70E0, 0x3C00, // row 1
+        0x6E28, 0x730B, 0x7356, 0x3C00, 0x6D3E, 0x72BD, 0x73C6, 0x3C00, 0x6F57, 0x7366, 0x72D2,
+        0x3C00, 0x7460, 0x74A8, 0x70E0, 0x3C00, // row 2
+        0x6D3E, 0x72BD, 0x73C6, 0x3C00, 0x6C6F, 0x727E, 0x7421, 0x3C00, 0x6E76, 0x7325, 0x7330,
+        0x3C00, 0x7460, 0x74A8, 0x70E0, 0x3C00, // row 3
+    ],
+};
+
+/// A 4x4 block with two partitions from seed 123, using HDR luminance endpoints with a
+/// small range and HDR RGB base+scale endpoints. Hand-assembled from the bit layout in the
+/// ASTC specification.
+pub const ASTC_HDR_TWO_PARTITIONS: HdrTestDataSet = HdrTestDataSet {
+    encoded: &[
+        0x42, 0x68, 0x8F, 0xFC, 0x51, 0x8D, 0xB3, 0x7C, 0xF6, 0x0E, 0x00, 0xC0, 0x8D, 0x02, 0xF8,
+        0x60,
+    ],
+    decoded: &[
+        0x2B58, 0x123A, 0x44BB, 0x3C00, 0x21C6, 0x08C6, 0x3B67, 0x3C00, 0x18C0, 0x0000, 0x3240,
+        0x3C00, 0x31A0, 0x31A0, 0x31A0, 0x3C00, // row 0
+        0x3460, 0x1B60, 0x4DC0, 0x3C00, 0x3460, 0x1B60, 0x4DC0, 0x3C00, 0x21C6, 0x08C6, 0x3B67,
+        0x3C00, 0x31A0, 0x31A0, 0x31A0, 0x3C00, // row 1
+        0x18C0, 0x0000, 0x3240, 0x3C00, 0x18C0, 0x0000, 0x3240, 0x3C00, 0x18C0, 0x0000, 0x3240,
+        0x3C00, 0x3209, 0x3209, 0x3209, 0x3C00, // row 2
+        0x21C6, 0x08C6, 0x3B67, 0x3C00, 0x18C0, 0x0000, 0x3240, 0x3C00, 0x3460, 0x1B60, 0x4DC0,
+        0x3C00, 0x3277, 0x3277, 0x3277, 0x3C00, // row 3
+    ],
+};
+
+/// A 4x4 block with HDR RGBA endpoints and a 3x3 weight grid, using a second weight
+/// plane for alpha. Hand-assembled from the bit layout in the ASTC specification.
+pub const ASTC_HDR_ALPHA: HdrTestDataSet = HdrTestDataSet {
+    encoded: &[
+        0xAE, 0xE5, 0x7B, 0x2C, 0x83, 0xE0, 0x55, 0x10, 0x27, 0x59, 0x00, 0x0C, 0x64, 0x71, 0xC3,
+        0xE9,
+    ],
+    decoded: &[
+        0x609C, 0x63B0, 0x63C4, 0x257C, 0x5FEA, 0x63B0, 0x62F5, 0x2630, 0x5FEA, 0x63B0, 0x62F5,
+        0x25E8, 0x609C, 0x63B0, 0x63C4, 0x2490, // row 0
+        0x5F67, 0x63B0, 0x629E, 0x26ED, 0x5FE1, 0x63B0, 0x62EE, 0x2729, 0x5FE1, 0x63B0, 0x62EE,
+        0x26C0, 0x5F67, 0x63B0, 0x629E, 0x25E8, // row 1
+        0x5F25, 0x63B0, 0x6277, 0x26ED, 0x5FF4, 0x63B0, 0x62FC, 0x26B4, 0x5FC5, 0x63B0, 0x62DA,
+        0x2660, 0x5EB0, 0x63B0, 0x6230, 0x25E8, // row 2
+        0x6025, 0x63B0, 0x6333, 0x257C, 0x6025, 0x63B0, 0x6333, 0x24D4, 0x5FC5, 0x63B0, 0x62DA,
+        0x2490, 0x5EB0, 0x63B0, 0x6230, 0x2490, // row 3
+    ],
+};
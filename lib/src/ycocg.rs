@@ -0,0 +1,106 @@
+// Copyright (c) 2018-2021 Jan Solanti <jhs@psonet.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to	deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be included
+// in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.
+// IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY
+// CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT,
+// TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION WITH THE
+// SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Scaled YCoCg colours in BC3 blocks, also known as YCoCg-DXT5
+//!
+//! Luma is stored in the alpha block, which has the most precision, and the chroma in red and
+//! green of the colour block, offset by 128. Blocks with little chroma scale it up by two or
+//! four to use more of the precision of the colour block, storing the scale in blue as 0, 8
+//! or 24. Alpha is not stored.
+
+use crate::colourblock::Bc1Block;
+use crate::{ColourSpace, ErrorMetric, Params};
+
+/// Converts the pixels of a block to scaled YCoCg, with the chroma in red and green, the scale
+/// in blue and the luma in alpha
+///
+/// The scale is the largest that keeps the chroma of every valid pixel in range.
+pub fn encode(rgba: &[[u8; 4]; 16], mask: u32) -> [[u8; 4]; 16] {
+    let chroma = |[r, g, b, _]: [u8; 4]| {
+        let [r, g, b] = [r, g, b].map(f32::from);
+        ((r - b) / 2.0, (2.0 * g - r - b) / 4.0)
+    };
+
+    let mut largest = 0.0f32;
+    for (i, &pixel) in rgba.iter().enumerate() {
+        if (mask & (1 << i)) != 0 {
+            let (co, cg) = chroma(pixel);
+            largest = largest.max(co.abs()).max(cg.abs());
+        }
+    }
+    let scale = [4, 2, 1]
+        .into_iter()
+        .find(|&scale| largest * scale as f32 <= 127.0)
+        .unwrap_or(1);
+    let blue = (scale as u8 - 1) * 8;
+
+    rgba.map(|pixel| {
+        let (co, cg) = chroma(pixel);
+        let [r, g, b] = [pixel[0], pixel[1], pixel[2]].map(u32::from);
+        let luma = ((r + 2 * g + b + 2) / 4) as u8;
+        let offset =
+            |value: f32| libm::roundf(value * scale as f32 + 128.0).clamp(0.0, 255.0) as u8;
+        [offset(co), offset(cg), blue, luma]
+    })
+}
+
+/// Returns the parameters the colour block of a YCoCg block is fitted with, which measure the
+/// error of the chroma alone
+pub fn params(params: &Params) -> Params {
+    Params {
+        weights: [1.0, 1.0, 0.0],
+        weigh_colour_by_alpha: false,
+        colour_space: ColourSpace::Linear,
+        error_metric: Some(ErrorMetric::Rgb),
+        ..*params
+    }
+}
+
+/// Writes the scale into the blue of both endpoints of a colour block, as the colour fit does
+/// not weigh blue
+pub fn store_scale(block: &mut [u8], blue: u8) {
+    let mut colour = Bc1Block::from_bytes(block);
+    let bits = u16::from(blue >> 3);
+    colour.colour0 = (colour.colour0 & !0x1f) | bits;
+    colour.colour1 = (colour.colour1 & !0x1f) | bits;
+    block[..8].copy_from_slice(&colour.to_bytes());
+}
+
+/// Returns whether both endpoints of a colour block hold the scale in blue
+pub fn has_scale(block: &[u8], blue: u8) -> bool {
+    let colour = Bc1Block::from_bytes(block);
+    let bits = u16::from(blue >> 3);
+    (colour.colour0 & 0x1f) == bits && (colour.colour1 & 0x1f) == bits
+}
+
+/// Converts pixels decoded from YCoCg BC3 blocks back to RGB with opaque alpha
+pub fn decode_ycocg(rgba: &mut [u8]) {
+    for pixel in rgba.chunks_exact_mut(4) {
+        let scale = f32::from((pixel[2] >> 3) + 1);
+        let co = (f32::from(pixel[0]) - 128.0) / scale;
+        let cg = (f32::from(pixel[1]) - 128.0) / scale;
+        let luma = f32::from(pixel[3]);
+
+        let rgb = [luma + co - cg, luma + cg, luma - co - cg];
+        let [r, g, b] = rgb.map(|value| libm::roundf(value).clamp(0.0, 255.0) as u8);
+        pixel.copy_from_slice(&[r, g, b, 255]);
+    }
+}